@@ -0,0 +1,74 @@
+use anyhow::Result;
+use playbyte_emulation::{EmulationThread, EmulatorRuntime};
+use playbyte_feed::LocalByteStore;
+use playbyte_libretro::MemoryRegion;
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+const FLUSH_INTERVAL: Duration = Duration::from_secs(10);
+const PERSISTED_REGIONS: [MemoryRegion; 2] = [MemoryRegion::SaveRam, MemoryRegion::Rtc];
+
+/// In-game (battery) saves of a ROM session, mirrored to `saves/` under the data root.
+///
+/// Only ROM sessions get one. A Byte's snapshot already carries its own cartridge RAM, and
+/// flushing it would overwrite the player's real save for that ROM.
+pub struct BatterySave {
+    rom_sha1: String,
+    flushed: HashMap<MemoryRegion, Vec<u8>>,
+    last_flush: Instant,
+}
+
+impl BatterySave {
+    /// Copies the ROM's saved memory into a freshly loaded runtime.
+    pub fn restore(
+        store: &LocalByteStore,
+        runtime: &mut EmulatorRuntime,
+        rom_sha1: &str,
+    ) -> Result<Self> {
+        let mut flushed = HashMap::new();
+        for region in PERSISTED_REGIONS {
+            let saved = match region {
+                MemoryRegion::Rtc => store.load_rtc(rom_sha1)?,
+                _ => store.load_save_ram(rom_sha1)?,
+            };
+            if let Some(data) = saved {
+                if runtime.read_memory(region)?.is_some() {
+                    runtime.write_memory(region, &data)?;
+                }
+            }
+            if let Some(current) = runtime.read_memory(region)? {
+                flushed.insert(region, current);
+            }
+        }
+        Ok(Self {
+            rom_sha1: rom_sha1.to_string(),
+            flushed,
+            last_flush: Instant::now(),
+        })
+    }
+
+    pub fn flush_due(&self) -> bool {
+        self.last_flush.elapsed() >= FLUSH_INTERVAL
+    }
+
+    /// Writes any region of the game on `emulation` that changed since the last flush.
+    pub fn flush(&mut self, store: &LocalByteStore, emulation: &EmulationThread) -> Result<()> {
+        self.last_flush = Instant::now();
+        for region in PERSISTED_REGIONS {
+            let Some(current) = emulation.call(move |runtime| runtime.read_memory(region))? else {
+                continue;
+            };
+            if self.flushed.get(&region) == Some(&current) {
+                continue;
+            }
+            match region {
+                MemoryRegion::Rtc => store.store_rtc(&self.rom_sha1, &current)?,
+                _ => store.store_save_ram(&self.rom_sha1, &current)?,
+            }
+            self.flushed.insert(region, current);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,54 @@
+use anyhow::Result;
+use playbyte_emulation::{EmulationThread, EmulatorRuntime};
+use playbyte_feed::LocalByteStore;
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+const FLUSH_INTERVAL: Duration = Duration::from_secs(10);
+
+/// A core's option values, mirrored to `data/core_options/<core>.json`.
+///
+/// Values change at runtime when something sets an option or the core redeclares its
+/// options, so the file is rewritten whenever what the core reports differs from it. Keys
+/// the core no longer declares are kept, so hand edits for other versions survive.
+pub struct CoreOptionsSave {
+    core_id: String,
+    saved: HashMap<String, String>,
+    last_flush: Instant,
+}
+
+impl CoreOptionsSave {
+    /// Writes the options a freshly loaded core declares, so there is a file to edit by hand.
+    pub fn seed(store: &LocalByteStore, runtime: &EmulatorRuntime, core_id: &str) -> Result<Self> {
+        let mut save = Self {
+            core_id: core_id.to_string(),
+            saved: store.load_core_options(core_id)?,
+            last_flush: Instant::now(),
+        };
+        save.write(store, runtime.core_option_values())?;
+        Ok(save)
+    }
+
+    pub fn flush_due(&self) -> bool {
+        self.last_flush.elapsed() >= FLUSH_INTERVAL
+    }
+
+    /// Writes the options of the core on `emulation` if any changed since the last flush.
+    pub fn flush(&mut self, store: &LocalByteStore, emulation: &EmulationThread) -> Result<()> {
+        self.last_flush = Instant::now();
+        let values = emulation.call(|runtime| Ok(runtime.core_option_values()))?;
+        self.write(store, values)
+    }
+
+    fn write(&mut self, store: &LocalByteStore, values: HashMap<String, String>) -> Result<()> {
+        let mut merged = self.saved.clone();
+        merged.extend(values);
+        if merged != self.saved || (!merged.is_empty() && !store.has_core_options(&self.core_id)) {
+            store.save_core_options(&self.core_id, &merged)?;
+            self.saved = merged;
+        }
+        Ok(())
+    }
+}
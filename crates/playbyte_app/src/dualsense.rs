@@ -182,16 +182,15 @@ fn emit_button_changes(
     ];
 
     for (button, before, after) in changes {
-        if before != after {
-            if proxy
+        if before != after
+            && proxy
                 .send_event(UserEvent::GamepadButton {
                     button,
                     pressed: after,
                 })
                 .is_err()
-            {
-                return true;
-            }
+        {
+            return true;
         }
     }
 
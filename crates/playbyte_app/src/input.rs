@@ -1,6 +1,14 @@
 use gilrs::Button;
 use winit::keyboard::KeyCode;
 
+/// Held to run the game backwards. These and the turbo bindings are not actions because
+/// they last while held.
+pub const REWIND_KEY: KeyCode = KeyCode::Backspace;
+pub const REWIND_BUTTON: Button = Button::LeftThumb;
+/// Held to run the game as fast as it will go.
+pub const TURBO_KEY: KeyCode = KeyCode::Space;
+pub const TURBO_BUTTON: Button = Button::RightThumb;
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Action {
     NextItem,
@@ -12,9 +20,27 @@ pub enum Action {
     OfficialPickerMove(i32),
     OfficialPickerConfirm,
     SelectIndex(usize),
-    RenameTitle { index: usize, title: String },
-    SetOfficialTitle { index: usize, title: String },
-    ClearOfficialTitle { index: usize },
+    RenameTitle {
+        index: usize,
+        title: String,
+    },
+    SetOfficialTitle {
+        index: usize,
+        title: String,
+    },
+    ClearOfficialTitle {
+        index: usize,
+    },
+    /// Plugs the next device the core offers into a port.
+    CyclePortDevice(u32),
+    /// Moves the keyboard to the next player.
+    CycleKeyboardPlayer,
+    SpeedUp,
+    SlowDown,
+    ResetSpeed,
+    ToggleDebugHud,
+    /// Starts or saves a ghost demo for the current Byte.
+    RecordGhost,
 }
 
 #[derive(Debug, Clone)]
@@ -45,15 +71,19 @@ pub fn action_from_key(key: KeyCode, pressed: bool) -> Option<Action> {
         KeyCode::PageDown => Some(Action::NextItem),
         KeyCode::Tab => Some(Action::ToggleOverlay),
         KeyCode::KeyB => Some(Action::CreateByte),
+        KeyCode::F1 => Some(Action::CyclePortDevice(0)),
+        KeyCode::F2 => Some(Action::CyclePortDevice(1)),
+        KeyCode::F3 => Some(Action::CycleKeyboardPlayer),
+        KeyCode::F4 => Some(Action::ToggleDebugHud),
+        KeyCode::Equal => Some(Action::SpeedUp),
+        KeyCode::Minus => Some(Action::SlowDown),
+        KeyCode::Digit0 => Some(Action::ResetSpeed),
+        KeyCode::KeyG => Some(Action::RecordGhost),
         _ => None,
     }
 }
 
-pub fn action_from_button(
-    button: Button,
-    pressed: bool,
-    context: ButtonContext,
-) -> Option<Action> {
+pub fn action_from_button(button: Button, pressed: bool, context: ButtonContext) -> Option<Action> {
     if !pressed {
         return None;
     }
@@ -78,7 +108,5 @@ pub fn action_from_button(
         };
     }
 
-    match button {
-        _ => None,
-    }
+    None
 }
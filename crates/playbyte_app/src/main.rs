@@ -1,24 +1,40 @@
+mod battery;
+mod core_options;
 mod dualsense;
 mod input;
+mod players;
+mod speed;
 mod ui;
 
 use anyhow::{bail, Context, Result};
+use battery::BatterySave;
 use bytemuck::{Pod, Zeroable};
+use core_options::CoreOptionsSave;
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
 use egui_wgpu::ScreenDescriptor;
-use gilrs::{Axis, Button, EventType, Gilrs};
+use gilrs::{Axis, Button, EventType, GamepadId, Gilrs};
 use image::codecs::png::PngEncoder;
 use image::{ColorType, ImageEncoder};
 use playbyte_emulation::{
-    AudioRingBuffer, EmulatorRuntime, JoypadState, RETRO_DEVICE_ID_JOYPAD_A,
-    RETRO_DEVICE_ID_JOYPAD_B, RETRO_DEVICE_ID_JOYPAD_DOWN, RETRO_DEVICE_ID_JOYPAD_L,
-    RETRO_DEVICE_ID_JOYPAD_LEFT, RETRO_DEVICE_ID_JOYPAD_R, RETRO_DEVICE_ID_JOYPAD_RIGHT,
-    RETRO_DEVICE_ID_JOYPAD_SELECT, RETRO_DEVICE_ID_JOYPAD_START, RETRO_DEVICE_ID_JOYPAD_UP,
-    RETRO_DEVICE_ID_JOYPAD_X, RETRO_DEVICE_ID_JOYPAD_Y,
+    input::{
+        RETRO_DEVICE_ID_ANALOG_X, RETRO_DEVICE_ID_ANALOG_Y, RETRO_DEVICE_ID_MOUSE_LEFT,
+        RETRO_DEVICE_ID_MOUSE_MIDDLE, RETRO_DEVICE_ID_MOUSE_RIGHT, RETRO_DEVICE_INDEX_ANALOG_LEFT,
+        RETRO_DEVICE_INDEX_ANALOG_RIGHT,
+    },
+    resample::MAX_FILL_FRAMES,
+    AudioResampler, AudioRingBuffer, AvInfoChange, EmulationEvent, EmulationThread,
+    EmulatorRuntime, GameContent, InputState, Movie, MovieRecorder, MovieStart, Pacing,
+    RewindConfig, RunAheadConfig, RuntimeConfig, RuntimeError, RuntimeView,
+    RETRO_DEVICE_ID_JOYPAD_A, RETRO_DEVICE_ID_JOYPAD_B, RETRO_DEVICE_ID_JOYPAD_DOWN,
+    RETRO_DEVICE_ID_JOYPAD_L, RETRO_DEVICE_ID_JOYPAD_LEFT, RETRO_DEVICE_ID_JOYPAD_R,
+    RETRO_DEVICE_ID_JOYPAD_RIGHT, RETRO_DEVICE_ID_JOYPAD_SELECT, RETRO_DEVICE_ID_JOYPAD_START,
+    RETRO_DEVICE_ID_JOYPAD_UP, RETRO_DEVICE_ID_JOYPAD_X, RETRO_DEVICE_ID_JOYPAD_Y,
 };
-use playbyte_feed::{LocalByteStore, RomLibrary};
-use playbyte_types::{ByteMetadata, System};
+use playbyte_feed::{BiosRegistry, BiosStatus, LocalByteStore, RomLibrary, RomSource};
+use playbyte_types::{ByteMetadata, Resolution, RunAheadSettings, System, BYTE_FORMAT_VERSION};
+use players::PlayerAssignments;
 use sha1::{Digest, Sha1};
+use speed::SpeedControl;
 use std::{
     collections::{HashMap, HashSet, VecDeque},
     fs,
@@ -33,7 +49,8 @@ use time::format_description::well_known::Rfc3339;
 use uuid::Uuid;
 use wgpu::util::DeviceExt;
 use winit::{
-    event::{ElementState, Event, WindowEvent},
+    dpi::PhysicalPosition,
+    event::{ElementState, Event, MouseButton, MouseScrollDelta, WindowEvent},
     event_loop::EventLoopBuilder,
     keyboard::{KeyCode, PhysicalKey},
     window::WindowBuilder,
@@ -86,6 +103,7 @@ const SDL_GAMECONTROLLERCONFIG: &str = "SDL_GAMECONTROLLERCONFIG";
 const DUALSENSE_USB_MAPPING: &str = "050000004c050000e60c000000010000,PS5 Controller,a:b1,b:b2,back:b8,dpdown:h0.4,dpleft:h0.8,dpright:h0.2,dpup:h0.1,guide:b12,leftshoulder:b4,leftstick:b10,lefttrigger:a3,leftx:a0,lefty:a1,misc1:b14,rightshoulder:b5,rightstick:b11,righttrigger:a4,rightx:a2,righty:a5,start:b9,touchpad:b13,x:b0,y:b3,platform:Mac OS X,";
 const DUALSENSE_BLUETOOTH_MAPPING: &str = "050000004c050000f20d000000010000,PS5 Controller,a:b1,b:b2,back:b8,dpdown:h0.4,dpleft:h0.8,dpright:h0.2,dpup:h0.1,guide:b12,leftshoulder:b4,leftstick:b10,lefttrigger:a3,leftx:a0,lefty:a1,rightshoulder:b5,rightstick:b11,righttrigger:a4,rightx:a2,righty:a5,start:b9,touchpad:b13,x:b0,y:b3,platform:Mac OS X,";
 const GAMEPAD_AXIS_THRESHOLD: f32 = 0.5;
+const LOAD_ERROR_LOG_LINES: usize = 6;
 
 fn configure_dualsense_mappings() {
     if !cfg!(target_os = "macos") {
@@ -110,6 +128,34 @@ fn configure_dualsense_mappings() {
     std::env::set_var(SDL_GAMECONTROLLERCONFIG, updated);
 }
 
+/// Maps a stick axis to `(RETRO_DEVICE_INDEX_ANALOG_*, RETRO_DEVICE_ID_ANALOG_*, value)`.
+/// gilrs reports up as positive, libretro as negative.
+fn axis_to_analog(axis: Axis, value: f32) -> Option<(u32, u32, f32)> {
+    match axis {
+        Axis::LeftStickX => Some((
+            RETRO_DEVICE_INDEX_ANALOG_LEFT,
+            RETRO_DEVICE_ID_ANALOG_X,
+            value,
+        )),
+        Axis::LeftStickY => Some((
+            RETRO_DEVICE_INDEX_ANALOG_LEFT,
+            RETRO_DEVICE_ID_ANALOG_Y,
+            -value,
+        )),
+        Axis::RightStickX => Some((
+            RETRO_DEVICE_INDEX_ANALOG_RIGHT,
+            RETRO_DEVICE_ID_ANALOG_X,
+            value,
+        )),
+        Axis::RightStickY => Some((
+            RETRO_DEVICE_INDEX_ANALOG_RIGHT,
+            RETRO_DEVICE_ID_ANALOG_Y,
+            -value,
+        )),
+        _ => None,
+    }
+}
+
 fn axis_to_dpad_buttons(axis: Axis, value: f32) -> Option<[(Button, bool); 2]> {
     let direction = if value <= -GAMEPAD_AXIS_THRESHOLD {
         -1
@@ -177,8 +223,13 @@ struct AppConfig {
     data_root: PathBuf,
     rom_root: PathBuf,
     cores_root: PathBuf,
+    bios_root: PathBuf,
     vsync: bool,
     dualsense_swipes: bool,
+    isolate_cores: bool,
+    pacing: Pacing,
+    /// Run-ahead for cores without an entry in `run_ahead.json`.
+    run_ahead: Option<RunAheadConfig>,
 }
 
 impl AppConfig {
@@ -191,8 +242,13 @@ impl AppConfig {
         let mut data_root_overridden = false;
         let mut rom_root_overridden = false;
         let mut cores_root_overridden = false;
+        let mut bios_root = None;
         let mut vsync = true;
         let mut dualsense_swipes = true;
+        let mut isolate_cores = false;
+        let mut pacing = Pacing::default();
+        let mut run_ahead_frames = 0;
+        let mut run_ahead_second_instance = false;
         let mut args = std::env::args().skip(1);
 
         while let Some(arg) = args.next() {
@@ -221,12 +277,33 @@ impl AppConfig {
                         cores_root_overridden = true;
                     }
                 }
+                "--bios" => {
+                    bios_root = args.next().map(PathBuf::from);
+                }
                 "--no-vsync" => {
                     vsync = false;
                 }
                 "--no-dualsense-swipes" => {
                     dualsense_swipes = false;
                 }
+                "--isolate-cores" => {
+                    isolate_cores = true;
+                }
+                "--pacing" => match args.next().as_deref() {
+                    Some("audio") => pacing = Pacing::Audio,
+                    Some("display") => pacing = Pacing::Display,
+                    Some("both") => pacing = Pacing::Both,
+                    other => {
+                        eprintln!("Unknown --pacing {other:?}; expected audio, display or both")
+                    }
+                },
+                "--run-ahead" => match args.next().map(|value| value.parse()) {
+                    Some(Ok(frames)) => run_ahead_frames = frames,
+                    _ => eprintln!("--run-ahead takes a number of frames"),
+                },
+                "--run-ahead-second-instance" => {
+                    run_ahead_second_instance = true;
+                }
                 _ => {}
             }
         }
@@ -249,14 +326,34 @@ impl AppConfig {
             }
         }
 
+        let bios_root = bios_root.unwrap_or_else(|| data_root.join("system"));
+
         Self {
             core_path,
             rom_path,
             data_root,
             rom_root,
             cores_root,
+            bios_root,
             vsync,
             dualsense_swipes,
+            isolate_cores,
+            pacing,
+            run_ahead: (run_ahead_frames > 0).then_some(RunAheadConfig {
+                frames: run_ahead_frames,
+                second_instance: run_ahead_second_instance,
+            }),
+        }
+    }
+
+    fn runtime_config(&self) -> RuntimeConfig {
+        RuntimeConfig {
+            isolate_core: self.isolate_cores,
+            system_directory: Some(self.bios_root.clone()),
+            save_directory: Some(LocalByteStore::new(&self.data_root).saves_root()),
+            rewind: Some(RewindConfig::default()),
+            run_ahead: self.run_ahead,
+            ..RuntimeConfig::default()
         }
     }
 }
@@ -281,16 +378,13 @@ fn resolve_assets_root() -> Option<PathBuf> {
         }
     }
 
-    for candidate in expanded {
-        if has_asset_dirs(&candidate) {
-            return Some(candidate);
-        }
-    }
-    None
+    expanded
+        .into_iter()
+        .find(|candidate| has_asset_dirs(candidate))
 }
 
-fn find_repo_root(start: &PathBuf) -> Option<PathBuf> {
-    let mut current = start.clone();
+fn find_repo_root(start: &Path) -> Option<PathBuf> {
+    let mut current = start.to_path_buf();
     loop {
         if current.join(".git").exists() || current.join("Cargo.toml").exists() {
             return Some(current);
@@ -302,7 +396,7 @@ fn find_repo_root(start: &PathBuf) -> Option<PathBuf> {
     None
 }
 
-fn has_asset_dirs(root: &PathBuf) -> bool {
+fn has_asset_dirs(root: &Path) -> bool {
     root.join("data").exists() || root.join("roms").exists() || root.join("cores").exists()
 }
 
@@ -316,14 +410,15 @@ fn default_data_root() -> Option<PathBuf> {
         Some(root)
     } else if cfg!(target_os = "windows") {
         std::env::var_os("APPDATA").map(|appdata| PathBuf::from(appdata).join("Playbyte"))
+    } else if let Some(xdg) = std::env::var_os("XDG_DATA_HOME") {
+        Some(PathBuf::from(xdg).join("playbyte"))
     } else {
-        if let Some(xdg) = std::env::var_os("XDG_DATA_HOME") {
-            Some(PathBuf::from(xdg).join("playbyte"))
-        } else if let Some(home) = std::env::var_os("HOME") {
-            Some(PathBuf::from(home).join(".local").join("share").join("playbyte"))
-        } else {
-            None
-        }
+        std::env::var_os("HOME").map(|home| {
+            PathBuf::from(home)
+                .join(".local")
+                .join("share")
+                .join("playbyte")
+        })
     }
 }
 
@@ -368,13 +463,15 @@ struct RuntimeMetadata {
     core_id: String,
     core_version: String,
     rom_sha1: String,
-    _rom_path: PathBuf,
+    _rom: RomSource,
     system: System,
 }
 
 struct RuntimeLoad {
     runtime: EmulatorRuntime,
     meta: RuntimeMetadata,
+    battery: Option<BatterySave>,
+    core_options: Option<CoreOptionsSave>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
@@ -385,14 +482,14 @@ enum SessionAutosaveKey {
 
 #[derive(Clone)]
 enum FeedItem {
-    Byte(ByteMetadata),
+    Byte(Box<ByteMetadata>),
     RomFallback(RomFallback),
 }
 
 #[derive(Clone)]
 struct RomFallback {
     rom_sha1: String,
-    rom_path: PathBuf,
+    rom: RomSource,
     system: System,
     title: String,
     official_title: Option<String>,
@@ -403,8 +500,8 @@ struct RomFallback {
 impl FeedItem {
     fn system(&self) -> System {
         match self {
-            FeedItem::Byte(byte) => byte.system.clone(),
-            FeedItem::RomFallback(fallback) => fallback.system.clone(),
+            FeedItem::Byte(byte) => byte.system,
+            FeedItem::RomFallback(fallback) => fallback.system,
         }
     }
 
@@ -427,7 +524,6 @@ impl FeedItem {
             FeedItem::RomFallback(fallback) => SessionAutosaveKey::Rom(fallback.rom_sha1.clone()),
         }
     }
-
 }
 
 struct FeedController {
@@ -436,6 +532,11 @@ struct FeedController {
     core_locator: CoreLocator,
     items: Vec<FeedItem>,
     current_index: usize,
+    isolate_cores: bool,
+    bios: BiosRegistry,
+    run_ahead: Option<RunAheadConfig>,
+    /// Per-core run-ahead from `run_ahead.json`, keyed by core id.
+    core_run_ahead: HashMap<String, RunAheadSettings>,
 }
 
 impl FeedController {
@@ -444,14 +545,27 @@ impl FeedController {
         let bytes = store.load_index()?;
         let rom_titles = store.load_rom_titles()?;
         let rom_overrides = store.load_rom_official_overrides()?;
+        let core_run_ahead = store.load_run_ahead()?;
 
         let mut roms = RomLibrary::new();
         roms.add_root(&config.rom_root);
         let _ = roms.scan()?;
 
+        // Create the directories up front so there is an obvious place to drop BIOS files.
+        fs::create_dir_all(&config.bios_root)?;
+        fs::create_dir_all(store.saves_root())?;
+        let mut bios = BiosRegistry::new(&config.bios_root);
+        let _ = bios.scan()?;
+
         let core_locator = CoreLocator::new(config.cores_root.clone());
-        let items =
-            build_feed_items(&store, &core_locator, &roms, &bytes, &rom_titles, &rom_overrides)?;
+        let items = build_feed_items(
+            &store,
+            &core_locator,
+            &roms,
+            &bytes,
+            &rom_titles,
+            &rom_overrides,
+        )?;
 
         Ok(Self {
             store,
@@ -459,9 +573,36 @@ impl FeedController {
             core_locator,
             items,
             current_index: 0,
+            isolate_cores: config.isolate_cores,
+            bios,
+            run_ahead: config.run_ahead,
+            core_run_ahead,
         })
     }
 
+    /// Problems with the BIOS files on disk worth telling the player about.
+    fn bios_warnings(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+        for entry in self.bios.entries() {
+            let name = entry.bios.file_name;
+            if let BiosStatus::Bad { .. } = entry.status {
+                warnings.push(format!(
+                    "{name} does not match the known {} dump",
+                    entry.bios.description
+                ));
+            }
+            if let Some(found) = &entry.found_elsewhere {
+                warnings.push(format!(
+                    "Found {} at {}; rename it to {}",
+                    entry.bios.description,
+                    found.display(),
+                    entry.path.display()
+                ));
+            }
+        }
+        warnings
+    }
+
     fn is_empty(&self) -> bool {
         self.items.is_empty()
     }
@@ -517,19 +658,26 @@ impl FeedController {
     }
 
     fn build_runtime_for_current(&self) -> Result<RuntimeLoad> {
-        let item = self.current().context("no selected item available in feed")?;
+        let item = self
+            .current()
+            .context("no selected item available in feed")?;
         match item {
             FeedItem::Byte(byte) => {
                 let core_path = self
                     .core_locator
                     .resolve(&byte.core_id)
                     .with_context(|| format!("missing core for {}", byte.core_id))?;
-                let rom_path = self
+                let rom = self
                     .roms
                     .find_by_hash(&byte.rom_sha1)
                     .with_context(|| format!("missing ROM for hash {}", byte.rom_sha1))?;
 
-                let runtime = EmulatorRuntime::new(core_path, rom_path.clone())?;
+                let runtime = EmulatorRuntime::with_content(
+                    &core_path,
+                    game_content(&rom)?,
+                    self.runtime_config(&core_path),
+                )?;
+                let core_options = self.seed_core_options(&core_path, &runtime);
                 let info = runtime.system_info();
                 if info.library_name != byte.core_id {
                     bail!(
@@ -546,15 +694,21 @@ impl FeedController {
                     );
                 }
                 let state = self.store.load_state(&byte.byte_id)?;
+                runtime.check_serialize_size(byte.serialize_size)?;
                 runtime.unserialize(&state)?;
                 let meta = RuntimeMetadata {
                     core_id: byte.core_id.clone(),
                     core_version: byte.core_semver.clone(),
                     rom_sha1: byte.rom_sha1.clone(),
-                    _rom_path: rom_path,
-                    system: byte.system.clone(),
+                    _rom: rom,
+                    system: byte.system,
                 };
-                Ok(RuntimeLoad { runtime, meta })
+                Ok(RuntimeLoad {
+                    runtime,
+                    meta,
+                    battery: None,
+                    core_options,
+                })
             }
             FeedItem::RomFallback(fallback) => {
                 let core_path = if let Some(path) = &fallback.core_path {
@@ -564,35 +718,92 @@ impl FeedController {
                         .resolve(&fallback.core_id)
                         .with_context(|| format!("missing core for {}", fallback.core_id))?
                 };
-                let runtime = EmulatorRuntime::new(core_path, fallback.rom_path.clone())?;
-                let meta = build_runtime_meta_from_runtime(&runtime, &fallback.rom_path)?;
-                Ok(RuntimeLoad { runtime, meta })
+                let mut runtime = EmulatorRuntime::with_content(
+                    &core_path,
+                    game_content(&fallback.rom)?,
+                    self.runtime_config(&core_path),
+                )?;
+                let core_options = self.seed_core_options(&core_path, &runtime);
+                let battery = BatterySave::restore(&self.store, &mut runtime, &fallback.rom_sha1)
+                    .context("failed to load battery save")?;
+                let meta = build_runtime_meta_from_runtime(&runtime, &fallback.rom)?;
+                Ok(RuntimeLoad {
+                    runtime,
+                    meta,
+                    battery: Some(battery),
+                    core_options,
+                })
             }
         }
     }
 
+    /// Starts the current Byte's ghost, if it has one. `load` should be at the Byte's state.
+    fn play_ghost(&self, load: &mut RuntimeLoad) -> Result<()> {
+        let Some(FeedItem::Byte(byte)) = self.current() else {
+            return Ok(());
+        };
+        let Some(ghost) = self.store.load_ghost(&byte.byte_id)? else {
+            return Ok(());
+        };
+        let movie = Movie::decode(&ghost)?;
+        if movie.rom_sha1 != byte.rom_sha1 {
+            bail!("it was recorded on a different ROM");
+        }
+        load.runtime.play_movie(movie)?;
+        Ok(())
+    }
+
+    fn runtime_config(&self, core_path: &Path) -> RuntimeConfig {
+        let core_id = core_id_from_path(core_path);
+        let core_options = core_id
+            .as_ref()
+            .and_then(|core_id| self.store.load_core_options(core_id).ok())
+            .unwrap_or_default();
+        let run_ahead = match core_id.and_then(|core_id| self.core_run_ahead.get(&core_id)) {
+            Some(settings) => Some(RunAheadConfig {
+                frames: settings.frames,
+                second_instance: settings.second_instance,
+            }),
+            None => self.run_ahead,
+        };
+        RuntimeConfig {
+            core_options,
+            isolate_core: self.isolate_cores,
+            system_directory: Some(self.bios.root().to_path_buf()),
+            save_directory: Some(self.store.saves_root()),
+            rewind: Some(RewindConfig::default()),
+            run_ahead,
+            ..RuntimeConfig::default()
+        }
+    }
+
+    fn seed_core_options(
+        &self,
+        core_path: &Path,
+        runtime: &EmulatorRuntime,
+    ) -> Option<CoreOptionsSave> {
+        let core_id = core_id_from_path(core_path)?;
+        CoreOptionsSave::seed(&self.store, runtime, &core_id).ok()
+    }
+
     fn add_byte(&mut self, metadata: ByteMetadata) {
         let rom_sha1 = metadata.rom_sha1.clone();
         self.items.retain(|item| {
             !matches!(item, FeedItem::RomFallback(fallback) if fallback.rom_sha1 == rom_sha1)
         });
-        self.items.push(FeedItem::Byte(metadata));
+        self.items.push(FeedItem::Byte(Box::new(metadata)));
         self.current_index = self.items.len().saturating_sub(1);
     }
 
-    fn add_fallback_rom(
-        &mut self,
-        rom_path: PathBuf,
-        core_path: Option<PathBuf>,
-    ) -> Result<()> {
-        let rom_sha1 = hash_rom(&rom_path)?;
+    fn add_fallback_rom(&mut self, rom: RomSource, core_path: Option<PathBuf>) -> Result<()> {
+        let rom_sha1 = hash_rom(&rom)?;
         if self.items.iter().any(|item| match item {
             FeedItem::Byte(byte) => byte.rom_sha1 == rom_sha1,
             FeedItem::RomFallback(fallback) => fallback.rom_sha1 == rom_sha1,
         }) {
             return Ok(());
         }
-        let system = system_from_rom_path(&rom_path);
+        let system = system_from_rom_path(rom.rom_name());
         let core_id = if let Some(path) = &core_path {
             core_id_from_path(path)
                 .ok_or_else(|| anyhow::anyhow!("unable to infer core id from path"))?
@@ -602,11 +813,11 @@ impl FeedController {
                 .items
                 .iter()
                 .filter_map(|item| match item {
-                    FeedItem::Byte(byte) => Some(byte.clone()),
+                    FeedItem::Byte(byte) => Some(ByteMetadata::clone(byte)),
                     FeedItem::RomFallback(_) => None,
                 })
                 .collect();
-            select_default_core(system.clone(), &available_cores, &bytes, &self.core_locator)
+            select_default_core(system, &available_cores, &bytes, &self.core_locator)
                 .ok_or_else(|| anyhow::anyhow!("no core available for fallback ROM"))?
         };
         let rom_titles = self.store.load_rom_titles()?;
@@ -614,18 +825,12 @@ impl FeedController {
         let title = rom_titles
             .get(&rom_sha1)
             .cloned()
-            .unwrap_or_else(|| title_from_rom_path(&rom_path));
-        let official_title = resolve_official_title(
-            &self.store,
-            &rom_sha1,
-            &rom_path,
-            system.clone(),
-            &title,
-            &rom_overrides,
-        );
+            .unwrap_or_else(|| title_from_rom_path(rom.rom_name()));
+        let official_title =
+            resolve_official_title(&self.store, &rom_sha1, &rom, system, &title, &rom_overrides);
         self.items.push(FeedItem::RomFallback(RomFallback {
             rom_sha1,
-            rom_path,
+            rom,
             system,
             title,
             official_title,
@@ -647,8 +852,12 @@ fn build_feed_items(
     rom_titles: &HashMap<String, String>,
     rom_overrides: &HashMap<String, String>,
 ) -> Result<Vec<FeedItem>> {
-    let mut items: Vec<FeedItem> = bytes.iter().cloned().map(FeedItem::Byte).collect();
-    let mut covered_roms: HashSet<String> = bytes.iter().map(|byte| byte.rom_sha1.clone()).collect();
+    let mut items: Vec<FeedItem> = bytes
+        .iter()
+        .map(|byte| FeedItem::Byte(Box::new(byte.clone())))
+        .collect();
+    let mut covered_roms: HashSet<String> =
+        bytes.iter().map(|byte| byte.rom_sha1.clone()).collect();
 
     let available_cores = list_core_ids(&core_locator.root);
     let nes_core = select_default_core(System::Nes, &available_cores, bytes, core_locator);
@@ -657,12 +866,12 @@ fn build_feed_items(
     let gba_core = select_default_core(System::Gba, &available_cores, bytes, core_locator);
 
     let mut rom_entries = roms.entries();
-    rom_entries.sort_by(|a, b| a.1.to_string_lossy().cmp(&b.1.to_string_lossy()));
-    for (rom_sha1, rom_path) in rom_entries {
+    rom_entries.sort_by_cached_key(|(_, rom)| rom.to_string());
+    for (rom_sha1, rom) in rom_entries {
         if covered_roms.contains(&rom_sha1) {
             continue;
         }
-        let system = system_from_rom_path(&rom_path);
+        let system = system_from_rom_path(rom.rom_name());
         let core_id = match system {
             System::Nes => nes_core.clone(),
             System::Snes => snes_core.clone(),
@@ -675,18 +884,12 @@ fn build_feed_items(
         let title = rom_titles
             .get(&rom_sha1)
             .cloned()
-            .unwrap_or_else(|| title_from_rom_path(&rom_path));
-        let official_title = resolve_official_title(
-            store,
-            &rom_sha1,
-            &rom_path,
-            system.clone(),
-            &title,
-            rom_overrides,
-        );
+            .unwrap_or_else(|| title_from_rom_path(rom.rom_name()));
+        let official_title =
+            resolve_official_title(store, &rom_sha1, &rom, system, &title, rom_overrides);
         items.push(FeedItem::RomFallback(RomFallback {
             rom_sha1: rom_sha1.clone(),
-            rom_path,
+            rom,
             system,
             title,
             official_title,
@@ -709,7 +912,7 @@ fn title_from_rom_path(path: &Path) -> String {
 fn resolve_official_title(
     store: &LocalByteStore,
     rom_sha1: &str,
-    rom_path: &PathBuf,
+    rom: &RomSource,
     system: System,
     display_title: &str,
     overrides: &HashMap<String, String>,
@@ -724,7 +927,7 @@ fn resolve_official_title(
     }
 
     if system == System::Snes {
-        if let Ok(Some(alt_sha1)) = hash_rom_without_snes_header(rom_path) {
+        if let Ok(Some(alt_sha1)) = hash_rom_without_snes_header(rom) {
             if let Some(title) = db.title_for_sha1(&alt_sha1) {
                 return Some(title.to_string());
             }
@@ -735,7 +938,7 @@ fn resolve_official_title(
         return Some(title);
     }
 
-    let fallback = title_from_rom_path(rom_path);
+    let fallback = title_from_rom_path(rom.rom_name());
     if fallback != display_title {
         return db.best_match(&fallback);
     }
@@ -743,17 +946,36 @@ fn resolve_official_title(
     None
 }
 
-fn core_id_from_path(path: &PathBuf) -> Option<String> {
+/// The last lines a core logged before a failed load, each on its own line.
+fn core_log_tail(err: &(dyn std::error::Error + 'static)) -> String {
+    let mut source = Some(err);
+    while let Some(err) = source {
+        if let Some(err) = err.downcast_ref::<RuntimeError>() {
+            let log = err.core_log();
+            let start = log.len().saturating_sub(LOAD_ERROR_LOG_LINES);
+            return log[start..]
+                .iter()
+                .map(|line| format!("\n{line}"))
+                .collect();
+        }
+        source = err.source();
+    }
+    String::new()
+}
+
+fn core_id_from_path(path: &Path) -> Option<String> {
     let filename = path.file_name()?.to_str()?;
     if let Some((core_id, _)) = filename.split_once("_libretro.") {
         if !core_id.is_empty() {
             return Some(core_id.to_string());
         }
     }
-    path.file_stem().and_then(|stem| stem.to_str()).map(|stem| stem.to_string())
+    path.file_stem()
+        .and_then(|stem| stem.to_str())
+        .map(|stem| stem.to_string())
 }
 
-fn list_core_ids(root: &PathBuf) -> Vec<String> {
+fn list_core_ids(root: &Path) -> Vec<String> {
     let mut cores = Vec::new();
     let Ok(entries) = fs::read_dir(root) else {
         return cores;
@@ -901,10 +1123,14 @@ struct State {
     num_indices: u32,
     texture_layout: wgpu::BindGroupLayout,
     video_texture: VideoTexture,
-    runtime: Option<EmulatorRuntime>,
+    /// Runs the game, when there is one, off the event loop.
+    emulation: EmulationThread,
     runtime_meta: Option<RuntimeMetadata>,
+    battery: Option<BatterySave>,
+    core_options: Option<CoreOptionsSave>,
     session_autosaves: HashMap<SessionAutosaveKey, Vec<u8>>,
     gilrs: Option<Gilrs>,
+    players: PlayerAssignments,
     audio_stream: Option<cpal::Stream>,
     feed: Option<FeedController>,
     feed_error: Option<String>,
@@ -914,8 +1140,17 @@ struct State {
     l2_held: bool,
     r2_held: bool,
     overlay_toggle_armed: bool,
+    /// The rewind key and gamepad button; the game runs backwards while either is held.
+    rewind_key_held: bool,
+    rewind_button_held: bool,
+    speed: SpeedControl,
+    turbo_key_held: bool,
+    turbo_button_held: bool,
+    /// Last cursor position over the window, for turning motion into mouse deltas.
+    cursor: Option<PhysicalPosition<f64>>,
+    /// Mouse motion in game pixels not yet handed to the core as whole pixels.
+    mouse_remainder: (f64, f64),
     last_update: Instant,
-    accumulator: f64,
     frame_stats: FrameStats,
     data_root: PathBuf,
 }
@@ -1077,42 +1312,62 @@ fn fs_main(in: VertexOut) -> @location(0) vec4<f32> {
             usage: wgpu::BufferUsages::INDEX,
         });
 
-    let mut feed = None;
-    let mut feed_error = None;
-    let mut runtime_load: Option<RuntimeLoad> = None;
+        let mut feed = None;
+        let mut feed_error = None;
+        let mut runtime_load: Option<RuntimeLoad> = None;
 
-    match FeedController::load(&app_config) {
-        Ok(controller) => {
-            if controller.is_empty() {
-                feed_error = Some("No Bytes or ROMs found to build the feed.".to_string());
+        match FeedController::load(&app_config) {
+            Ok(controller) => {
+                if controller.is_empty() {
+                    feed_error = Some("No Bytes or ROMs found to build the feed.".to_string());
+                }
+                feed = Some(controller);
             }
-            feed = Some(controller);
+            Err(err) => feed_error = Some(format!("Feed error: {err}")),
         }
-        Err(err) => feed_error = Some(format!("Feed error: {err}")),
-    }
 
-    if let (Some(core), Some(rom)) = (app_config.core_path.clone(), app_config.rom_path.clone())
-    {
-        if let Some(controller) = feed.as_mut() {
-            if let Err(err) = controller.add_fallback_rom(rom, Some(core)) {
-                feed_error = Some(format!("Feed ROM error: {err}"));
-            }
-        } else {
-            match EmulatorRuntime::new(core, rom.clone()) {
-                Ok(rt) => match build_runtime_meta_from_runtime(&rt, &rom) {
-                    Ok(meta) => runtime_load = Some(RuntimeLoad { runtime: rt, meta }),
-                    Err(err) => feed_error = Some(format!("Runtime meta error: {err}")),
-                },
-                Err(err) => feed_error = Some(format!("Runtime error: {err}")),
+        if let (Some(core), Some(rom)) = (app_config.core_path.clone(), app_config.rom_path.clone())
+        {
+            let rom = RomSource::file(rom);
+            if let Some(controller) = feed.as_mut() {
+                if let Err(err) = controller.add_fallback_rom(rom, Some(core)) {
+                    feed_error = Some(format!("Feed ROM error: {err}"));
+                }
+            } else {
+                match EmulatorRuntime::with_config(core, &rom.path, app_config.runtime_config()) {
+                    Ok(rt) => match build_runtime_meta_from_runtime(&rt, &rom) {
+                        Ok(meta) => {
+                            runtime_load = Some(RuntimeLoad {
+                                runtime: rt,
+                                meta,
+                                battery: None,
+                                core_options: None,
+                            })
+                        }
+                        Err(err) => feed_error = Some(format!("Runtime meta error: {err}")),
+                    },
+                    Err(err) => {
+                        feed_error = Some(format!("Runtime error: {err}{}", core_log_tail(&err)))
+                    }
+                }
             }
         }
-    }
 
         if let Some(controller) = &feed {
             if !controller.is_empty() {
                 match controller.build_runtime_for_current() {
-                    Ok(load) => runtime_load = Some(load),
-                    Err(err) => feed_error = Some(format!("Load feed item failed: {err}")),
+                    Ok(mut load) => {
+                        if let Err(err) = controller.play_ghost(&mut load) {
+                            feed_error = Some(format!("Ghost not played: {err}"));
+                        }
+                        runtime_load = Some(load);
+                    }
+                    Err(err) => {
+                        feed_error = Some(format!(
+                            "Load feed item failed: {err}{}",
+                            core_log_tail(err.as_ref())
+                        ))
+                    }
                 }
             }
         }
@@ -1120,19 +1375,33 @@ fn fs_main(in: VertexOut) -> @location(0) vec4<f32> {
         if let Some(controller) = &feed {
             controller.prefetch_neighbors();
         }
+        let bios_warnings = feed
+            .as_ref()
+            .map(FeedController::bios_warnings)
+            .unwrap_or_default();
 
-        let (runtime, runtime_meta) = match runtime_load {
-            Some(load) => (Some(load.runtime), Some(load.meta)),
-            None => (None, None),
+        let (runtime, runtime_meta, battery, core_options) = match runtime_load {
+            Some(load) => (
+                Some(load.runtime),
+                Some(load.meta),
+                load.battery,
+                load.core_options,
+            ),
+            None => (None, None, None, None),
         };
         configure_dualsense_mappings();
         let (gilrs, gamepad_error) = match Gilrs::new() {
             Ok(gilrs) => (Some(gilrs), None),
             Err(err) => (None, Some(format!("Gamepad init failed: {err}"))),
         };
-        let audio_stream = runtime
-            .as_ref()
-            .and_then(|rt| build_audio_stream(rt.audio_buffer()).ok());
+        let mut emulation = EmulationThread::spawn()?;
+        emulation.set_pacing(app_config.pacing);
+        if let Some(runtime) = runtime {
+            emulation.load(runtime);
+        }
+        let audio_stream = emulation
+            .runtime()
+            .and_then(|view| build_audio_stream(view).ok());
 
         let gui = GuiState::new(window, &device, surface_config.format);
         let mut ui = ui::UiState::new(&gui.ctx);
@@ -1157,9 +1426,19 @@ fn fs_main(in: VertexOut) -> @location(0) vec4<f32> {
         if !detected_gamepads.is_empty() {
             dualsense_buttons_enabled.store(false, Ordering::Relaxed);
         }
+        let mut players = PlayerAssignments::new();
+        if let Some(gilrs) = gilrs.as_ref() {
+            for (id, gamepad) in gilrs.gamepads() {
+                players.connect(id, gamepad.name());
+            }
+        }
+        ui.show_players(players.summary());
         for message in detected_gamepads {
             ui.push_toast(ui::ToastKind::Success, message);
         }
+        for message in bios_warnings {
+            ui.push_toast(ui::ToastKind::Error, message);
+        }
 
         Ok(Self {
             surface,
@@ -1173,10 +1452,13 @@ fn fs_main(in: VertexOut) -> @location(0) vec4<f32> {
             num_indices: INDICES.len() as u32,
             texture_layout,
             video_texture,
-            runtime,
+            emulation,
             runtime_meta,
+            battery,
+            core_options,
             session_autosaves: HashMap::new(),
             gilrs,
+            players,
             audio_stream,
             feed,
             feed_error,
@@ -1186,8 +1468,14 @@ fn fs_main(in: VertexOut) -> @location(0) vec4<f32> {
             l2_held: false,
             r2_held: false,
             overlay_toggle_armed: true,
+            rewind_key_held: false,
+            rewind_button_held: false,
+            speed: SpeedControl::new(),
+            turbo_key_held: false,
+            turbo_button_held: false,
+            cursor: None,
+            mouse_remainder: (0.0, 0.0),
             last_update: Instant::now(),
-            accumulator: 0.0,
             frame_stats: FrameStats::new(120),
             data_root: app_config.data_root,
         })
@@ -1287,28 +1575,97 @@ fn fs_main(in: VertexOut) -> @location(0) vec4<f32> {
 
     fn update(&mut self, dt: Duration) {
         self.frame_stats.record(dt);
-        let input_state = self.runtime.as_ref().map(|runtime| runtime.input_state());
+        let input_state = self.emulation.runtime().map(RuntimeView::input_state);
         self.poll_gamepads(input_state);
-        if let Some(runtime) = self.runtime.as_mut() {
-            self.accumulator += dt.as_secs_f64();
-            let frame_time = 1.0 / runtime.fps();
-            let frame_time = frame_time.max(1.0 / 1000.0);
-            self.accumulator = self.accumulator.min(frame_time * 5.0);
-            while self.accumulator >= frame_time {
-                runtime.run_frame();
-                self.accumulator -= frame_time;
+        self.speed
+            .set_turbo(self.turbo_key_held || self.turbo_button_held);
+        self.emulation.set_speed(self.speed.multiplier());
+        self.emulation
+            .set_rewinding(self.rewind_key_held || self.rewind_button_held);
+        self.emulation.tick(dt);
+        if let Some(frame) = self.emulation.new_frame() {
+            self.update_video_texture(&frame);
+        }
+        let mut failure = None;
+        while let Some(event) = self.emulation.poll_event() {
+            match event {
+                EmulationEvent::AvChanged(change) => self.apply_av_change(change),
+                EmulationEvent::Stopped(err) => failure = Some(err),
             }
-            if let Some(frame) = runtime.latest_frame() {
-                self.update_video_texture(&frame);
+        }
+        if let Some(err) = failure {
+            self.handle_runtime_failure(err);
+        } else {
+            if self.battery.as_ref().is_some_and(BatterySave::flush_due) {
+                self.flush_battery_save();
+            }
+            if self
+                .core_options
+                .as_ref()
+                .is_some_and(CoreOptionsSave::flush_due)
+            {
+                self.flush_core_options();
             }
         }
     }
 
+    /// Follows a core switching resolution or timing mid-game.
+    ///
+    /// The video texture already follows the frame size and the viewport follows the AV
+    /// info, so only timing changes need work here.
+    fn apply_av_change(&mut self, change: AvInfoChange) {
+        if !change.timing_changed {
+            return;
+        }
+        // The emulation thread restarts its pacing; reopen audio for the new sample rate.
+        if let Some(view) = self.emulation.runtime() {
+            self.audio_stream = build_audio_stream(view).ok();
+        }
+    }
+
+    /// Writes changed in-game save memory of the current ROM session to disk.
+    fn flush_battery_save(&mut self) {
+        let (Some(battery), Some(feed)) = (self.battery.as_mut(), self.feed.as_ref()) else {
+            return;
+        };
+        if let Err(err) = battery.flush(&feed.store, &self.emulation) {
+            self.ui
+                .push_toast(ui::ToastKind::Error, format!("Saving game failed: {err}"));
+        }
+    }
+
+    /// Writes the current core's options to disk if any changed while it ran.
+    fn flush_core_options(&mut self) {
+        let (Some(options), Some(feed)) = (self.core_options.as_mut(), self.feed.as_ref()) else {
+            return;
+        };
+        if let Err(err) = options.flush(&feed.store, &self.emulation) {
+            self.ui.push_toast(
+                ui::ToastKind::Error,
+                format!("Saving core options failed: {err}"),
+            );
+        }
+    }
+
+    /// Tears down a runtime whose core host died; the feed stays usable.
+    fn handle_runtime_failure(&mut self, err: RuntimeError) {
+        // The core is gone, so whatever was last flushed is the save that survives.
+        self.audio_stream = None;
+        self.emulation.unload();
+        self.runtime_meta = None;
+        self.battery = None;
+        self.core_options = None;
+        let message = format!("Emulation stopped: {err}");
+        self.feed_error = Some(message.clone());
+        self.ui.push_toast(ui::ToastKind::Error, message);
+    }
+
     fn handle_gamepad_button(
         &mut self,
         button: Button,
         pressed: bool,
-        input: Option<Arc<Mutex<JoypadState>>>,
+        port: Option<usize>,
+        input: Option<Arc<Mutex<InputState>>>,
     ) {
         if button == Button::LeftTrigger2 {
             self.l2_held = pressed;
@@ -1341,20 +1698,49 @@ fn fs_main(in: VertexOut) -> @location(0) vec4<f32> {
             }
         }
 
+        if button == input::REWIND_BUTTON {
+            self.rewind_button_held = pressed && !context.capture_gameplay();
+            return;
+        }
+        if button == input::TURBO_BUTTON {
+            self.turbo_button_held = pressed && !context.capture_gameplay();
+            return;
+        }
         if context.capture_gameplay() {
             return;
         }
 
+        let Some(port) = port else {
+            return;
+        };
         if let Some(id) = map_gilrs_button(button) {
             if let Some(input) = input {
                 if let Ok(mut guard) = input.lock() {
-                    guard.set_button(id, pressed);
+                    guard.joypads[port].set_button(id, pressed);
                 }
             }
         }
     }
 
-    fn poll_gamepads(&mut self, input: Option<Arc<Mutex<JoypadState>>>) {
+    fn handle_gamepad_axis(
+        &mut self,
+        axis: Axis,
+        value: f32,
+        port: Option<usize>,
+        input: Option<&Mutex<InputState>>,
+    ) {
+        let (Some((index, id, value)), Some(port)) = (axis_to_analog(axis, value), port) else {
+            return;
+        };
+        let capture = self.ui.is_overlay_visible()
+            || self.ui.is_official_picker_open()
+            || self.ui.is_editing_text();
+        if let Some(mut guard) = input.and_then(|input| input.lock().ok()) {
+            guard.analogs[port].set_axis(index, id, if capture { 0.0 } else { value });
+        }
+    }
+
+    fn poll_gamepads(&mut self, input: Option<Arc<Mutex<InputState>>>) {
         let events = {
             let Some(gilrs) = self.gilrs.as_mut() else {
                 return;
@@ -1367,19 +1753,16 @@ fn fs_main(in: VertexOut) -> @location(0) vec4<f32> {
                     }
                     _ => None,
                 };
-                events.push((event.event, name));
+                events.push((event.id, event.event, name));
             }
             events
         };
         let mut saw_disconnect = false;
-        for (event, name) in events {
+        for (id, event, name) in events {
             match event {
                 EventType::Connected => {
                     let name = name.as_deref().unwrap_or("Unknown");
-                    self.ui.push_toast(
-                        ui::ToastKind::Success,
-                        format!("Gamepad connected: {name}"),
-                    );
+                    self.connect_gamepad(id, name);
                     self.dualsense_buttons_enabled
                         .store(false, Ordering::Relaxed);
                     continue;
@@ -1390,33 +1773,36 @@ fn fs_main(in: VertexOut) -> @location(0) vec4<f32> {
                         ui::ToastKind::Error,
                         format!("Gamepad disconnected: {name}"),
                     );
+                    self.disconnect_gamepad(id, input.as_deref());
                     saw_disconnect = true;
                     continue;
                 }
                 _ => {}
             }
+            let port = self.players.gamepad_port(id);
 
             if let EventType::ButtonChanged(button, value, _) = event {
                 let pressed = value >= GAMEPAD_AXIS_THRESHOLD;
-                self.handle_gamepad_button(button, pressed, input.clone());
+                self.handle_gamepad_button(button, pressed, port, input.clone());
                 continue;
             }
 
             if let EventType::AxisChanged(axis, value, _) = event {
+                self.handle_gamepad_axis(axis, value, port, input.as_deref());
                 if let Some(buttons) = axis_to_dpad_buttons(axis, value) {
                     for (button, pressed) in buttons {
-                        self.handle_gamepad_button(button, pressed, input.clone());
+                        self.handle_gamepad_button(button, pressed, port, input.clone());
                     }
                 }
                 continue;
             }
 
             if let EventType::ButtonPressed(button, _) = event {
-                self.handle_gamepad_button(button, true, input.clone());
+                self.handle_gamepad_button(button, true, port, input.clone());
                 continue;
             }
             if let EventType::ButtonReleased(button, _) = event {
-                self.handle_gamepad_button(button, false, input.clone());
+                self.handle_gamepad_button(button, false, port, input.clone());
                 continue;
             }
         }
@@ -1431,7 +1817,42 @@ fn fs_main(in: VertexOut) -> @location(0) vec4<f32> {
         }
     }
 
+    fn connect_gamepad(&mut self, id: GamepadId, name: &str) {
+        if self.players.gamepad_port(id).is_some() {
+            return;
+        }
+        match self.players.connect(id, name) {
+            Some(port) => self.ui.push_toast(
+                ui::ToastKind::Success,
+                format!("Gamepad connected: {name} (Player {})", port + 1),
+            ),
+            None => self.ui.push_toast(
+                ui::ToastKind::Error,
+                format!("Gamepad connected: {name}, but every player already has one"),
+            ),
+        }
+        self.ui.show_players(self.players.summary());
+    }
+
+    fn disconnect_gamepad(&mut self, id: GamepadId, input: Option<&Mutex<InputState>>) {
+        let Some(port) = self.players.disconnect(id) else {
+            return;
+        };
+        if let Some(mut guard) = input.and_then(|input| input.lock().ok()) {
+            guard.release_pad(port);
+        }
+        self.ui.show_players(self.players.summary());
+    }
+
     fn handle_keyboard(&mut self, key: KeyCode, pressed: bool) {
+        if key == input::REWIND_KEY {
+            self.rewind_key_held = pressed && !self.ui.is_editing_text();
+            return;
+        }
+        if key == input::TURBO_KEY {
+            self.turbo_key_held = pressed && !self.ui.is_editing_text();
+            return;
+        }
         if self.ui.is_editing_text() {
             return;
         }
@@ -1443,14 +1864,152 @@ fn fs_main(in: VertexOut) -> @location(0) vec4<f32> {
             return;
         }
 
-        let Some(runtime) = self.runtime.as_ref() else {
+        let Some(runtime) = self.emulation.runtime() else {
             return;
         };
         let Some(id) = map_keycode(key) else {
             return;
         };
         if let Ok(mut guard) = runtime.input_state().lock() {
-            guard.set_button(id, pressed);
+            guard.joypads[self.players.keyboard_port()].set_button(id, pressed);
+        }
+    }
+
+    /// The part of the window the game is drawn in, as `[x, y, width, height]`.
+    fn game_viewport(&self) -> Option<[f32; 4]> {
+        let runtime = self.emulation.runtime()?;
+        let aspect = runtime.av_info().geometry.display_aspect();
+        Some(letterbox(
+            self.config.width as f32,
+            self.config.height as f32,
+            aspect,
+        ))
+    }
+
+    /// Whether pointer input belongs to the UI rather than the game.
+    fn ui_wants_pointer(&self) -> bool {
+        self.ui.is_overlay_visible()
+            || self.ui.is_official_picker_open()
+            || self.gui.ctx.wants_pointer_input()
+    }
+
+    fn handle_cursor_moved(&mut self, position: PhysicalPosition<f64>) {
+        let previous = self.cursor.replace(position);
+        let Some([x, y, width, height]) = self.game_viewport() else {
+            return;
+        };
+        let Some(runtime) = self.emulation.runtime() else {
+            return;
+        };
+        let input = runtime.input_state();
+        let Ok(mut guard) = input.lock() else {
+            return;
+        };
+        if self.ui_wants_pointer() {
+            guard.pointer.leave();
+            return;
+        }
+        guard.pointer.set_position(
+            (position.x as f32 - x) / width,
+            (position.y as f32 - y) / height,
+        );
+
+        // Mouse deltas are in game pixels, so motion feels the same at any window size.
+        let Some(previous) = previous else {
+            return;
+        };
+        let geometry = runtime.av_info().geometry;
+        let scale_x = geometry.base_width as f64 / width.max(1.0) as f64;
+        let scale_y = geometry.base_height as f64 / height.max(1.0) as f64;
+        let dx = (position.x - previous.x) * scale_x + self.mouse_remainder.0;
+        let dy = (position.y - previous.y) * scale_y + self.mouse_remainder.1;
+        self.mouse_remainder = (dx.fract(), dy.fract());
+        guard.mouse.move_by(dx.trunc() as i32, dy.trunc() as i32);
+    }
+
+    fn handle_cursor_left(&mut self) {
+        self.cursor = None;
+        if let Some(runtime) = self.emulation.runtime() {
+            if let Ok(mut guard) = runtime.input_state().lock() {
+                guard.pointer.leave();
+            }
+        }
+    }
+
+    fn handle_mouse_button(&mut self, button: MouseButton, pressed: bool) {
+        let id = match button {
+            MouseButton::Left => RETRO_DEVICE_ID_MOUSE_LEFT,
+            MouseButton::Right => RETRO_DEVICE_ID_MOUSE_RIGHT,
+            MouseButton::Middle => RETRO_DEVICE_ID_MOUSE_MIDDLE,
+            _ => return,
+        };
+        // Releases always go through so no button stays held after clicking into the UI.
+        if pressed && self.ui_wants_pointer() {
+            return;
+        }
+        let Some(runtime) = self.emulation.runtime() else {
+            return;
+        };
+        if let Ok(mut guard) = runtime.input_state().lock() {
+            guard.mouse.set_button(id, pressed);
+            if id == RETRO_DEVICE_ID_MOUSE_LEFT {
+                guard.pointer.pressed = pressed;
+            }
+        }
+    }
+
+    fn handle_mouse_wheel(&mut self, delta: MouseScrollDelta) {
+        if self.ui_wants_pointer() {
+            return;
+        }
+        let clicks = match delta {
+            MouseScrollDelta::LineDelta(_, y) => y.round() as i32,
+            MouseScrollDelta::PixelDelta(position) => position.y.signum() as i32,
+        };
+        if let Some(runtime) = self.emulation.runtime() {
+            if let Ok(mut guard) = runtime.input_state().lock() {
+                guard.mouse.scroll(clicks);
+            }
+        }
+    }
+
+    /// Plugs the next device the core lists for `port` into it.
+    fn cycle_port_device(&mut self, port: u32) {
+        let Some(runtime) = self.emulation.runtime() else {
+            return;
+        };
+        let types = runtime
+            .controller_info()
+            .into_iter()
+            .nth(port as usize)
+            .map(|info| info.types)
+            .unwrap_or_default();
+        if types.is_empty() {
+            self.ui.push_toast(
+                ui::ToastKind::Error,
+                format!("The core has no devices for port {}", port + 1),
+            );
+            return;
+        }
+        let current = runtime.port_device(port);
+        let next = types
+            .iter()
+            .position(|controller| controller.device == current)
+            .map_or(0, |index| (index + 1) % types.len());
+        let controller = &types[next];
+        let device = controller.device;
+        match self
+            .emulation
+            .call(move |runtime| runtime.set_port_device(port, device))
+        {
+            Ok(()) => self.ui.push_toast(
+                ui::ToastKind::Success,
+                format!("Port {}: {}", port + 1, controller.description),
+            ),
+            Err(err) => self.ui.push_toast(
+                ui::ToastKind::Error,
+                format!("Changing the port {} device failed: {err}", port + 1),
+            ),
         }
     }
 
@@ -1493,6 +2052,26 @@ fn fs_main(in: VertexOut) -> @location(0) vec4<f32> {
             Action::SetOfficialTitle { index, title } => self.set_official_title(index, title),
             Action::ClearOfficialTitle { index } => self.clear_official_title(index),
             Action::ToggleOverlay => self.ui.toggle_overlay(),
+            Action::CyclePortDevice(port) => self.cycle_port_device(port),
+            Action::CycleKeyboardPlayer => {
+                let old_port = self.players.keyboard_port();
+                let port = self.players.cycle_keyboard();
+                if let Some(runtime) = self.emulation.runtime() {
+                    if let Ok(mut guard) = runtime.input_state().lock() {
+                        guard.joypads[old_port] = Default::default();
+                    }
+                }
+                self.ui.push_toast(
+                    ui::ToastKind::Success,
+                    format!("Keyboard: Player {}", port + 1),
+                );
+                self.ui.show_players(self.players.summary());
+            }
+            Action::SpeedUp => self.speed.faster(),
+            Action::SlowDown => self.speed.slower(),
+            Action::ResetSpeed => self.speed.reset(),
+            Action::ToggleDebugHud => self.ui.toggle_debug_hud(),
+            Action::RecordGhost => self.toggle_ghost_recording(),
         }
     }
 
@@ -1524,7 +2103,7 @@ fn fs_main(in: VertexOut) -> @location(0) vec4<f32> {
                     return;
                 }
                 fallback.title = if trimmed.is_empty() {
-                    title_from_rom_path(&fallback.rom_path)
+                    title_from_rom_path(fallback.rom.rom_name())
                 } else {
                     trimmed.to_string()
                 };
@@ -1545,29 +2124,24 @@ fn fs_main(in: VertexOut) -> @location(0) vec4<f32> {
         };
         let store = feed.store.clone();
         let trimmed = title.trim();
-        match item {
-            FeedItem::RomFallback(mut fallback) => {
-                if let Err(err) = store.set_rom_official_override(&fallback.rom_sha1, Some(trimmed)) {
-                    let message = format!("Official game update failed: {err}");
-                    self.feed_error = Some(message.clone());
-                    self.ui.push_toast(ui::ToastKind::Error, message);
-                    return;
-                }
-                fallback.official_title = if trimmed.is_empty() {
-                    None
-                } else {
-                    Some(trimmed.to_string())
-                };
-                let rom_sha1 = fallback.rom_sha1.clone();
-                feed.items[index] = FeedItem::RomFallback(fallback);
-                self.ui.invalidate_cover_art(&rom_sha1);
-                self.feed_error = None;
-                self.ui.push_toast(
-                    ui::ToastKind::Success,
-                    "Official game updated".to_string(),
-                );
+        if let FeedItem::RomFallback(mut fallback) = item {
+            if let Err(err) = store.set_rom_official_override(&fallback.rom_sha1, Some(trimmed)) {
+                let message = format!("Official game update failed: {err}");
+                self.feed_error = Some(message.clone());
+                self.ui.push_toast(ui::ToastKind::Error, message);
+                return;
             }
-            _ => {}
+            fallback.official_title = if trimmed.is_empty() {
+                None
+            } else {
+                Some(trimmed.to_string())
+            };
+            let rom_sha1 = fallback.rom_sha1.clone();
+            feed.items[index] = FeedItem::RomFallback(fallback);
+            self.ui.invalidate_cover_art(&rom_sha1);
+            self.feed_error = None;
+            self.ui
+                .push_toast(ui::ToastKind::Success, "Official game updated".to_string());
         }
     }
 
@@ -1579,25 +2153,20 @@ fn fs_main(in: VertexOut) -> @location(0) vec4<f32> {
             return;
         };
         let store = feed.store.clone();
-        match item {
-            FeedItem::RomFallback(mut fallback) => {
-                if let Err(err) = store.set_rom_official_override(&fallback.rom_sha1, None) {
-                    let message = format!("Official game update failed: {err}");
-                    self.feed_error = Some(message.clone());
-                    self.ui.push_toast(ui::ToastKind::Error, message);
-                    return;
-                }
-                fallback.official_title = None;
-                let rom_sha1 = fallback.rom_sha1.clone();
-                feed.items[index] = FeedItem::RomFallback(fallback);
-                self.ui.invalidate_cover_art(&rom_sha1);
-                self.feed_error = None;
-                self.ui.push_toast(
-                    ui::ToastKind::Success,
-                    "Official game cleared".to_string(),
-                );
+        if let FeedItem::RomFallback(mut fallback) = item {
+            if let Err(err) = store.set_rom_official_override(&fallback.rom_sha1, None) {
+                let message = format!("Official game update failed: {err}");
+                self.feed_error = Some(message.clone());
+                self.ui.push_toast(ui::ToastKind::Error, message);
+                return;
             }
-            _ => {}
+            fallback.official_title = None;
+            let rom_sha1 = fallback.rom_sha1.clone();
+            feed.items[index] = FeedItem::RomFallback(fallback);
+            self.ui.invalidate_cover_art(&rom_sha1);
+            self.feed_error = None;
+            self.ui
+                .push_toast(ui::ToastKind::Success, "Official game cleared".to_string());
         }
     }
 
@@ -1605,10 +2174,10 @@ fn fs_main(in: VertexOut) -> @location(0) vec4<f32> {
         let Some(key) = key else {
             return;
         };
-        let Some(runtime) = self.runtime.as_ref() else {
+        if self.emulation.runtime().is_none() {
             return;
-        };
-        match runtime.serialize() {
+        }
+        match self.emulation.serialize() {
             Ok(state) => {
                 self.session_autosaves.insert(key, state);
             }
@@ -1619,12 +2188,17 @@ fn fs_main(in: VertexOut) -> @location(0) vec4<f32> {
         }
     }
 
-    fn restore_session_autosave(&mut self, key: Option<SessionAutosaveKey>, load: &mut RuntimeLoad) {
+    /// Returns whether there was an autosave to restore.
+    fn restore_session_autosave(
+        &mut self,
+        key: Option<SessionAutosaveKey>,
+        load: &mut RuntimeLoad,
+    ) -> bool {
         let Some(key) = key else {
-            return;
+            return false;
         };
         let Some(state) = self.session_autosaves.get(&key) else {
-            return;
+            return false;
         };
         if let Err(err) = load.runtime.unserialize(state) {
             self.ui.push_toast(
@@ -1632,6 +2206,84 @@ fn fs_main(in: VertexOut) -> @location(0) vec4<f32> {
                 format!("Autosave restore failed: {err}"),
             );
         }
+        true
+    }
+
+    /// Starts recording a ghost from the current Byte's state, or saves the one being
+    /// recorded.
+    fn toggle_ghost_recording(&mut self) {
+        let Some(feed) = self.feed.as_ref() else {
+            return;
+        };
+        let Some(FeedItem::Byte(byte)) = feed.current().cloned() else {
+            self.ui.push_toast(
+                ui::ToastKind::Error,
+                "Ghosts can only be recorded on Bytes.".to_string(),
+            );
+            return;
+        };
+        let store = feed.store.clone();
+        let recording = self
+            .emulation
+            .call(|runtime| Ok(runtime.is_recording()))
+            .unwrap_or(false);
+        if !recording {
+            let result = store
+                .load_state(&byte.byte_id)
+                .map_err(anyhow::Error::from)
+                .and_then(|state| {
+                    self.emulation.call(move |runtime| {
+                        runtime.stop_movie();
+                        runtime.check_serialize_size(byte.serialize_size)?;
+                        runtime.unserialize(&state)?;
+                        let start = MovieStart::Byte(byte.byte_id.clone());
+                        let recorder = MovieRecorder::new(runtime, &byte.rom_sha1, start);
+                        runtime.start_recording(recorder);
+                        Ok(())
+                    })?;
+                    Ok(())
+                });
+            match result {
+                Ok(()) => self.ui.push_toast(
+                    ui::ToastKind::Success,
+                    "Recording a ghost from the Byte; press G to save it.".to_string(),
+                ),
+                Err(err) => self.ui.push_toast(
+                    ui::ToastKind::Error,
+                    format!("Ghost recording failed: {err}"),
+                ),
+            }
+            return;
+        }
+
+        let result = self
+            .emulation
+            .call(|runtime| Ok(runtime.stop_recording()))
+            .map_err(anyhow::Error::from)
+            .and_then(|movie| {
+                let movie = movie.context("no ghost was being recorded")?;
+                let frames = movie.frames.len();
+                let metadata = store.store_ghost(&byte.byte_id, &movie.encode()?)?;
+                Ok((metadata, frames))
+            });
+        match result {
+            Ok((metadata, frames)) => {
+                if let Some(feed) = self.feed.as_mut() {
+                    if let Some(item) = feed.items.iter_mut().find(|item| {
+                        matches!(item, FeedItem::Byte(byte) if byte.byte_id == metadata.byte_id)
+                    }) {
+                        *item = FeedItem::Byte(Box::new(metadata));
+                    }
+                }
+                self.ui.push_toast(
+                    ui::ToastKind::Success,
+                    format!("Ghost saved ({frames} frames)"),
+                );
+            }
+            Err(err) => self
+                .ui
+                .push_toast(ui::ToastKind::Error, format!("Saving ghost failed: {err}")),
+        }
     }
 
     fn navigate_feed(&mut self, delta: i32) {
@@ -1657,36 +2309,7 @@ fn fs_main(in: VertexOut) -> @location(0) vec4<f32> {
         }
 
         self.store_session_autosave(leaving_key);
-
-        // Libretro cores (and our callback wiring) are effectively single-instance.
-        // Drop the current runtime BEFORE constructing the next one to avoid
-        // shared-global-state cores (e.g. bsnes) corrupting each other during swaps.
-        self.audio_stream = None;
-        self.runtime = None;
-        self.runtime_meta = None;
-
-        let result = {
-            let Some(feed) = self.feed.as_ref() else {
-                return;
-            };
-            feed.build_runtime_for_current()
-        };
-
-        match result {
-            Ok(mut load) => {
-                let entering_key = self
-                    .feed
-                    .as_ref()
-                    .and_then(|feed| feed.current().map(FeedItem::session_autosave_key));
-                self.restore_session_autosave(entering_key, &mut load);
-                self.apply_runtime_load(load);
-                self.feed_error = None;
-                if let Some(feed) = self.feed.as_ref() {
-                    feed.prefetch_neighbors();
-                }
-            }
-            Err(err) => self.feed_error = Some(format!("Load feed item failed: {err}")),
-        }
+        self.load_current_feed_item();
     }
 
     fn select_feed_index(&mut self, index: usize) {
@@ -1706,12 +2329,16 @@ fn fs_main(in: VertexOut) -> @location(0) vec4<f32> {
         }
 
         self.store_session_autosave(leaving_key);
+        self.load_current_feed_item();
+    }
 
-        // See note in `navigate_feed`.
-        self.audio_stream = None;
-        self.runtime = None;
-        self.runtime_meta = None;
+    fn load_current_feed_item(&mut self) {
+        self.flush_battery_save();
+        self.flush_core_options();
 
+        // Each core instance gets its own callback slot (and its own copy of the core
+        // library when the same core is already loaded), so the next runtime is built
+        // before the current one is dropped.
         let result = {
             let Some(feed) = self.feed.as_ref() else {
                 return;
@@ -1725,27 +2352,47 @@ fn fs_main(in: VertexOut) -> @location(0) vec4<f32> {
                     .feed
                     .as_ref()
                     .and_then(|feed| feed.current().map(FeedItem::session_autosave_key));
-                self.restore_session_autosave(entering_key, &mut load);
+                if !self.restore_session_autosave(entering_key, &mut load) {
+                    let played = self.feed.as_ref().map(|feed| feed.play_ghost(&mut load));
+                    if let Some(Err(err)) = played {
+                        self.ui
+                            .push_toast(ui::ToastKind::Error, format!("Ghost not played: {err}"));
+                    }
+                }
                 self.apply_runtime_load(load);
                 self.feed_error = None;
                 if let Some(feed) = self.feed.as_ref() {
                     feed.prefetch_neighbors();
                 }
             }
-            Err(err) => self.feed_error = Some(format!("Load feed item failed: {err}")),
+            Err(err) => {
+                self.audio_stream = None;
+                self.emulation.unload();
+                self.runtime_meta = None;
+                self.battery = None;
+                self.core_options = None;
+                self.feed_error = Some(format!(
+                    "Load feed item failed: {err}{}",
+                    core_log_tail(err.as_ref())
+                ));
+            }
         }
     }
 
     fn apply_runtime_load(&mut self, load: RuntimeLoad) {
-        self.audio_stream = build_audio_stream(load.runtime.audio_buffer()).ok();
+        self.emulation.load(load.runtime);
+        self.audio_stream = self
+            .emulation
+            .runtime()
+            .and_then(|view| build_audio_stream(view).ok());
         self.runtime_meta = Some(load.meta);
-        self.runtime = Some(load.runtime);
-        self.accumulator = 0.0;
+        self.battery = load.battery;
+        self.core_options = load.core_options;
         self.ui.trigger_transition();
     }
 
     fn create_byte(&mut self) {
-        let runtime = match self.runtime.as_ref() {
+        let runtime = match self.emulation.runtime() {
             Some(runtime) => runtime,
             None => {
                 self.feed_error = Some("No active runtime to capture".to_string());
@@ -1756,18 +2403,29 @@ fn fs_main(in: VertexOut) -> @location(0) vec4<f32> {
                 return;
             }
         };
+        let portable = self
+            .emulation
+            .call(|runtime| Ok(runtime.is_state_portable()))
+            .unwrap_or(false);
+        if !portable {
+            let message = format!(
+                "{} savestates only load in this session; Bytes cannot be shared",
+                runtime.system_info().library_name
+            );
+            self.feed_error = Some(message.clone());
+            self.ui.push_toast(ui::ToastKind::Error, message);
+            return;
+        }
         let meta = match self.runtime_meta.as_ref() {
             Some(meta) => meta.clone(),
             None => {
                 self.feed_error = Some("Missing runtime metadata".to_string());
-                self.ui.push_toast(
-                    ui::ToastKind::Error,
-                    "Missing runtime metadata".to_string(),
-                );
+                self.ui
+                    .push_toast(ui::ToastKind::Error, "Missing runtime metadata".to_string());
                 return;
             }
         };
-        let frame = match runtime.latest_frame() {
+        let frame = match self.emulation.latest_frame() {
             Some(frame) => frame,
             None => {
                 self.feed_error = Some("No frame available for thumbnail".to_string());
@@ -1778,25 +2436,29 @@ fn fs_main(in: VertexOut) -> @location(0) vec4<f32> {
                 return;
             }
         };
-        let state = match runtime.serialize() {
-            Ok(state) => state,
+        // The size is asked for alongside the state, so both come from the same frame.
+        let sized = self
+            .emulation
+            .call(|runtime| Ok((runtime.serialize_size()?, runtime.serialize()?)));
+        let (serialize_size, state) = match sized {
+            Ok(sized) => sized,
             Err(err) => {
                 self.feed_error = Some(format!("Serialize failed: {err}"));
-                self.ui.push_toast(
-                    ui::ToastKind::Error,
-                    format!("Serialize failed: {err}"),
-                );
+                self.ui
+                    .push_toast(ui::ToastKind::Error, format!("Serialize failed: {err}"));
                 return;
             }
         };
+        let core_options = self
+            .emulation
+            .call(|runtime| Ok(runtime.core_option_values()))
+            .unwrap_or_default();
         let thumbnail = match encode_thumbnail(&frame) {
             Ok(png) => png,
             Err(err) => {
                 self.feed_error = Some(format!("Thumbnail failed: {err}"));
-                self.ui.push_toast(
-                    ui::ToastKind::Error,
-                    format!("Thumbnail failed: {err}"),
-                );
+                self.ui
+                    .push_toast(ui::ToastKind::Error, format!("Thumbnail failed: {err}"));
                 return;
             }
         };
@@ -1807,7 +2469,7 @@ fn fs_main(in: VertexOut) -> @location(0) vec4<f32> {
             .unwrap_or_else(|_| "unknown".to_string());
         let metadata = ByteMetadata {
             byte_id: byte_id.clone(),
-            system: meta.system.clone(),
+            system: meta.system,
             core_id: meta.core_id.clone(),
             core_semver: meta.core_version.clone(),
             rom_sha1: meta.rom_sha1.clone(),
@@ -1819,6 +2481,18 @@ fn fs_main(in: VertexOut) -> @location(0) vec4<f32> {
             created_at,
             thumbnail_path: "thumbnail.png".to_string(),
             state_path: "state.zst".to_string(),
+            ghost_path: None,
+            format_version: BYTE_FORMAT_VERSION,
+            // Filled in by `save_byte`.
+            state_sha256: String::new(),
+            thumbnail_sha256: String::new(),
+            state_size: 0,
+            serialize_size: serialize_size as u64,
+            core_options: core_options.into_iter().collect(),
+            resolution: Some(Resolution {
+                width: frame.width,
+                height: frame.height,
+            }),
         };
 
         let store = if let Some(feed) = self.feed.as_ref() {
@@ -1827,12 +2501,15 @@ fn fs_main(in: VertexOut) -> @location(0) vec4<f32> {
             LocalByteStore::new(&self.data_root)
         };
 
-        if let Err(err) = store.save_byte(&metadata, &state, &thumbnail) {
-            self.feed_error = Some(format!("Save Byte failed: {err}"));
-            self.ui
-                .push_toast(ui::ToastKind::Error, format!("Save Byte failed: {err}"));
-            return;
-        }
+        let metadata = match store.save_byte(&metadata, &state, &thumbnail) {
+            Ok(metadata) => metadata,
+            Err(err) => {
+                self.feed_error = Some(format!("Save Byte failed: {err}"));
+                self.ui
+                    .push_toast(ui::ToastKind::Error, format!("Save Byte failed: {err}"));
+                return;
+            }
+        };
 
         if let Some(feed) = self.feed.as_mut() {
             feed.add_byte(metadata.clone());
@@ -1849,7 +2526,7 @@ fn fs_main(in: VertexOut) -> @location(0) vec4<f32> {
         if frame.width == 0 || frame.height == 0 {
             return;
         }
-        let rgba = convert_frame_to_rgba(frame);
+        let rgba = frame.to_rgba();
         if frame.width != self.video_texture.width || frame.height != self.video_texture.height {
             self.video_texture = Self::create_video_texture(
                 &self.device,
@@ -1964,9 +2641,10 @@ fn fs_main(in: VertexOut) -> @location(0) vec4<f32> {
             &self.gui.ctx,
             ui::UiContext {
                 feed: self.feed.as_ref(),
-                runtime: self.runtime.as_ref(),
+                runtime: self.emulation.runtime(),
                 frame_stats: &self.frame_stats,
                 feed_error: self.feed_error.as_deref(),
+                speed: (!self.speed.is_normal()).then(|| self.speed.label()),
             },
         );
 
@@ -2041,11 +2719,18 @@ fn fs_main(in: VertexOut) -> @location(0) vec4<f32> {
                 timestamp_writes: None,
                 occlusion_query_set: None,
             });
+            let (width, height) = (self.config.width as f32, self.config.height as f32);
+            if let Some(runtime) = self.emulation.runtime() {
+                let aspect = runtime.av_info().geometry.display_aspect();
+                let [x, y, w, h] = letterbox(width, height, aspect);
+                render_pass.set_viewport(x, y, w, h, 0.0, 1.0);
+            }
             render_pass.set_pipeline(&self.render_pipeline);
             render_pass.set_bind_group(0, &self.video_texture.bind_group, &[]);
             render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
             render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
             render_pass.draw_indexed(0..self.num_indices, 0, 0..1);
+            render_pass.set_viewport(0.0, 0.0, width, height, 0.0, 1.0);
 
             self.gui.renderer.render(
                 &mut render_pass,
@@ -2093,87 +2778,23 @@ fn map_keycode(key: KeyCode) -> Option<u32> {
     }
 }
 
-fn convert_frame_to_rgba(frame: &playbyte_libretro::VideoFrame) -> Vec<u8> {
-    let width = frame.width as usize;
-    let height = frame.height as usize;
-    if width == 0 || height == 0 {
-        return Vec::new();
+/// The largest `[x, y, width, height]` of the given aspect ratio centred in the surface.
+fn letterbox(width: f32, height: f32, aspect: f32) -> [f32; 4] {
+    if width <= 0.0 || height <= 0.0 || aspect <= 0.0 {
+        return [0.0, 0.0, width.max(1.0), height.max(1.0)];
     }
-    let mut out = vec![0u8; width * height * 4];
-
-    let bytes_per_pixel = match frame.pixel_format {
-        playbyte_libretro::RetroPixelFormat::Xrgb8888 => 4,
-        playbyte_libretro::RetroPixelFormat::Rgb565 | playbyte_libretro::RetroPixelFormat::_0rgb1555 => 2,
+    let (w, h) = if width / height > aspect {
+        (height * aspect, height)
+    } else {
+        (width, width / aspect)
     };
-    let min_pitch = width.saturating_mul(bytes_per_pixel);
-    if frame.pitch < min_pitch {
-        return out;
-    }
-    if frame.data.len() < frame.pitch.saturating_mul(height) {
-        return out;
-    }
-
-    match frame.pixel_format {
-        playbyte_libretro::RetroPixelFormat::Xrgb8888 => {
-            for y in 0..height {
-                let row_start = y * frame.pitch;
-                let row = &frame.data[row_start..row_start + min_pitch];
-                for x in 0..width {
-                    let src = x * 4;
-                    let dst = (y * width + x) * 4;
-                    let b = row[src];
-                    let g = row[src + 1];
-                    let r = row[src + 2];
-                    out[dst] = r;
-                    out[dst + 1] = g;
-                    out[dst + 2] = b;
-                    out[dst + 3] = 0xff;
-                }
-            }
-        }
-        playbyte_libretro::RetroPixelFormat::Rgb565 => {
-            for y in 0..height {
-                let row_start = y * frame.pitch;
-                let row = &frame.data[row_start..row_start + min_pitch];
-                for x in 0..width {
-                    let src = x * 2;
-                    let value = u16::from_le_bytes([row[src], row[src + 1]]);
-                    let r = ((value >> 11) & 0x1f) as u8;
-                    let g = ((value >> 5) & 0x3f) as u8;
-                    let b = (value & 0x1f) as u8;
-                    let dst = (y * width + x) * 4;
-                    out[dst] = (r << 3) | (r >> 2);
-                    out[dst + 1] = (g << 2) | (g >> 4);
-                    out[dst + 2] = (b << 3) | (b >> 2);
-                    out[dst + 3] = 0xff;
-                }
-            }
-        }
-        playbyte_libretro::RetroPixelFormat::_0rgb1555 => {
-            for y in 0..height {
-                let row_start = y * frame.pitch;
-                let row = &frame.data[row_start..row_start + min_pitch];
-                for x in 0..width {
-                    let src = x * 2;
-                    let value = u16::from_le_bytes([row[src], row[src + 1]]);
-                    let r = ((value >> 10) & 0x1f) as u8;
-                    let g = ((value >> 5) & 0x1f) as u8;
-                    let b = (value & 0x1f) as u8;
-                    let dst = (y * width + x) * 4;
-                    out[dst] = (r << 3) | (r >> 2);
-                    out[dst + 1] = (g << 3) | (g >> 2);
-                    out[dst + 2] = (b << 3) | (b >> 2);
-                    out[dst + 3] = 0xff;
-                }
-            }
-        }
-    }
-
-    out
+    let x = ((width - w) / 2.0).floor();
+    let y = ((height - h) / 2.0).floor();
+    [x, y, w, h]
 }
 
 fn encode_thumbnail(frame: &playbyte_libretro::VideoFrame) -> Result<Vec<u8>> {
-    let rgba = convert_frame_to_rgba(frame);
+    let rgba = frame.to_rgba();
     let mut png = Vec::new();
     let encoder = PngEncoder::new(&mut png);
     encoder.write_image(&rgba, frame.width, frame.height, ColorType::Rgba8.into())?;
@@ -2182,21 +2803,32 @@ fn encode_thumbnail(frame: &playbyte_libretro::VideoFrame) -> Result<Vec<u8>> {
 
 fn build_runtime_meta_from_runtime(
     runtime: &EmulatorRuntime,
-    rom_path: &PathBuf,
+    rom: &RomSource,
 ) -> Result<RuntimeMetadata> {
     let info = runtime.system_info();
-    let rom_sha1 = hash_rom(rom_path)?;
+    let rom_sha1 = hash_rom(rom)?;
     Ok(RuntimeMetadata {
         core_id: info.library_name.clone(),
         core_version: info.library_version.clone(),
         rom_sha1,
-        _rom_path: rom_path.clone(),
-        system: system_from_rom_path(rom_path),
+        _rom: rom.clone(),
+        system: system_from_rom_path(rom.rom_name()),
     })
 }
 
-fn hash_rom_without_snes_header(path: &PathBuf) -> Result<Option<String>> {
-    let data = fs::read(path)?;
+/// Plain files are handed to the core by path; archived ROMs are extracted into memory.
+fn game_content(rom: &RomSource) -> Result<GameContent> {
+    if !rom.is_archived() {
+        return Ok(GameContent::Path(rom.path.clone()));
+    }
+    Ok(GameContent::Memory {
+        name: rom.rom_name().to_string_lossy().into_owned(),
+        data: rom.read()?,
+    })
+}
+
+fn hash_rom_without_snes_header(rom: &RomSource) -> Result<Option<String>> {
+    let data = rom.read()?;
     if data.len() <= 512 || data.len() % 1024 != 512 {
         return Ok(None);
     }
@@ -2205,14 +2837,14 @@ fn hash_rom_without_snes_header(path: &PathBuf) -> Result<Option<String>> {
     Ok(Some(format!("{:x}", hasher.finalize())))
 }
 
-fn hash_rom(path: &PathBuf) -> Result<String> {
-    let data = fs::read(path)?;
+fn hash_rom(rom: &RomSource) -> Result<String> {
+    let data = rom.read()?;
     let mut hasher = Sha1::new();
     hasher.update(data);
     Ok(format!("{:x}", hasher.finalize()))
 }
 
-fn system_from_rom_path(path: &PathBuf) -> System {
+fn system_from_rom_path(path: &Path) -> System {
     match path
         .extension()
         .and_then(|ext| ext.to_str())
@@ -2226,36 +2858,53 @@ fn system_from_rom_path(path: &PathBuf) -> System {
     }
 }
 
-fn build_audio_stream(audio: Arc<AudioRingBuffer>) -> Result<cpal::Stream> {
+/// Opens the default output device and plays the runtime's audio on it, resampled from the
+/// core's rate to the device's.
+fn build_audio_stream(runtime: &RuntimeView) -> Result<cpal::Stream> {
     let host = cpal::default_host();
     let device = host
         .default_output_device()
         .ok_or_else(|| anyhow::anyhow!("No output audio device available"))?;
     let config = device.default_output_config()?;
     let sample_format = config.sample_format();
+    let resampler = AudioResampler::new(
+        runtime.av_info().timing.sample_rate,
+        config.sample_rate().0 as f64,
+        config.channels() as usize,
+    );
     let stream_config = config.into();
+    let audio = runtime.audio_buffer();
 
     let err_fn = |err| eprintln!("audio stream error: {err}");
 
     let stream = match sample_format {
-        cpal::SampleFormat::F32 => device.build_output_stream(
-            &stream_config,
-            move |data: &mut [f32], _| write_audio(data, &audio),
-            err_fn,
-            None,
-        )?,
-        cpal::SampleFormat::I16 => device.build_output_stream(
-            &stream_config,
-            move |data: &mut [i16], _| write_audio(data, &audio),
-            err_fn,
-            None,
-        )?,
-        cpal::SampleFormat::U16 => device.build_output_stream(
-            &stream_config,
-            move |data: &mut [u16], _| write_audio(data, &audio),
-            err_fn,
-            None,
-        )?,
+        cpal::SampleFormat::F32 => {
+            let mut output = AudioOutput::new(audio, resampler);
+            device.build_output_stream(
+                &stream_config,
+                move |data: &mut [f32], _| output.write(data),
+                err_fn,
+                None,
+            )?
+        }
+        cpal::SampleFormat::I16 => {
+            let mut output = AudioOutput::new(audio, resampler);
+            device.build_output_stream(
+                &stream_config,
+                move |data: &mut [i16], _| output.write(data),
+                err_fn,
+                None,
+            )?
+        }
+        cpal::SampleFormat::U16 => {
+            let mut output = AudioOutput::new(audio, resampler);
+            device.build_output_stream(
+                &stream_config,
+                move |data: &mut [u16], _| output.write(data),
+                err_fn,
+                None,
+            )?
+        }
         _ => return Err(anyhow::anyhow!("Unsupported audio sample format")),
     };
 
@@ -2263,19 +2912,40 @@ fn build_audio_stream(audio: Arc<AudioRingBuffer>) -> Result<cpal::Stream> {
     Ok(stream)
 }
 
-fn write_audio<T>(output: &mut [T], audio: &AudioRingBuffer)
-where
-    T: cpal::Sample + cpal::FromSample<f32>,
-{
-    let mut temp = vec![0i16; output.len()];
-    audio.pop_samples(&mut temp);
-    for (dst, sample) in output.iter_mut().zip(temp.into_iter()) {
-        let sample_f32 = sample as f32 / i16::MAX as f32;
-        *dst = T::from_sample(sample_f32);
+/// The state the audio callback keeps between calls.
+struct AudioOutput {
+    audio: Arc<AudioRingBuffer>,
+    resampler: AudioResampler,
+    scratch: Vec<f32>,
+}
+
+impl AudioOutput {
+    fn new(audio: Arc<AudioRingBuffer>, resampler: AudioResampler) -> Self {
+        // Sized up front: the callback runs on a realtime thread and must not allocate.
+        let scratch = vec![0.0; MAX_FILL_FRAMES * resampler.channels()];
+        Self {
+            audio,
+            resampler,
+            scratch,
+        }
+    }
+
+    fn write<T>(&mut self, output: &mut [T])
+    where
+        T: cpal::Sample + cpal::FromSample<f32>,
+    {
+        for piece in output.chunks_mut(self.scratch.len()) {
+            let scratch = &mut self.scratch[..piece.len()];
+            self.resampler.fill(&self.audio, scratch);
+            for (dst, &sample) in piece.iter_mut().zip(scratch.iter()) {
+                *dst = T::from_sample(sample);
+            }
+        }
     }
 }
 
 fn main() -> Result<()> {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("warn")).init();
     let app_config = AppConfig::from_env();
     let event_loop = EventLoopBuilder::<UserEvent>::with_user_event().build()?;
     let proxy = event_loop.create_proxy();
@@ -2305,12 +2975,17 @@ fn main() -> Result<()> {
                 window.request_redraw();
             }
             Event::UserEvent(UserEvent::GamepadButton { button, pressed }) => {
-                let input = state.runtime.as_ref().map(|runtime| runtime.input_state());
-                state.handle_gamepad_button(button, pressed, input);
+                // The DualSense HID listener only ever drives player 1.
+                let input = state.emulation.runtime().map(RuntimeView::input_state);
+                state.handle_gamepad_button(button, pressed, Some(0), input);
                 window.request_redraw();
             }
             Event::WindowEvent { event, window_id } if window_id == window.id() => match event {
-                WindowEvent::CloseRequested => elwt.exit(),
+                WindowEvent::CloseRequested => {
+                    state.flush_battery_save();
+                    state.flush_core_options();
+                    elwt.exit();
+                }
                 WindowEvent::Resized(size) => state.resize(size),
                 WindowEvent::RedrawRequested => match state.render(&window) {
                     Ok(()) => {}
@@ -2324,6 +2999,14 @@ fn main() -> Result<()> {
                         state.handle_keyboard(code, pressed);
                     }
                 }
+                WindowEvent::CursorMoved { position, .. } => state.handle_cursor_moved(position),
+                WindowEvent::CursorLeft { .. } => state.handle_cursor_left(),
+                WindowEvent::MouseInput {
+                    state: button_state,
+                    button,
+                    ..
+                } => state.handle_mouse_button(button, button_state == ElementState::Pressed),
+                WindowEvent::MouseWheel { delta, .. } => state.handle_mouse_wheel(delta),
                 _ => {}
             },
             Event::AboutToWait => {
@@ -0,0 +1,85 @@
+use gilrs::GamepadId;
+use playbyte_emulation::MAX_PORTS;
+
+/// Which controller plays as which player.
+///
+/// Gamepads take the lowest free port when they connect and keep it until they disconnect,
+/// so plugging a second pad in never moves the first one. The keyboard is not a port of its
+/// own; it plays as player 1 unless moved, alongside whichever gamepad is there.
+pub struct PlayerAssignments {
+    keyboard_port: usize,
+    gamepads: Vec<AssignedGamepad>,
+}
+
+struct AssignedGamepad {
+    id: GamepadId,
+    name: String,
+    port: usize,
+}
+
+impl PlayerAssignments {
+    pub fn new() -> Self {
+        Self {
+            keyboard_port: 0,
+            gamepads: Vec::new(),
+        }
+    }
+
+    pub fn keyboard_port(&self) -> usize {
+        self.keyboard_port
+    }
+
+    /// Moves the keyboard to the next player; returns the new port.
+    pub fn cycle_keyboard(&mut self) -> usize {
+        self.keyboard_port = (self.keyboard_port + 1) % MAX_PORTS;
+        self.keyboard_port
+    }
+
+    pub fn gamepad_port(&self, id: GamepadId) -> Option<usize> {
+        self.gamepads
+            .iter()
+            .find(|gamepad| gamepad.id == id)
+            .map(|gamepad| gamepad.port)
+    }
+
+    /// Gives a newly connected gamepad the lowest free port. Returns `None` when every port
+    /// is taken or the gamepad already has one.
+    pub fn connect(&mut self, id: GamepadId, name: &str) -> Option<usize> {
+        if self.gamepad_port(id).is_some() {
+            return None;
+        }
+        let port =
+            (0..MAX_PORTS).find(|&port| !self.gamepads.iter().any(|pad| pad.port == port))?;
+        self.gamepads.push(AssignedGamepad {
+            id,
+            name: name.to_string(),
+            port,
+        });
+        Some(port)
+    }
+
+    /// Frees a gamepad's port; returns the port it had.
+    pub fn disconnect(&mut self, id: GamepadId) -> Option<usize> {
+        let index = self.gamepads.iter().position(|gamepad| gamepad.id == id)?;
+        Some(self.gamepads.remove(index).port)
+    }
+
+    /// Player numbers, counting from 1, with the controllers playing as them, e.g.
+    /// `(2, "Keyboard, Xbox Controller")`. Players without a controller are left out.
+    pub fn summary(&self) -> Vec<(usize, String)> {
+        (0..MAX_PORTS)
+            .filter_map(|port| {
+                let mut names: Vec<&str> = self
+                    .gamepads
+                    .iter()
+                    .filter(|gamepad| gamepad.port == port)
+                    .map(|gamepad| gamepad.name.as_str())
+                    .collect();
+                if self.keyboard_port == port {
+                    names.insert(0, "Keyboard");
+                }
+                (!names.is_empty()).then(|| (port + 1, names.join(", ")))
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,54 @@
+/// Speeds the game can run at, as multiples of the core's frame rate.
+const MULTIPLIERS: [f64; 6] = [0.25, 0.5, 1.0, 2.0, 4.0, 8.0];
+const NORMAL: usize = 2;
+
+/// How fast the emulation loop runs the game.
+///
+/// Speed steps through `MULTIPLIERS`; turbo is separate and lasts while its binding is held,
+/// running as many frames as the update budget allows.
+pub struct SpeedControl {
+    index: usize,
+    turbo: bool,
+}
+
+impl SpeedControl {
+    pub fn new() -> Self {
+        Self {
+            index: NORMAL,
+            turbo: false,
+        }
+    }
+
+    /// The speed multiplier, or `None` while turbo is on.
+    pub fn multiplier(&self) -> Option<f64> {
+        (!self.turbo).then_some(MULTIPLIERS[self.index])
+    }
+
+    pub fn is_normal(&self) -> bool {
+        !self.turbo && self.index == NORMAL
+    }
+
+    pub fn faster(&mut self) {
+        self.index = (self.index + 1).min(MULTIPLIERS.len() - 1);
+    }
+
+    pub fn slower(&mut self) {
+        self.index = self.index.saturating_sub(1);
+    }
+
+    pub fn reset(&mut self) {
+        self.index = NORMAL;
+    }
+
+    pub fn set_turbo(&mut self, turbo: bool) {
+        self.turbo = turbo;
+    }
+
+    /// e.g. "0.25x", "4x" or "Turbo".
+    pub fn label(&self) -> String {
+        match self.multiplier() {
+            Some(multiplier) => format!("{multiplier}x"),
+            None => "Turbo".to_string(),
+        }
+    }
+}
@@ -5,7 +5,7 @@ mod thumbnails;
 use crate::{FeedController, FrameStats};
 use components::{badge, hero_preview, hint_strip, library_card, primary_button, toast};
 use cover_art::CoverArtCache;
-use playbyte_emulation::EmulatorRuntime;
+use playbyte_emulation::{AudioStats, RuntimeView};
 use playbyte_types::System;
 use std::time::{Duration, Instant};
 
@@ -16,9 +16,11 @@ const OFFICIAL_PICKER_LIMIT: usize = 24;
 
 pub struct UiContext<'a> {
     pub feed: Option<&'a FeedController>,
-    pub runtime: Option<&'a EmulatorRuntime>,
+    pub runtime: Option<&'a RuntimeView>,
     pub frame_stats: &'a FrameStats,
     pub feed_error: Option<&'a str>,
+    /// Emulation speed while it is not normal, e.g. "2x" or "Turbo".
+    pub speed: Option<String>,
 }
 
 pub struct UiOutput {
@@ -43,6 +45,9 @@ pub struct UiState {
     rename_draft: String,
     last_centered_index: Option<usize>,
     official_picker: Option<OfficialPickerState>,
+    players: Vec<(usize, String)>,
+    players_changed_at: Option<Instant>,
+    debug_hud: bool,
 }
 
 impl UiState {
@@ -61,6 +66,9 @@ impl UiState {
             rename_draft: String::new(),
             last_centered_index: None,
             official_picker: None,
+            players: Vec::new(),
+            players_changed_at: None,
+            debug_hud: false,
         }
     }
 
@@ -72,7 +80,7 @@ impl UiState {
             self.render_top_bar(ctx, &data, &mut actions);
             self.render_library(ctx, &data, &mut actions);
         } else {
-            self.render_minimal_overlay(ctx);
+            self.render_minimal_overlay(ctx, data.speed.as_deref());
         }
 
         if self.should_show_hint(now) {
@@ -88,6 +96,10 @@ impl UiState {
         }
 
         self.render_official_picker(ctx, &mut actions);
+        self.render_players(ctx, now);
+        if self.debug_hud {
+            self.render_debug_hud(ctx, &data);
+        }
         self.render_toasts(ctx, now);
         self.render_transition(ctx, now);
 
@@ -102,6 +114,16 @@ impl UiState {
         });
     }
 
+    /// Updates which controller plays as which player, and shows it for a few seconds.
+    pub fn show_players(&mut self, players: Vec<(usize, String)>) {
+        self.players = players;
+        self.players_changed_at = Some(Instant::now());
+    }
+
+    pub fn toggle_debug_hud(&mut self) {
+        self.debug_hud = !self.debug_hud;
+    }
+
     pub fn trigger_transition(&mut self) {
         self.transition_start = Some(Instant::now());
     }
@@ -185,16 +207,14 @@ impl UiState {
         fallback: &crate::RomFallback,
         store: &playbyte_feed::LocalByteStore,
     ) {
-        let titles = store
-            .list_romdb_titles(fallback.system.clone())
-            .unwrap_or_default();
+        let titles = store.list_romdb_titles(fallback.system).unwrap_or_default();
         let normalized_titles = titles
             .iter()
             .map(|title| normalize_picker_query(title))
             .collect();
         self.official_picker = Some(OfficialPickerState {
             index,
-            system: fallback.system.clone(),
+            system: fallback.system,
             titles,
             normalized_titles,
             query: String::new(),
@@ -225,8 +245,7 @@ impl UiState {
                     ui.add_space(12.0);
                     if let Some(fps) = data.frame_stats.avg_fps() {
                         ui.label(
-                            egui::RichText::new(format!("{fps:.0} fps"))
-                                .color(self.theme.text_dim),
+                            egui::RichText::new(format!("{fps:.0} fps")).color(self.theme.text_dim),
                         );
                     }
                     if let Some(runtime) = &data.runtime {
@@ -234,6 +253,10 @@ impl UiState {
                             egui::RichText::new(format!("emu {:.1} Hz", runtime.fps()))
                                 .color(self.theme.text_dim),
                         );
+                        ui.label(
+                            egui::RichText::new(audio_health(runtime.audio_stats()))
+                                .color(self.theme.text_dim),
+                        );
                     }
                     if let Some(feed) = data.feed {
                         ui.label(
@@ -243,11 +266,11 @@ impl UiState {
                     }
 
                     ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
-                        if data.runtime.is_some() {
-                            if primary_button(ui, "Save to Play Later", &self.theme).clicked() {
-                                actions.push(Action::CreateByte);
-                                self.record_interaction();
-                            }
+                        if data.runtime.is_some()
+                            && primary_button(ui, "Save to Play Later", &self.theme).clicked()
+                        {
+                            actions.push(Action::CreateByte);
+                            self.record_interaction();
                         }
                     });
                 });
@@ -336,10 +359,20 @@ impl UiState {
                                         System::Gbc => "GBC",
                                         System::Gba => "GBA",
                                     };
-                                    badge(ui, system_label, self.theme.accent_soft, self.theme.text);
+                                    badge(
+                                        ui,
+                                        system_label,
+                                        self.theme.accent_soft,
+                                        self.theme.text,
+                                    );
                                     if let crate::FeedItem::Byte(byte) = current {
                                         for tag in byte.tags.iter().take(3) {
-                                            badge(ui, tag, self.theme.panel_alt, self.theme.text_dim);
+                                            badge(
+                                                ui,
+                                                tag,
+                                                self.theme.panel_alt,
+                                                self.theme.text_dim,
+                                            );
                                         }
                                     }
                                 });
@@ -375,7 +408,8 @@ impl UiState {
                                     }
                                     crate::FeedItem::RomFallback(fallback) => {
                                         let filename = fallback
-                                            .rom_path
+                                            .rom
+                                            .rom_name()
                                             .file_name()
                                             .and_then(|name| name.to_str())
                                             .unwrap_or("ROM");
@@ -457,14 +491,12 @@ impl UiState {
                                             actions.push(Action::SelectIndex(idx));
                                             self.record_interaction();
                                         }
-                                        if selected {
-                                            if self.last_centered_index != Some(idx) {
-                                                ui.scroll_to_rect(
-                                                    response.rect,
-                                                    Some(egui::Align::Center),
-                                                );
-                                                self.last_centered_index = Some(idx);
-                                            }
+                                        if selected && self.last_centered_index != Some(idx) {
+                                            ui.scroll_to_rect(
+                                                response.rect,
+                                                Some(egui::Align::Center),
+                                            );
+                                            self.last_centered_index = Some(idx);
                                         }
                                     }
                                 });
@@ -552,7 +584,7 @@ impl UiState {
         }
     }
 
-    fn render_minimal_overlay(&self, ctx: &egui::Context) {
+    fn render_minimal_overlay(&self, ctx: &egui::Context, speed: Option<&str>) {
         egui::Area::new(egui::Id::new("minimal_overlay"))
             .anchor(egui::Align2::LEFT_TOP, [20.0, 20.0])
             .show(ctx, |ui| {
@@ -566,12 +598,90 @@ impl UiState {
                         .size(13.0)
                         .color(self.theme.text_dim),
                 );
+                if let Some(speed) = speed {
+                    ui.add_space(6.0);
+                    badge(ui, speed, self.theme.accent, self.theme.text_on_accent);
+                }
+            });
+    }
+
+    /// Player assignments, while the library is open and briefly after they change.
+    fn render_players(&self, ctx: &egui::Context, now: Instant) {
+        let recently_changed = self
+            .players_changed_at
+            .is_some_and(|at| now.saturating_duration_since(at) < Duration::from_secs(4));
+        if self.players.is_empty() || !(self.overlay_visible || recently_changed) {
+            return;
+        }
+        egui::Area::new(egui::Id::new("players"))
+            .anchor(egui::Align2::LEFT_BOTTOM, [20.0, -20.0])
+            .show(ctx, |ui| {
+                egui::Frame::none()
+                    .fill(self.theme.panel_alt)
+                    .rounding(egui::Rounding::same(14.0))
+                    .inner_margin(egui::Margin::symmetric(12.0, 8.0))
+                    .show(ui, |ui| {
+                        for (player, controllers) in &self.players {
+                            ui.horizontal(|ui| {
+                                badge(
+                                    ui,
+                                    &format!("P{player}"),
+                                    self.theme.accent,
+                                    self.theme.text_on_accent,
+                                );
+                                ui.label(
+                                    egui::RichText::new(controllers)
+                                        .size(13.0)
+                                        .color(self.theme.text),
+                                );
+                            });
+                        }
+                    });
+            });
+    }
+
+    /// Frame pacing and audio buffer numbers, toggled with F4.
+    fn render_debug_hud(&self, ctx: &egui::Context, data: &UiContext<'_>) {
+        let mut lines = Vec::new();
+        if let Some(fps) = data.frame_stats.avg_fps() {
+            lines.push(format!("render {fps:.1} fps"));
+        }
+        if let Some(runtime) = data.runtime {
+            let stats = runtime.audio_stats();
+            lines.push(format!("emu {:.2} Hz", runtime.fps()));
+            lines.push(format!("speed {}", data.speed.as_deref().unwrap_or("1x")));
+            lines.push(format!(
+                "audio {:.0}% of {} samples",
+                stats.fill() * 100.0,
+                stats.capacity
+            ));
+            lines.push(format!("underruns {}", stats.underruns));
+            lines.push(format!("overruns {}", stats.overruns));
+        }
+        egui::Area::new(egui::Id::new("debug_hud"))
+            .anchor(egui::Align2::RIGHT_BOTTOM, [-20.0, -20.0])
+            .show(ctx, |ui| {
+                egui::Frame::none()
+                    .fill(self.theme.panel_alt)
+                    .rounding(egui::Rounding::same(10.0))
+                    .inner_margin(egui::Margin::symmetric(10.0, 8.0))
+                    .show(ui, |ui| {
+                        for line in &lines {
+                            ui.label(
+                                egui::RichText::new(line)
+                                    .monospace()
+                                    .size(12.0)
+                                    .color(self.theme.text),
+                            );
+                        }
+                    });
             });
     }
 
     fn render_toasts(&mut self, ctx: &egui::Context, now: Instant) {
         let duration = Duration::from_secs(3);
-        self.toasts.retain(|toast| now.saturating_duration_since(toast.created_at) < duration);
+        self.toasts
+            .retain(|toast| now.saturating_duration_since(toast.created_at) < duration);
         if self.toasts.is_empty() {
             return;
         }
@@ -643,11 +753,16 @@ impl OfficialPickerState {
                 } else if normalized.starts_with(&query) {
                     score = 800;
                 }
-                score -= (normalized.len() as i32 - query.len() as i32).abs().min(200);
+                score -= (normalized.len() as i32 - query.len() as i32)
+                    .abs()
+                    .min(200);
                 matches.push((score, idx));
             }
         }
-        matches.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| self.titles[a.1].cmp(&self.titles[b.1])));
+        matches.sort_by(|a, b| {
+            b.0.cmp(&a.0)
+                .then_with(|| self.titles[a.1].cmp(&self.titles[b.1]))
+        });
         matches
             .into_iter()
             .take(limit)
@@ -782,3 +897,20 @@ fn toast_colors(kind: ToastKind, theme: &UiTheme) -> (egui::Color32, egui::Color
         ToastKind::Error => (theme.error, theme.text_on_accent),
     }
 }
+
+/// e.g. "audio 12%", or "audio 0% (3 underruns)" once the buffer has run dry.
+fn audio_health(stats: AudioStats) -> String {
+    let mut problems = Vec::new();
+    if stats.underruns > 0 {
+        problems.push(format!("{} underruns", stats.underruns));
+    }
+    if stats.overruns > 0 {
+        problems.push(format!("{} overruns", stats.overruns));
+    }
+    let fill = format!("audio {:.0}%", stats.fill() * 100.0);
+    if problems.is_empty() {
+        fill
+    } else {
+        format!("{fill} ({})", problems.join(", "))
+    }
+}
@@ -0,0 +1,413 @@
+//! `playbyte`: runs cores without a window, for CI smoke tests and bulk jobs.
+
+mod script;
+mod wav;
+
+use anyhow::{bail, Context, Result};
+use image::codecs::png::PngEncoder;
+use image::{ColorType, ImageEncoder};
+use playbyte_emulation::{EmulatorRuntime, GameContent, RuntimeConfig, MAX_PORTS};
+use playbyte_feed::{LocalByteStore, RomLibrary, RomSource};
+use playbyte_libretro::VideoFrame;
+use playbyte_types::ByteMetadata;
+use script::InputScript;
+use sha1::{Digest, Sha1};
+use std::{
+    collections::BTreeSet,
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+};
+use wav::WavWriter;
+
+const USAGE: &str = "\
+Usage:
+  playbyte run --core <path> --rom <path> [options]
+  playbyte run --byte <id> [options]
+  playbyte thumbnails [--frames <n>]
+
+Options for run:
+  --frames <n>            frames to run (default 60)
+  --input <path>          input script to hold buttons from
+  --load-state <path>     restore a savestate before the first frame
+  --screenshot <frame>    save a PNG after that frame; repeatable
+  --screenshot-every <n>  save a PNG every n frames
+  --out <dir>             where screenshots go (default .)
+  --wav <path>            save the audio the game produced
+  --save-state <path>     save a savestate after the last frame
+  --hashes <path>         save the SHA-1 of every frame's picture, one per line
+  --isolate               run the core in a playbyte_core_host process
+
+Bytes, their ROMs and their cores are looked up under:
+  --data <dir>            Byte storage (default ./data)
+  --roms <dir>            ROM search root (default ./roms)
+  --cores <dir>           libretro cores (default ./cores)
+  --bios <dir>            system directory for cores (default <data>/system)
+
+thumbnails runs every Byte for --frames frames (default 1) and replaces its thumbnail.
+";
+
+#[derive(Default)]
+struct Args {
+    command: Option<String>,
+    core: Option<PathBuf>,
+    rom: Option<PathBuf>,
+    byte: Option<String>,
+    frames: Option<u64>,
+    input: Option<PathBuf>,
+    load_state: Option<PathBuf>,
+    screenshots: BTreeSet<u64>,
+    screenshot_every: Option<u64>,
+    out: Option<PathBuf>,
+    wav: Option<PathBuf>,
+    save_state: Option<PathBuf>,
+    hashes: Option<PathBuf>,
+    isolate: bool,
+    data: Option<PathBuf>,
+    roms: Option<PathBuf>,
+    cores: Option<PathBuf>,
+    bios: Option<PathBuf>,
+}
+
+impl Args {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self> {
+        let mut parsed = Self {
+            command: args.next(),
+            ..Self::default()
+        };
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().with_context(|| format!("{arg} needs a value"));
+            match arg.as_str() {
+                "--core" => parsed.core = Some(value()?.into()),
+                "--rom" => parsed.rom = Some(value()?.into()),
+                "--byte" => parsed.byte = Some(value()?),
+                "--frames" => parsed.frames = Some(parse_count(&arg, &value()?)?),
+                "--input" => parsed.input = Some(value()?.into()),
+                "--load-state" => parsed.load_state = Some(value()?.into()),
+                "--screenshot" => {
+                    parsed.screenshots.insert(parse_count(&arg, &value()?)?);
+                }
+                "--screenshot-every" => {
+                    parsed.screenshot_every = Some(parse_count(&arg, &value()?)?);
+                }
+                "--out" => parsed.out = Some(value()?.into()),
+                "--wav" => parsed.wav = Some(value()?.into()),
+                "--save-state" => parsed.save_state = Some(value()?.into()),
+                "--hashes" => parsed.hashes = Some(value()?.into()),
+                "--isolate" => parsed.isolate = true,
+                "--data" => parsed.data = Some(value()?.into()),
+                "--roms" => parsed.roms = Some(value()?.into()),
+                "--cores" => parsed.cores = Some(value()?.into()),
+                "--bios" => parsed.bios = Some(value()?.into()),
+                _ => bail!("unknown argument {arg}"),
+            }
+        }
+        Ok(parsed)
+    }
+
+    fn data_root(&self) -> PathBuf {
+        self.data.clone().unwrap_or_else(|| PathBuf::from("./data"))
+    }
+
+    fn store(&self) -> LocalByteStore {
+        LocalByteStore::new(self.data_root())
+    }
+
+    fn rom_library(&self) -> Result<RomLibrary> {
+        let mut roms = RomLibrary::new();
+        roms.add_root(self.roms.clone().unwrap_or_else(|| PathBuf::from("./roms")));
+        roms.scan()?;
+        Ok(roms)
+    }
+
+    fn cores_root(&self) -> PathBuf {
+        self.cores
+            .clone()
+            .unwrap_or_else(|| PathBuf::from("./cores"))
+    }
+
+    fn runtime_config(&self, core_id: Option<&str>) -> RuntimeConfig {
+        let bios = self
+            .bios
+            .clone()
+            .unwrap_or_else(|| self.data_root().join("system"));
+        let core_options = core_id
+            .and_then(|core_id| self.store().load_core_options(core_id).ok())
+            .unwrap_or_default();
+        RuntimeConfig {
+            core_options,
+            isolate_core: self.isolate,
+            system_directory: bios.is_dir().then_some(bios),
+            ..RuntimeConfig::default()
+        }
+    }
+}
+
+fn parse_count(arg: &str, value: &str) -> Result<u64> {
+    value
+        .parse()
+        .with_context(|| format!("{arg} takes a number, not `{value}`"))
+}
+
+fn main() -> Result<()> {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("warn")).init();
+    let args = Args::parse(std::env::args().skip(1))?;
+    match args.command.as_deref() {
+        Some("run") => run(&args),
+        Some("thumbnails") => thumbnails(&args),
+        _ => {
+            eprint!("{USAGE}");
+            Ok(())
+        }
+    }
+}
+
+/// Loads the game `args` point at: a core and ROM, or a Byte.
+fn load(args: &Args) -> Result<EmulatorRuntime> {
+    if let Some(byte_id) = &args.byte {
+        let store = args.store();
+        store.load_index()?;
+        let byte = store.get(byte_id)?;
+        return load_byte(args, &store, &args.rom_library()?, &byte);
+    }
+
+    let (Some(core), Some(rom)) = (&args.core, &args.rom) else {
+        bail!("run needs --core and --rom, or --byte\n\n{USAGE}");
+    };
+    let core_id = core_id_from_path(core);
+    let content = game_content(&RomSource::file(rom))?;
+    Ok(EmulatorRuntime::with_content(
+        core,
+        content,
+        args.runtime_config(core_id.as_deref()),
+    )?)
+}
+
+/// Loads a Byte's ROM into its core and restores its savestate. `--core` stands in for the
+/// core it names when given.
+fn load_byte(
+    args: &Args,
+    store: &LocalByteStore,
+    roms: &RomLibrary,
+    byte: &ByteMetadata,
+) -> Result<EmulatorRuntime> {
+    let core = match &args.core {
+        Some(core) => core.clone(),
+        None => find_core(&args.cores_root(), &byte.core_id)
+            .with_context(|| format!("missing core for {}", byte.core_id))?,
+    };
+    let rom = roms
+        .find_by_hash(&byte.rom_sha1)
+        .with_context(|| format!("missing ROM for hash {}", byte.rom_sha1))?;
+    let runtime = EmulatorRuntime::with_content(
+        core,
+        game_content(&rom)?,
+        args.runtime_config(Some(&byte.core_id)),
+    )?;
+    let info = runtime.system_info();
+    if info.library_name != byte.core_id || info.library_version != byte.core_semver {
+        bail!(
+            "Byte {} needs {} {}, found {} {}",
+            byte.byte_id,
+            byte.core_id,
+            byte.core_semver,
+            info.library_name,
+            info.library_version
+        );
+    }
+    runtime.check_serialize_size(byte.serialize_size)?;
+    runtime.unserialize(&store.load_state(&byte.byte_id)?)?;
+    Ok(runtime)
+}
+
+fn run(args: &Args) -> Result<()> {
+    let mut runtime = load(args)?;
+    if let Some(path) = &args.load_state {
+        let state = fs::read(path)
+            .with_context(|| format!("failed to read savestate {}", path.display()))?;
+        runtime.unserialize(&state)?;
+    }
+    let script = match &args.input {
+        Some(path) => InputScript::load(path)?,
+        None => InputScript::default(),
+    };
+    let out = args.out.clone().unwrap_or_else(|| PathBuf::from("."));
+    if !args.screenshots.is_empty() || args.screenshot_every.is_some() {
+        fs::create_dir_all(&out)?;
+    }
+    let mut wav = match &args.wav {
+        Some(path) => {
+            let sample_rate = runtime.av_info().timing.sample_rate.round() as u32;
+            Some(WavWriter::create(path, sample_rate)?)
+        }
+        None => None,
+    };
+    let mut hashes = match &args.hashes {
+        Some(path) => Some(std::io::BufWriter::new(fs::File::create(path)?)),
+        None => None,
+    };
+
+    let frames = args.frames.unwrap_or(60);
+    let input = runtime.input_state();
+    let audio = runtime.audio_buffer();
+    let mut samples = Vec::new();
+    let mut last_hash = None;
+    for frame in 1..=frames {
+        {
+            let mut input = input
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+            for port in 0..MAX_PORTS {
+                input.joypads[port] = script.joypad(frame, port);
+            }
+        }
+        runtime
+            .run_frame()
+            .with_context(|| format!("frame {frame} failed"))?;
+
+        // Drain the audio every frame either way, so the ring never overruns.
+        samples.resize(audio.len(), 0);
+        audio.pop_samples(&mut samples);
+        let sample_rate = runtime.av_info().timing.sample_rate.round() as u32;
+        if let Some(writer) = wav.take_if(|wav| wav.sample_rate() != sample_rate) {
+            // A WAV file has one rate, so keep what was recorded at the old one and stop.
+            let old_rate = writer.sample_rate();
+            writer.finish()?;
+            bail!("sample rate changed from {old_rate} to {sample_rate} Hz at frame {frame}");
+        }
+        if let Some(wav) = wav.as_mut() {
+            wav.write(&samples)?;
+        }
+
+        let Some(picture) = runtime.latest_frame() else {
+            continue;
+        };
+        let hash = frame_hash(&picture);
+        if let Some(hashes) = hashes.as_mut() {
+            writeln!(hashes, "{frame} {hash}")?;
+        }
+        last_hash = Some(hash);
+        let every = args
+            .screenshot_every
+            .is_some_and(|n| n > 0 && frame % n == 0);
+        if every || args.screenshots.contains(&frame) {
+            let path = out.join(format!("frame_{frame:06}.png"));
+            fs::write(&path, encode_png(&picture)?)
+                .with_context(|| format!("failed to write {}", path.display()))?;
+        }
+    }
+
+    if let Some(wav) = wav {
+        wav.finish()?;
+    }
+    if let Some(mut hashes) = hashes {
+        hashes.flush()?;
+    }
+    if let Some(path) = &args.save_state {
+        fs::write(path, runtime.serialize()?)
+            .with_context(|| format!("failed to write {}", path.display()))?;
+    }
+    match last_hash {
+        Some(hash) => println!("frame {frames} {hash}"),
+        None => println!("frame {frames} no picture"),
+    }
+    Ok(())
+}
+
+/// Re-renders the thumbnail of every Byte in the store.
+fn thumbnails(args: &Args) -> Result<()> {
+    let store = args.store();
+    let roms = args.rom_library()?;
+    let frames = args.frames.unwrap_or(1).max(1);
+    let mut failed = 0;
+    for byte in store.load_index()? {
+        let result = render_thumbnail(args, &store, &roms, &byte, frames)
+            .and_then(|png| Ok(store.store_thumbnail(&byte.byte_id, &png)?));
+        match result {
+            Ok(()) => println!("{} updated", byte.byte_id),
+            Err(err) => {
+                eprintln!("{} failed: {err:#}", byte.byte_id);
+                failed += 1;
+            }
+        }
+    }
+    if failed > 0 {
+        bail!("{failed} thumbnails could not be rendered");
+    }
+    Ok(())
+}
+
+fn render_thumbnail(
+    args: &Args,
+    store: &LocalByteStore,
+    roms: &RomLibrary,
+    byte: &ByteMetadata,
+    frames: u64,
+) -> Result<Vec<u8>> {
+    let mut runtime = load_byte(args, store, roms, byte)?;
+    for _ in 0..frames {
+        runtime.run_frame()?;
+    }
+    let frame = runtime.latest_frame().context("the core drew no picture")?;
+    encode_png(&frame)
+}
+
+/// Plain files are handed to the core by path; archived ROMs are extracted into memory.
+fn game_content(rom: &RomSource) -> Result<GameContent> {
+    if !rom.is_archived() {
+        return Ok(GameContent::Path(rom.path.clone()));
+    }
+    Ok(GameContent::Memory {
+        name: rom.rom_name().to_string_lossy().into_owned(),
+        data: rom.read()?,
+    })
+}
+
+/// Finds `<id>`, `<id>_libretro.<ext>` or `<id>.<ext>` in the cores directory.
+fn find_core(root: &Path, core_id: &str) -> Option<PathBuf> {
+    let ext = if cfg!(target_os = "windows") {
+        "dll"
+    } else if cfg!(target_os = "macos") {
+        "dylib"
+    } else {
+        "so"
+    };
+    [
+        root.join(core_id),
+        root.join(format!("{core_id}_libretro.{ext}")),
+        root.join(format!("{core_id}.{ext}")),
+    ]
+    .into_iter()
+    .find(|path| path.exists())
+}
+
+fn core_id_from_path(path: &Path) -> Option<String> {
+    let filename = path.file_name()?.to_str()?;
+    if let Some((core_id, _)) = filename.split_once("_libretro.") {
+        if !core_id.is_empty() {
+            return Some(core_id.to_string());
+        }
+    }
+    path.file_stem()
+        .and_then(|stem| stem.to_str())
+        .map(str::to_string)
+}
+
+fn frame_hash(frame: &VideoFrame) -> String {
+    let mut hasher = Sha1::new();
+    // Hash the picture rather than the raw buffer, whose row padding varies between cores.
+    hasher.update(frame.to_rgba());
+    format!("{:x}", hasher.finalize())
+}
+
+fn encode_png(frame: &VideoFrame) -> Result<Vec<u8>> {
+    let rgba = frame.to_rgba();
+    let mut png = Vec::new();
+    PngEncoder::new(&mut png).write_image(
+        &rgba,
+        frame.width,
+        frame.height,
+        ColorType::Rgba8.into(),
+    )?;
+    Ok(png)
+}
@@ -0,0 +1,119 @@
+//! Input scripts: which buttons are held on which frames.
+//!
+//! Each line holds buttons on one port for a frame or a range of frames:
+//!
+//! ```text
+//! # frame(s)  port  buttons
+//! 30          0     start
+//! 100-160     0     right,b
+//! 120         1     a
+//! ```
+//!
+//! Frames count from 1, the first frame run, and ranges include both ends. Buttons are
+//! `a b x y l r start select up down left right`. Lines for the same frame and port add
+//! up; buttons not held by any line are released. `#` starts a comment.
+
+use anyhow::{bail, Context, Result};
+use playbyte_emulation::{
+    JoypadState, MAX_PORTS, RETRO_DEVICE_ID_JOYPAD_A, RETRO_DEVICE_ID_JOYPAD_B,
+    RETRO_DEVICE_ID_JOYPAD_DOWN, RETRO_DEVICE_ID_JOYPAD_L, RETRO_DEVICE_ID_JOYPAD_LEFT,
+    RETRO_DEVICE_ID_JOYPAD_R, RETRO_DEVICE_ID_JOYPAD_RIGHT, RETRO_DEVICE_ID_JOYPAD_SELECT,
+    RETRO_DEVICE_ID_JOYPAD_START, RETRO_DEVICE_ID_JOYPAD_UP, RETRO_DEVICE_ID_JOYPAD_X,
+    RETRO_DEVICE_ID_JOYPAD_Y,
+};
+use std::{fs, ops::RangeInclusive, path::Path};
+
+struct Hold {
+    frames: RangeInclusive<u64>,
+    port: usize,
+    buttons: u16,
+}
+
+#[derive(Default)]
+pub struct InputScript {
+    holds: Vec<Hold>,
+}
+
+impl InputScript {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("failed to read input script {}", path.display()))?;
+        Self::parse(&text).with_context(|| format!("bad input script {}", path.display()))
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut holds = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let hold = parse_line(line).with_context(|| format!("line {}", number + 1))?;
+            holds.push(hold);
+        }
+        Ok(Self { holds })
+    }
+
+    /// What `port`'s joypad holds on `frame`.
+    pub fn joypad(&self, frame: u64, port: usize) -> JoypadState {
+        let bits = self
+            .holds
+            .iter()
+            .filter(|hold| hold.port == port && hold.frames.contains(&frame))
+            .fold(0, |bits, hold| bits | hold.buttons);
+        JoypadState::from_bits(bits)
+    }
+}
+
+fn parse_line(line: &str) -> Result<Hold> {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    let [frames, port, buttons] = fields[..] else {
+        bail!("expected `frames port buttons`, found `{line}`");
+    };
+    let frames = match frames.split_once('-') {
+        Some((start, end)) => parse_frame(start)?..=parse_frame(end)?,
+        None => parse_frame(frames)?..=parse_frame(frames)?,
+    };
+    if frames.is_empty() {
+        bail!("frame range ends before it starts");
+    }
+    let port: usize = port.parse().with_context(|| format!("bad port `{port}`"))?;
+    if port >= MAX_PORTS {
+        bail!("port {port} is past the last port, {}", MAX_PORTS - 1);
+    }
+    let mut mask = 0;
+    for name in buttons.split(',').filter(|name| !name.is_empty()) {
+        mask |= 1 << button_id(name)?;
+    }
+    Ok(Hold {
+        frames,
+        port,
+        buttons: mask,
+    })
+}
+
+fn parse_frame(value: &str) -> Result<u64> {
+    match value.parse() {
+        Ok(0) => bail!("frames count from 1"),
+        Ok(frame) => Ok(frame),
+        Err(_) => bail!("bad frame `{value}`"),
+    }
+}
+
+fn button_id(name: &str) -> Result<u32> {
+    Ok(match name.to_ascii_lowercase().as_str() {
+        "a" => RETRO_DEVICE_ID_JOYPAD_A,
+        "b" => RETRO_DEVICE_ID_JOYPAD_B,
+        "x" => RETRO_DEVICE_ID_JOYPAD_X,
+        "y" => RETRO_DEVICE_ID_JOYPAD_Y,
+        "l" => RETRO_DEVICE_ID_JOYPAD_L,
+        "r" => RETRO_DEVICE_ID_JOYPAD_R,
+        "start" => RETRO_DEVICE_ID_JOYPAD_START,
+        "select" => RETRO_DEVICE_ID_JOYPAD_SELECT,
+        "up" => RETRO_DEVICE_ID_JOYPAD_UP,
+        "down" => RETRO_DEVICE_ID_JOYPAD_DOWN,
+        "left" => RETRO_DEVICE_ID_JOYPAD_LEFT,
+        "right" => RETRO_DEVICE_ID_JOYPAD_RIGHT,
+        _ => bail!("unknown button `{name}`"),
+    })
+}
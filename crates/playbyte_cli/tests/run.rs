@@ -0,0 +1,171 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("playbyte-cli-{name}-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).expect("create scratch dir");
+    dir
+}
+
+/// Runs `playbyte run` on the mock core and returns the hash it printed last.
+fn run(mock: &Path, rom: &Path, extra: &[&str]) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_playbyte"))
+        .arg("run")
+        .arg("--core")
+        .arg(mock)
+        .arg("--rom")
+        .arg(rom)
+        .args(extra)
+        .output()
+        .expect("start playbyte");
+    assert!(
+        output.status.success(),
+        "playbyte failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let stdout = String::from_utf8(output.stdout).expect("utf-8 output");
+    let last = stdout.lines().last().expect("a frame hash line");
+    last.rsplit(' ').next().expect("hash").to_string()
+}
+
+#[test]
+fn run_writes_screenshots_audio_states_and_hashes() {
+    let Some(mock) = playbyte_libretro::testing::mock_core_path() else {
+        eprintln!("Skipping CLI test: mock core not built");
+        return;
+    };
+    let dir = scratch_dir("outputs");
+    let rom = dir.join("game.mock");
+    fs::write(&rom, "").expect("write content");
+    let script = dir.join("input.txt");
+    fs::write(&script, "# hold right, then tap a\n1-20 0 right\n10 0 a\n").expect("write script");
+
+    let shots = dir.join("shots");
+    let wav = dir.join("audio.wav");
+    let state = dir.join("state.bin");
+    let hashes = dir.join("hashes.txt");
+    let hash = run(
+        mock,
+        &rom,
+        &[
+            "--frames",
+            "30",
+            "--input",
+            script.to_str().unwrap(),
+            "--screenshot",
+            "10",
+            "--screenshot-every",
+            "15",
+            "--out",
+            shots.to_str().unwrap(),
+            "--wav",
+            wav.to_str().unwrap(),
+            "--save-state",
+            state.to_str().unwrap(),
+            "--hashes",
+            hashes.to_str().unwrap(),
+        ],
+    );
+
+    for frame in [10, 15, 30] {
+        let png = fs::read(shots.join(format!("frame_{frame:06}.png"))).expect("screenshot");
+        assert!(png.starts_with(b"\x89PNG"));
+    }
+    assert!(!shots.join("frame_000020.png").exists());
+
+    // The mock core queues 800 stereo frames per video frame.
+    let wav = fs::read(wav).expect("wav");
+    assert_eq!(&wav[..4], b"RIFF");
+    assert_eq!(&wav[8..16], b"WAVEfmt ");
+    assert_eq!(wav.len(), 44 + 30 * 800 * 2 * 2);
+
+    let hashes = fs::read_to_string(hashes).expect("hashes");
+    let lines: Vec<&str> = hashes.lines().collect();
+    assert_eq!(lines.len(), 30);
+    assert_eq!(lines[29], format!("30 {hash}"));
+
+    // The mock core reseeds on A, so the script shows in the picture.
+    assert_ne!(run(mock, &rom, &["--frames", "30"]), hash);
+
+    // Ten frames from the state after twenty land on the same picture as thirty in a row.
+    let midway = dir.join("midway.bin");
+    run(
+        mock,
+        &rom,
+        &[
+            "--frames",
+            "20",
+            "--input",
+            script.to_str().unwrap(),
+            "--save-state",
+            midway.to_str().unwrap(),
+        ],
+    );
+    let resumed = run(
+        mock,
+        &rom,
+        &["--frames", "10", "--load-state", midway.to_str().unwrap()],
+    );
+    assert_eq!(resumed, hash);
+    assert!(!fs::read(state).expect("state").is_empty());
+}
+
+#[test]
+fn bad_input_scripts_are_reported() {
+    let Some(mock) = playbyte_libretro::testing::mock_core_path() else {
+        eprintln!("Skipping CLI test: mock core not built");
+        return;
+    };
+    let dir = scratch_dir("bad-script");
+    let rom = dir.join("game.mock");
+    fs::write(&rom, "").expect("write content");
+    let script = dir.join("input.txt");
+    fs::write(&script, "5 0 jump\n").expect("write script");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_playbyte"))
+        .args(["run", "--frames", "5", "--input"])
+        .arg(&script)
+        .arg("--core")
+        .arg(mock)
+        .arg("--rom")
+        .arg(&rom)
+        .output()
+        .expect("start playbyte");
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("unknown button `jump`"), "{stderr}");
+}
+
+#[test]
+fn wav_output_stops_when_the_sample_rate_changes() {
+    let Some(mock) = playbyte_libretro::testing::mock_core_path() else {
+        eprintln!("Skipping CLI test: mock core not built");
+        return;
+    };
+    let dir = scratch_dir("rate-change");
+    let rom = dir.join("game.mock");
+    fs::write(&rom, "timing").expect("write content");
+    let wav = dir.join("audio.wav");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_playbyte"))
+        .args(["run", "--frames", "20", "--wav"])
+        .arg(&wav)
+        .arg("--core")
+        .arg(mock)
+        .arg("--rom")
+        .arg(&rom)
+        .output()
+        .expect("start playbyte");
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("from 48000 to 32000 Hz at frame 10"),
+        "{stderr}"
+    );
+    // The nine frames before the change are kept in a finished file.
+    assert_eq!(fs::read(wav).expect("wav").len(), 44 + 9 * 800 * 2 * 2);
+}
@@ -0,0 +1,173 @@
+//! The queue of samples between the core and the audio device.
+//!
+//! It is a lock-free single-producer, single-consumer ring: the core (or the thread talking
+//! to the core host) pushes, and the audio callback pops, without either waiting on the
+//! other. Both sides take `&self` so the buffer can sit in an `Arc`, but there must only be
+//! one of each at a time; a second producer or consumer would garble samples.
+
+use std::sync::atomic::{AtomicBool, AtomicI16, AtomicU32, AtomicU64, AtomicUsize, Ordering};
+
+/// Audio health counters, for the overlay and debug HUD.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AudioStats {
+    /// Times the device asked for more samples than were queued; the gap played as silence.
+    pub underruns: u64,
+    /// Times the core produced samples while the buffer was full; the excess was dropped.
+    pub overruns: u64,
+    /// Samples queued, counting both channels.
+    pub queued: usize,
+    pub capacity: usize,
+}
+
+impl AudioStats {
+    /// How full the buffer is, from 0.0 to 1.0.
+    pub fn fill(&self) -> f32 {
+        if self.capacity == 0 {
+            return 0.0;
+        }
+        self.queued as f32 / self.capacity as f32
+    }
+}
+
+/// Interleaved stereo samples on their way from the core to the audio device.
+///
+/// When the game runs faster or slower than normal, `set_playback_rate` keeps the buffer
+/// from overflowing or running dry: frames are dropped or interpolated on the way in, which
+/// shifts the pitch along with the speed.
+#[derive(Debug)]
+pub struct AudioRingBuffer {
+    slots: Box<[AtomicI16]>,
+    /// Samples ever written and read; their difference is what is queued.
+    written: AtomicUsize,
+    read: AtomicUsize,
+    muted: AtomicBool,
+    underruns: AtomicU64,
+    overruns: AtomicU64,
+    /// Core frames consumed per frame queued, as `f64` bits. Set by anyone.
+    rate: AtomicU64,
+    /// Producer-only stretch state: where the next queued frame falls, in core frames after
+    /// the previous core frame (`f64` bits), and that frame's two samples.
+    position: AtomicU64,
+    previous: AtomicU32,
+}
+
+impl AudioRingBuffer {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            slots: (0..capacity).map(|_| AtomicI16::new(0)).collect(),
+            written: AtomicUsize::new(0),
+            read: AtomicUsize::new(0),
+            muted: AtomicBool::new(false),
+            underruns: AtomicU64::new(0),
+            overruns: AtomicU64::new(0),
+            rate: AtomicU64::new(1.0f64.to_bits()),
+            position: AtomicU64::new(1.0f64.to_bits()),
+            previous: AtomicU32::new(0),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+
+    /// Drops pushed samples while set, e.g. for frames run during rewind.
+    pub fn set_muted(&self, muted: bool) {
+        self.muted.store(muted, Ordering::Relaxed);
+    }
+
+    /// How fast the game runs relative to normal speed, e.g. 2.0 keeps every other frame
+    /// the core produces and 0.5 adds one in between each.
+    pub fn set_playback_rate(&self, rate: f64) {
+        if rate.is_finite() && rate > 0.0 {
+            self.rate.store(rate.to_bits(), Ordering::Relaxed);
+        }
+    }
+
+    /// Queues samples from the core. Whatever does not fit is dropped and counted as an
+    /// overrun. Producer side.
+    pub fn push_samples(&self, samples: &[i16]) {
+        if self.muted.load(Ordering::Relaxed) {
+            return;
+        }
+        let capacity = self.capacity();
+        let read = self.read.load(Ordering::Acquire);
+        let mut written = self.written.load(Ordering::Relaxed);
+        let rate = f64::from_bits(self.rate.load(Ordering::Relaxed));
+        let mut position = f64::from_bits(self.position.load(Ordering::Relaxed));
+        let mut previous = unpack_frame(self.previous.load(Ordering::Relaxed));
+        let mut overrun = false;
+
+        for frame in samples.chunks_exact(2) {
+            let current = [frame[0], frame[1]];
+            while position <= 1.0 {
+                if written.wrapping_sub(read) + 2 > capacity {
+                    overrun = true;
+                } else {
+                    for (&from, &to) in previous.iter().zip(&current) {
+                        let sample = from as f64 + (to as f64 - from as f64) * position;
+                        self.slots[written % capacity]
+                            .store(sample.round() as i16, Ordering::Relaxed);
+                        written = written.wrapping_add(1);
+                    }
+                }
+                position += rate;
+            }
+            position -= 1.0;
+            previous = current;
+        }
+
+        self.position.store(position.to_bits(), Ordering::Relaxed);
+        self.previous.store(pack_frame(previous), Ordering::Relaxed);
+        self.written.store(written, Ordering::Release);
+        if overrun {
+            self.overruns.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Fills `out` with queued samples and returns how many there were. A shortfall is padded
+    /// with silence and counted as an underrun. Consumer side.
+    pub fn pop_samples(&self, out: &mut [i16]) -> usize {
+        let capacity = self.capacity();
+        let written = self.written.load(Ordering::Acquire);
+        let read = self.read.load(Ordering::Relaxed);
+        let count = out.len().min(written.wrapping_sub(read));
+        for (offset, sample) in out[..count].iter_mut().enumerate() {
+            *sample = self.slots[read.wrapping_add(offset) % capacity].load(Ordering::Relaxed);
+        }
+        out[count..].fill(0);
+        self.read.store(read.wrapping_add(count), Ordering::Release);
+        if count < out.len() {
+            self.underruns.fetch_add(1, Ordering::Relaxed);
+        }
+        count
+    }
+
+    /// Samples waiting to be played, counting both channels.
+    pub fn len(&self) -> usize {
+        // Read first: `written` never falls behind it.
+        let read = self.read.load(Ordering::Acquire);
+        let written = self.written.load(Ordering::Acquire);
+        written.wrapping_sub(read).min(self.capacity())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn stats(&self) -> AudioStats {
+        AudioStats {
+            underruns: self.underruns.load(Ordering::Relaxed),
+            overruns: self.overruns.load(Ordering::Relaxed),
+            queued: self.len(),
+            capacity: self.capacity(),
+        }
+    }
+}
+
+fn pack_frame([left, right]: [i16; 2]) -> u32 {
+    (left as u16 as u32) << 16 | right as u16 as u32
+}
+
+fn unpack_frame(bits: u32) -> [i16; 2] {
+    [(bits >> 16) as u16 as i16, bits as u16 as i16]
+}
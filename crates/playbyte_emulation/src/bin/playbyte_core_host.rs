@@ -0,0 +1,13 @@
+//! Helper process that hosts a single libretro core for an isolated `EmulatorRuntime`.
+
+use std::process::ExitCode;
+
+fn main() -> ExitCode {
+    match playbyte_emulation::core_host::serve() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("playbyte_core_host: {err}");
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,1184 @@
+//! Out-of-process core hosting.
+//!
+//! With `RuntimeConfig::isolate_core` set, the core runs inside the `playbyte_core_host`
+//! helper instead of the app. Frames and audio come back through a memory-mapped temp
+//! file; commands, replies and save states travel over the helper's stdin and stdout. If
+//! the helper dies, or takes longer than its reply timeout over a command, the call reports
+//! `RuntimeError::CoreCrashed` instead of taking the app down with it or hanging it.
+
+use crate::{AudioRingBuffer, GameContent, InputSnapshot, RuntimeConfig, RuntimeError};
+use memmap2::MmapMut;
+use playbyte_libretro::{
+    Callbacks, ControllerDescription, ControllerInfo, CoreOption, CoreOptionValue, LibretroCore,
+    LibretroError, LogLevel, LogLine, MemoryRegion, RetroGameGeometry, RetroPixelFormat,
+    RetroSystemTiming, SavestateContext, SerializationQuirks, SystemAvInfo, SystemInfo, VideoFrame,
+};
+use std::{
+    collections::HashMap,
+    fs::{File, OpenOptions},
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    process::{Child, ChildStdin, ChildStdout, Command as ProcessCommand, Stdio},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc::{self, Receiver, RecvTimeoutError},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+/// Environment variable that overrides where the helper binary is looked up.
+pub const CORE_HOST_ENV: &str = "PLAYBYTE_CORE_HOST";
+/// File name (without platform suffix) of the helper binary.
+pub const CORE_HOST_BINARY: &str = "playbyte_core_host";
+
+const MAX_FRAME_BYTES: usize = 16 * 1024 * 1024;
+const MAX_AUDIO_SAMPLES: usize = 64 * 1024;
+const HEADER_BYTES: usize = 64;
+const SHARED_MEMORY_BYTES: usize = HEADER_BYTES + MAX_FRAME_BYTES + MAX_AUDIO_SAMPLES * 2;
+
+const HEADER_FRAME_WIDTH: usize = 0;
+const HEADER_FRAME_HEIGHT: usize = 4;
+const HEADER_FRAME_PITCH: usize = 8;
+const HEADER_FRAME_FORMAT: usize = 12;
+const HEADER_FRAME_LEN: usize = 16;
+const HEADER_AUDIO_LEN: usize = 20;
+const AUDIO_OFFSET: usize = HEADER_BYTES + MAX_FRAME_BYTES;
+
+const CMD_INIT: u8 = 1;
+const CMD_LOAD_GAME: u8 = 2;
+const CMD_RUN_FRAME: u8 = 3;
+const CMD_SERIALIZE: u8 = 4;
+const CMD_UNSERIALIZE: u8 = 5;
+const CMD_SET_CORE_OPTION: u8 = 6;
+const CMD_CORE_OPTIONS: u8 = 7;
+const CMD_READ_MEMORY: u8 = 8;
+const CMD_WRITE_MEMORY: u8 = 9;
+const CMD_RECENT_LOG: u8 = 10;
+const CMD_SET_PORT_DEVICE: u8 = 11;
+const CMD_SET_FAST_FORWARDING: u8 = 12;
+const CMD_SERIALIZE_SIZE: u8 = 13;
+
+const REPLY_OK: u8 = 0;
+const REPLY_ERR: u8 = 1;
+
+const SHUTDOWN_GRACE: Duration = Duration::from_millis(500);
+/// How long the helper may take to answer a command before it is considered hung.
+pub const REPLY_TIMEOUT: Duration = Duration::from_secs(10);
+
+static NEXT_SHARED_MEMORY_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// A command byte or reply status and its payload, as framed on the pipes.
+type Message = (u8, Vec<u8>);
+
+/// Resolves the helper binary: explicit path, then `PLAYBYTE_CORE_HOST`, then next to the
+/// running executable.
+pub fn locate_core_host(explicit: Option<&Path>) -> PathBuf {
+    let file_name = format!("{CORE_HOST_BINARY}{}", std::env::consts::EXE_SUFFIX);
+    explicit
+        .map(Path::to_path_buf)
+        .or_else(|| std::env::var_os(CORE_HOST_ENV).map(PathBuf::from))
+        .or_else(|| {
+            std::env::current_exe()
+                .ok()
+                .and_then(|exe| exe.parent().map(|dir| dir.join(&file_name)))
+        })
+        .unwrap_or_else(|| PathBuf::from(file_name))
+}
+
+fn memory_region_from_u32(value: u32) -> Option<MemoryRegion> {
+    match value {
+        0 => Some(MemoryRegion::SaveRam),
+        1 => Some(MemoryRegion::Rtc),
+        2 => Some(MemoryRegion::SystemRam),
+        3 => Some(MemoryRegion::VideoRam),
+        _ => None,
+    }
+}
+
+fn log_level_from_u8(value: u8) -> LogLevel {
+    match value {
+        0 => LogLevel::Debug,
+        1 => LogLevel::Info,
+        2 => LogLevel::Warn,
+        _ => LogLevel::Error,
+    }
+}
+
+fn savestate_context_from_u8(value: u8) -> SavestateContext {
+    match value {
+        1 => SavestateContext::RunaheadSameInstance,
+        2 => SavestateContext::RunaheadSameBinary,
+        3 => SavestateContext::RollbackNetplay,
+        _ => SavestateContext::Normal,
+    }
+}
+
+fn pixel_format_from_u32(value: u32) -> RetroPixelFormat {
+    match value {
+        1 => RetroPixelFormat::Xrgb8888,
+        2 => RetroPixelFormat::Rgb565,
+        _ => RetroPixelFormat::_0rgb1555,
+    }
+}
+
+/// Frame and audio exchange area shared by the app and the helper.
+struct SharedMemory {
+    map: MmapMut,
+}
+
+impl SharedMemory {
+    fn create(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+        file.set_len(SHARED_MEMORY_BYTES as u64)?;
+        Self::map(&file)
+    }
+
+    fn open(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        if file.metadata()?.len() < SHARED_MEMORY_BYTES as u64 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "shared memory file is too small",
+            ));
+        }
+        Self::map(&file)
+    }
+
+    fn map(file: &File) -> io::Result<Self> {
+        let map = unsafe { MmapMut::map_mut(file)? };
+        Ok(Self { map })
+    }
+
+    fn read_u32(&self, offset: usize) -> u32 {
+        let mut bytes = [0u8; 4];
+        bytes.copy_from_slice(&self.map[offset..offset + 4]);
+        u32::from_le_bytes(bytes)
+    }
+
+    fn write_u32(&mut self, offset: usize, value: u32) {
+        self.map[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+    }
+
+    fn begin_frame(&mut self) {
+        self.write_u32(HEADER_FRAME_LEN, 0);
+        self.write_u32(HEADER_AUDIO_LEN, 0);
+    }
+
+    fn write_frame(
+        &mut self,
+        data: &[u8],
+        width: u32,
+        height: u32,
+        pitch: usize,
+        format: RetroPixelFormat,
+    ) {
+        if data.len() > MAX_FRAME_BYTES {
+            return;
+        }
+        self.map[HEADER_BYTES..HEADER_BYTES + data.len()].copy_from_slice(data);
+        self.write_u32(HEADER_FRAME_WIDTH, width);
+        self.write_u32(HEADER_FRAME_HEIGHT, height);
+        self.write_u32(HEADER_FRAME_PITCH, pitch as u32);
+        self.write_u32(HEADER_FRAME_FORMAT, format as u32);
+        self.write_u32(HEADER_FRAME_LEN, data.len() as u32);
+    }
+
+    fn push_audio(&mut self, samples: &[i16]) {
+        let used = self.read_u32(HEADER_AUDIO_LEN) as usize;
+        let count = samples.len().min(MAX_AUDIO_SAMPLES - used);
+        for (index, sample) in samples[..count].iter().enumerate() {
+            let offset = AUDIO_OFFSET + (used + index) * 2;
+            self.map[offset..offset + 2].copy_from_slice(&sample.to_le_bytes());
+        }
+        self.write_u32(HEADER_AUDIO_LEN, (used + count) as u32);
+    }
+
+    fn read_frame(&self) -> Option<VideoFrame> {
+        let len = self.read_u32(HEADER_FRAME_LEN) as usize;
+        if len == 0 || len > MAX_FRAME_BYTES {
+            return None;
+        }
+        Some(VideoFrame {
+            width: self.read_u32(HEADER_FRAME_WIDTH),
+            height: self.read_u32(HEADER_FRAME_HEIGHT),
+            pitch: self.read_u32(HEADER_FRAME_PITCH) as usize,
+            pixel_format: pixel_format_from_u32(self.read_u32(HEADER_FRAME_FORMAT)),
+            data: self.map[HEADER_BYTES..HEADER_BYTES + len].to_vec(),
+        })
+    }
+
+    fn read_audio(&self, out: &mut Vec<i16>) {
+        let len = (self.read_u32(HEADER_AUDIO_LEN) as usize).min(MAX_AUDIO_SAMPLES);
+        out.clear();
+        out.extend(
+            self.map[AUDIO_OFFSET..AUDIO_OFFSET + len * 2]
+                .chunks_exact(2)
+                .map(|bytes| i16::from_le_bytes([bytes[0], bytes[1]])),
+        );
+    }
+}
+
+/// Little-endian encoding shared by the host protocol and movie files.
+#[derive(Default)]
+pub(crate) struct Writer {
+    buf: Vec<u8>,
+}
+
+impl Writer {
+    pub(crate) fn u8(&mut self, value: u8) -> &mut Self {
+        self.buf.push(value);
+        self
+    }
+
+    pub(crate) fn u32(&mut self, value: u32) -> &mut Self {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    pub(crate) fn u64(&mut self, value: u64) -> &mut Self {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    pub(crate) fn f64(&mut self, value: f64) -> &mut Self {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    pub(crate) fn bool(&mut self, value: bool) -> &mut Self {
+        self.u8(value as u8)
+    }
+
+    pub(crate) fn bytes(&mut self, value: &[u8]) -> &mut Self {
+        self.u32(value.len() as u32);
+        self.buf.extend_from_slice(value);
+        self
+    }
+
+    pub(crate) fn str(&mut self, value: &str) -> &mut Self {
+        self.bytes(value.as_bytes())
+    }
+
+    pub(crate) fn opt_str(&mut self, value: Option<&str>) -> &mut Self {
+        match value {
+            Some(value) => self.bool(true).str(value),
+            None => self.bool(false),
+        }
+    }
+
+    pub(crate) fn finish(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.buf)
+    }
+}
+
+pub(crate) struct Reader<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn new(buf: &'a [u8]) -> Self {
+        Self { buf }
+    }
+
+    pub(crate) fn take(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        if self.buf.len() < len {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "unexpected end of data",
+            ));
+        }
+        let (head, tail) = self.buf.split_at(len);
+        self.buf = tail;
+        Ok(head)
+    }
+
+    pub(crate) fn u8(&mut self) -> io::Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub(crate) fn u32(&mut self) -> io::Result<u32> {
+        let mut bytes = [0u8; 4];
+        bytes.copy_from_slice(self.take(4)?);
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    pub(crate) fn u64(&mut self) -> io::Result<u64> {
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(self.take(8)?);
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    pub(crate) fn f64(&mut self) -> io::Result<f64> {
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(self.take(8)?);
+        Ok(f64::from_le_bytes(bytes))
+    }
+
+    pub(crate) fn bool(&mut self) -> io::Result<bool> {
+        Ok(self.u8()? != 0)
+    }
+
+    pub(crate) fn bytes(&mut self) -> io::Result<&'a [u8]> {
+        let len = self.u32()? as usize;
+        self.take(len)
+    }
+
+    pub(crate) fn string(&mut self) -> io::Result<String> {
+        String::from_utf8(self.bytes()?.to_vec())
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+
+    pub(crate) fn opt_string(&mut self) -> io::Result<Option<String>> {
+        if self.bool()? {
+            Ok(Some(self.string()?))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+fn write_message(out: &mut impl Write, tag: u8, payload: &[u8]) -> io::Result<()> {
+    out.write_all(&[tag])?;
+    out.write_all(&(payload.len() as u32).to_le_bytes())?;
+    out.write_all(payload)?;
+    out.flush()
+}
+
+fn read_message(input: &mut impl Read) -> io::Result<Message> {
+    let mut header = [0u8; 5];
+    input.read_exact(&mut header)?;
+    let len = u32::from_le_bytes([header[1], header[2], header[3], header[4]]) as usize;
+    let mut payload = vec![0u8; len];
+    input.read_exact(&mut payload)?;
+    Ok((header[0], payload))
+}
+
+fn write_system_info(writer: &mut Writer, info: &SystemInfo) {
+    writer
+        .str(&info.library_name)
+        .str(&info.library_version)
+        .str(&info.valid_extensions)
+        .bool(info.need_fullpath)
+        .bool(info.block_extract);
+}
+
+fn read_system_info(reader: &mut Reader) -> io::Result<SystemInfo> {
+    Ok(SystemInfo {
+        library_name: reader.string()?,
+        library_version: reader.string()?,
+        valid_extensions: reader.string()?,
+        need_fullpath: reader.bool()?,
+        block_extract: reader.bool()?,
+    })
+}
+
+fn write_av_info(writer: &mut Writer, info: &SystemAvInfo) {
+    writer
+        .u32(info.geometry.base_width)
+        .u32(info.geometry.base_height)
+        .u32(info.geometry.max_width)
+        .u32(info.geometry.max_height)
+        .f64(info.geometry.aspect_ratio as f64)
+        .f64(info.timing.fps)
+        .f64(info.timing.sample_rate);
+}
+
+fn read_av_info(reader: &mut Reader) -> io::Result<SystemAvInfo> {
+    Ok(SystemAvInfo {
+        geometry: RetroGameGeometry {
+            base_width: reader.u32()?,
+            base_height: reader.u32()?,
+            max_width: reader.u32()?,
+            max_height: reader.u32()?,
+            aspect_ratio: reader.f64()? as f32,
+        },
+        timing: RetroSystemTiming {
+            fps: reader.f64()?,
+            sample_rate: reader.f64()?,
+        },
+    })
+}
+
+fn write_core_options(
+    writer: &mut Writer,
+    options: &[CoreOption],
+    values: &HashMap<String, String>,
+) {
+    writer.u32(options.len() as u32);
+    for option in options {
+        writer
+            .str(&option.key)
+            .str(&option.description)
+            .opt_str(option.info.as_deref())
+            .opt_str(option.category.as_deref())
+            .u32(option.values.len() as u32);
+        for value in &option.values {
+            writer.str(&value.value).opt_str(value.label.as_deref());
+        }
+        writer
+            .str(&option.default_value)
+            .bool(option.visible)
+            .opt_str(values.get(&option.key).map(String::as_str));
+    }
+}
+
+fn read_core_options(
+    reader: &mut Reader,
+) -> io::Result<(Vec<CoreOption>, HashMap<String, String>)> {
+    let count = reader.u32()? as usize;
+    let mut options = Vec::with_capacity(count);
+    let mut values = HashMap::with_capacity(count);
+    for _ in 0..count {
+        let key = reader.string()?;
+        let description = reader.string()?;
+        let info = reader.opt_string()?;
+        let category = reader.opt_string()?;
+        let value_count = reader.u32()? as usize;
+        let mut choices = Vec::with_capacity(value_count);
+        for _ in 0..value_count {
+            choices.push(CoreOptionValue {
+                value: reader.string()?,
+                label: reader.opt_string()?,
+            });
+        }
+        let default_value = reader.string()?;
+        let visible = reader.bool()?;
+        if let Some(value) = reader.opt_string()? {
+            values.insert(key.clone(), value);
+        }
+        options.push(CoreOption {
+            key,
+            description,
+            info,
+            category,
+            values: choices,
+            default_value,
+            visible,
+        });
+    }
+    Ok((options, values))
+}
+
+fn write_controller_info(writer: &mut Writer, ports: &[ControllerInfo]) {
+    writer.u32(ports.len() as u32);
+    for port in ports {
+        writer.u32(port.types.len() as u32);
+        for controller in &port.types {
+            writer.str(&controller.description).u32(controller.device);
+        }
+    }
+}
+
+fn read_controller_info(reader: &mut Reader) -> io::Result<Vec<ControllerInfo>> {
+    let count = reader.u32()? as usize;
+    let mut ports = Vec::with_capacity(count);
+    for _ in 0..count {
+        let type_count = reader.u32()? as usize;
+        let mut types = Vec::with_capacity(type_count);
+        for _ in 0..type_count {
+            types.push(ControllerDescription {
+                description: reader.string()?,
+                device: reader.u32()?,
+            });
+        }
+        ports.push(ControllerInfo { types });
+    }
+    Ok(ports)
+}
+
+/// Signed values travel as their two's complement bit patterns.
+pub(crate) fn write_input(writer: &mut Writer, input: &InputSnapshot) {
+    for ((&joypad, analog), &device) in input
+        .joypads
+        .iter()
+        .zip(&input.analogs)
+        .zip(&input.port_devices)
+    {
+        writer
+            .u32(device)
+            .u32(joypad as u32)
+            .u32(analog.left_x as u32)
+            .u32(analog.left_y as u32)
+            .u32(analog.right_x as u32)
+            .u32(analog.right_y as u32);
+    }
+    let mouse = &input.mouse;
+    let pointer = &input.pointer;
+    writer
+        .u32(mouse.delta_x as u32)
+        .u32(mouse.delta_y as u32)
+        .u32(mouse.wheel as u32)
+        .bool(mouse.left)
+        .bool(mouse.right)
+        .bool(mouse.middle)
+        .u32(pointer.x as u32)
+        .u32(pointer.y as u32)
+        .bool(pointer.inside)
+        .bool(pointer.pressed);
+}
+
+pub(crate) fn read_input(reader: &mut Reader) -> io::Result<InputSnapshot> {
+    let mut input = InputSnapshot::default();
+    for ((joypad, analog), device) in input
+        .joypads
+        .iter_mut()
+        .zip(&mut input.analogs)
+        .zip(&mut input.port_devices)
+    {
+        *device = reader.u32()?;
+        *joypad = reader.u32()? as u16;
+        analog.left_x = reader.u32()? as i16;
+        analog.left_y = reader.u32()? as i16;
+        analog.right_x = reader.u32()? as i16;
+        analog.right_y = reader.u32()? as i16;
+    }
+    let mouse = &mut input.mouse;
+    mouse.delta_x = reader.u32()? as i32;
+    mouse.delta_y = reader.u32()? as i32;
+    mouse.wheel = reader.u32()? as i32;
+    mouse.left = reader.bool()?;
+    mouse.right = reader.bool()?;
+    mouse.middle = reader.bool()?;
+    let pointer = &mut input.pointer;
+    pointer.x = reader.u32()? as i16;
+    pointer.y = reader.u32()? as i16;
+    pointer.inside = reader.bool()?;
+    pointer.pressed = reader.bool()?;
+    Ok(input)
+}
+
+fn write_log(writer: &mut Writer, lines: &[LogLine]) {
+    writer.u32(lines.len() as u32);
+    for line in lines {
+        writer.u8(line.level as u8).str(&line.message);
+    }
+}
+
+fn read_log(reader: &mut Reader) -> io::Result<Vec<LogLine>> {
+    let count = reader.u32()? as usize;
+    let mut lines = Vec::with_capacity(count);
+    for _ in 0..count {
+        lines.push(LogLine {
+            level: log_level_from_u8(reader.u8()?),
+            message: reader.string()?,
+        });
+    }
+    Ok(lines)
+}
+
+/// App-side handle to a running helper process.
+pub(crate) struct CoreHost {
+    child: Child,
+    commands: Option<ChildStdin>,
+    /// Messages read off the helper's stdout by a thread of their own, so waiting for
+    /// one can give up.
+    replies: Receiver<io::Result<Message>>,
+    reply_timeout: Duration,
+    shared: SharedMemory,
+    shared_path: PathBuf,
+    system_info: SystemInfo,
+    pixel_format: RetroPixelFormat,
+    serialization_quirks: SerializationQuirks,
+    controller_info: Vec<ControllerInfo>,
+    audio_scratch: Vec<i16>,
+    crashed: Option<String>,
+}
+
+impl CoreHost {
+    pub fn spawn(
+        host_binary: &Path,
+        core_path: &Path,
+        config: &RuntimeConfig,
+    ) -> Result<Self, RuntimeError> {
+        if !cfg!(unix) {
+            return Err(RuntimeError::CoreHostSpawn(unsupported_platform()));
+        }
+        let shared_path = std::env::temp_dir().join(format!(
+            "playbyte-core-host-{}-{}.shm",
+            std::process::id(),
+            NEXT_SHARED_MEMORY_ID.fetch_add(1, Ordering::Relaxed)
+        ));
+        let shared = SharedMemory::create(&shared_path).map_err(RuntimeError::CoreHostSpawn)?;
+
+        let mut child = match ProcessCommand::new(host_binary)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .spawn()
+        {
+            Ok(child) => child,
+            Err(err) => {
+                let _ = std::fs::remove_file(&shared_path);
+                return Err(RuntimeError::CoreHostSpawn(err));
+            }
+        };
+        let commands = child.stdin.take();
+        let stdout = child.stdout.take().expect("core host stdout is piped");
+        let replies = match spawn_reply_reader(stdout) {
+            Ok(replies) => replies,
+            Err(err) => {
+                let _ = child.kill();
+                let _ = child.wait();
+                let _ = std::fs::remove_file(&shared_path);
+                return Err(RuntimeError::CoreHostSpawn(err));
+            }
+        };
+
+        let mut host = Self {
+            child,
+            commands,
+            replies,
+            reply_timeout: config.core_host_timeout.unwrap_or(REPLY_TIMEOUT),
+            shared,
+            shared_path,
+            system_info: SystemInfo {
+                library_name: String::new(),
+                library_version: String::new(),
+                valid_extensions: String::new(),
+                need_fullpath: false,
+                block_extract: false,
+            },
+            pixel_format: RetroPixelFormat::_0rgb1555,
+            serialization_quirks: SerializationQuirks::default(),
+            controller_info: Vec::new(),
+            audio_scratch: Vec::new(),
+            crashed: None,
+        };
+
+        let system_directory = config
+            .system_directory
+            .as_deref()
+            .map(Path::to_string_lossy);
+        let save_directory = config.save_directory.as_deref().map(Path::to_string_lossy);
+        let mut writer = Writer::default();
+        writer
+            .str(&core_path.to_string_lossy())
+            .str(&host.shared_path.to_string_lossy())
+            .opt_str(system_directory.as_deref())
+            .opt_str(save_directory.as_deref())
+            .u32(config.core_options.len() as u32);
+        for (key, value) in &config.core_options {
+            writer.str(key).str(value);
+        }
+        let reply = host.request(CMD_INIT, &writer.finish())?;
+        host.system_info = read_system_info(&mut Reader::new(&reply)).map_err(protocol_error)?;
+        Ok(host)
+    }
+
+    pub fn system_info(&self) -> &SystemInfo {
+        &self.system_info
+    }
+
+    pub fn pixel_format(&self) -> RetroPixelFormat {
+        self.pixel_format
+    }
+
+    /// Quirks the core declared up to the end of `load_game`.
+    pub fn serialization_quirks(&self) -> SerializationQuirks {
+        self.serialization_quirks
+    }
+
+    /// Devices the core accepts per port, as of the end of `load_game`.
+    pub fn controller_info(&self) -> &[ControllerInfo] {
+        &self.controller_info
+    }
+
+    pub fn load_game(&mut self, content: &GameContent) -> Result<SystemAvInfo, RuntimeError> {
+        let mut writer = Writer::default();
+        match content {
+            GameContent::Path(path) => writer.str(&path.to_string_lossy()).bool(false),
+            GameContent::Memory { name, data } => writer.str(name).bool(true).bytes(data),
+        };
+        let payload = writer.finish();
+        let reply = self.request(CMD_LOAD_GAME, &payload)?;
+        let mut reader = Reader::new(&reply);
+        if !reader.bool().map_err(protocol_error)? {
+            // The helper survives a refused game, so fetch what the core logged about it.
+            let log = self.recent_log().unwrap_or_default();
+            return Err(LibretroError::LoadGame { log }.into());
+        }
+        let av_info = read_av_info(&mut reader).map_err(protocol_error)?;
+        self.serialization_quirks = SerializationQuirks(reader.u64().map_err(protocol_error)?);
+        self.controller_info = read_controller_info(&mut reader).map_err(protocol_error)?;
+        Ok(av_info)
+    }
+
+    pub fn recent_log(&mut self) -> Result<Vec<LogLine>, RuntimeError> {
+        let reply = self.request(CMD_RECENT_LOG, &[])?;
+        read_log(&mut Reader::new(&reply)).map_err(protocol_error)
+    }
+
+    /// Runs one frame; returns the new AV info if the core changed it during the frame.
+    pub fn run_frame(
+        &mut self,
+        input: &InputSnapshot,
+        latest_frame: &Mutex<Option<VideoFrame>>,
+        audio: &AudioRingBuffer,
+    ) -> Result<Option<SystemAvInfo>, RuntimeError> {
+        let mut writer = Writer::default();
+        write_input(&mut writer, input);
+        let payload = writer.finish();
+        let reply = self.request(CMD_RUN_FRAME, &payload)?;
+        let mut reader = Reader::new(&reply);
+        let av_change = if reader.bool().map_err(protocol_error)? {
+            Some(read_av_info(&mut reader).map_err(protocol_error)?)
+        } else {
+            None
+        };
+
+        if let Some(frame) = self.shared.read_frame() {
+            self.pixel_format = frame.pixel_format;
+            let mut guard = latest_frame
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+            *guard = Some(frame);
+        }
+        let mut samples = std::mem::take(&mut self.audio_scratch);
+        self.shared.read_audio(&mut samples);
+        audio.push_samples(&samples);
+        self.audio_scratch = samples;
+        Ok(av_change)
+    }
+
+    pub fn set_port_device(&mut self, port: u32, device: u32) -> Result<(), RuntimeError> {
+        let payload = Writer::default().u32(port).u32(device).finish();
+        self.request(CMD_SET_PORT_DEVICE, &payload).map(|_| ())
+    }
+
+    pub fn set_fast_forwarding(&mut self, fast_forwarding: bool) -> Result<(), RuntimeError> {
+        let payload = Writer::default().bool(fast_forwarding).finish();
+        self.request(CMD_SET_FAST_FORWARDING, &payload).map(|_| ())
+    }
+
+    pub fn serialize_size(&mut self) -> Result<usize, RuntimeError> {
+        let reply = self.request(CMD_SERIALIZE_SIZE, &[])?;
+        Ok(Reader::new(&reply).u64().map_err(protocol_error)? as usize)
+    }
+
+    pub fn serialize(&mut self, context: SavestateContext) -> Result<Vec<u8>, RuntimeError> {
+        self.request(CMD_SERIALIZE, &[context as u8])
+    }
+
+    pub fn unserialize(
+        &mut self,
+        context: SavestateContext,
+        data: &[u8],
+    ) -> Result<(), RuntimeError> {
+        let payload = Writer::default().u8(context as u8).bytes(data).finish();
+        self.request(CMD_UNSERIALIZE, &payload).map(|_| ())
+    }
+
+    pub fn set_core_option(&mut self, key: &str, value: &str) -> Result<(), RuntimeError> {
+        let payload = Writer::default().str(key).str(value).finish();
+        self.request(CMD_SET_CORE_OPTION, &payload).map(|_| ())
+    }
+
+    pub fn core_options(
+        &mut self,
+    ) -> Result<(Vec<CoreOption>, HashMap<String, String>), RuntimeError> {
+        let reply = self.request(CMD_CORE_OPTIONS, &[])?;
+        read_core_options(&mut Reader::new(&reply)).map_err(protocol_error)
+    }
+
+    pub fn read_memory(&mut self, region: MemoryRegion) -> Result<Option<Vec<u8>>, RuntimeError> {
+        let payload = Writer::default().u32(region as u32).finish();
+        let reply = self.request(CMD_READ_MEMORY, &payload)?;
+        let mut reader = Reader::new(&reply);
+        if reader.bool().map_err(protocol_error)? {
+            Ok(Some(reader.bytes().map_err(protocol_error)?.to_vec()))
+        } else {
+            Ok(None)
+        }
+    }
+
+    pub fn write_memory(&mut self, region: MemoryRegion, data: &[u8]) -> Result<(), RuntimeError> {
+        let payload = Writer::default().u32(region as u32).bytes(data).finish();
+        self.request(CMD_WRITE_MEMORY, &payload).map(|_| ())
+    }
+
+    fn request(&mut self, command: u8, payload: &[u8]) -> Result<Vec<u8>, RuntimeError> {
+        if let Some(reason) = &self.crashed {
+            return Err(RuntimeError::CoreCrashed(reason.clone()));
+        }
+        let sent = match self.commands.as_mut() {
+            Some(commands) => write_message(commands, command, payload),
+            None => Err(io::Error::from(io::ErrorKind::BrokenPipe)),
+        };
+        if sent.is_err() {
+            return Err(RuntimeError::CoreCrashed(self.reap(None)));
+        }
+        match self.replies.recv_timeout(self.reply_timeout) {
+            Ok(Ok((REPLY_OK, reply))) => Ok(reply),
+            Ok(Ok((_, reply))) => Err(RuntimeError::CoreHost(
+                String::from_utf8_lossy(&reply).into_owned(),
+            )),
+            Ok(Err(_)) | Err(RecvTimeoutError::Disconnected) => {
+                Err(RuntimeError::CoreCrashed(self.reap(None)))
+            }
+            Err(RecvTimeoutError::Timeout) => {
+                let reason = format!(
+                    "core host did not reply within {} ms",
+                    self.reply_timeout.as_millis()
+                );
+                Err(RuntimeError::CoreCrashed(self.reap(Some(reason))))
+            }
+        }
+    }
+
+    /// Kills the helper and collects its exit status. `reason` replaces the status as what
+    /// later calls report, for helpers that were killed for hanging.
+    fn reap(&mut self, reason: Option<String>) -> String {
+        self.commands = None;
+        let _ = self.child.kill();
+        let status = match self.child.wait() {
+            Ok(status) => format!("core host exited ({status})"),
+            Err(err) => format!("core host was lost: {err}"),
+        };
+        let reason = reason.unwrap_or(status);
+        self.crashed = Some(reason.clone());
+        reason
+    }
+}
+
+impl Drop for CoreHost {
+    fn drop(&mut self) {
+        // Closing stdin asks the helper to unload the core and exit on its own.
+        self.commands = None;
+        let deadline = Instant::now() + SHUTDOWN_GRACE;
+        loop {
+            match self.child.try_wait() {
+                Ok(Some(_)) => break,
+                Ok(None) if Instant::now() < deadline => {
+                    std::thread::sleep(Duration::from_millis(10))
+                }
+                _ => {
+                    let _ = self.child.kill();
+                    let _ = self.child.wait();
+                    break;
+                }
+            }
+        }
+        let _ = std::fs::remove_file(&self.shared_path);
+    }
+}
+
+/// Reads replies off the helper's stdout until it closes or a message comes out malformed.
+fn spawn_reply_reader(mut stdout: ChildStdout) -> io::Result<Receiver<io::Result<Message>>> {
+    let (sender, replies) = mpsc::channel();
+    std::thread::Builder::new()
+        .name("core-host-replies".to_string())
+        .spawn(move || loop {
+            let message = read_message(&mut stdout);
+            let failed = message.is_err();
+            if sender.send(message).is_err() || failed {
+                break;
+            }
+        })?;
+    Ok(replies)
+}
+
+fn protocol_error(err: io::Error) -> RuntimeError {
+    RuntimeError::CoreHost(format!("malformed reply from core host: {err}"))
+}
+
+/// Helper-side state once a core has been loaded.
+struct HostedCore {
+    core: LibretroCore,
+    shared: Arc<Mutex<SharedMemory>>,
+    /// The input sent with the frame being run; the core latches it when it polls.
+    input: Arc<Mutex<InputSnapshot>>,
+}
+
+/// Entry point of the `playbyte_core_host` helper binary.
+///
+/// Serves commands from stdin until the app closes the pipe.
+pub fn serve() -> io::Result<()> {
+    // Core log output goes to stderr, which the app shares with the helper.
+    if log::set_logger(&StderrLog).is_ok() {
+        log::set_max_level(log::LevelFilter::Info);
+    }
+    let mut replies = reply_channel()?;
+    let mut commands = io::stdin().lock();
+    let mut hosted: Option<HostedCore> = None;
+
+    loop {
+        let (command, payload) = match read_message(&mut commands) {
+            Ok(message) => message,
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+            Err(err) => return Err(err),
+        };
+        match handle_command(&mut hosted, command, &payload) {
+            Ok(reply) => write_message(&mut replies, REPLY_OK, &reply)?,
+            Err(message) => write_message(&mut replies, REPLY_ERR, message.as_bytes())?,
+        }
+    }
+}
+
+struct StderrLog;
+
+impl log::Log for StderrLog {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= log::Level::Info
+    }
+
+    fn log(&self, record: &log::Record) {
+        if self.enabled(record.metadata()) {
+            eprintln!("[{} {}] {}", record.level(), record.target(), record.args());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+fn handle_command(
+    hosted: &mut Option<HostedCore>,
+    command: u8,
+    payload: &[u8],
+) -> Result<Vec<u8>, String> {
+    let mut reader = Reader::new(payload);
+    if command == CMD_INIT {
+        let core = init_core(&mut reader).map_err(|err| err.to_string())?;
+        let mut writer = Writer::default();
+        write_system_info(&mut writer, core.core.system_info());
+        *hosted = Some(core);
+        return Ok(writer.finish());
+    }
+
+    let hosted = hosted
+        .as_mut()
+        .ok_or_else(|| "core host has not been initialised".to_string())?;
+    match command {
+        CMD_LOAD_GAME => {
+            let path_or_name = reader.string().map_err(|err| err.to_string())?;
+            let in_memory = reader.bool().map_err(|err| err.to_string())?;
+            let loaded = if in_memory {
+                let data = reader.bytes().map_err(|err| err.to_string())?.to_vec();
+                hosted.core.load_game_from_memory(&path_or_name, data)
+            } else {
+                hosted.core.load_game(path_or_name)
+            };
+            match loaded {
+                Ok(()) => {}
+                // A refusal from the core itself is reported apart from other failures.
+                Err(LibretroError::LoadGame { .. }) => {
+                    return Ok(Writer::default().bool(false).finish())
+                }
+                Err(err) => return Err(err.to_string()),
+            }
+            let mut writer = Writer::default();
+            writer.bool(true);
+            write_av_info(&mut writer, &hosted.core.system_av_info());
+            writer.u64(hosted.core.serialization_quirks().0);
+            write_controller_info(&mut writer, &hosted.core.controller_info());
+            Ok(writer.finish())
+        }
+        CMD_RUN_FRAME => {
+            let input = read_input(&mut reader).map_err(|err| err.to_string())?;
+            *hosted
+                .input
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner()) = input;
+            hosted
+                .shared
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .begin_frame();
+            hosted.core.run_frame();
+            let av_change = hosted.core.take_av_change();
+            let mut writer = Writer::default();
+            writer.bool(av_change.is_some());
+            if let Some(info) = &av_change {
+                write_av_info(&mut writer, info);
+            }
+            Ok(writer.finish())
+        }
+        CMD_SERIALIZE => {
+            let context = reader.u8().map_err(|err| err.to_string())?;
+            hosted
+                .core
+                .set_savestate_context(savestate_context_from_u8(context));
+            let state = hosted.core.serialize();
+            hosted.core.set_savestate_context(SavestateContext::Normal);
+            state.map_err(|err| err.to_string())
+        }
+        CMD_UNSERIALIZE => {
+            let context = reader.u8().map_err(|err| err.to_string())?;
+            let data = reader.bytes().map_err(|err| err.to_string())?;
+            hosted
+                .core
+                .set_savestate_context(savestate_context_from_u8(context));
+            let loaded = hosted.core.unserialize(data);
+            hosted.core.set_savestate_context(SavestateContext::Normal);
+            loaded.map_err(|err| err.to_string())?;
+            Ok(Vec::new())
+        }
+        CMD_SET_CORE_OPTION => {
+            let key = reader.string().map_err(|err| err.to_string())?;
+            let value = reader.string().map_err(|err| err.to_string())?;
+            hosted
+                .core
+                .set_core_option(&key, &value)
+                .map_err(|err| err.to_string())?;
+            Ok(Vec::new())
+        }
+        CMD_CORE_OPTIONS => {
+            let mut writer = Writer::default();
+            write_core_options(
+                &mut writer,
+                &hosted.core.core_options(),
+                &hosted.core.core_option_values(),
+            );
+            Ok(writer.finish())
+        }
+        CMD_READ_MEMORY => {
+            let region = read_memory_region(&mut reader)?;
+            let mut writer = Writer::default();
+            match hosted.core.memory(region) {
+                Some(memory) => writer.bool(true).bytes(memory),
+                None => writer.bool(false),
+            };
+            Ok(writer.finish())
+        }
+        CMD_WRITE_MEMORY => {
+            let region = read_memory_region(&mut reader)?;
+            let data = reader.bytes().map_err(|err| err.to_string())?;
+            hosted
+                .core
+                .write_memory(region, data)
+                .map_err(|err| err.to_string())?;
+            Ok(Vec::new())
+        }
+        CMD_SET_PORT_DEVICE => {
+            let port = reader.u32().map_err(|err| err.to_string())?;
+            let device = reader.u32().map_err(|err| err.to_string())?;
+            // The port assignments reach the input callback with the next frame's input.
+            hosted.core.set_controller_port_device(port, device);
+            Ok(Vec::new())
+        }
+        CMD_SET_FAST_FORWARDING => {
+            let fast_forwarding = reader.bool().map_err(|err| err.to_string())?;
+            hosted.core.set_fast_forwarding(fast_forwarding);
+            Ok(Vec::new())
+        }
+        CMD_SERIALIZE_SIZE => Ok(Writer::default()
+            .u64(hosted.core.serialize_size() as u64)
+            .finish()),
+        CMD_RECENT_LOG => {
+            let mut writer = Writer::default();
+            write_log(&mut writer, &hosted.core.recent_log());
+            Ok(writer.finish())
+        }
+        other => Err(format!("unknown core host command {other}")),
+    }
+}
+
+fn read_memory_region(reader: &mut Reader) -> Result<MemoryRegion, String> {
+    let value = reader.u32().map_err(|err| err.to_string())?;
+    memory_region_from_u32(value).ok_or_else(|| format!("unknown memory region {value}"))
+}
+
+fn init_core(reader: &mut Reader) -> Result<HostedCore, Box<dyn std::error::Error>> {
+    let core_path = reader.string()?;
+    let shared_path = reader.string()?;
+    let system_directory = reader.opt_string()?;
+    let save_directory = reader.opt_string()?;
+    let option_count = reader.u32()? as usize;
+    let mut core_options = HashMap::with_capacity(option_count);
+    for _ in 0..option_count {
+        let key = reader.string()?;
+        let value = reader.string()?;
+        core_options.insert(key, value);
+    }
+
+    let shared = Arc::new(Mutex::new(SharedMemory::open(Path::new(&shared_path))?));
+    let input = Arc::new(Mutex::new(InputSnapshot::default()));
+    let video_shared = Arc::clone(&shared);
+    let audio_shared = Arc::clone(&shared);
+    let input_poll_cb = Arc::clone(&input);
+    let latched = Arc::new(Mutex::new(InputSnapshot::default()));
+    let latched_cb = Arc::clone(&latched);
+
+    let callbacks = Callbacks::new(
+        Box::new(move |data, width, height, pitch, format| {
+            video_shared
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .write_frame(data, width, height, pitch, format);
+        }),
+        Box::new(move |samples| {
+            audio_shared
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .push_audio(samples);
+        }),
+        Box::new(move || {
+            let mut pending = input_poll_cb
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+            *latched
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner()) = *pending;
+            pending.mouse.clear_motion();
+        }),
+        Box::new(move |port, device, index, id| {
+            latched_cb
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .value(port, device, index, id)
+        }),
+    )
+    .with_core_options(core_options)
+    .with_input_bitmasks();
+    let callbacks = RuntimeConfig {
+        system_directory: system_directory.map(PathBuf::from),
+        save_directory: save_directory.map(PathBuf::from),
+        ..RuntimeConfig::default()
+    }
+    .apply_directories(callbacks);
+
+    let core = LibretroCore::load(core_path, callbacks)?;
+    Ok(HostedCore {
+        core,
+        shared,
+        input,
+    })
+}
+
+/// Returns the stream replies are written to.
+///
+/// Cores are free to print to stdout, so the protocol moves to a private copy of the
+/// descriptor and stdout itself is pointed at stderr. That needs Unix descriptors; elsewhere
+/// a core's output would land in the middle of replies, so the helper refuses to run.
+#[cfg(unix)]
+fn reply_channel() -> io::Result<Box<dyn Write>> {
+    use std::os::unix::io::FromRawFd;
+
+    unsafe {
+        let fd = libc::dup(libc::STDOUT_FILENO);
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        if libc::dup2(libc::STDERR_FILENO, libc::STDOUT_FILENO) < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Box::new(File::from_raw_fd(fd)))
+    }
+}
+
+#[cfg(not(unix))]
+fn reply_channel() -> io::Result<Box<dyn Write>> {
+    Err(unsupported_platform())
+}
+
+fn unsupported_platform() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        "isolated cores are only supported on Unix",
+    )
+}
@@ -0,0 +1,254 @@
+//! Checks that a core plays the same input the same way every time.
+//!
+//! Bytes, rewind, run-ahead, movies and netplay all count on a restored state playing out
+//! exactly as it did the first time. `verify_determinism` plays a game for a few thousand
+//! frames on random input, hashing the picture, the audio and the state after every frame,
+//! and saves states at random checkpoints. A second instance of the core then plays the
+//! same input from power-on. Each time it reaches a checkpoint it jumps back to one of the
+//! states saved a little earlier and plays forward from there again. Every frame it plays
+//! has to hash the same as the first time, and the first one that does not is reported.
+
+use crate::{fnv1a, EmulatorRuntime, GameContent, InputSnapshot, RuntimeConfig, RuntimeError};
+use std::{fmt, path::Path};
+
+/// Every joypad button, as `JoypadState::bits` packs them.
+const ALL_BUTTONS: u16 = 0x0fff;
+/// The longest a random input is held for, in frames.
+const MAX_HOLD: u64 = 30;
+/// How many checkpoints back a jump may go.
+const MAX_JUMP: usize = 4;
+
+#[derive(Debug, Clone)]
+pub struct DeterminismConfig {
+    /// Frames in the first run; the second plays more, counting the ones it plays again.
+    pub frames: u32,
+    /// Seeds the input and the checkpoints, so a seed always checks the same way.
+    pub seed: u64,
+    /// Average frames between checkpoints.
+    pub checkpoint_interval: u32,
+    /// How both instances are loaded. Rewind and run-ahead are left off.
+    pub runtime: RuntimeConfig,
+}
+
+impl Default for DeterminismConfig {
+    fn default() -> Self {
+        Self {
+            frames: 3000,
+            seed: 1,
+            checkpoint_interval: 240,
+            runtime: RuntimeConfig::default(),
+        }
+    }
+}
+
+/// What came out different on a divergent frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DivergenceKind {
+    Video,
+    Audio,
+    State,
+}
+
+impl fmt::Display for DivergenceKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Video => "video",
+            Self::Audio => "audio",
+            Self::State => "state",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Divergence {
+    /// The frame that came out different, counting from 1 at power-on.
+    pub frame: u32,
+    pub kind: DivergenceKind,
+    /// The frame whose state was restored last before it, or `None` when the game had
+    /// played straight through from power-on.
+    pub restored_from: Option<u32>,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} differs at frame {}", self.kind, self.frame)?;
+        match self.restored_from {
+            Some(frame) => write!(f, " after restoring the state from frame {frame}"),
+            None => write!(f, " playing from power-on"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DeterminismReport {
+    /// Frames the second instance played and compared, counting the ones played again.
+    pub frames_compared: u64,
+    /// States the second instance jumped back to.
+    pub restores: u32,
+    /// The first frame that came out different, if any did.
+    pub divergence: Option<Divergence>,
+}
+
+impl DeterminismReport {
+    pub fn is_deterministic(&self) -> bool {
+        self.divergence.is_none()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct FrameHashes {
+    video: u64,
+    audio: u64,
+    state: u64,
+}
+
+impl FrameHashes {
+    /// What differs between two plays of the same frame, picture first.
+    fn compare(&self, other: &Self) -> Option<DivergenceKind> {
+        if self.video != other.video {
+            Some(DivergenceKind::Video)
+        } else if self.audio != other.audio {
+            Some(DivergenceKind::Audio)
+        } else if self.state != other.state {
+            Some(DivergenceKind::State)
+        } else {
+            None
+        }
+    }
+}
+
+/// xorshift64; determinism checks have to be repeatable, not random.
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Self(seed | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// A number in `0..bound`; `bound` must not be zero.
+    fn below(&mut self, bound: u64) -> u64 {
+        self.next() % bound
+    }
+}
+
+/// Plays `content` on the core at `core_path` twice as described in the module docs and
+/// reports the first frame that came out different. Errors are the core failing to load,
+/// run or save and restore states, which is not a divergence.
+pub fn verify_determinism(
+    core_path: &Path,
+    content: GameContent,
+    config: &DeterminismConfig,
+) -> Result<DeterminismReport, RuntimeError> {
+    let runtime_config = RuntimeConfig {
+        rewind: None,
+        run_ahead: None,
+        ..config.runtime.clone()
+    };
+    let mut rng = Rng::new(config.seed);
+    let inputs = random_inputs(config.frames, &mut rng);
+    let checkpoints = checkpoints(config, &mut rng);
+
+    let mut first =
+        EmulatorRuntime::with_content(core_path, content.clone(), runtime_config.clone())?;
+    let mut expected = Vec::with_capacity(inputs.len());
+    let mut saved = Vec::with_capacity(checkpoints.len());
+    for &input in &inputs {
+        expected.push(play(&mut first, input)?);
+        let frame = expected.len() as u32;
+        if checkpoints.contains(&frame) {
+            saved.push((frame, first.serialize()?));
+        }
+    }
+    drop(first);
+
+    let mut second = EmulatorRuntime::with_content(core_path, content, runtime_config)?;
+    let mut report = DeterminismReport::default();
+    let mut frame = 0;
+    let mut restored_from = None;
+    // The checkpoint the second instance jumps back from next.
+    let mut next = 0;
+    while let Some(&input) = inputs.get(frame as usize) {
+        let hashes = play(&mut second, input)?;
+        report.frames_compared += 1;
+        if let Some(kind) = expected[frame as usize].compare(&hashes) {
+            report.divergence = Some(Divergence {
+                frame: frame + 1,
+                kind,
+                restored_from,
+            });
+            return Ok(report);
+        }
+        frame += 1;
+        if saved.get(next).is_some_and(|(at, _)| *at == frame) {
+            if next > 0 {
+                let back =
+                    next.saturating_sub(MAX_JUMP) + rng.below(next.min(MAX_JUMP) as u64) as usize;
+                let (at, state) = &saved[back];
+                second.unserialize(state)?;
+                frame = *at;
+                restored_from = Some(*at);
+                report.restores += 1;
+            }
+            next += 1;
+        }
+    }
+    Ok(report)
+}
+
+/// Joypad input for ports 0 and 1, each combination held for a random while.
+fn random_inputs(frames: u32, rng: &mut Rng) -> Vec<InputSnapshot> {
+    let mut inputs = Vec::with_capacity(frames as usize);
+    let mut input = InputSnapshot::default();
+    let mut held_for = 0;
+    for _ in 0..frames {
+        if held_for == 0 {
+            input.joypads[0] = rng.next() as u16 & ALL_BUTTONS;
+            input.joypads[1] = rng.next() as u16 & ALL_BUTTONS;
+            held_for = 1 + rng.below(MAX_HOLD);
+        }
+        held_for -= 1;
+        inputs.push(input);
+    }
+    inputs
+}
+
+/// The frames to save states after, between half and one and a half intervals apart.
+fn checkpoints(config: &DeterminismConfig, rng: &mut Rng) -> Vec<u32> {
+    let interval = u64::from(config.checkpoint_interval.max(2));
+    let mut checkpoints = Vec::new();
+    let mut frame = 0;
+    loop {
+        frame += interval / 2 + rng.below(interval);
+        match u32::try_from(frame) {
+            Ok(frame) if frame < config.frames => checkpoints.push(frame),
+            _ => return checkpoints,
+        }
+    }
+}
+
+/// Runs a frame and hashes what came out of it, draining the audio it queued.
+fn play(runtime: &mut EmulatorRuntime, input: InputSnapshot) -> Result<FrameHashes, RuntimeError> {
+    runtime.run_frame_with_input(input, false)?;
+    let video = runtime
+        .latest_frame()
+        .map_or(0, |frame| fnv1a(&frame.to_rgba()));
+    let audio = runtime.audio_buffer();
+    let mut samples = vec![0; audio.len()];
+    audio.pop_samples(&mut samples);
+    let samples: Vec<u8> = samples
+        .iter()
+        .flat_map(|sample| sample.to_le_bytes())
+        .collect();
+    Ok(FrameHashes {
+        video,
+        audio: fnv1a(&samples),
+        state: fnv1a(&runtime.serialize()?),
+    })
+}
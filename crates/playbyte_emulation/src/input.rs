@@ -0,0 +1,318 @@
+//! Input devices beyond the joypad, and the per-port state the runtime answers
+//! `retro_input_state` from.
+//!
+//! Only the base class of a device id (its low byte) decides how a port is answered, so
+//! core-specific subclasses such as a "Super Scope" lightgun behave like their base device.
+
+use crate::{
+    JoypadState, MAX_PORTS, RETRO_DEVICE_ANALOG, RETRO_DEVICE_ID_JOYPAD_MASK, RETRO_DEVICE_JOYPAD,
+    RETRO_DEVICE_LIGHTGUN, RETRO_DEVICE_MOUSE, RETRO_DEVICE_NONE, RETRO_DEVICE_POINTER,
+};
+
+pub const RETRO_DEVICE_INDEX_ANALOG_LEFT: u32 = 0;
+pub const RETRO_DEVICE_INDEX_ANALOG_RIGHT: u32 = 1;
+pub const RETRO_DEVICE_INDEX_ANALOG_BUTTON: u32 = 2;
+pub const RETRO_DEVICE_ID_ANALOG_X: u32 = 0;
+pub const RETRO_DEVICE_ID_ANALOG_Y: u32 = 1;
+
+pub const RETRO_DEVICE_ID_MOUSE_X: u32 = 0;
+pub const RETRO_DEVICE_ID_MOUSE_Y: u32 = 1;
+pub const RETRO_DEVICE_ID_MOUSE_LEFT: u32 = 2;
+pub const RETRO_DEVICE_ID_MOUSE_RIGHT: u32 = 3;
+pub const RETRO_DEVICE_ID_MOUSE_WHEELUP: u32 = 4;
+pub const RETRO_DEVICE_ID_MOUSE_WHEELDOWN: u32 = 5;
+pub const RETRO_DEVICE_ID_MOUSE_MIDDLE: u32 = 6;
+
+pub const RETRO_DEVICE_ID_LIGHTGUN_SCREEN_X: u32 = 13;
+pub const RETRO_DEVICE_ID_LIGHTGUN_SCREEN_Y: u32 = 14;
+pub const RETRO_DEVICE_ID_LIGHTGUN_IS_OFFSCREEN: u32 = 15;
+pub const RETRO_DEVICE_ID_LIGHTGUN_TRIGGER: u32 = 2;
+pub const RETRO_DEVICE_ID_LIGHTGUN_RELOAD: u32 = 16;
+pub const RETRO_DEVICE_ID_LIGHTGUN_AUX_A: u32 = 3;
+
+pub const RETRO_DEVICE_ID_POINTER_X: u32 = 0;
+pub const RETRO_DEVICE_ID_POINTER_Y: u32 = 1;
+pub const RETRO_DEVICE_ID_POINTER_PRESSED: u32 = 2;
+pub const RETRO_DEVICE_ID_POINTER_COUNT: u32 = 3;
+
+/// Pointer and lightgun coordinates run from `-POINTER_RANGE` to `POINTER_RANGE` across the
+/// game viewport.
+pub const POINTER_RANGE: i16 = 0x7fff;
+
+/// The base device class of a device id, e.g. `RETRO_DEVICE_LIGHTGUN` for a Super Scope.
+pub fn device_base(device: u32) -> u32 {
+    device & 0xff
+}
+
+/// Analog sticks, in libretro's range where negative is left and up.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct AnalogState {
+    pub left_x: i16,
+    pub left_y: i16,
+    pub right_x: i16,
+    pub right_y: i16,
+}
+
+impl AnalogState {
+    /// Sets one stick axis from a -1.0..=1.0 value.
+    pub fn set_axis(&mut self, index: u32, id: u32, value: f32) {
+        let value = (value.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+        match (index, id) {
+            (RETRO_DEVICE_INDEX_ANALOG_LEFT, RETRO_DEVICE_ID_ANALOG_X) => self.left_x = value,
+            (RETRO_DEVICE_INDEX_ANALOG_LEFT, RETRO_DEVICE_ID_ANALOG_Y) => self.left_y = value,
+            (RETRO_DEVICE_INDEX_ANALOG_RIGHT, RETRO_DEVICE_ID_ANALOG_X) => self.right_x = value,
+            (RETRO_DEVICE_INDEX_ANALOG_RIGHT, RETRO_DEVICE_ID_ANALOG_Y) => self.right_y = value,
+            _ => {}
+        }
+    }
+
+    fn value(&self, index: u32, id: u32) -> i16 {
+        match (index, id) {
+            (RETRO_DEVICE_INDEX_ANALOG_LEFT, RETRO_DEVICE_ID_ANALOG_X) => self.left_x,
+            (RETRO_DEVICE_INDEX_ANALOG_LEFT, RETRO_DEVICE_ID_ANALOG_Y) => self.left_y,
+            (RETRO_DEVICE_INDEX_ANALOG_RIGHT, RETRO_DEVICE_ID_ANALOG_X) => self.right_x,
+            (RETRO_DEVICE_INDEX_ANALOG_RIGHT, RETRO_DEVICE_ID_ANALOG_Y) => self.right_y,
+            _ => 0,
+        }
+    }
+}
+
+/// A relative mouse. Motion and wheel clicks add up until the core next polls.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct MouseState {
+    /// Motion since the last poll, in game pixels.
+    pub delta_x: i32,
+    pub delta_y: i32,
+    /// Wheel clicks since the last poll; positive is away from the user.
+    pub wheel: i32,
+    pub left: bool,
+    pub right: bool,
+    pub middle: bool,
+}
+
+impl MouseState {
+    pub fn move_by(&mut self, dx: i32, dy: i32) {
+        self.delta_x = self.delta_x.saturating_add(dx);
+        self.delta_y = self.delta_y.saturating_add(dy);
+    }
+
+    pub fn scroll(&mut self, clicks: i32) {
+        self.wheel = self.wheel.saturating_add(clicks);
+    }
+
+    /// Takes a `RETRO_DEVICE_ID_MOUSE_*` button id.
+    pub fn set_button(&mut self, id: u32, pressed: bool) {
+        match id {
+            RETRO_DEVICE_ID_MOUSE_LEFT => self.left = pressed,
+            RETRO_DEVICE_ID_MOUSE_RIGHT => self.right = pressed,
+            RETRO_DEVICE_ID_MOUSE_MIDDLE => self.middle = pressed,
+            _ => {}
+        }
+    }
+
+    /// Clears the motion and wheel clicks once the core has seen them.
+    pub(crate) fn clear_motion(&mut self) {
+        self.delta_x = 0;
+        self.delta_y = 0;
+        self.wheel = 0;
+    }
+
+    fn value(&self, id: u32) -> i16 {
+        match id {
+            RETRO_DEVICE_ID_MOUSE_X => clamp_i16(self.delta_x),
+            RETRO_DEVICE_ID_MOUSE_Y => clamp_i16(self.delta_y),
+            RETRO_DEVICE_ID_MOUSE_LEFT => self.left as i16,
+            RETRO_DEVICE_ID_MOUSE_RIGHT => self.right as i16,
+            RETRO_DEVICE_ID_MOUSE_MIDDLE => self.middle as i16,
+            RETRO_DEVICE_ID_MOUSE_WHEELUP => (self.wheel > 0) as i16,
+            RETRO_DEVICE_ID_MOUSE_WHEELDOWN => (self.wheel < 0) as i16,
+            _ => 0,
+        }
+    }
+}
+
+/// An absolute position over the game viewport, shared by the pointer and lightgun devices.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PointerState {
+    pub x: i16,
+    pub y: i16,
+    /// Whether the position is over the game rather than the letterbox bars or outside the
+    /// window.
+    pub inside: bool,
+    pub pressed: bool,
+}
+
+impl PointerState {
+    /// Places the pointer from coordinates relative to the game viewport, where 0.0 and 1.0
+    /// are its edges. Positions past the edges are clamped and reported as outside.
+    pub fn set_position(&mut self, x: f32, y: f32) {
+        self.inside = (0.0..=1.0).contains(&x) && (0.0..=1.0).contains(&y);
+        self.x = to_pointer_axis(x);
+        self.y = to_pointer_axis(y);
+    }
+
+    /// The pointer left the window.
+    pub fn leave(&mut self) {
+        self.inside = false;
+    }
+
+    fn value(&self, id: u32) -> i16 {
+        match id {
+            RETRO_DEVICE_ID_POINTER_X if self.inside => self.x,
+            RETRO_DEVICE_ID_POINTER_Y if self.inside => self.y,
+            RETRO_DEVICE_ID_POINTER_PRESSED => (self.inside && self.pressed) as i16,
+            RETRO_DEVICE_ID_POINTER_COUNT => (self.inside && self.pressed) as i16,
+            _ => 0,
+        }
+    }
+}
+
+/// The devices the frontend drives, and which device sits in each port. Each port has a
+/// joypad and analog sticks of its own; there is one mouse and one pointer.
+///
+/// The core never reads this directly: it sees an `InputSnapshot` taken when it polls.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InputState {
+    pub joypads: [JoypadState; MAX_PORTS],
+    pub analogs: [AnalogState; MAX_PORTS],
+    pub mouse: MouseState,
+    pub pointer: PointerState,
+    port_devices: [u32; MAX_PORTS],
+}
+
+impl Default for InputState {
+    fn default() -> Self {
+        Self {
+            joypads: Default::default(),
+            analogs: Default::default(),
+            mouse: MouseState::default(),
+            pointer: PointerState::default(),
+            port_devices: [RETRO_DEVICE_JOYPAD; MAX_PORTS],
+        }
+    }
+}
+
+impl InputState {
+    /// The device plugged into a port. Every port starts out with a joypad; ports past
+    /// `MAX_PORTS` have nothing.
+    pub fn port_device(&self, port: u32) -> u32 {
+        self.port_devices
+            .get(port as usize)
+            .copied()
+            .unwrap_or(RETRO_DEVICE_NONE)
+    }
+
+    pub(crate) fn set_port_device(&mut self, port: u32, device: u32) {
+        if let Some(plugged) = self.port_devices.get_mut(port as usize) {
+            *plugged = device;
+        }
+    }
+
+    /// Lets go of everything on a port's pad, e.g. when its controller is unplugged.
+    pub fn release_pad(&mut self, port: usize) {
+        if port < MAX_PORTS {
+            self.joypads[port] = JoypadState::default();
+            self.analogs[port] = AnalogState::default();
+        }
+    }
+
+    pub fn snapshot(&self) -> InputSnapshot {
+        InputSnapshot {
+            joypads: std::array::from_fn(|port| self.joypads[port].bits()),
+            analogs: self.analogs,
+            mouse: self.mouse,
+            pointer: self.pointer,
+            port_devices: self.port_devices,
+        }
+    }
+
+    /// Takes the snapshot the core sees next and consumes the relative mouse motion in it.
+    pub(crate) fn latch(&mut self) -> InputSnapshot {
+        let snapshot = self.snapshot();
+        self.mouse.clear_motion();
+        snapshot
+    }
+}
+
+/// Every port's input as the core sees it for one frame.
+///
+/// The runtime latches one from `InputState` when the core calls `retro_input_poll`, so
+/// input that changes while the frame runs waits for the next poll. Joypads are kept as
+/// `RETRO_DEVICE_ID_JOYPAD_MASK` bitmasks, which cores that ask for GET_INPUT_BITMASKS read
+/// whole.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InputSnapshot {
+    pub joypads: [u16; MAX_PORTS],
+    pub analogs: [AnalogState; MAX_PORTS],
+    pub mouse: MouseState,
+    pub pointer: PointerState,
+    pub port_devices: [u32; MAX_PORTS],
+}
+
+impl Default for InputSnapshot {
+    fn default() -> Self {
+        InputState::default().snapshot()
+    }
+}
+
+impl InputSnapshot {
+    /// Answers `retro_input_state`.
+    ///
+    /// Joypad and analog queries are answered from the port's own pad while it holds a
+    /// joypad or analog pad. Mouse and lightgun queries are answered on any port holding that
+    /// device, and the pointer on port 0 as well as on ports that hold a pointer.
+    pub fn value(&self, port: u32, device: u32, index: u32, id: u32) -> i16 {
+        let pad = port as usize;
+        let plugged = self
+            .port_devices
+            .get(pad)
+            .map_or(RETRO_DEVICE_NONE, |&device| device_base(device));
+        match device_base(device) {
+            RETRO_DEVICE_JOYPAD | RETRO_DEVICE_ANALOG
+                if plugged != RETRO_DEVICE_JOYPAD && plugged != RETRO_DEVICE_ANALOG =>
+            {
+                0
+            }
+            RETRO_DEVICE_JOYPAD if id == RETRO_DEVICE_ID_JOYPAD_MASK => self.joypads[pad] as i16,
+            RETRO_DEVICE_JOYPAD => self.button(pad, id),
+            RETRO_DEVICE_ANALOG if index == RETRO_DEVICE_INDEX_ANALOG_BUTTON => {
+                self.button(pad, id) * i16::MAX
+            }
+            RETRO_DEVICE_ANALOG => self.analogs[pad].value(index, id),
+            RETRO_DEVICE_MOUSE if plugged == RETRO_DEVICE_MOUSE => self.mouse.value(id),
+            RETRO_DEVICE_LIGHTGUN if plugged == RETRO_DEVICE_LIGHTGUN => self.lightgun_value(id),
+            RETRO_DEVICE_POINTER if port == 0 || plugged == RETRO_DEVICE_POINTER => {
+                self.pointer.value(id)
+            }
+            _ => 0,
+        }
+    }
+
+    fn button(&self, pad: usize, id: u32) -> i16 {
+        self.joypads[pad]
+            .checked_shr(id)
+            .map_or(0, |bits| (bits & 1) as i16)
+    }
+
+    /// The lightgun aims with the pointer and fires with the mouse buttons.
+    fn lightgun_value(&self, id: u32) -> i16 {
+        match id {
+            RETRO_DEVICE_ID_LIGHTGUN_SCREEN_X => self.pointer.x,
+            RETRO_DEVICE_ID_LIGHTGUN_SCREEN_Y => self.pointer.y,
+            RETRO_DEVICE_ID_LIGHTGUN_IS_OFFSCREEN => !self.pointer.inside as i16,
+            RETRO_DEVICE_ID_LIGHTGUN_TRIGGER => self.mouse.left as i16,
+            RETRO_DEVICE_ID_LIGHTGUN_RELOAD => self.mouse.right as i16,
+            RETRO_DEVICE_ID_LIGHTGUN_AUX_A => self.mouse.middle as i16,
+            _ => 0,
+        }
+    }
+}
+
+fn clamp_i16(value: i32) -> i16 {
+    value.clamp(i16::MIN as i32, i16::MAX as i32) as i16
+}
+
+fn to_pointer_axis(value: f32) -> i16 {
+    let value = value.clamp(0.0, 1.0) * 2.0 - 1.0;
+    (value * POINTER_RANGE as f32).round() as i16
+}
@@ -1,12 +1,50 @@
-use playbyte_libretro::{Callbacks, LibretroCore, LibretroError, RetroPixelFormat, VideoFrame};
+pub mod audio;
+pub mod core_host;
+pub mod determinism;
+pub mod input;
+pub mod movie;
+pub mod netplay;
+pub mod resample;
+pub mod rewind;
+pub mod run_ahead;
+pub mod thread;
+pub mod triple_buffer;
+
+pub use audio::{AudioRingBuffer, AudioStats};
+use core_host::CoreHost;
+pub use determinism::{
+    verify_determinism, DeterminismConfig, DeterminismReport, Divergence, DivergenceKind,
+};
+pub use input::{AnalogState, InputSnapshot, InputState, MouseState, PointerState};
+pub use movie::{Movie, MoviePlayer, MovieRecorder, MovieStart};
+pub use netplay::{
+    FrameAdvance, LoopbackConfig, LoopbackTransport, NetplayConfig, NetplayError, NetplaySession,
+    NetplayStats, Transport, UdpTransport,
+};
+pub use playbyte_libretro::MAX_PORTS;
+use playbyte_libretro::{
+    Callbacks, ControllerInfo, CoreOption, LibretroCore, LibretroError, LogLine, MemoryRegion,
+    RetroPixelFormat, SavestateContext, SerializationQuirks, SystemAvInfo, SystemInfo, VideoFrame,
+};
+pub use resample::AudioResampler;
+pub use rewind::{RewindBuffer, RewindConfig};
+use run_ahead::RunAhead;
+pub use run_ahead::RunAheadConfig;
 use std::{
-    collections::VecDeque,
-    path::Path,
+    collections::HashMap,
+    path::{Path, PathBuf},
     sync::{Arc, Mutex},
+    time::Duration,
 };
 use thiserror::Error;
+pub use thread::{EmulationEvent, EmulationThread, Pacing, RuntimeView};
 
+pub const RETRO_DEVICE_NONE: u32 = 0;
 pub const RETRO_DEVICE_JOYPAD: u32 = 1;
+pub const RETRO_DEVICE_MOUSE: u32 = 2;
+pub const RETRO_DEVICE_LIGHTGUN: u32 = 4;
+pub const RETRO_DEVICE_ANALOG: u32 = 5;
+pub const RETRO_DEVICE_POINTER: u32 = 6;
 pub const RETRO_DEVICE_ID_JOYPAD_B: u32 = 0;
 pub const RETRO_DEVICE_ID_JOYPAD_Y: u32 = 1;
 pub const RETRO_DEVICE_ID_JOYPAD_SELECT: u32 = 2;
@@ -19,8 +57,10 @@ pub const RETRO_DEVICE_ID_JOYPAD_A: u32 = 8;
 pub const RETRO_DEVICE_ID_JOYPAD_X: u32 = 9;
 pub const RETRO_DEVICE_ID_JOYPAD_L: u32 = 10;
 pub const RETRO_DEVICE_ID_JOYPAD_R: u32 = 11;
+/// Asks for every button of a joypad at once, as a bitmask indexed by button id.
+pub const RETRO_DEVICE_ID_JOYPAD_MASK: u32 = 256;
 
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
 pub struct JoypadState {
     pub a: bool,
     pub b: bool,
@@ -77,45 +117,21 @@ impl JoypadState {
             0
         }
     }
-}
 
-#[derive(Debug)]
-pub struct AudioRingBuffer {
-    inner: Mutex<VecDeque<i16>>,
-    capacity: usize,
-}
-
-impl AudioRingBuffer {
-    pub fn new(capacity: usize) -> Self {
-        Self {
-            inner: Mutex::new(VecDeque::with_capacity(capacity)),
-            capacity,
-        }
+    /// Packs the buttons into a mask indexed by `RETRO_DEVICE_ID_JOYPAD_*`.
+    pub fn bits(&self) -> u16 {
+        (RETRO_DEVICE_ID_JOYPAD_B..=RETRO_DEVICE_ID_JOYPAD_R)
+            .filter(|&id| self.value_for_id(id) != 0)
+            .fold(0, |mask, id| mask | (1 << id))
     }
 
-    pub fn push_samples(&self, samples: &[i16]) {
-        let mut guard = match self.inner.lock() {
-            Ok(guard) => guard,
-            Err(poisoned) => poisoned.into_inner(),
-        };
-
-        for &sample in samples {
-            if guard.len() == self.capacity {
-                guard.pop_front();
-            }
-            guard.push_back(sample);
-        }
-    }
-
-    pub fn pop_samples(&self, out: &mut [i16]) {
-        let mut guard = match self.inner.lock() {
-            Ok(guard) => guard,
-            Err(poisoned) => poisoned.into_inner(),
-        };
-
-        for sample in out.iter_mut() {
-            *sample = guard.pop_front().unwrap_or(0);
+    /// The inverse of `bits`.
+    pub fn from_bits(bits: u16) -> Self {
+        let mut state = Self::default();
+        for id in RETRO_DEVICE_ID_JOYPAD_B..=RETRO_DEVICE_ID_JOYPAD_R {
+            state.set_button(id, bits & (1 << id) != 0);
         }
+        state
     }
 }
 
@@ -129,14 +145,123 @@ pub enum RuntimeError {
         rom_ext: String,
         valid_extensions: String,
     },
+    #[error("failed to start core host: {0}")]
+    CoreHostSpawn(std::io::Error),
+    #[error("core host error: {0}")]
+    CoreHost(String),
+    #[error("core crashed: {0}")]
+    CoreCrashed(String),
+    #[error("rewind buffer error: {0}")]
+    Rewind(std::io::Error),
+    #[error("no game is loaded")]
+    NoGame,
+    #[error("movie was recorded on {expected}, but this game runs on {found}")]
+    MovieMismatch { expected: String, found: String },
+    #[error("state was saved when the core reported {saved} bytes, but it now reports {current}")]
+    SerializeSizeMismatch { saved: u64, current: u64 },
+}
+
+impl RuntimeError {
+    /// What the core logged before a failed load, oldest first; empty for other errors.
+    pub fn core_log(&self) -> &[LogLine] {
+        match self {
+            Self::Libretro(err) => err.core_log(),
+            _ => &[],
+        }
+    }
+}
+
+/// Host-side settings applied while a core is being loaded.
+#[derive(Debug, Clone, Default)]
+pub struct RuntimeConfig {
+    /// Core option values to use instead of the core defaults, keyed by option key.
+    pub core_options: HashMap<String, String>,
+    /// Run the core in a separate `playbyte_core_host` process so a crash inside the core
+    /// surfaces as `RuntimeError::CoreCrashed` instead of aborting the app. Unix only;
+    /// elsewhere loading fails with `RuntimeError::CoreHostSpawn`.
+    pub isolate_core: bool,
+    /// Helper binary to use for isolated cores; see `core_host::locate_core_host`.
+    pub core_host: Option<PathBuf>,
+    /// How long an isolated core may take over a command before it is killed as hung;
+    /// `None` means `core_host::REPLY_TIMEOUT`.
+    pub core_host_timeout: Option<Duration>,
+    /// Answer for GET_SYSTEM_DIRECTORY; cores look for BIOS and boot ROMs here.
+    pub system_directory: Option<PathBuf>,
+    /// Answer for GET_SAVE_DIRECTORY, for cores that write save files on their own.
+    pub save_directory: Option<PathBuf>,
+    /// Keep savestates while the game runs so `EmulatorRuntime::rewind_step` can go back.
+    pub rewind: Option<RewindConfig>,
+    /// Show frames from ahead of the game to hide its input lag; see `run_ahead`.
+    pub run_ahead: Option<RunAheadConfig>,
+}
+
+impl RuntimeConfig {
+    fn apply_directories(&self, mut callbacks: Callbacks) -> Callbacks {
+        if let Some(path) = &self.system_directory {
+            callbacks = callbacks.with_system_directory(path);
+        }
+        if let Some(path) = &self.save_directory {
+            callbacks = callbacks.with_save_directory(path);
+        }
+        callbacks
+    }
+}
+
+/// What to load into a core.
+#[derive(Debug, Clone)]
+pub enum GameContent {
+    Path(PathBuf),
+    /// Content that is not a file of its own, such as a ROM extracted from an archive.
+    /// `name` stands in for the path, so its extension should match the content.
+    Memory {
+        name: String,
+        data: Vec<u8>,
+    },
+}
+
+impl GameContent {
+    fn extension(&self) -> Option<String> {
+        match self {
+            Self::Path(path) => rom_extension(path),
+            Self::Memory { name, .. } => rom_extension(Path::new(name)),
+        }
+    }
+}
+
+/// A mid-game change of the core's AV info, from SET_GEOMETRY or SET_SYSTEM_AV_INFO.
+#[derive(Debug, Clone, Copy)]
+pub struct AvInfoChange {
+    pub av_info: SystemAvInfo,
+    /// The frame rate or sample rate changed too, not just the geometry.
+    pub timing_changed: bool,
+}
+
+enum CoreBackend {
+    InProcess(LibretroCore),
+    Isolated(Mutex<CoreHost>),
+}
+
+/// What the runtime does with the input of each frame it runs.
+enum MovieHook {
+    Recording(MovieRecorder),
+    Playing(MoviePlayer),
 }
 
 pub struct EmulatorRuntime {
-    core: LibretroCore,
-    input_state: Arc<Mutex<JoypadState>>,
+    backend: CoreBackend,
+    system_info: SystemInfo,
+    input_state: Arc<Mutex<InputState>>,
+    /// The input the core last polled.
+    latched: Arc<Mutex<InputSnapshot>>,
+    /// Input the core sees in place of `input_state` while a movie plays.
+    forced_input: Arc<Mutex<Option<InputSnapshot>>>,
+    movie: Option<MovieHook>,
+    run_ahead: Option<RunAhead>,
     audio: Arc<AudioRingBuffer>,
     latest_frame: Arc<Mutex<Option<VideoFrame>>>,
-    fps: f64,
+    av_info: SystemAvInfo,
+    av_change: Option<AvInfoChange>,
+    rewind: Option<RewindBuffer>,
 }
 
 impl EmulatorRuntime {
@@ -144,11 +269,60 @@ impl EmulatorRuntime {
         core_path: impl AsRef<Path>,
         rom_path: impl AsRef<Path>,
     ) -> Result<Self, RuntimeError> {
-        let rom_extension = rom_path
-            .as_ref()
-            .extension()
-            .and_then(|ext| ext.to_str())
-            .map(|ext| ext.to_ascii_lowercase());
+        Self::with_config(core_path, rom_path, RuntimeConfig::default())
+    }
+
+    pub fn with_config(
+        core_path: impl AsRef<Path>,
+        rom_path: impl AsRef<Path>,
+        config: RuntimeConfig,
+    ) -> Result<Self, RuntimeError> {
+        let content = GameContent::Path(rom_path.as_ref().to_path_buf());
+        Self::with_content(core_path, content, config)
+    }
+
+    pub fn with_content(
+        core_path: impl AsRef<Path>,
+        content: GameContent,
+        config: RuntimeConfig,
+    ) -> Result<Self, RuntimeError> {
+        let core_path = core_path.as_ref();
+        let Some(run_ahead) = config.run_ahead else {
+            return Self::load(core_path, content, config);
+        };
+        let ahead_config = RuntimeConfig {
+            rewind: None,
+            run_ahead: None,
+            ..config.clone()
+        };
+        let ahead_content = run_ahead.second_instance.then(|| content.clone());
+        let mut runtime = Self::load(core_path, content, config)?;
+        let Some(run_ahead) =
+            run_ahead::effective_config(run_ahead, runtime.serialization_quirks())
+        else {
+            return Ok(runtime);
+        };
+        let frames = run_ahead.frames;
+        runtime.run_ahead = Some(match ahead_content.filter(|_| run_ahead.second_instance) {
+            Some(content) => RunAhead::SecondInstance {
+                frames,
+                ahead: Box::new(Self::load(core_path, content, ahead_config)?),
+            },
+            None => RunAhead::SingleInstance { frames },
+        });
+        Ok(runtime)
+    }
+
+    fn load(
+        core_path: &Path,
+        content: GameContent,
+        config: RuntimeConfig,
+    ) -> Result<Self, RuntimeError> {
+        if config.isolate_core {
+            return Self::spawn_isolated(core_path, &content, config);
+        }
+
+        let rom_extension = content.extension();
 
         let latest_frame = Arc::new(Mutex::new(None));
         let latest_frame_cb = Arc::clone(&latest_frame);
@@ -156,13 +330,19 @@ impl EmulatorRuntime {
         let audio = Arc::new(AudioRingBuffer::new(48_000 * 2));
         let audio_cb = Arc::clone(&audio);
 
-        let input_state = Arc::new(Mutex::new(JoypadState::default()));
-        let input_cb = Arc::clone(&input_state);
+        let input_state = Arc::new(Mutex::new(InputState::default()));
+        let input_poll_cb = Arc::clone(&input_state);
+        let latched = Arc::new(Mutex::new(InputSnapshot::default()));
+        let latched_poll_cb = Arc::clone(&latched);
+        let latched_cb = Arc::clone(&latched);
+        let forced_input = Arc::new(Mutex::new(None));
+        let forced_input_cb = Arc::clone(&forced_input);
 
         let callbacks = Callbacks::new(
             Box::new(move |data, width, height, pitch, format| {
-                let mut guard =
-                    latest_frame_cb.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+                let mut guard = latest_frame_cb
+                    .lock()
+                    .unwrap_or_else(|poisoned| poisoned.into_inner());
                 *guard = Some(VideoFrame {
                     width,
                     height,
@@ -174,62 +354,402 @@ impl EmulatorRuntime {
             Box::new(move |samples| {
                 audio_cb.push_samples(samples);
             }),
-            Box::new(|| {}),
-            Box::new(move |port, device, _index, id| {
-                if port != 0 || device != RETRO_DEVICE_JOYPAD {
-                    return 0;
-                }
-                let guard = input_cb.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
-                guard.value_for_id(id)
+            Box::new(move || {
+                let forced = *forced_input_cb
+                    .lock()
+                    .unwrap_or_else(|poisoned| poisoned.into_inner());
+                let snapshot = forced.unwrap_or_else(|| {
+                    input_poll_cb
+                        .lock()
+                        .unwrap_or_else(|poisoned| poisoned.into_inner())
+                        .latch()
+                });
+                *latched_poll_cb
+                    .lock()
+                    .unwrap_or_else(|poisoned| poisoned.into_inner()) = snapshot;
+            }),
+            Box::new(move |port, device, index, id| {
+                let guard = latched_cb
+                    .lock()
+                    .unwrap_or_else(|poisoned| poisoned.into_inner());
+                guard.value(port, device, index, id)
             }),
-        );
+        )
+        .with_core_options(config.core_options.clone())
+        .with_input_bitmasks();
+        let callbacks = config.apply_directories(callbacks);
 
         if let Some(ext) = rom_extension.as_deref() {
-            let info = LibretroCore::probe_system_info(core_path.as_ref())?;
-            if !core_supports_extension(&info.valid_extensions, ext) {
-                return Err(RuntimeError::IncompatibleRom {
-                    core: info.library_name.clone(),
-                    rom_ext: ext.to_string(),
-                    valid_extensions: info.valid_extensions.clone(),
-                });
-            }
+            let info = LibretroCore::probe_system_info(core_path)?;
+            check_rom_extension(&info, ext)?;
         }
 
         let mut core = LibretroCore::load(core_path, callbacks)?;
-        core.load_game(rom_path)?;
-        let av_info = core.system_av_info();
-        let fps = if av_info.timing.fps.is_finite()
-            && av_info.timing.fps >= 1.0
-            && av_info.timing.fps <= 240.0
-        {
-            av_info.timing.fps
-        } else {
-            60.0
-        };
+        match content {
+            GameContent::Path(path) => core.load_game(path)?,
+            GameContent::Memory { name, data } => core.load_game_from_memory(&name, data)?,
+        }
 
         Ok(Self {
-            core,
+            system_info: core.system_info().clone(),
+            av_info: core.system_av_info(),
+            backend: CoreBackend::InProcess(core),
             input_state,
+            latched,
+            forced_input,
+            movie: None,
+            run_ahead: None,
             audio,
             latest_frame,
-            fps,
+            av_change: None,
+            rewind: config.rewind.map(RewindBuffer::new),
+        })
+    }
+
+    fn spawn_isolated(
+        core_path: &Path,
+        content: &GameContent,
+        config: RuntimeConfig,
+    ) -> Result<Self, RuntimeError> {
+        let host_binary = core_host::locate_core_host(config.core_host.as_deref());
+        let mut host = CoreHost::spawn(&host_binary, core_path, &config)?;
+        if let Some(ext) = content.extension() {
+            check_rom_extension(host.system_info(), &ext)?;
+        }
+        let av_info = host.load_game(content)?;
+
+        Ok(Self {
+            system_info: host.system_info().clone(),
+            backend: CoreBackend::Isolated(Mutex::new(host)),
+            input_state: Arc::new(Mutex::new(InputState::default())),
+            latched: Arc::new(Mutex::new(InputSnapshot::default())),
+            forced_input: Arc::new(Mutex::new(None)),
+            movie: None,
+            run_ahead: None,
+            audio: Arc::new(AudioRingBuffer::new(48_000 * 2)),
+            latest_frame: Arc::new(Mutex::new(None)),
+            av_info,
+            av_change: None,
+            rewind: config.rewind.map(RewindBuffer::new),
         })
     }
 
     pub fn fps(&self) -> f64 {
-        self.fps
+        sanitize_fps(self.av_info)
     }
 
-    pub fn system_info(&self) -> &playbyte_libretro::SystemInfo {
-        self.core.system_info()
+    /// Current geometry and timing, including any mid-game changes.
+    pub fn av_info(&self) -> SystemAvInfo {
+        self.av_info
+    }
+
+    /// The AV info change since the last call, if the core made one.
+    ///
+    /// Several changes between calls are merged into one carrying the latest AV info.
+    pub fn take_av_change(&mut self) -> Option<AvInfoChange> {
+        self.av_change.take()
+    }
+
+    pub fn system_info(&self) -> &SystemInfo {
+        &self.system_info
     }
 
     pub fn pixel_format(&self) -> RetroPixelFormat {
-        self.core.pixel_format()
+        match &self.backend {
+            CoreBackend::InProcess(core) => core.pixel_format(),
+            CoreBackend::Isolated(host) => lock_host(host).pixel_format(),
+        }
+    }
+
+    pub fn core_options(&self) -> Vec<CoreOption> {
+        match &self.backend {
+            CoreBackend::InProcess(core) => core.core_options(),
+            CoreBackend::Isolated(host) => lock_host(host)
+                .core_options()
+                .map(|(options, _)| options)
+                .unwrap_or_default(),
+        }
+    }
+
+    pub fn core_option_values(&self) -> HashMap<String, String> {
+        match &self.backend {
+            CoreBackend::InProcess(core) => core.core_option_values(),
+            CoreBackend::Isolated(host) => lock_host(host)
+                .core_options()
+                .map(|(_, values)| values)
+                .unwrap_or_default(),
+        }
+    }
+
+    pub fn set_core_option(&self, key: &str, value: &str) -> Result<(), RuntimeError> {
+        match &self.backend {
+            CoreBackend::InProcess(core) => Ok(core.set_core_option(key, value)?),
+            CoreBackend::Isolated(host) => lock_host(host).set_core_option(key, value),
+        }
+    }
+
+    /// What the core logged most recently, oldest first.
+    pub fn recent_log(&self) -> Result<Vec<LogLine>, RuntimeError> {
+        match &self.backend {
+            CoreBackend::InProcess(core) => Ok(core.recent_log()),
+            CoreBackend::Isolated(host) => lock_host(host).recent_log(),
+        }
+    }
+
+    /// Devices the core accepts, indexed by port.
+    pub fn controller_info(&self) -> Vec<ControllerInfo> {
+        match &self.backend {
+            CoreBackend::InProcess(core) => core.controller_info(),
+            CoreBackend::Isolated(host) => lock_host(host).controller_info().to_vec(),
+        }
+    }
+
+    /// The device plugged into a port; see `InputState::port_device`.
+    pub fn port_device(&self, port: u32) -> u32 {
+        self.lock_input().port_device(port)
+    }
+
+    /// Plugs a device into a port, through `retro_set_controller_port_device`. Queries for
+    /// it are answered from `input_state` from then on.
+    pub fn set_port_device(&self, port: u32, device: u32) -> Result<(), RuntimeError> {
+        match &self.backend {
+            CoreBackend::InProcess(core) => core.set_controller_port_device(port, device),
+            CoreBackend::Isolated(host) => lock_host(host).set_port_device(port, device)?,
+        }
+        self.lock_input().set_port_device(port, device);
+        Ok(())
+    }
+
+    /// Tells the core whether the game runs faster than normal speed, which some cores use
+    /// to skip work such as audio filtering. The emulation thread keeps this up to date.
+    pub fn set_fast_forwarding(&self, fast_forwarding: bool) -> Result<(), RuntimeError> {
+        match &self.backend {
+            CoreBackend::InProcess(core) => core.set_fast_forwarding(fast_forwarding),
+            CoreBackend::Isolated(host) => lock_host(host).set_fast_forwarding(fast_forwarding)?,
+        }
+        if let Some(RunAhead::SecondInstance { ahead, .. }) = &self.run_ahead {
+            ahead.set_fast_forwarding(fast_forwarding)?;
+        }
+        Ok(())
+    }
+
+    /// Whether savestates can be restored by other sessions, e.g. when shared as a Byte.
+    pub fn is_state_portable(&self) -> bool {
+        match &self.backend {
+            CoreBackend::InProcess(core) => core.is_state_portable(),
+            CoreBackend::Isolated(host) => lock_host(host).serialization_quirks().is_portable(),
+        }
+    }
+
+    /// Flags from SET_SERIALIZATION_QUIRKS.
+    pub fn serialization_quirks(&self) -> SerializationQuirks {
+        match &self.backend {
+            CoreBackend::InProcess(core) => core.serialization_quirks(),
+            CoreBackend::Isolated(host) => lock_host(host).serialization_quirks(),
+        }
+    }
+
+    /// The run-ahead in effect, after the core's quirks had their say; `None` when off.
+    pub fn run_ahead(&self) -> Option<RunAheadConfig> {
+        self.run_ahead.as_ref().map(|run_ahead| RunAheadConfig {
+            frames: run_ahead.frames(),
+            second_instance: run_ahead.is_second_instance(),
+        })
+    }
+
+    pub fn is_isolated(&self) -> bool {
+        matches!(self.backend, CoreBackend::Isolated(_))
+    }
+
+    /// Runs one frame. Isolated cores can fail here when the helper process dies, and any
+    /// core when a rewind snapshot cannot be stored.
+    pub fn run_frame(&mut self) -> Result<(), RuntimeError> {
+        let forced = match self.movie.as_mut() {
+            Some(MovieHook::Playing(player)) => {
+                let input = player.next_input();
+                if input.is_none() {
+                    // The movie is over; live input takes over from here.
+                    self.movie = None;
+                }
+                input
+            }
+            _ => None,
+        };
+        self.set_forced_input(forced);
+        self.run_core_frame()?;
+        if let Some(MovieHook::Recording(recorder)) = self.movie.as_mut() {
+            let input = *self
+                .latched
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+            recorder.record(input);
+        }
+        self.run_ahead_step()?;
+        self.record_rewind()
+    }
+
+    /// Runs one frame on `input` instead of `input_state`, leaving rewind and movies alone,
+    /// for callers that keep their own states such as netplay. `muted` drops the frame's
+    /// audio, for frames run again after a rollback.
+    pub fn run_frame_with_input(
+        &mut self,
+        input: InputSnapshot,
+        muted: bool,
+    ) -> Result<(), RuntimeError> {
+        self.set_forced_input(Some(input));
+        self.audio.set_muted(muted);
+        let ran = self.run_core_frame();
+        self.audio.set_muted(false);
+        self.set_forced_input(None);
+        ran
+    }
+
+    /// Records the input of every frame `run_frame` runs from now on, replacing any movie
+    /// being recorded or played. Rewinding is not recorded, so a movie recorded across a
+    /// rewind does not play back the same.
+    pub fn start_recording(&mut self, recorder: MovieRecorder) {
+        self.set_forced_input(None);
+        self.movie = Some(MovieHook::Recording(recorder));
+    }
+
+    /// Ends recording and returns the movie, if one was being recorded.
+    pub fn stop_recording(&mut self) -> Option<Movie> {
+        match self.movie.take() {
+            Some(MovieHook::Recording(recorder)) => Some(recorder.finish()),
+            other => {
+                self.movie = other;
+                None
+            }
+        }
+    }
+
+    pub fn is_recording(&self) -> bool {
+        matches!(self.movie, Some(MovieHook::Recording(_)))
+    }
+
+    /// Plays a movie: each frame `run_frame` runs takes its input from the movie instead of
+    /// `input_state` until the movie is over. A movie that starts from a savestate restores
+    /// it first; one that starts from a Byte expects that Byte's state to be loaded already.
+    /// The ROM is not checked, as the runtime does not know its hash.
+    pub fn play_movie(&mut self, movie: Movie) -> Result<(), RuntimeError> {
+        let info = &self.system_info;
+        if movie.core_id != info.library_name || movie.core_version != info.library_version {
+            return Err(RuntimeError::MovieMismatch {
+                expected: format!("{} {}", movie.core_id, movie.core_version),
+                found: format!("{} {}", info.library_name, info.library_version),
+            });
+        }
+        if let MovieStart::State(state) = &movie.start {
+            self.unserialize(state)?;
+        }
+        self.movie = Some(MovieHook::Playing(MoviePlayer::new(movie)));
+        Ok(())
+    }
+
+    pub fn is_playing_movie(&self) -> bool {
+        matches!(self.movie, Some(MovieHook::Playing(_)))
+    }
+
+    /// Stops playing or recording a movie; whatever was recorded is dropped.
+    pub fn stop_movie(&mut self) {
+        self.movie = None;
+        self.set_forced_input(None);
+    }
+
+    fn set_forced_input(&self, input: Option<InputSnapshot>) {
+        *self
+            .forced_input
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = input;
+    }
+
+    /// Goes back to the previous rewind snapshot and runs one frame from it, with its audio
+    /// muted, so there is a picture to show. Past the oldest snapshot it stays there.
+    /// Returns false when there is nothing to rewind to, including when rewind is off.
+    pub fn rewind_step(&mut self) -> Result<bool, RuntimeError> {
+        let Some(rewind) = self.rewind.as_mut() else {
+            return Ok(false);
+        };
+        let Some(state) = rewind.pop().map_err(RuntimeError::Rewind)? else {
+            return Ok(false);
+        };
+        // A movie cannot pick up from an earlier frame, so rewinding ends playback.
+        if self.is_playing_movie() {
+            self.stop_movie();
+        }
+        self.unserialize(&state)?;
+        self.audio.set_muted(true);
+        let ran = self.run_core_frame();
+        self.audio.set_muted(false);
+        ran.map(|()| true)
+    }
+
+    /// The rewind snapshots kept so far, when rewind is on.
+    pub fn rewind_buffer(&self) -> Option<&RewindBuffer> {
+        self.rewind.as_ref()
+    }
+
+    fn record_rewind(&mut self) -> Result<(), RuntimeError> {
+        if !self.rewind.as_mut().is_some_and(RewindBuffer::tick) {
+            return Ok(());
+        }
+        let state = match self.serialize() {
+            Ok(state) => state,
+            Err(err @ RuntimeError::CoreCrashed(_)) => return Err(err),
+            // The core cannot save states to rewind through; stop asking every frame.
+            Err(err) => {
+                log::warn!("rewind disabled: {err}");
+                self.rewind = None;
+                return Ok(());
+            }
+        };
+        if let Some(rewind) = self.rewind.as_mut() {
+            rewind.push(state).map_err(RuntimeError::Rewind)?;
+        }
+        Ok(())
     }
 
-    pub fn run_frame(&mut self) {
-        self.core.run_frame();
+    fn run_core_frame(&mut self) -> Result<(), RuntimeError> {
+        let changed = match &mut self.backend {
+            CoreBackend::InProcess(core) => {
+                core.run_frame();
+                core.take_av_change()
+            }
+            CoreBackend::Isolated(host) => {
+                // The host latches this again when its core polls.
+                let forced = *self
+                    .forced_input
+                    .lock()
+                    .unwrap_or_else(|poisoned| poisoned.into_inner());
+                let input = forced.unwrap_or_else(|| {
+                    self.input_state
+                        .lock()
+                        .unwrap_or_else(|poisoned| poisoned.into_inner())
+                        .latch()
+                });
+                *self
+                    .latched
+                    .lock()
+                    .unwrap_or_else(|poisoned| poisoned.into_inner()) = input;
+                lock_host(host).run_frame(&input, &self.latest_frame, &self.audio)?
+            }
+        };
+        if let Some(av_info) = changed {
+            self.record_av_change(av_info);
+        }
+        Ok(())
+    }
+
+    fn record_av_change(&mut self, av_info: SystemAvInfo) {
+        let old = self.av_info.timing;
+        let timing_changed = old.fps != av_info.timing.fps
+            || old.sample_rate != av_info.timing.sample_rate
+            || self.av_change.is_some_and(|pending| pending.timing_changed);
+        self.av_info = av_info;
+        self.av_change = Some(AvInfoChange {
+            av_info,
+            timing_changed,
+        });
     }
 
     pub fn latest_frame(&self) -> Option<VideoFrame> {
@@ -243,16 +763,131 @@ impl EmulatorRuntime {
         Arc::clone(&self.audio)
     }
 
-    pub fn input_state(&self) -> Arc<Mutex<JoypadState>> {
+    /// Underruns, overruns and fill level of the audio buffer.
+    pub fn audio_stats(&self) -> AudioStats {
+        self.audio.stats()
+    }
+
+    pub fn input_state(&self) -> Arc<Mutex<InputState>> {
         Arc::clone(&self.input_state)
     }
 
+    fn lock_input(&self) -> std::sync::MutexGuard<'_, InputState> {
+        self.input_state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// What the core's `retro_serialize_size` reports right now.
+    pub fn serialize_size(&self) -> Result<usize, RuntimeError> {
+        match &self.backend {
+            CoreBackend::InProcess(core) => Ok(core.serialize_size()),
+            CoreBackend::Isolated(host) => lock_host(host).serialize_size(),
+        }
+    }
+
+    /// Checks the `serialize_size` a state was saved under against the core's. 0 means the
+    /// size is unknown and passes, as does any size on cores whose states vary in size.
+    pub fn check_serialize_size(&self, saved: u64) -> Result<(), RuntimeError> {
+        if saved == 0 || self.serialization_quirks().variable_size() {
+            return Ok(());
+        }
+        let current = self.serialize_size()? as u64;
+        if current != saved {
+            return Err(RuntimeError::SerializeSizeMismatch { saved, current });
+        }
+        Ok(())
+    }
+
     pub fn serialize(&self) -> Result<Vec<u8>, RuntimeError> {
-        Ok(self.core.serialize()?)
+        self.serialize_in(SavestateContext::Normal)
     }
 
     pub fn unserialize(&self, data: &[u8]) -> Result<(), RuntimeError> {
-        Ok(self.core.unserialize(data)?)
+        self.unserialize_in(SavestateContext::Normal, data)
+    }
+
+    /// Saves a state, telling the core through GET_SAVESTATE_CONTEXT what it is for.
+    pub(crate) fn serialize_in(&self, context: SavestateContext) -> Result<Vec<u8>, RuntimeError> {
+        match &self.backend {
+            CoreBackend::InProcess(core) => {
+                core.set_savestate_context(context);
+                let state = core.serialize();
+                core.set_savestate_context(SavestateContext::Normal);
+                Ok(state?)
+            }
+            CoreBackend::Isolated(host) => lock_host(host).serialize(context),
+        }
+    }
+
+    /// Restores a state, telling the core through GET_SAVESTATE_CONTEXT what it is for.
+    pub(crate) fn unserialize_in(
+        &self,
+        context: SavestateContext,
+        data: &[u8],
+    ) -> Result<(), RuntimeError> {
+        match &self.backend {
+            CoreBackend::InProcess(core) => {
+                core.set_savestate_context(context);
+                let loaded = core.unserialize(data);
+                core.set_savestate_context(SavestateContext::Normal);
+                Ok(loaded?)
+            }
+            CoreBackend::Isolated(host) => lock_host(host).unserialize(context, data),
+        }
+    }
+
+    /// Copies out a memory region, or `None` when the core does not expose it.
+    pub fn read_memory(&self, region: MemoryRegion) -> Result<Option<Vec<u8>>, RuntimeError> {
+        match &self.backend {
+            CoreBackend::InProcess(core) => Ok(core.memory(region).map(<[u8]>::to_vec)),
+            CoreBackend::Isolated(host) => lock_host(host).read_memory(region),
+        }
+    }
+
+    pub fn write_memory(&mut self, region: MemoryRegion, data: &[u8]) -> Result<(), RuntimeError> {
+        match &mut self.backend {
+            CoreBackend::InProcess(core) => Ok(core.write_memory(region, data)?),
+            CoreBackend::Isolated(host) => lock_host(host).write_memory(region, data),
+        }
+    }
+}
+
+fn lock_host(host: &Mutex<CoreHost>) -> std::sync::MutexGuard<'_, CoreHost> {
+    host.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// FNV-1a, which hashes the same whatever the build, so hashes can be compared across
+/// machines.
+pub(crate) fn fnv1a(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+fn rom_extension(rom_path: &Path) -> Option<String> {
+    rom_path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_ascii_lowercase())
+}
+
+fn check_rom_extension(info: &SystemInfo, ext: &str) -> Result<(), RuntimeError> {
+    if core_supports_extension(&info.valid_extensions, ext) {
+        return Ok(());
+    }
+    Err(RuntimeError::IncompatibleRom {
+        core: info.library_name.clone(),
+        rom_ext: ext.to_string(),
+        valid_extensions: info.valid_extensions.clone(),
+    })
+}
+
+fn sanitize_fps(av_info: SystemAvInfo) -> f64 {
+    if av_info.timing.fps.is_finite() && av_info.timing.fps >= 1.0 && av_info.timing.fps <= 240.0 {
+        av_info.timing.fps
+    } else {
+        60.0
     }
 }
 
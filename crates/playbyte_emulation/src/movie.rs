@@ -0,0 +1,186 @@
+//! Input movies: the input every port saw on every frame, from a known starting point.
+//!
+//! A movie starts either from a savestate it carries or from a Byte, whose state the player
+//! loads before playing. Replaying the input frame by frame from that start reproduces the
+//! run on the same core and ROM, so movies store the core name and version and the ROM
+//! SHA-1 to check against.
+//!
+//! Movie files (`.pbm`) are the magic `PBM1`, a format version, then the rest
+//! zstd-compressed: the header strings, the start, and one input snapshot per frame.
+
+use crate::{
+    core_host::{read_input, write_input, Reader, Writer},
+    EmulatorRuntime, InputSnapshot,
+};
+use std::{fs, io, path::Path};
+
+const MAGIC: &[u8; 4] = b"PBM1";
+const VERSION: u32 = 1;
+const START_STATE: u8 = 0;
+const START_BYTE: u8 = 1;
+
+/// Where a movie's first frame runs from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MovieStart {
+    /// A savestate, restored when playback starts.
+    State(Vec<u8>),
+    /// The state of the Byte with this id, which must be loaded before playback starts.
+    Byte(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Movie {
+    /// `library_name` of the core the movie was recorded on.
+    pub core_id: String,
+    /// `library_version` of that core.
+    pub core_version: String,
+    pub rom_sha1: String,
+    pub start: MovieStart,
+    /// The input latched on each frame, first frame first.
+    pub frames: Vec<InputSnapshot>,
+}
+
+impl Movie {
+    pub fn encode(&self) -> io::Result<Vec<u8>> {
+        let mut body = Writer::default();
+        body.str(&self.core_id)
+            .str(&self.core_version)
+            .str(&self.rom_sha1);
+        match &self.start {
+            MovieStart::State(state) => body.u8(START_STATE).bytes(state),
+            MovieStart::Byte(byte_id) => body.u8(START_BYTE).str(byte_id),
+        };
+        body.u32(self.frames.len() as u32);
+        for input in &self.frames {
+            write_input(&mut body, input);
+        }
+
+        let mut out = Vec::from(&MAGIC[..]);
+        out.extend_from_slice(&VERSION.to_le_bytes());
+        out.extend(zstd::stream::encode_all(&body.finish()[..], 3)?);
+        Ok(out)
+    }
+
+    pub fn decode(data: &[u8]) -> io::Result<Self> {
+        let mut header = Reader::new(data);
+        if header.take(MAGIC.len())? != MAGIC {
+            return Err(invalid("not a movie file"));
+        }
+        let version = header.u32()?;
+        if version != VERSION {
+            return Err(invalid(&format!("unsupported movie version {version}")));
+        }
+        let body = zstd::stream::decode_all(&data[MAGIC.len() + 4..])?;
+
+        let mut reader = Reader::new(&body);
+        let core_id = reader.string()?;
+        let core_version = reader.string()?;
+        let rom_sha1 = reader.string()?;
+        let start = match reader.u8()? {
+            START_STATE => MovieStart::State(reader.bytes()?.to_vec()),
+            START_BYTE => MovieStart::Byte(reader.string()?),
+            other => return Err(invalid(&format!("unknown movie start {other}"))),
+        };
+        let count = reader.u32()? as usize;
+        // Every snapshot takes well over one byte, so a count past the data is corrupt.
+        if count > body.len() {
+            return Err(invalid("movie frame count is past the end of the file"));
+        }
+        let mut frames = Vec::with_capacity(count);
+        for _ in 0..count {
+            frames.push(read_input(&mut reader)?);
+        }
+        Ok(Self {
+            core_id,
+            core_version,
+            rom_sha1,
+            start,
+            frames,
+        })
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        Self::decode(&fs::read(path)?)
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, self.encode()?)
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// Collects the input of each frame a runtime runs; see `EmulatorRuntime::start_recording`.
+#[derive(Debug)]
+pub struct MovieRecorder {
+    movie: Movie,
+}
+
+impl MovieRecorder {
+    /// Starts an empty movie of the game loaded in `runtime`. The runtime is expected to be
+    /// at `start` when recording begins.
+    pub fn new(runtime: &EmulatorRuntime, rom_sha1: &str, start: MovieStart) -> Self {
+        let info = runtime.system_info();
+        Self {
+            movie: Movie {
+                core_id: info.library_name.clone(),
+                core_version: info.library_version.clone(),
+                rom_sha1: rom_sha1.to_string(),
+                start,
+                frames: Vec::new(),
+            },
+        }
+    }
+
+    pub fn record(&mut self, input: InputSnapshot) {
+        self.movie.frames.push(input);
+    }
+
+    /// Frames recorded so far.
+    pub fn len(&self) -> usize {
+        self.movie.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.movie.frames.is_empty()
+    }
+
+    pub fn finish(self) -> Movie {
+        self.movie
+    }
+}
+
+/// Hands out a movie's input one frame at a time; see `EmulatorRuntime::play_movie`.
+#[derive(Debug)]
+pub struct MoviePlayer {
+    movie: Movie,
+    next: usize,
+}
+
+impl MoviePlayer {
+    pub fn new(movie: Movie) -> Self {
+        Self { movie, next: 0 }
+    }
+
+    /// The input for the next frame, or `None` once the movie is over.
+    pub fn next_input(&mut self) -> Option<InputSnapshot> {
+        let input = self.movie.frames.get(self.next).copied()?;
+        self.next += 1;
+        Some(input)
+    }
+
+    /// Frames played so far.
+    pub fn position(&self) -> usize {
+        self.next
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.next >= self.movie.frames.len()
+    }
+
+    pub fn movie(&self) -> &Movie {
+        &self.movie
+    }
+}
@@ -0,0 +1,522 @@
+//! Rollback netplay for two players, in the style of GGPO.
+//!
+//! Each peer runs its own copy of the game and never waits on the network for a frame it
+//! can predict: a frame whose remote input has not arrived runs on the last remote input
+//! that did. When the real input turns out different, the session loads the state it saved
+//! before the first mispredicted frame and runs the frames since then again, muted. It only
+//! stops to wait once it is `max_prediction` frames past the last confirmed remote input.
+//!
+//! Only the joypads on ports 0 and 1 travel between peers; every other input stays idle so
+//! both games see the same thing. Every `hash_interval` frames, once the inputs before it are
+//! confirmed on both sides, each peer sends a hash of its state at that frame, and hashes
+//! that differ are reported as a desync. A check that cannot be made, because one peer no
+//! longer had the state or its hash was lost, is dropped once a later one has been made.
+//!
+//! Packets are small and unreliable: every input packet repeats all local inputs the peer
+//! has not acknowledged yet, so lost or reordered packets only delay confirmation.
+
+use crate::{
+    core_host::{Reader, Writer},
+    fnv1a, EmulatorRuntime, InputSnapshot, RuntimeError,
+};
+use playbyte_libretro::SavestateContext;
+use std::{
+    collections::{HashMap, VecDeque},
+    io,
+    net::{SocketAddr, ToSocketAddrs, UdpSocket},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use thiserror::Error;
+
+const PACKET_INPUT: u8 = 1;
+const PACKET_HASH: u8 = 2;
+/// Inputs repeated in one packet at most; the rest follow once these are acknowledged.
+const MAX_INPUTS_PER_PACKET: u32 = 64;
+const MAX_PACKET_LEN: usize = 2048;
+
+/// Carries packets between the two peers. Delivery may drop, repeat or reorder packets.
+pub trait Transport {
+    fn send(&mut self, packet: &[u8]) -> io::Result<()>;
+    /// The next packet that has arrived, or `None` when there is none. Never blocks.
+    fn recv(&mut self) -> io::Result<Option<Vec<u8>>>;
+}
+
+/// A UDP socket that only talks to one peer.
+pub struct UdpTransport {
+    socket: UdpSocket,
+}
+
+impl UdpTransport {
+    /// Binds a socket; `connect` it to the peer before use.
+    pub fn bind(local: impl ToSocketAddrs) -> io::Result<Self> {
+        let socket = UdpSocket::bind(local)?;
+        socket.set_nonblocking(true)?;
+        Ok(Self { socket })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    pub fn connect(&self, peer: impl ToSocketAddrs) -> io::Result<()> {
+        self.socket.connect(peer)
+    }
+}
+
+impl Transport for UdpTransport {
+    fn send(&mut self, packet: &[u8]) -> io::Result<()> {
+        match self.socket.send(packet) {
+            Ok(_) => Ok(()),
+            // An earlier packet found no one listening yet; the peer may still start.
+            Err(err) if err.kind() == io::ErrorKind::ConnectionRefused => Ok(()),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn recv(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let mut buf = [0u8; MAX_PACKET_LEN];
+        match self.socket.recv(&mut buf) {
+            Ok(len) => Ok(Some(buf[..len].to_vec())),
+            Err(err)
+                if err.kind() == io::ErrorKind::WouldBlock
+                    || err.kind() == io::ErrorKind::ConnectionRefused =>
+            {
+                Ok(None)
+            }
+            Err(err) => Err(err),
+        }
+    }
+}
+
+/// The network a `LoopbackTransport` pair pretends to be.
+#[derive(Debug, Clone, Copy)]
+pub struct LoopbackConfig {
+    /// How long each packet takes to arrive.
+    pub latency: Duration,
+    /// Share of packets dropped, from 0.0 to 1.0.
+    pub loss: f64,
+    /// Picks which packets are dropped, so a run can be repeated.
+    pub seed: u64,
+}
+
+impl Default for LoopbackConfig {
+    fn default() -> Self {
+        Self {
+            latency: Duration::from_millis(50),
+            loss: 0.0,
+            seed: 1,
+        }
+    }
+}
+
+type PacketQueue = Arc<Mutex<VecDeque<(Instant, Vec<u8>)>>>;
+
+/// One end of an in-process link with simulated latency and loss, for trying netplay on
+/// one machine.
+pub struct LoopbackTransport {
+    outgoing: PacketQueue,
+    incoming: PacketQueue,
+    config: LoopbackConfig,
+    rng: u64,
+}
+
+impl LoopbackTransport {
+    /// Two transports connected to each other.
+    pub fn pair(config: LoopbackConfig) -> (Self, Self) {
+        let a_to_b = PacketQueue::default();
+        let b_to_a = PacketQueue::default();
+        let a = Self {
+            outgoing: Arc::clone(&a_to_b),
+            incoming: Arc::clone(&b_to_a),
+            config,
+            rng: config.seed | 1,
+        };
+        let b = Self {
+            outgoing: b_to_a,
+            incoming: a_to_b,
+            config,
+            rng: config.seed.rotate_left(32) | 1,
+        };
+        (a, b)
+    }
+
+    /// xorshift64, mapped to 0.0..1.0.
+    fn next_random(&mut self) -> f64 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        (self.rng >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+impl Transport for LoopbackTransport {
+    fn send(&mut self, packet: &[u8]) -> io::Result<()> {
+        if self.next_random() < self.config.loss {
+            return Ok(());
+        }
+        let due = Instant::now() + self.config.latency;
+        lock_queue(&self.outgoing).push_back((due, packet.to_vec()));
+        Ok(())
+    }
+
+    fn recv(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let mut queue = lock_queue(&self.incoming);
+        match queue.front() {
+            Some((due, _)) if *due <= Instant::now() => Ok(queue.pop_front().map(|(_, p)| p)),
+            _ => Ok(None),
+        }
+    }
+}
+
+fn lock_queue(queue: &PacketQueue) -> std::sync::MutexGuard<'_, VecDeque<(Instant, Vec<u8>)>> {
+    queue
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct NetplayConfig {
+    /// The port this peer plays on, 0 or 1; the other peer plays on the other one.
+    pub local_port: usize,
+    /// Frames between reading local input and using it. Each frame of delay hides a frame
+    /// of latency without rolling back.
+    pub input_delay: u32,
+    /// How far past the last confirmed remote input the game may run before it waits.
+    pub max_prediction: u32,
+    /// Frames between state hash checks; 0 turns them off.
+    pub hash_interval: u32,
+}
+
+impl Default for NetplayConfig {
+    fn default() -> Self {
+        Self {
+            local_port: 0,
+            input_delay: 2,
+            max_prediction: 8,
+            hash_interval: 60,
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum NetplayError {
+    #[error(transparent)]
+    Runtime(#[from] RuntimeError),
+    #[error("netplay transport error: {0}")]
+    Transport(io::Error),
+    #[error("the games drifted apart: state hashes differ at frame {frame}")]
+    Desync { frame: u32 },
+    #[error("no saved state left to roll back to frame {frame}")]
+    RollbackTooFar { frame: u32 },
+}
+
+/// What `NetplaySession::advance` did.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameAdvance {
+    /// A new frame ran, after running `resimulated` earlier frames again.
+    Ran { resimulated: u32 },
+    /// The remote inputs are too far behind, so no frame ran; try again later.
+    Waiting,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct NetplayStats {
+    /// Frames run.
+    pub frame: u32,
+    /// Frames whose remote input has arrived.
+    pub confirmed: u32,
+    pub rollbacks: u32,
+    /// Frames run again after rollbacks, in total.
+    pub resimulated: u64,
+    /// State hashes that matched the peer's.
+    pub hashes_matched: u32,
+    /// State hash checks dropped because one side had no hash for the frame.
+    pub hashes_skipped: u32,
+}
+
+/// One peer of a two-player rollback session, driving an `EmulatorRuntime` that both
+/// peers loaded from the same state.
+pub struct NetplaySession<T> {
+    transport: T,
+    config: NetplayConfig,
+    /// The next frame to run.
+    frame: u32,
+    /// This peer's joypad on every frame so far, `input_delay` frames ahead of `frame`.
+    local: Vec<u16>,
+    /// The peer's joypad on every frame it has confirmed.
+    remote: Vec<u16>,
+    /// The remote joypad each frame run so far used, predicted or not.
+    used_remote: Vec<u16>,
+    /// Local inputs the peer has confirmed.
+    peer_ack: u32,
+    rollback_to: Option<u32>,
+    /// States at the start of the most recent frames, oldest first.
+    states: VecDeque<(u32, Vec<u8>)>,
+    next_hash: u32,
+    /// This peer's hashes waiting for the peer's, `None` where the state was already gone.
+    local_hashes: HashMap<u32, Option<u64>>,
+    remote_hashes: HashMap<u32, u64>,
+    /// The latest frame whose hashes were compared.
+    last_checked: u32,
+    stats: NetplayStats,
+}
+
+impl<T: Transport> NetplaySession<T> {
+    pub fn new(transport: T, config: NetplayConfig) -> Self {
+        Self {
+            transport,
+            config,
+            frame: 0,
+            local: vec![0; config.input_delay as usize],
+            remote: Vec::new(),
+            used_remote: Vec::new(),
+            peer_ack: 0,
+            rollback_to: None,
+            states: VecDeque::new(),
+            next_hash: config.hash_interval,
+            local_hashes: HashMap::new(),
+            remote_hashes: HashMap::new(),
+            last_checked: 0,
+            stats: NetplayStats::default(),
+        }
+    }
+
+    /// The next frame to run.
+    pub fn frame(&self) -> u32 {
+        self.frame
+    }
+
+    pub fn stats(&self) -> NetplayStats {
+        NetplayStats {
+            frame: self.frame,
+            confirmed: self.remote.len() as u32,
+            ..self.stats
+        }
+    }
+
+    pub fn transport(&self) -> &T {
+        &self.transport
+    }
+
+    /// Takes in the local joypad (as a `RETRO_DEVICE_ID_JOYPAD_MASK` bitmask) and runs the
+    /// next frame, unless the peer is too far behind.
+    pub fn advance(
+        &mut self,
+        runtime: &mut EmulatorRuntime,
+        joypad: u16,
+    ) -> Result<FrameAdvance, NetplayError> {
+        let resimulated = self.sync(runtime)?;
+        if self.frame >= self.remote.len() as u32 + self.config.max_prediction {
+            self.send_inputs()?;
+            return Ok(FrameAdvance::Waiting);
+        }
+        self.local.push(joypad);
+        self.send_inputs()?;
+        self.save_state(runtime, self.frame)?;
+        self.run(runtime, self.frame, false)?;
+        self.frame += 1;
+        Ok(FrameAdvance::Ran { resimulated })
+    }
+
+    /// Exchanges packets and corrects mispredicted frames without running a new one, for
+    /// while the game is paused or this peer has stopped.
+    pub fn poll(&mut self, runtime: &mut EmulatorRuntime) -> Result<(), NetplayError> {
+        self.sync(runtime)?;
+        self.send_inputs()
+    }
+
+    /// Returns how many frames were run again.
+    fn sync(&mut self, runtime: &mut EmulatorRuntime) -> Result<u32, NetplayError> {
+        while let Some(packet) = self.transport.recv().map_err(NetplayError::Transport)? {
+            // Anything can arrive over UDP; packets that do not parse are dropped.
+            let _ = self.handle_packet(&packet);
+        }
+        let resimulated = match self.rollback_to.take() {
+            Some(target) => self.rollback(runtime, target)?,
+            None => 0,
+        };
+        self.check_hashes()?;
+        Ok(resimulated)
+    }
+
+    fn handle_packet(&mut self, packet: &[u8]) -> io::Result<()> {
+        let mut reader = Reader::new(packet);
+        match reader.u8()? {
+            PACKET_INPUT => {
+                let ack = reader.u32()?;
+                self.peer_ack = self.peer_ack.max(ack);
+                let start = reader.u32()?;
+                let count = reader.u32()?;
+                for frame in start..start.saturating_add(count) {
+                    let joypad = reader.u32()? as u16;
+                    // Inputs are taken in order; a gap waits for the peer to repeat it.
+                    if frame as usize > self.remote.len() {
+                        break;
+                    }
+                    if frame as usize == self.remote.len() {
+                        self.confirm_remote(frame, joypad);
+                    }
+                }
+            }
+            PACKET_HASH => {
+                let frame = reader.u32()?;
+                let hash = reader.u64()?;
+                // A late hash for a frame already given up on would be counted twice.
+                if frame > self.last_checked {
+                    self.remote_hashes.insert(frame, hash);
+                }
+            }
+            tag => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("unknown netplay packet {tag}"),
+                ))
+            }
+        }
+        Ok(())
+    }
+
+    fn confirm_remote(&mut self, frame: u32, joypad: u16) {
+        let mispredicted = self
+            .used_remote
+            .get(frame as usize)
+            .is_some_and(|&used| used != joypad);
+        if mispredicted {
+            self.rollback_to = Some(self.rollback_to.map_or(frame, |target| target.min(frame)));
+        }
+        self.remote.push(joypad);
+    }
+
+    fn rollback(
+        &mut self,
+        runtime: &mut EmulatorRuntime,
+        target: u32,
+    ) -> Result<u32, NetplayError> {
+        let state = self
+            .states
+            .iter()
+            .find(|(frame, _)| *frame == target)
+            .map(|(_, state)| state.clone())
+            .ok_or(NetplayError::RollbackTooFar { frame: target })?;
+        runtime.unserialize_in(SavestateContext::RollbackNetplay, &state)?;
+        for frame in target..self.frame {
+            if frame > target {
+                self.save_state(runtime, frame)?;
+            }
+            self.run(runtime, frame, true)?;
+        }
+        let resimulated = self.frame - target;
+        self.stats.rollbacks += 1;
+        self.stats.resimulated += u64::from(resimulated);
+        Ok(resimulated)
+    }
+
+    fn save_state(&mut self, runtime: &EmulatorRuntime, frame: u32) -> Result<(), NetplayError> {
+        let state = runtime.serialize_in(SavestateContext::RollbackNetplay)?;
+        self.states.retain(|(saved, _)| *saved < frame);
+        self.states.push_back((frame, state));
+        // Rollbacks never reach past the oldest unconfirmed frame, at most
+        // `max_prediction` back.
+        while self.states.len() > self.config.max_prediction as usize + 2 {
+            self.states.pop_front();
+        }
+        Ok(())
+    }
+
+    fn run(
+        &mut self,
+        runtime: &mut EmulatorRuntime,
+        frame: u32,
+        muted: bool,
+    ) -> Result<(), NetplayError> {
+        let index = frame as usize;
+        let remote = match self.remote.get(index) {
+            Some(&joypad) => joypad,
+            // Players mostly hold what they held last.
+            None => self.remote.last().copied().unwrap_or(0),
+        };
+        self.used_remote.truncate(index);
+        self.used_remote.push(remote);
+
+        let mut input = InputSnapshot::default();
+        let local_port = self.config.local_port.min(1);
+        input.joypads[local_port] = self.local[index];
+        input.joypads[1 - local_port] = remote;
+        runtime.run_frame_with_input(input, muted)?;
+        Ok(())
+    }
+
+    fn send_inputs(&mut self) -> Result<(), NetplayError> {
+        let end = self.local.len() as u32;
+        let start = self.peer_ack.min(end);
+        let end = end.min(start + MAX_INPUTS_PER_PACKET);
+        let mut writer = Writer::default();
+        writer
+            .u8(PACKET_INPUT)
+            .u32(self.remote.len() as u32)
+            .u32(start)
+            .u32(end - start);
+        for &joypad in &self.local[start as usize..end as usize] {
+            writer.u32(u32::from(joypad));
+        }
+        self.transport
+            .send(&writer.finish())
+            .map_err(NetplayError::Transport)
+    }
+
+    /// Hashes states whose inputs are all confirmed, sends the hashes, and compares them
+    /// with the peer's.
+    fn check_hashes(&mut self) -> Result<(), NetplayError> {
+        if self.config.hash_interval == 0 {
+            return Ok(());
+        }
+        // The state at the start of a frame is settled once every earlier input is.
+        let settled = (self.remote.len() as u32).min(self.frame.saturating_sub(1));
+        while self.next_hash <= settled {
+            let frame = self.next_hash;
+            self.next_hash += self.config.hash_interval;
+            let hash = self
+                .states
+                .iter()
+                .find(|(saved, _)| *saved == frame)
+                .map(|(_, state)| fnv1a(state));
+            self.local_hashes.insert(frame, hash);
+            let Some(hash) = hash else {
+                continue;
+            };
+            let mut writer = Writer::default();
+            writer.u8(PACKET_HASH).u32(frame).u64(hash);
+            self.transport
+                .send(&writer.finish())
+                .map_err(NetplayError::Transport)?;
+        }
+
+        let mut frames: Vec<u32> = self
+            .remote_hashes
+            .keys()
+            .copied()
+            .filter(|frame| self.local_hashes.contains_key(frame))
+            .collect();
+        frames.sort_unstable();
+        for frame in frames {
+            let remote = self.remote_hashes.remove(&frame);
+            match self.local_hashes.remove(&frame).flatten() {
+                Some(hash) if Some(hash) != remote => return Err(NetplayError::Desync { frame }),
+                Some(_) => self.stats.hashes_matched += 1,
+                None => self.stats.hashes_skipped += 1,
+            }
+            self.last_checked = frame;
+        }
+
+        // Hash packets are not resent, so a hash older than the last pair compared has lost
+        // its partner for good.
+        let last_checked = self.last_checked;
+        let before = self.local_hashes.len() + self.remote_hashes.len();
+        self.local_hashes.retain(|frame, _| *frame > last_checked);
+        self.remote_hashes.retain(|frame, _| *frame > last_checked);
+        let dropped = before - self.local_hashes.len() - self.remote_hashes.len();
+        self.stats.hashes_skipped += dropped as u32;
+        Ok(())
+    }
+}
@@ -0,0 +1,140 @@
+//! Converts the core's stereo samples to the audio device's rate and channel layout.
+//!
+//! Cores run at whatever rate their hardware did, e.g. 32040 Hz for the SNES, while devices
+//! want 44.1 or 48 kHz. The resampler interpolates with a four-point cubic and applies dynamic
+//! rate control: the ratio is nudged by up to half a percent so the ring buffer hovers around
+//! `TARGET_LATENCY` instead of slowly running dry or filling up as the two clocks drift.
+
+use crate::AudioRingBuffer;
+use std::collections::VecDeque;
+
+/// How much audio the ring buffer should hold, in seconds.
+pub const TARGET_LATENCY: f64 = 0.05;
+/// The most dynamic rate control moves the ratio, as a fraction of it. Half a percent is
+/// below what ears notice as a pitch change.
+pub const MAX_RATE_DEVIATION: f64 = 0.005;
+/// The most device frames `fill` converts in one go. Its buffers are sized for this in
+/// `new`, so the audio callback never allocates; bigger requests are filled in pieces.
+pub const MAX_FILL_FRAMES: usize = 8192;
+
+pub struct AudioResampler {
+    source_rate: f64,
+    device_rate: f64,
+    channels: usize,
+    /// Source frames around the output position, oldest first; it lies between the second
+    /// and third.
+    window: [[f32; 2]; 4],
+    /// How far past `window[1]` the next output frame falls, in source frames.
+    position: f64,
+    /// Source frames taken from the ring buffer but not yet moved into `window`.
+    pending: VecDeque<[f32; 2]>,
+    /// Room for the most samples one piece of a `fill` takes from the ring buffer.
+    scratch: Vec<i16>,
+    ratio: f64,
+}
+
+impl AudioResampler {
+    pub fn new(source_rate: f64, device_rate: f64, channels: usize) -> Self {
+        let source_rate = if source_rate.is_finite() && source_rate > 0.0 {
+            source_rate
+        } else {
+            device_rate
+        };
+        // The output position can sit up to one device frame past the window.
+        let max_ratio = source_rate / device_rate * (1.0 + MAX_RATE_DEVIATION);
+        let max_source_frames = ((MAX_FILL_FRAMES + 1) as f64 * max_ratio).ceil() as usize + 2;
+        Self {
+            source_rate,
+            device_rate,
+            channels: channels.max(1),
+            window: [[0.0; 2]; 4],
+            position: 0.0,
+            pending: VecDeque::with_capacity(max_source_frames),
+            scratch: vec![0; max_source_frames * 2],
+            ratio: source_rate / device_rate,
+        }
+    }
+
+    /// Interleaved channels in the device frames `fill` writes.
+    pub fn channels(&self) -> usize {
+        self.channels
+    }
+
+    /// Source frames consumed per device frame during the last `fill`, including the
+    /// dynamic rate control adjustment.
+    pub fn ratio(&self) -> f64 {
+        self.ratio
+    }
+
+    /// Fills `out`, interleaved with the device's channel count, from the ring buffer.
+    ///
+    /// Stereo goes to the first two channels and the rest stay silent; a mono device gets
+    /// both mixed. When the ring buffer runs dry the gap is filled with silence and counted
+    /// as an underrun.
+    pub fn fill(&mut self, audio: &AudioRingBuffer, out: &mut [f32]) {
+        for piece in out.chunks_mut(MAX_FILL_FRAMES * self.channels) {
+            self.fill_piece(audio, piece);
+        }
+    }
+
+    /// `fill` for at most `MAX_FILL_FRAMES` frames.
+    fn fill_piece(&mut self, audio: &AudioRingBuffer, out: &mut [f32]) {
+        let frames = out.len() / self.channels;
+        let queued = (audio.len() / 2 + self.pending.len()) as f64;
+        let target = self.source_rate * TARGET_LATENCY;
+        let deviation = ((queued - target) / target).clamp(-1.0, 1.0) * MAX_RATE_DEVIATION;
+        self.ratio = self.source_rate / self.device_rate * (1.0 + deviation);
+
+        let needed = (self.position + frames as f64 * self.ratio).ceil() as usize;
+        let missing = needed
+            .saturating_sub(self.pending.len())
+            .min(self.scratch.len() / 2);
+        // On an underrun only the samples that were there are queued; the frames after them
+        // come out silent below without being kept for later.
+        let popped = audio.pop_samples(&mut self.scratch[..missing * 2]);
+        self.pending.extend(
+            self.scratch[..popped]
+                .chunks_exact(2)
+                .map(|frame| [to_f32(frame[0]), to_f32(frame[1])]),
+        );
+
+        for chunk in out.chunks_exact_mut(self.channels) {
+            while self.position >= 1.0 {
+                let next = self.pending.pop_front().unwrap_or([0.0; 2]);
+                self.window.rotate_left(1);
+                self.window[3] = next;
+                self.position -= 1.0;
+            }
+            let t = self.position as f32;
+            let [p0, p1, p2, p3] = self.window;
+            let left = cubic(p0[0], p1[0], p2[0], p3[0], t);
+            let right = cubic(p0[1], p1[1], p2[1], p3[1], t);
+            map_channels(left, right, chunk);
+            self.position += self.ratio;
+        }
+        out[frames * self.channels..].fill(0.0);
+    }
+}
+
+fn to_f32(sample: i16) -> f32 {
+    sample as f32 / i16::MAX as f32
+}
+
+/// Catmull-Rom interpolation between `p1` and `p2`.
+fn cubic(p0: f32, p1: f32, p2: f32, p3: f32, t: f32) -> f32 {
+    p1 + 0.5
+        * t
+        * (p2 - p0 + t * (2.0 * p0 - 5.0 * p1 + 4.0 * p2 - p3 + t * (3.0 * (p1 - p2) + p3 - p0)))
+}
+
+fn map_channels(left: f32, right: f32, frame: &mut [f32]) {
+    match frame {
+        [mono] => *mono = (left + right) * 0.5,
+        [first, second, rest @ ..] => {
+            *first = left;
+            *second = right;
+            rest.fill(0.0);
+        }
+        [] => {}
+    }
+}
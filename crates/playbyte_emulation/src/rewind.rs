@@ -0,0 +1,127 @@
+//! Rewind: a memory-bounded ring of savestates taken while the game runs.
+//!
+//! Only the newest state is kept whole. Each older one is stored as the XOR of it and the
+//! state after it, compressed with zstd. Neighbouring states differ in few bytes, so the
+//! deltas are mostly zeros and shrink to a small fraction of a full state.
+
+use std::{collections::VecDeque, io};
+
+/// zstd level for deltas; they are taken every few frames, so speed matters more than size.
+const DELTA_COMPRESSION_LEVEL: i32 = 1;
+
+#[derive(Debug, Clone, Copy)]
+pub struct RewindConfig {
+    /// Frames between snapshots. Each rewind step goes back this far.
+    pub interval: u32,
+    /// Bytes the buffer may hold, counting the newest state and the compressed deltas. The
+    /// oldest snapshots are dropped to stay under it.
+    pub memory_limit: usize,
+}
+
+impl Default for RewindConfig {
+    fn default() -> Self {
+        Self {
+            interval: 2,
+            memory_limit: 64 * 1024 * 1024,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct RewindBuffer {
+    config: RewindConfig,
+    newest: Option<Vec<u8>>,
+    /// Compressed deltas, oldest first. The last one leads from `newest` to the state before.
+    deltas: VecDeque<Vec<u8>>,
+    delta_bytes: usize,
+    frames_until_snapshot: u32,
+}
+
+impl RewindBuffer {
+    pub fn new(config: RewindConfig) -> Self {
+        Self {
+            config,
+            newest: None,
+            deltas: VecDeque::new(),
+            delta_bytes: 0,
+            frames_until_snapshot: 0,
+        }
+    }
+
+    pub fn config(&self) -> RewindConfig {
+        self.config
+    }
+
+    /// Counts a frame that ran; returns true when a snapshot is due.
+    pub fn tick(&mut self) -> bool {
+        if self.frames_until_snapshot > 1 {
+            self.frames_until_snapshot -= 1;
+            return false;
+        }
+        self.frames_until_snapshot = self.config.interval.max(1);
+        true
+    }
+
+    /// Stores a new newest state, dropping the oldest snapshots that no longer fit.
+    pub fn push(&mut self, state: Vec<u8>) -> io::Result<()> {
+        if let Some(previous) = self.newest.take() {
+            let delta = xor_delta(&previous, &state);
+            let compressed = zstd::stream::encode_all(&delta[..], DELTA_COMPRESSION_LEVEL)?;
+            self.delta_bytes += compressed.len();
+            self.deltas.push_back(compressed);
+        }
+        self.newest = Some(state);
+        while self.memory_used() > self.config.memory_limit {
+            let Some(oldest) = self.deltas.pop_front() else {
+                break;
+            };
+            self.delta_bytes -= oldest.len();
+        }
+        Ok(())
+    }
+
+    /// Takes the newest snapshot and makes the one before it the newest. The oldest snapshot
+    /// is handed out again rather than removed, so holding rewind stays on it.
+    pub fn pop(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let Some(newest) = self.newest.as_ref() else {
+            return Ok(None);
+        };
+        self.frames_until_snapshot = self.config.interval.max(1);
+        let Some(compressed) = self.deltas.pop_back() else {
+            return Ok(Some(newest.clone()));
+        };
+        self.delta_bytes -= compressed.len();
+        let delta = zstd::stream::decode_all(&compressed[..])?;
+        let previous = xor_delta(&delta, newest);
+        Ok(self.newest.replace(previous))
+    }
+
+    /// Snapshots held, including the newest.
+    pub fn len(&self) -> usize {
+        self.newest.iter().count() + self.deltas.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.newest.is_none()
+    }
+
+    pub fn memory_used(&self) -> usize {
+        self.newest.as_ref().map_or(0, Vec::len) + self.delta_bytes
+    }
+
+    pub fn clear(&mut self) {
+        self.newest = None;
+        self.deltas.clear();
+        self.delta_bytes = 0;
+        self.frames_until_snapshot = 0;
+    }
+}
+
+/// XORs `base` with `other`, as long as `base`. Bytes past the end of `other` count as zero,
+/// so states of different sizes round-trip: `xor_delta(&xor_delta(a, b), b) == a`.
+fn xor_delta(base: &[u8], other: &[u8]) -> Vec<u8> {
+    base.iter()
+        .enumerate()
+        .map(|(index, byte)| byte ^ other.get(index).copied().unwrap_or(0))
+        .collect()
+}
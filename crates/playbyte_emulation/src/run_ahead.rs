@@ -0,0 +1,109 @@
+//! Run-ahead: hides input lag built into a game by showing a frame from the near future.
+//!
+//! Many games only react to a button a frame or two after it is pressed. After each real
+//! frame, the runtime runs `frames` more on the same input with their audio muted, shows
+//! the last of them, and goes back to the real frame. In single-instance mode that means
+//! saving and restoring the state on the one core every frame. In second-instance mode a
+//! second copy of the core receives the state instead and runs the frames ahead, so the
+//! core the sound comes from never jumps back.
+//!
+//! Run-ahead restores a state every frame, so it only runs on cores whose states have no
+//! caveats: a core that reports any serialization quirk never runs ahead.
+
+use crate::{EmulatorRuntime, RuntimeError};
+use playbyte_libretro::{SavestateContext, SerializationQuirks};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RunAheadConfig {
+    /// Frames to run ahead; matching the game's own lag removes it, more skips frames.
+    pub frames: u32,
+    /// Run ahead on a second instance of the core instead of restoring the first.
+    pub second_instance: bool,
+}
+
+pub(crate) enum RunAhead {
+    SingleInstance {
+        frames: u32,
+    },
+    SecondInstance {
+        frames: u32,
+        ahead: Box<EmulatorRuntime>,
+    },
+}
+
+impl RunAhead {
+    pub(crate) fn frames(&self) -> u32 {
+        match self {
+            Self::SingleInstance { frames } | Self::SecondInstance { frames, .. } => *frames,
+        }
+    }
+
+    pub(crate) fn is_second_instance(&self) -> bool {
+        matches!(self, Self::SecondInstance { .. })
+    }
+}
+
+/// What a core's quirks leave of the asked-for config, or `None` when run-ahead is off.
+pub(crate) fn effective_config(
+    config: RunAheadConfig,
+    quirks: SerializationQuirks,
+) -> Option<RunAheadConfig> {
+    if config.frames == 0 {
+        return None;
+    }
+    if !quirks.is_empty() {
+        log::warn!(
+            "run-ahead disabled: the core reports serialization quirks {:#x}",
+            quirks.0
+        );
+        return None;
+    }
+    Some(config)
+}
+
+impl EmulatorRuntime {
+    /// Runs ahead of the frame that just ran and leaves the frame ahead as the latest one.
+    pub(crate) fn run_ahead_step(&mut self) -> Result<(), RuntimeError> {
+        let Some(run_ahead) = self.run_ahead.as_ref() else {
+            return Ok(());
+        };
+        let frames = run_ahead.frames();
+        let context = if run_ahead.is_second_instance() {
+            SavestateContext::RunaheadSameBinary
+        } else {
+            SavestateContext::RunaheadSameInstance
+        };
+        let state = match self.serialize_in(context) {
+            Ok(state) => state,
+            Err(err @ RuntimeError::CoreCrashed(_)) => return Err(err),
+            // The core cannot save states right now; show frames as they come.
+            Err(err) => {
+                log::warn!("run-ahead disabled: {err}");
+                self.run_ahead = None;
+                return Ok(());
+            }
+        };
+        let input = *self
+            .latched
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        if let Some(RunAhead::SecondInstance { ahead, .. }) = self.run_ahead.as_mut() {
+            ahead.unserialize_in(context, &state)?;
+            for _ in 0..frames {
+                ahead.run_frame_with_input(input, true)?;
+            }
+            let frame = ahead.latest_frame();
+            *self
+                .latest_frame
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner()) = frame;
+            return Ok(());
+        }
+
+        for _ in 0..frames {
+            self.run_frame_with_input(input, true)?;
+        }
+        self.unserialize_in(context, &state)
+    }
+}
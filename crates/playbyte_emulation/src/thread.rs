@@ -0,0 +1,533 @@
+//! Runs an `EmulatorRuntime` on a thread of its own.
+//!
+//! The render loop hands the thread games and commands over a channel and picks up finished
+//! frames from a triple buffer, so a slow UI frame or a window drag no longer holds up the
+//! game or its audio. Input needs no channel: the runtime's `InputState` is shared, and the
+//! core latches it whenever it polls.
+
+use crate::{
+    resample::TARGET_LATENCY, sanitize_fps, triple_buffer, AudioRingBuffer, AudioStats,
+    AvInfoChange, EmulatorRuntime, InputState, RuntimeError,
+};
+use playbyte_libretro::{ControllerInfo, SystemAvInfo, SystemInfo, VideoFrame};
+use std::{
+    sync::{
+        mpsc::{self, Receiver, RecvTimeoutError, Sender},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+use triple_buffer::{TripleReader, TripleWriter};
+
+/// How long turbo runs frames before it checks for commands again.
+pub const TURBO_FRAME_BUDGET: Duration = Duration::from_millis(12);
+/// The most audio pacing speeds up or slows down the game, as a fraction of its speed.
+const MAX_AUDIO_DEVIATION: f64 = 0.02;
+
+/// Which clock decides when the next frame is due.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Pacing {
+    /// The thread keeps time on its own, running slightly fast while the audio buffer holds
+    /// less than `TARGET_LATENCY` of sound and slightly slow while it holds more. Keeps the
+    /// game going while the window is dragged.
+    #[default]
+    Audio,
+    /// Frames are owed as the render loop reports time with `EmulationThread::tick`.
+    Display,
+    /// Ticks from the render loop, nudged by the audio buffer as with `Audio`.
+    Both,
+}
+
+impl Pacing {
+    fn uses_display(self) -> bool {
+        matches!(self, Self::Display | Self::Both)
+    }
+
+    fn uses_audio(self) -> bool {
+        matches!(self, Self::Audio | Self::Both)
+    }
+}
+
+/// Something that happened to the game on the emulation thread.
+#[derive(Debug)]
+pub enum EmulationEvent {
+    AvChanged(AvInfoChange),
+    /// Running a frame failed, e.g. because the core host died; the runtime is gone.
+    Stopped(RuntimeError),
+}
+
+/// What the render side knows about the game on the emulation thread without asking it.
+pub struct RuntimeView {
+    system_info: SystemInfo,
+    av_info: SystemAvInfo,
+    controller_info: Vec<ControllerInfo>,
+    input_state: Arc<Mutex<InputState>>,
+    audio: Arc<AudioRingBuffer>,
+}
+
+impl RuntimeView {
+    fn new(runtime: &EmulatorRuntime) -> Self {
+        Self {
+            system_info: runtime.system_info().clone(),
+            av_info: runtime.av_info(),
+            controller_info: runtime.controller_info(),
+            input_state: runtime.input_state(),
+            audio: runtime.audio_buffer(),
+        }
+    }
+
+    pub fn fps(&self) -> f64 {
+        sanitize_fps(self.av_info)
+    }
+
+    /// Current geometry and timing, including mid-game changes seen through `poll_event`.
+    pub fn av_info(&self) -> SystemAvInfo {
+        self.av_info
+    }
+
+    pub fn system_info(&self) -> &SystemInfo {
+        &self.system_info
+    }
+
+    /// Devices the core accepts, indexed by port, as of loading.
+    pub fn controller_info(&self) -> Vec<ControllerInfo> {
+        self.controller_info.clone()
+    }
+
+    /// The device plugged into a port; see `InputState::port_device`.
+    pub fn port_device(&self, port: u32) -> u32 {
+        self.input_state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .port_device(port)
+    }
+
+    pub fn input_state(&self) -> Arc<Mutex<InputState>> {
+        Arc::clone(&self.input_state)
+    }
+
+    pub fn audio_buffer(&self) -> Arc<AudioRingBuffer> {
+        Arc::clone(&self.audio)
+    }
+
+    pub fn audio_stats(&self) -> AudioStats {
+        self.audio.stats()
+    }
+}
+
+type Job = Box<dyn FnOnce(Option<&mut EmulatorRuntime>) + Send>;
+
+enum Command {
+    /// Replaces the game, tagged with a number that events about it carry.
+    Load(u64, Box<EmulatorRuntime>),
+    Unload,
+    Call(Job),
+    SetPaused(bool),
+    SetSpeed(Option<f64>),
+    SetRewinding(bool),
+    SetPacing(Pacing),
+    Tick(Duration),
+    Stop,
+}
+
+/// The handle to a thread that owns the running game.
+///
+/// Settings are only sent when they change, so they can be set every update.
+pub struct EmulationThread {
+    commands: Sender<Command>,
+    events: Receiver<(u64, EmulationEvent)>,
+    frames: TripleReader<Option<VideoFrame>>,
+    thread: Option<JoinHandle<()>>,
+    view: Option<RuntimeView>,
+    generation: u64,
+    paused: bool,
+    speed: Option<f64>,
+    rewinding: bool,
+    pacing: Pacing,
+}
+
+impl EmulationThread {
+    pub fn spawn() -> std::io::Result<Self> {
+        let (commands, inbox) = mpsc::channel();
+        let (outbox, events) = mpsc::channel();
+        let (writer, frames) = triple_buffer::triple_buffer();
+        let worker = Worker {
+            runtime: None,
+            generation: 0,
+            frames: writer,
+            events: outbox,
+            paused: false,
+            speed: Some(1.0),
+            rewinding: false,
+            pacing: Pacing::default(),
+            credit: 0.0,
+            clock: Instant::now(),
+        };
+        let thread = thread::Builder::new()
+            .name("playbyte-emulation".to_string())
+            .spawn(move || worker.run(inbox))?;
+        Ok(Self {
+            commands,
+            events,
+            frames,
+            thread: Some(thread),
+            view: None,
+            generation: 0,
+            paused: false,
+            speed: Some(1.0),
+            rewinding: false,
+            pacing: Pacing::default(),
+        })
+    }
+
+    /// Hands a game to the thread, dropping the one it ran before.
+    pub fn load(&mut self, runtime: EmulatorRuntime) {
+        self.generation += 1;
+        self.view = Some(RuntimeView::new(&runtime));
+        self.send(Command::Load(self.generation, Box::new(runtime)));
+    }
+
+    pub fn unload(&mut self) {
+        self.view = None;
+        self.send(Command::Unload);
+    }
+
+    /// The game on the thread, if there is one.
+    pub fn runtime(&self) -> Option<&RuntimeView> {
+        self.view.as_ref()
+    }
+
+    /// Runs `job` against the game between frames and waits for its result.
+    pub fn call<T, F>(&self, job: F) -> Result<T, RuntimeError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut EmulatorRuntime) -> Result<T, RuntimeError> + Send + 'static,
+    {
+        let (reply, result) = mpsc::channel();
+        self.send(Command::Call(Box::new(move |runtime| {
+            let _ = reply.send(runtime.map_or(Err(RuntimeError::NoGame), job));
+        })));
+        result.recv().unwrap_or_else(|_| Err(thread_gone()))
+    }
+
+    pub fn serialize(&self) -> Result<Vec<u8>, RuntimeError> {
+        self.call(|runtime| runtime.serialize())
+    }
+
+    pub fn unserialize(&self, data: Vec<u8>) -> Result<(), RuntimeError> {
+        self.call(move |runtime| runtime.unserialize(&data))
+    }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        if self.paused != paused {
+            self.paused = paused;
+            self.send(Command::SetPaused(paused));
+        }
+    }
+
+    /// Runs the game at a multiple of its normal speed, or as fast as it goes for `None`.
+    pub fn set_speed(&mut self, speed: Option<f64>) {
+        if self.speed != speed {
+            self.speed = speed;
+            self.send(Command::SetSpeed(speed));
+        }
+    }
+
+    /// Steps backwards through rewind snapshots instead of running forwards while set.
+    pub fn set_rewinding(&mut self, rewinding: bool) {
+        if self.rewinding != rewinding {
+            self.rewinding = rewinding;
+            self.send(Command::SetRewinding(rewinding));
+        }
+    }
+
+    pub fn set_pacing(&mut self, pacing: Pacing) {
+        if self.pacing != pacing {
+            self.pacing = pacing;
+            self.send(Command::SetPacing(pacing));
+        }
+    }
+
+    /// Reports display time passing; frames are owed by it under `Pacing::Display` and
+    /// `Pacing::Both`, and it is ignored otherwise.
+    pub fn tick(&self, elapsed: Duration) {
+        if self.pacing.uses_display() {
+            self.send(Command::Tick(elapsed));
+        }
+    }
+
+    /// The newest frame, if one finished since the last call.
+    pub fn new_frame(&mut self) -> Option<VideoFrame> {
+        if !self.frames.update() {
+            return None;
+        }
+        self.frames.read().clone()
+    }
+
+    /// The frame last returned by `new_frame`.
+    pub fn latest_frame(&self) -> Option<VideoFrame> {
+        self.frames.read().clone()
+    }
+
+    /// The next event about the current game. Events about games since replaced are
+    /// dropped, and the view is kept up to date with the rest.
+    pub fn poll_event(&mut self) -> Option<EmulationEvent> {
+        loop {
+            let (generation, event) = match self.events.try_recv() {
+                Ok(event) => event,
+                Err(mpsc::TryRecvError::Empty) => return None,
+                Err(mpsc::TryRecvError::Disconnected) => {
+                    self.view.take()?;
+                    return Some(EmulationEvent::Stopped(thread_gone()));
+                }
+            };
+            if generation != self.generation {
+                continue;
+            }
+            match &event {
+                EmulationEvent::AvChanged(change) => {
+                    if let Some(view) = self.view.as_mut() {
+                        view.av_info = change.av_info;
+                    }
+                }
+                EmulationEvent::Stopped(_) => self.view = None,
+            }
+            return Some(event);
+        }
+    }
+
+    fn send(&self, command: Command) {
+        // A thread that is gone shows up through `poll_event` and `call`.
+        let _ = self.commands.send(command);
+    }
+}
+
+impl Drop for EmulationThread {
+    fn drop(&mut self) {
+        self.send(Command::Stop);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn thread_gone() -> RuntimeError {
+    RuntimeError::CoreCrashed("the emulation thread stopped".to_string())
+}
+
+struct Worker {
+    runtime: Option<EmulatorRuntime>,
+    generation: u64,
+    frames: TripleWriter<Option<VideoFrame>>,
+    events: Sender<(u64, EmulationEvent)>,
+    paused: bool,
+    speed: Option<f64>,
+    rewinding: bool,
+    pacing: Pacing,
+    /// Game time owed, in seconds of normal-speed play.
+    credit: f64,
+    /// When the thread's own clock last added to `credit`.
+    clock: Instant,
+}
+
+impl Worker {
+    fn run(mut self, commands: Receiver<Command>) {
+        loop {
+            let command = match self.wait_time() {
+                None => commands.recv().map_err(|_| RecvTimeoutError::Disconnected),
+                Some(timeout) => commands.recv_timeout(timeout),
+            };
+            match command {
+                Ok(command) => {
+                    if !self.handle(command) {
+                        return;
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => return,
+            }
+            while let Ok(command) = commands.try_recv() {
+                if !self.handle(command) {
+                    return;
+                }
+            }
+            self.advance();
+        }
+    }
+
+    /// Applies a command; returns false once the thread should exit.
+    fn handle(&mut self, command: Command) -> bool {
+        match command {
+            Command::Load(generation, runtime) => {
+                self.runtime = Some(*runtime);
+                self.generation = generation;
+                self.frames.publish(None);
+                self.restart_clock();
+                self.report_fast_forwarding();
+            }
+            Command::Unload => {
+                self.runtime = None;
+                self.frames.publish(None);
+            }
+            Command::Call(job) => job(self.runtime.as_mut()),
+            Command::SetPaused(paused) => {
+                self.paused = paused;
+                self.restart_clock();
+            }
+            Command::SetSpeed(speed) => {
+                self.speed = speed;
+                self.restart_clock();
+                self.report_fast_forwarding();
+            }
+            Command::SetRewinding(rewinding) => self.rewinding = rewinding,
+            Command::SetPacing(pacing) => {
+                self.pacing = pacing;
+                self.restart_clock();
+            }
+            Command::Tick(elapsed) => {
+                if let (Some(speed), true) = (self.speed, self.pacing.uses_display()) {
+                    self.credit += elapsed.as_secs_f64() * speed * self.audio_nudge();
+                }
+            }
+            Command::Stop => return false,
+        }
+        true
+    }
+
+    /// Tells the game whether it runs faster than normal speed, turbo included.
+    fn report_fast_forwarding(&mut self) {
+        let fast_forwarding = self.speed.is_none_or(|speed| speed > 1.0);
+        let Some(runtime) = self.runtime.as_ref() else {
+            return;
+        };
+        if let Err(err) = runtime.set_fast_forwarding(fast_forwarding) {
+            self.runtime = None;
+            self.emit(EmulationEvent::Stopped(err));
+        }
+    }
+
+    fn restart_clock(&mut self) {
+        self.credit = 0.0;
+        self.clock = Instant::now();
+    }
+
+    /// How long to wait for commands before running frames, or `None` to wait for one.
+    fn wait_time(&self) -> Option<Duration> {
+        let runtime = self.runtime.as_ref().filter(|_| !self.paused)?;
+        let Some(speed) = self.speed else {
+            return Some(Duration::ZERO);
+        };
+        let frame_time = frame_time(runtime);
+        if self.credit >= frame_time {
+            return Some(Duration::ZERO);
+        }
+        if self.pacing.uses_display() {
+            return None;
+        }
+        let owed = (frame_time - self.credit) / (speed * self.audio_nudge());
+        let elapsed = self.clock.elapsed().as_secs_f64();
+        Some(Duration::from_secs_f64((owed - elapsed).max(0.0)))
+    }
+
+    /// Runs the frames owed, publishes the last one and reports what changed.
+    fn advance(&mut self) {
+        if self.paused {
+            return;
+        }
+        let Some(runtime) = self.runtime.as_ref() else {
+            return;
+        };
+        let frame_time = frame_time(runtime);
+        let mut frames = 0u32;
+        let mut result = Ok(());
+        let playback_rate = match self.speed {
+            Some(speed) => {
+                if !self.pacing.uses_display() {
+                    let now = Instant::now();
+                    let elapsed = now.saturating_duration_since(self.clock).as_secs_f64();
+                    self.credit += elapsed * speed * self.audio_nudge();
+                    self.clock = now;
+                }
+                self.credit = self.credit.min(frame_time * 5.0 * speed.max(1.0));
+                while self.credit >= frame_time {
+                    result = self.step();
+                    if result.is_err() {
+                        break;
+                    }
+                    self.credit -= frame_time;
+                    frames += 1;
+                }
+                speed
+            }
+            None => {
+                let start = Instant::now();
+                let deadline = start + TURBO_FRAME_BUDGET;
+                while Instant::now() < deadline {
+                    result = self.step();
+                    if result.is_err() {
+                        break;
+                    }
+                    frames += 1;
+                }
+                // Turbo speed is whatever the machine manages; measure it for the audio.
+                let elapsed = self.clock.elapsed().as_secs_f64().max(frame_time);
+                self.clock = start;
+                (frames as f64 * frame_time / elapsed).max(1.0)
+            }
+        };
+
+        let Some(runtime) = self.runtime.as_mut() else {
+            return;
+        };
+        if let Err(err) = result {
+            self.runtime = None;
+            self.emit(EmulationEvent::Stopped(err));
+            return;
+        }
+        runtime.audio_buffer().set_playback_rate(playback_rate);
+        let change = runtime.take_av_change();
+        if frames > 0 {
+            self.frames.publish(runtime.latest_frame());
+        }
+        if let Some(change) = change {
+            if change.timing_changed {
+                self.restart_clock();
+            }
+            self.emit(EmulationEvent::AvChanged(change));
+        }
+    }
+
+    fn step(&mut self) -> Result<(), RuntimeError> {
+        let Some(runtime) = self.runtime.as_mut() else {
+            return Ok(());
+        };
+        if self.rewinding {
+            runtime.rewind_step().map(|_| ())
+        } else {
+            runtime.run_frame()
+        }
+    }
+
+    /// Scales game time so the audio buffer drifts back towards `TARGET_LATENCY`; 1.0
+    /// unless pacing uses audio.
+    fn audio_nudge(&self) -> f64 {
+        let Some(runtime) = self.runtime.as_ref().filter(|_| self.pacing.uses_audio()) else {
+            return 1.0;
+        };
+        let target = runtime.av_info().timing.sample_rate * 2.0 * TARGET_LATENCY;
+        if !target.is_finite() || target <= 0.0 {
+            return 1.0;
+        }
+        let queued = runtime.audio_stats().queued as f64;
+        1.0 + ((target - queued) / target).clamp(-1.0, 1.0) * MAX_AUDIO_DEVIATION
+    }
+
+    fn emit(&self, event: EmulationEvent) {
+        let _ = self.events.send((self.generation, event));
+    }
+}
+
+fn frame_time(runtime: &EmulatorRuntime) -> f64 {
+    (1.0 / runtime.fps()).max(1.0 / 1000.0)
+}
@@ -0,0 +1,81 @@
+//! Hands the newest value from one thread to another without either waiting.
+//!
+//! There are three slots: the writer fills one, the reader looks at another, and the third
+//! holds the newest finished value. Publishing and picking up are single atomic swaps of
+//! the spare slot's index, so the writer never waits on a slow reader and the reader always
+//! gets the latest value, skipping any it was too slow to see. Each slot sits behind a
+//! mutex to stay in safe code, but only one side ever owns a slot at a time, so the locks
+//! are never contended.
+
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc, Mutex, MutexGuard,
+};
+
+/// Set on the spare slot's index when it holds a value the reader has not picked up.
+const FRESH: usize = 0b100;
+const INDEX: usize = 0b011;
+
+struct Shared<T> {
+    slots: [Mutex<T>; 3],
+    spare: AtomicUsize,
+}
+
+impl<T> Shared<T> {
+    fn slot(&self, index: usize) -> MutexGuard<'_, T> {
+        self.slots[index]
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Creates a triple buffer whose slots start out as `T::default()`.
+pub fn triple_buffer<T: Default>() -> (TripleWriter<T>, TripleReader<T>) {
+    let shared = Arc::new(Shared {
+        slots: Default::default(),
+        spare: AtomicUsize::new(1),
+    });
+    let writer = TripleWriter {
+        shared: Arc::clone(&shared),
+        index: 0,
+    };
+    let reader = TripleReader { shared, index: 2 };
+    (writer, reader)
+}
+
+pub struct TripleWriter<T> {
+    shared: Arc<Shared<T>>,
+    index: usize,
+}
+
+impl<T> TripleWriter<T> {
+    /// Makes `value` the newest one, replacing any the reader has not picked up yet.
+    pub fn publish(&mut self, value: T) {
+        *self.shared.slot(self.index) = value;
+        let spare = self.shared.spare.swap(self.index | FRESH, Ordering::AcqRel);
+        self.index = spare & INDEX;
+    }
+}
+
+pub struct TripleReader<T> {
+    shared: Arc<Shared<T>>,
+    index: usize,
+}
+
+impl<T> TripleReader<T> {
+    /// Moves on to the newest published value. Returns false, and keeps the current one,
+    /// when nothing was published since the last update.
+    pub fn update(&mut self) -> bool {
+        if self.shared.spare.load(Ordering::Acquire) & FRESH == 0 {
+            return false;
+        }
+        let spare = self.shared.spare.swap(self.index, Ordering::AcqRel);
+        self.index = spare & INDEX;
+        true
+    }
+
+    /// The value picked up by the last `update`.
+    pub fn read(&self) -> MutexGuard<'_, T> {
+        self.shared.slot(self.index)
+    }
+}
@@ -0,0 +1,140 @@
+use playbyte_emulation::{
+    resample::{MAX_FILL_FRAMES, TARGET_LATENCY},
+    AudioResampler, AudioRingBuffer, AudioStats,
+};
+
+/// A stereo ramp: frame `i` is `[i * 10, -i * 10]`.
+fn ramp(frames: i16) -> Vec<i16> {
+    (0..frames).flat_map(|i| [i * 10, -i * 10]).collect()
+}
+
+fn drain(buffer: &AudioRingBuffer, samples: usize) -> Vec<i16> {
+    let mut out = vec![0; samples];
+    buffer.pop_samples(&mut out);
+    out
+}
+
+#[test]
+fn playback_rate_drops_or_interpolates_frames() {
+    let buffer = AudioRingBuffer::new(4096);
+    buffer.push_samples(&ramp(4));
+    assert_eq!(drain(&buffer, 8), ramp(4));
+
+    // Double speed keeps every other frame.
+    let buffer = AudioRingBuffer::new(4096);
+    buffer.set_playback_rate(2.0);
+    buffer.push_samples(&ramp(8));
+    assert_eq!(drain(&buffer, 10), [0, 0, 20, -20, 40, -40, 60, -60, 0, 0]);
+
+    // Half speed adds a frame halfway between each pair.
+    let buffer = AudioRingBuffer::new(4096);
+    buffer.set_playback_rate(0.5);
+    buffer.push_samples(&ramp(3));
+    assert_eq!(
+        drain(&buffer, 12),
+        [0, 0, 5, -5, 10, -10, 15, -15, 20, -20, 0, 0]
+    );
+}
+
+#[test]
+fn full_and_empty_buffers_are_counted() {
+    let buffer = AudioRingBuffer::new(8);
+    buffer.push_samples(&ramp(3));
+    buffer.push_samples(&ramp(3));
+    assert_eq!(
+        buffer.stats(),
+        AudioStats {
+            underruns: 0,
+            overruns: 1,
+            queued: 8,
+            capacity: 8,
+        }
+    );
+    // The newest samples are the ones dropped.
+    assert_eq!(drain(&buffer, 8), [0, 0, 10, -10, 20, -20, 0, 0]);
+
+    buffer.push_samples(&ramp(1));
+    assert_eq!(drain(&buffer, 4), [0, 0, 0, 0]);
+    let stats = buffer.stats();
+    assert_eq!((stats.underruns, stats.queued), (1, 0));
+    assert_eq!(stats.fill(), 0.0);
+}
+
+#[test]
+fn resampler_converts_rate_and_maps_channels() {
+    let audio = AudioRingBuffer::new(1 << 16);
+    audio.push_samples(&[16384, -16384].repeat(1200));
+
+    let mut quad = AudioResampler::new(24_000.0, 48_000.0, 4);
+    let mut out = vec![1.0; 4 * 1000];
+    quad.fill(&audio, &mut out);
+    let ratio = quad.ratio();
+    assert!((ratio - 0.5).abs() <= 0.5 * 0.005, "ratio {ratio}");
+    // A thousand device frames at about half a source frame each.
+    let consumed = 1200 - audio.len() / 2;
+    assert!((500..=504).contains(&consumed), "consumed {consumed}");
+    let last = &out[out.len() - 4..];
+    assert!((last[0] - 0.5).abs() < 1e-3 && (last[1] + 0.5).abs() < 1e-3);
+    assert_eq!(&last[2..], [0.0, 0.0]);
+
+    let mut mono = AudioResampler::new(24_000.0, 48_000.0, 1);
+    let mut out = vec![1.0; 100];
+    mono.fill(&audio, &mut out);
+    assert!(out.iter().all(|sample| sample.abs() < 1e-6));
+}
+
+#[test]
+fn rate_control_follows_the_buffer_fill() {
+    let source_rate = 32_040.0;
+    let target = (source_rate * TARGET_LATENCY) as usize;
+    let base = source_rate / 48_000.0;
+
+    let audio = AudioRingBuffer::new(1 << 16);
+    audio.push_samples(&vec![0; target * 2 * 4]);
+    let mut resampler = AudioResampler::new(source_rate, 48_000.0, 2);
+    resampler.fill(&audio, &mut [0.0; 2 * 256]);
+    assert!(resampler.ratio() > base, "a full buffer drains faster");
+
+    let audio = AudioRingBuffer::new(1 << 16);
+    audio.push_samples(&vec![0; target / 2 * 2]);
+    resampler.fill(&audio, &mut [0.0; 2 * 256]);
+    assert!(resampler.ratio() < base, "a low buffer drains slower");
+}
+
+#[test]
+fn underruns_do_not_delay_the_audio_after_them() {
+    let audio = AudioRingBuffer::new(1 << 16);
+    let mut resampler = AudioResampler::new(48_000.0, 48_000.0, 2);
+    for _ in 0..4 {
+        let mut out = vec![1.0; 2 * 1000];
+        resampler.fill(&audio, &mut out);
+        assert!(out.iter().all(|sample| *sample == 0.0));
+    }
+    assert_eq!(audio.stats().underruns, 4);
+
+    // The silence played during the underruns is not queued in front of the new audio, so
+    // it starts as soon as the interpolation window has reached it.
+    audio.push_samples(&[16384, 16384].repeat(1000));
+    let mut out = vec![0.0; 2 * 16];
+    resampler.fill(&audio, &mut out);
+    let first = out.chunks_exact(2).position(|frame| frame[0] > 0.25);
+    assert_eq!(first, Some(2), "{out:?}");
+}
+
+#[test]
+fn large_device_buffers_are_filled_in_pieces() {
+    let frames = MAX_FILL_FRAMES * 2 + 100;
+    let audio = AudioRingBuffer::new(1 << 17);
+    audio.push_samples(&[16384, -16384].repeat(frames + 100));
+    let mut resampler = AudioResampler::new(48_000.0, 48_000.0, 2);
+    let mut out = vec![0.0; 2 * frames];
+    resampler.fill(&audio, &mut out);
+    let consumed = frames + 100 - audio.len() / 2;
+    assert!(
+        consumed.abs_diff(frames) <= frames / 100,
+        "consumed {consumed}"
+    );
+    assert_eq!(audio.stats().underruns, 0);
+    let last = &out[out.len() - 2..];
+    assert!((last[0] - 0.5).abs() < 1e-3 && (last[1] + 0.5).abs() < 1e-3);
+}
@@ -0,0 +1,263 @@
+use playbyte_emulation::{EmulatorRuntime, RunAheadConfig, RuntimeConfig, RuntimeError};
+use playbyte_libretro::{testing::mock_content, LibretroError};
+use sha1::{Digest, Sha1};
+use std::{
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+fn isolated_config() -> RuntimeConfig {
+    RuntimeConfig {
+        isolate_core: true,
+        core_host: Some(PathBuf::from(env!("CARGO_BIN_EXE_playbyte_core_host"))),
+        ..RuntimeConfig::default()
+    }
+}
+
+fn frame_hash(frame: &playbyte_libretro::VideoFrame) -> String {
+    let mut hasher = Sha1::new();
+    hasher.update(&frame.data);
+    format!("{:x}", hasher.finalize())
+}
+
+#[test]
+#[cfg(unix)]
+fn core_host_reports_load_failures() {
+    let missing = std::env::temp_dir().join("playbyte-missing-core.so");
+    match EmulatorRuntime::with_config(&missing, "game.bin", isolated_config()) {
+        Err(RuntimeError::CoreHost(message)) => {
+            assert!(message.contains("failed to load library"), "{message}")
+        }
+        Err(err) => panic!("unexpected error: {err}"),
+        Ok(_) => panic!("missing core loaded"),
+    }
+}
+
+#[test]
+fn missing_core_host_binary_is_reported() {
+    let config = RuntimeConfig {
+        isolate_core: true,
+        core_host: Some(std::env::temp_dir().join("playbyte-no-such-host")),
+        ..RuntimeConfig::default()
+    };
+    let result = EmulatorRuntime::with_config("core.so", "game.bin", config);
+    assert!(matches!(result, Err(RuntimeError::CoreHostSpawn(_))));
+}
+
+#[test]
+#[cfg(not(unix))]
+fn isolation_is_refused_off_unix() {
+    let missing = std::env::temp_dir().join("playbyte-missing-core.dll");
+    match EmulatorRuntime::with_config(&missing, "game.bin", isolated_config()) {
+        Err(RuntimeError::CoreHostSpawn(err)) => {
+            assert_eq!(err.kind(), std::io::ErrorKind::Unsupported)
+        }
+        Err(err) => panic!("unexpected error: {err}"),
+        Ok(_) => panic!("isolated core loaded"),
+    }
+}
+
+#[test]
+#[cfg(unix)]
+fn isolated_core_matches_in_process_core() {
+    let core = match std::env::var("PLAYBYTE_CORE_PATH") {
+        Ok(value) => value,
+        Err(_) => {
+            eprintln!("Skipping core host test: PLAYBYTE_CORE_PATH not set");
+            return;
+        }
+    };
+    let rom = match std::env::var("PLAYBYTE_ROM_PATH") {
+        Ok(value) => value,
+        Err(_) => {
+            eprintln!("Skipping core host test: PLAYBYTE_ROM_PATH not set");
+            return;
+        }
+    };
+
+    let mut local = EmulatorRuntime::new(&core, &rom).expect("runtime init failed");
+    let mut isolated = EmulatorRuntime::with_config(&core, &rom, isolated_config())
+        .expect("isolated runtime init failed");
+    for _ in 0..30 {
+        local.run_frame().expect("run frame failed");
+        isolated.run_frame().expect("isolated run frame failed");
+    }
+    let state = isolated.serialize().expect("serialize failed");
+    local.unserialize(&state).expect("unserialize failed");
+    for _ in 0..10 {
+        local.run_frame().expect("run frame failed");
+        isolated.run_frame().expect("isolated run frame failed");
+    }
+
+    let frame_a = local.latest_frame().expect("missing in-process frame");
+    let frame_b = isolated.latest_frame().expect("missing isolated frame");
+    assert_eq!(frame_hash(&frame_a), frame_hash(&frame_b));
+}
+
+#[test]
+fn av_changes_cross_the_process_boundary() {
+    let Some((mock, rom)) = mock_content("av", "resize timing") else {
+        return;
+    };
+
+    let local = EmulatorRuntime::new(mock, &rom).expect("runtime init failed");
+    let isolated = EmulatorRuntime::with_config(mock, &rom, isolated_config())
+        .expect("isolated runtime init failed");
+    for mut runtime in [local, isolated] {
+        assert_eq!(runtime.fps(), 60.0);
+        for _ in 0..9 {
+            runtime.run_frame().expect("run frame failed");
+        }
+        assert!(runtime.take_av_change().is_none());
+        runtime.run_frame().expect("run frame failed");
+        let change = runtime.take_av_change().expect("missing AV change");
+        assert!(change.timing_changed);
+        assert_eq!(change.av_info.geometry.base_width, 320);
+        assert_eq!(runtime.fps(), 50.0);
+        assert!(runtime.take_av_change().is_none());
+    }
+
+    let _ = std::fs::remove_file(rom);
+}
+
+#[test]
+fn isolated_load_failures_keep_their_cause() {
+    let Some((mock, rom)) = mock_content("refused", "fail") else {
+        return;
+    };
+
+    match EmulatorRuntime::with_config(mock, &rom, isolated_config()) {
+        Err(err @ RuntimeError::Libretro(LibretroError::LoadGame { .. })) => assert!(err
+            .core_log()
+            .iter()
+            .any(|line| line.message == "content asked to fail")),
+        Err(err) => panic!("unexpected error: {err}"),
+        Ok(_) => panic!("refused content loaded"),
+    }
+    let _ = std::fs::remove_file(&rom);
+
+    match EmulatorRuntime::with_config(mock, &rom, isolated_config()) {
+        Err(RuntimeError::CoreHost(message)) => {
+            assert!(message.contains("failed to read content"), "{message}")
+        }
+        Err(err) => panic!("unexpected error: {err}"),
+        Ok(_) => panic!("missing content loaded"),
+    }
+}
+
+#[test]
+fn hung_core_host_is_killed() {
+    let Some((mock, rom)) = mock_content("hang", "hang") else {
+        return;
+    };
+    let config = RuntimeConfig {
+        core_host_timeout: Some(Duration::from_millis(300)),
+        ..isolated_config()
+    };
+    let mut runtime = EmulatorRuntime::with_config(mock, &rom, config).expect("runtime init");
+
+    let started = Instant::now();
+    match runtime.run_frame() {
+        Err(RuntimeError::CoreCrashed(reason)) => {
+            assert!(reason.contains("did not reply"), "{reason}")
+        }
+        other => panic!("hung frame returned {other:?}"),
+    }
+    assert!(started.elapsed() < Duration::from_secs(5));
+    // The helper is gone, so later calls fail straight away.
+    assert!(matches!(
+        runtime.serialize(),
+        Err(RuntimeError::CoreCrashed(_))
+    ));
+
+    let _ = std::fs::remove_file(rom);
+}
+
+#[test]
+fn fast_forwarding_crosses_the_process_boundary() {
+    let Some((mock, rom)) = mock_content("fast", "") else {
+        return;
+    };
+    let mut runtime =
+        EmulatorRuntime::with_config(mock, &rom, isolated_config()).expect("runtime init");
+
+    runtime.run_frame().expect("run frame failed");
+    runtime
+        .set_fast_forwarding(true)
+        .expect("set fast-forwarding");
+    runtime.run_frame().expect("run frame failed");
+    let answers: Vec<String> = runtime
+        .recent_log()
+        .expect("recent log")
+        .into_iter()
+        .filter_map(|line| {
+            line.message
+                .strip_prefix("env GET_FASTFORWARDING = ")
+                .map(str::to_string)
+        })
+        .collect();
+    assert_eq!(answers, ["false", "true"]);
+
+    let _ = std::fs::remove_file(rom);
+}
+
+#[test]
+fn savestate_context_crosses_the_process_boundary() {
+    let Some((mock, rom)) = mock_content("context", "") else {
+        return;
+    };
+    let config = RuntimeConfig {
+        run_ahead: Some(RunAheadConfig {
+            frames: 1,
+            second_instance: false,
+        }),
+        ..isolated_config()
+    };
+    let mut runtime = EmulatorRuntime::with_config(mock, &rom, config).expect("runtime init");
+
+    runtime.run_frame().expect("run frame failed");
+    runtime.serialize().expect("serialize");
+    let answers: Vec<String> = runtime
+        .recent_log()
+        .expect("recent log")
+        .into_iter()
+        .filter_map(|line| {
+            line.message
+                .strip_prefix("env GET_SAVESTATE_CONTEXT = ")
+                .map(str::to_string)
+        })
+        .collect();
+    assert_eq!(answers, ["1", "0"]);
+
+    let _ = std::fs::remove_file(rom);
+}
+
+#[test]
+fn serialize_sizes_are_checked_in_and_out_of_process() {
+    let Some((mock, rom)) = mock_content("size", "") else {
+        return;
+    };
+    for config in [RuntimeConfig::default(), isolated_config()] {
+        let mut runtime = EmulatorRuntime::with_config(mock, &rom, config).expect("runtime init");
+        runtime.run_frame().expect("run frame failed");
+        let size = runtime.serialize_size().expect("serialize size");
+        assert_eq!(size, runtime.serialize().expect("serialize").len());
+
+        let size = size as u64;
+        runtime.check_serialize_size(size).expect("same size");
+        // Bytes migrated from before sizes were recorded do not know theirs.
+        runtime.check_serialize_size(0).expect("unknown size");
+        assert!(matches!(
+            runtime.check_serialize_size(size + 1),
+            Err(RuntimeError::SerializeSizeMismatch { saved, current })
+                if saved == size + 1 && current == size
+        ));
+    }
+
+    // Cores whose states vary in size are not held to one.
+    std::fs::write(&rom, "quirks:4").expect("write content");
+    let runtime = EmulatorRuntime::new(mock, &rom).expect("runtime init");
+    runtime.check_serialize_size(1).expect("variable size");
+
+    let _ = std::fs::remove_file(rom);
+}
@@ -1,11 +1,6 @@
-use playbyte_emulation::EmulatorRuntime;
-use sha1::{Digest, Sha1};
-
-fn frame_hash(frame: &playbyte_libretro::VideoFrame) -> String {
-    let mut hasher = Sha1::new();
-    hasher.update(&frame.data);
-    format!("{:x}", hasher.finalize())
-}
+use playbyte_emulation::{verify_determinism, DeterminismConfig, DivergenceKind, GameContent};
+use playbyte_libretro::testing::mock_content;
+use std::path::Path;
 
 #[test]
 fn libretro_state_determinism() {
@@ -24,23 +19,66 @@ fn libretro_state_determinism() {
         }
     };
 
-    let mut runtime = EmulatorRuntime::new(core, rom).expect("runtime init failed");
-    for _ in 0..30 {
-        runtime.run_frame();
+    let report = verify_determinism(
+        Path::new(&core),
+        GameContent::Path(rom.into()),
+        &DeterminismConfig::default(),
+    )
+    .expect("verify determinism");
+    assert!(report.restores > 0, "{report:?}");
+    if let Some(divergence) = report.divergence {
+        panic!("{divergence}");
     }
-    let state = runtime.serialize().expect("serialize failed");
-    for _ in 0..10 {
-        runtime.run_frame();
-    }
-    let frame_a = runtime.latest_frame().expect("missing frame A");
-    let hash_a = frame_hash(&frame_a);
+}
 
-    runtime.unserialize(&state).expect("unserialize failed");
-    for _ in 0..10 {
-        runtime.run_frame();
-    }
-    let frame_b = runtime.latest_frame().expect("missing frame B");
-    let hash_b = frame_hash(&frame_b);
+#[test]
+fn deterministic_cores_pass() {
+    let Some((mock, rom)) = mock_content("determinism-pass", "") else {
+        return;
+    };
+    let config = DeterminismConfig {
+        frames: 1000,
+        checkpoint_interval: 50,
+        ..DeterminismConfig::default()
+    };
+    let report =
+        verify_determinism(mock, GameContent::Path(rom), &config).expect("verify determinism");
+    assert!(report.is_deterministic(), "{report:?}");
+    assert!(report.restores >= 10, "{report:?}");
+    assert!(report.frames_compared > 1000, "{report:?}");
+}
 
-    assert_eq!(hash_a, hash_b, "Frame hashes diverged after reload");
+#[test]
+fn the_first_divergent_frame_is_reported() {
+    // The drifting mock draws a different picture once it has been restored to an
+    // earlier frame, starting with the frame right after the restored one.
+    let Some((mock, rom)) = mock_content("determinism-drift", "drift") else {
+        return;
+    };
+    let config = DeterminismConfig {
+        frames: 600,
+        checkpoint_interval: 100,
+        ..DeterminismConfig::default()
+    };
+    let report =
+        verify_determinism(mock, GameContent::Path(rom), &config).expect("verify determinism");
+    let divergence = report.divergence.expect("divergence found");
+    assert_eq!(divergence.kind, DivergenceKind::Video);
+    let restored_from = divergence.restored_from.expect("after a restore");
+    assert_eq!(divergence.frame, restored_from + 1);
+    assert_eq!(report.restores, 1);
+
+    // Played straight through, it never drifts.
+    let Some((mock, rom)) = mock_content("determinism-drift-straight", "drift") else {
+        return;
+    };
+    let config = DeterminismConfig {
+        frames: 200,
+        checkpoint_interval: 1000,
+        ..DeterminismConfig::default()
+    };
+    let report =
+        verify_determinism(mock, GameContent::Path(rom), &config).expect("verify determinism");
+    assert!(report.is_deterministic(), "{report:?}");
+    assert_eq!(report.restores, 0);
 }
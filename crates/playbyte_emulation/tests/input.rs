@@ -0,0 +1,127 @@
+use playbyte_emulation::{
+    EmulatorRuntime, InputState, RuntimeConfig, RETRO_DEVICE_ID_JOYPAD_A,
+    RETRO_DEVICE_ID_JOYPAD_MASK, RETRO_DEVICE_JOYPAD, RETRO_DEVICE_MOUSE,
+};
+use playbyte_libretro::{testing::mock_content, MemoryRegion};
+use std::path::{Path, PathBuf};
+
+/// What the mock core read from the input callback during its last frame.
+fn input_echo(runtime: &EmulatorRuntime) -> Vec<i16> {
+    runtime
+        .read_memory(MemoryRegion::VideoRam)
+        .expect("read input echo")
+        .expect("mock core exposes its input echo")
+        .chunks_exact(2)
+        .map(|bytes| i16::from_le_bytes([bytes[0], bytes[1]]))
+        .collect()
+}
+
+/// The same content in-process and behind the core host.
+fn runtimes(mock: &Path, rom: &Path) -> [EmulatorRuntime; 2] {
+    let isolated = RuntimeConfig {
+        isolate_core: true,
+        core_host: Some(PathBuf::from(env!("CARGO_BIN_EXE_playbyte_core_host"))),
+        ..RuntimeConfig::default()
+    };
+    [
+        EmulatorRuntime::new(mock, rom).expect("runtime init failed"),
+        EmulatorRuntime::with_config(mock, rom, isolated).expect("isolated runtime init failed"),
+    ]
+}
+
+#[test]
+fn devices_answer_on_the_ports_they_are_plugged_into() {
+    let Some((mock, rom)) = mock_content("input", "") else {
+        return;
+    };
+
+    for mut runtime in runtimes(mock, &rom) {
+        assert_eq!(runtime.controller_info().len(), 2);
+        let gun = runtime.controller_info()[1].types[1].device;
+
+        runtime
+            .set_port_device(1, RETRO_DEVICE_MOUSE)
+            .expect("plug in mouse");
+        assert_eq!(runtime.port_device(1), RETRO_DEVICE_MOUSE);
+        {
+            let input = runtime.input_state();
+            let mut input = input.lock().unwrap();
+            input.joypads[0].set_button(RETRO_DEVICE_ID_JOYPAD_A, true);
+            input.analogs[0].set_axis(0, 0, -1.0);
+            input.mouse.move_by(5, -3);
+            input.mouse.scroll(1);
+            input.mouse.left = true;
+            input.pointer.set_position(0.75, 0.5);
+            input.pointer.pressed = true;
+        }
+        runtime.run_frame().expect("run frame failed");
+        assert_eq!(
+            input_echo(&runtime),
+            [-32767, 0, 1, 256, 5, -3, 1, 1, 16384, 0, 1]
+        );
+
+        // Mouse motion is relative and only reported once; buttons stay held.
+        runtime.run_frame().expect("run frame failed");
+        assert_eq!(&input_echo(&runtime)[4..8], [0, 0, 1, 0]);
+
+        runtime.set_port_device(1, gun).expect("plug in lightgun");
+        runtime
+            .input_state()
+            .lock()
+            .unwrap()
+            .pointer
+            .set_position(1.5, 0.0);
+        runtime.run_frame().expect("run frame failed");
+        assert_eq!(
+            input_echo(&runtime),
+            [-32767, 0, 1, 256, 32767, -32767, 1, 1, 0, 0, 0]
+        );
+    }
+
+    let _ = std::fs::remove_file(rom);
+}
+
+#[test]
+fn every_port_has_a_joypad_of_its_own() {
+    let Some((mock, rom)) = mock_content("ports", "") else {
+        return;
+    };
+
+    for mut runtime in runtimes(mock, &rom) {
+        {
+            let input = runtime.input_state();
+            let mut input = input.lock().unwrap();
+            input.joypads[1].set_button(RETRO_DEVICE_ID_JOYPAD_A, true);
+            input.analogs[1].set_axis(0, 1, 1.0);
+        }
+        runtime.run_frame().expect("run frame failed");
+        assert_eq!(&input_echo(&runtime)[..8], [0, 0, 0, 0, 0, 32767, 1, 256]);
+
+        runtime.input_state().lock().unwrap().release_pad(1);
+        runtime.run_frame().expect("run frame failed");
+        assert_eq!(&input_echo(&runtime)[4..8], [0, 0, 0, 0]);
+    }
+
+    let _ = std::fs::remove_file(rom);
+}
+
+#[test]
+fn snapshots_keep_the_input_they_latched() {
+    let mut input = InputState::default();
+    input.joypads[2].set_button(RETRO_DEVICE_ID_JOYPAD_A, true);
+    let snapshot = input.snapshot();
+    input.joypads[2].set_button(RETRO_DEVICE_ID_JOYPAD_A, false);
+
+    let a = |port| snapshot.value(port, RETRO_DEVICE_JOYPAD, 0, RETRO_DEVICE_ID_JOYPAD_A);
+    assert_eq!((a(2), a(0)), (1, 0));
+    assert_eq!(
+        snapshot.value(2, RETRO_DEVICE_JOYPAD, 0, RETRO_DEVICE_ID_JOYPAD_MASK),
+        1 << RETRO_DEVICE_ID_JOYPAD_A
+    );
+    assert_eq!(
+        input
+            .snapshot()
+            .value(2, RETRO_DEVICE_JOYPAD, 0, RETRO_DEVICE_ID_JOYPAD_MASK),
+        0
+    );
+}
@@ -0,0 +1,107 @@
+use playbyte_emulation::{
+    EmulatorRuntime, InputSnapshot, InputState, Movie, MovieRecorder, MovieStart, RuntimeError,
+    RETRO_DEVICE_ID_JOYPAD_A,
+};
+use playbyte_libretro::testing::mock_content;
+
+fn mock_runtime(name: &str) -> Option<EmulatorRuntime> {
+    let (mock, rom) = mock_content(&format!("movie-{name}"), "")?;
+    Some(EmulatorRuntime::new(mock, &rom).expect("runtime init"))
+}
+
+fn hold_a(runtime: &EmulatorRuntime, pressed: bool) {
+    runtime.input_state().lock().expect("input lock").joypads[0]
+        .set_button(RETRO_DEVICE_ID_JOYPAD_A, pressed);
+}
+
+#[test]
+fn movies_survive_encoding() {
+    let mut state = InputState::default();
+    state.joypads[1].set_button(RETRO_DEVICE_ID_JOYPAD_A, true);
+    state.analogs[0].left_x = -1234;
+    state.mouse.move_by(-3, 7);
+    state.pointer.set_position(0.25, 0.75);
+    let movie = Movie {
+        core_id: "Mock".to_string(),
+        core_version: "1.0".to_string(),
+        rom_sha1: "da39a3ee5e6b4b0d3255bfef95601890afd80709".to_string(),
+        start: MovieStart::State(vec![1, 2, 3, 4]),
+        frames: vec![InputSnapshot::default(), state.snapshot(), state.snapshot()],
+    };
+    let encoded = movie.encode().expect("encode");
+    assert!(encoded.starts_with(b"PBM1"));
+    assert_eq!(Movie::decode(&encoded).expect("decode"), movie);
+
+    let from_byte = Movie {
+        start: MovieStart::Byte("byte-1".to_string()),
+        frames: Vec::new(),
+        ..movie
+    };
+    let encoded = from_byte.encode().expect("encode");
+    assert_eq!(Movie::decode(&encoded).expect("decode"), from_byte);
+
+    assert!(Movie::decode(b"PBM1").is_err());
+    assert!(Movie::decode(&encoded[..encoded.len() - 4]).is_err());
+    assert!(Movie::decode(b"not a movie").is_err());
+}
+
+#[test]
+fn played_movies_repeat_the_recorded_run() {
+    let Some(mut runtime) = mock_runtime("replay") else {
+        return;
+    };
+    runtime.run_frame().expect("run frame");
+    let start = runtime.serialize().expect("serialize");
+
+    // The mock core reseeds while A is held, so the input shows in its state.
+    let recorder = MovieRecorder::new(&runtime, "sha1", MovieStart::State(start.clone()));
+    runtime.start_recording(recorder);
+    assert!(runtime.is_recording());
+    let mut states = Vec::new();
+    for frame in 0..20 {
+        hold_a(&runtime, frame % 3 == 0);
+        runtime.run_frame().expect("run frame");
+        states.push(runtime.serialize().expect("serialize"));
+    }
+    let movie = runtime.stop_recording().expect("recorded movie");
+    assert_eq!(movie.frames.len(), 20);
+    assert!(runtime.stop_recording().is_none());
+
+    // Live input is ignored while the movie plays.
+    let movie = Movie::decode(&movie.encode().expect("encode")).expect("decode");
+    hold_a(&runtime, true);
+    runtime.play_movie(movie).expect("play movie");
+    for expected in &states {
+        assert!(runtime.is_playing_movie());
+        runtime.run_frame().expect("run frame");
+        assert_eq!(&runtime.serialize().expect("serialize"), expected);
+    }
+
+    // Once it is over, the player takes control again.
+    runtime.run_frame().expect("run frame");
+    assert!(!runtime.is_playing_movie());
+    runtime
+        .unserialize(states.last().unwrap())
+        .expect("unserialize");
+    hold_a(&runtime, false);
+    runtime.run_frame().expect("run frame");
+    let released = runtime.serialize().expect("serialize");
+    runtime
+        .unserialize(states.last().unwrap())
+        .expect("unserialize");
+    hold_a(&runtime, true);
+    runtime.run_frame().expect("run frame");
+    assert_ne!(runtime.serialize().expect("serialize"), released);
+}
+
+#[test]
+fn movies_from_other_cores_are_refused() {
+    let Some(mut runtime) = mock_runtime("mismatch") else {
+        return;
+    };
+    let mut movie = MovieRecorder::new(&runtime, "sha1", MovieStart::Byte("byte".into())).finish();
+    movie.core_version.push_str("-other");
+    let err = runtime.play_movie(movie).expect_err("version differs");
+    assert!(matches!(err, RuntimeError::MovieMismatch { .. }), "{err}");
+    assert!(!runtime.is_playing_movie());
+}
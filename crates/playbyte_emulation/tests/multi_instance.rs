@@ -0,0 +1,52 @@
+use playbyte_emulation::EmulatorRuntime;
+use sha1::{Digest, Sha1};
+
+fn frame_hash(frame: &playbyte_libretro::VideoFrame) -> String {
+    let mut hasher = Sha1::new();
+    hasher.update(&frame.data);
+    format!("{:x}", hasher.finalize())
+}
+
+#[test]
+fn same_core_runs_in_two_instances() {
+    let core = match std::env::var("PLAYBYTE_CORE_PATH") {
+        Ok(value) => value,
+        Err(_) => {
+            eprintln!("Skipping multi-instance test: PLAYBYTE_CORE_PATH not set");
+            return;
+        }
+    };
+    let rom = match std::env::var("PLAYBYTE_ROM_PATH") {
+        Ok(value) => value,
+        Err(_) => {
+            eprintln!("Skipping multi-instance test: PLAYBYTE_ROM_PATH not set");
+            return;
+        }
+    };
+
+    let mut first = EmulatorRuntime::new(&core, &rom).expect("first runtime init failed");
+    for _ in 0..30 {
+        first.run_frame().expect("run frame failed");
+    }
+    let state = first.serialize().expect("serialize failed");
+
+    // The second instance must not disturb the first one's globals.
+    let mut second = EmulatorRuntime::new(&core, &rom).expect("second runtime init failed");
+    second.unserialize(&state).expect("unserialize failed");
+    for _ in 0..10 {
+        first.run_frame().expect("run frame failed");
+        second.run_frame().expect("run frame failed");
+    }
+    let frame_a = first
+        .latest_frame()
+        .expect("missing frame from first instance");
+    let frame_b = second
+        .latest_frame()
+        .expect("missing frame from second instance");
+
+    assert_eq!(
+        frame_hash(&frame_a),
+        frame_hash(&frame_b),
+        "Instances diverged while running side by side"
+    );
+}
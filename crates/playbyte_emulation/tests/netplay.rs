@@ -0,0 +1,225 @@
+use playbyte_emulation::{
+    EmulatorRuntime, FrameAdvance, InputSnapshot, LoopbackConfig, LoopbackTransport, NetplayConfig,
+    NetplayError, NetplaySession, Transport, UdpTransport,
+};
+use playbyte_libretro::testing::mock_content;
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+const A: u16 = 1 << playbyte_emulation::RETRO_DEVICE_ID_JOYPAD_A;
+
+fn mock_runtime(name: &str) -> Option<EmulatorRuntime> {
+    let (mock, rom) = mock_content(&format!("netplay-{name}"), "")?;
+    Some(EmulatorRuntime::new(mock, &rom).expect("runtime init"))
+}
+
+/// What a player presses on their `step`th call to `advance`. The mock core reseeds on
+/// port 0's A, so player 1's presses show in the state.
+fn pressed(port: usize, step: u32) -> u16 {
+    match port {
+        0 if step % 7 < 3 => A,
+        1 if step.is_multiple_of(5) => A,
+        _ => 0,
+    }
+}
+
+struct Peer<T> {
+    runtime: EmulatorRuntime,
+    session: NetplaySession<T>,
+    port: usize,
+    steps: u32,
+}
+
+impl<T: Transport> Peer<T> {
+    fn new(runtime: EmulatorRuntime, transport: T, config: NetplayConfig) -> Self {
+        Self {
+            runtime,
+            session: NetplaySession::new(transport, config),
+            port: config.local_port,
+            steps: 0,
+        }
+    }
+
+    fn step(&mut self, frames: u32) -> Result<(), NetplayError> {
+        if self.session.frame() >= frames {
+            return self.session.poll(&mut self.runtime);
+        }
+        let joypad = pressed(self.port, self.steps);
+        if let FrameAdvance::Ran { .. } = self.session.advance(&mut self.runtime, joypad)? {
+            self.steps += 1;
+        }
+        Ok(())
+    }
+
+    fn settled(&self, frames: u32) -> bool {
+        let stats = self.session.stats();
+        stats.frame >= frames && stats.confirmed >= frames
+    }
+}
+
+/// Runs both peers until each has run `frames` frames on confirmed input.
+fn play<T: Transport>(a: &mut Peer<T>, b: &mut Peer<T>, frames: u32) {
+    let deadline = Instant::now() + Duration::from_secs(20);
+    while !(a.settled(frames) && b.settled(frames)) {
+        assert!(Instant::now() < deadline, "netplay stalled");
+        a.step(frames).expect("peer a");
+        b.step(frames).expect("peer b");
+        thread::sleep(Duration::from_millis(1));
+    }
+}
+
+/// The state after `frames` frames played offline on the same inputs.
+fn offline_state(runtime: &mut EmulatorRuntime, delay: u32, frames: u32) -> Vec<u8> {
+    for frame in 0..frames {
+        let mut input = InputSnapshot::default();
+        if let Some(step) = frame.checked_sub(delay) {
+            input.joypads[0] = pressed(0, step);
+            input.joypads[1] = pressed(1, step);
+        }
+        runtime
+            .run_frame_with_input(input, false)
+            .expect("run frame");
+    }
+    runtime.serialize().expect("serialize")
+}
+
+fn configs(hash_interval: u32) -> (NetplayConfig, NetplayConfig) {
+    let config = NetplayConfig {
+        input_delay: 1,
+        max_prediction: 8,
+        hash_interval,
+        ..NetplayConfig::default()
+    };
+    (
+        config,
+        NetplayConfig {
+            local_port: 1,
+            ..config
+        },
+    )
+}
+
+#[test]
+fn peers_agree_after_rollbacks_over_a_lossy_link() {
+    let (Some(first), Some(second), Some(mut offline)) = (
+        mock_runtime("lossy-a"),
+        mock_runtime("lossy-b"),
+        mock_runtime("lossy-offline"),
+    ) else {
+        return;
+    };
+    let (near, far) = LoopbackTransport::pair(LoopbackConfig {
+        latency: Duration::from_millis(15),
+        loss: 0.2,
+        seed: 7,
+    });
+    let (config_a, config_b) = configs(10);
+    let mut a = Peer::new(first, near, config_a);
+    let mut b = Peer::new(second, far, config_b);
+
+    play(&mut a, &mut b, 120);
+
+    let state = a.runtime.serialize().expect("serialize");
+    assert_eq!(b.runtime.serialize().expect("serialize"), state);
+    assert_eq!(offline_state(&mut offline, 1, 120), state);
+    // The second peer predicts the first one's presses and gets them wrong.
+    let stats = b.session.stats();
+    assert!(stats.rollbacks > 0, "{stats:?}");
+    assert!(stats.resimulated >= u64::from(stats.rollbacks));
+    assert!(a.session.stats().hashes_matched + stats.hashes_matched > 0);
+    // The core is told the states it saves and loads are for rollbacks.
+    let log = b.runtime.recent_log().expect("recent log");
+    assert!(log
+        .iter()
+        .any(|line| line.message == "env GET_SAVESTATE_CONTEXT = 3"));
+}
+
+#[test]
+fn peers_play_over_udp() {
+    let (Some(first), Some(second)) = (mock_runtime("udp-a"), mock_runtime("udp-b")) else {
+        return;
+    };
+    let near = UdpTransport::bind("127.0.0.1:0").expect("bind");
+    let far = UdpTransport::bind("127.0.0.1:0").expect("bind");
+    near.connect(far.local_addr().expect("addr"))
+        .expect("connect");
+    far.connect(near.local_addr().expect("addr"))
+        .expect("connect");
+    let (config_a, config_b) = configs(10);
+    let mut a = Peer::new(first, near, config_a);
+    let mut b = Peer::new(second, far, config_b);
+
+    play(&mut a, &mut b, 60);
+
+    assert_eq!(
+        a.runtime.serialize().expect("serialize"),
+        b.runtime.serialize().expect("serialize")
+    );
+}
+
+#[test]
+fn diverging_games_are_reported() {
+    let (Some(first), Some(second)) = (mock_runtime("desync-a"), mock_runtime("desync-b")) else {
+        return;
+    };
+    let (near, far) = LoopbackTransport::pair(LoopbackConfig {
+        latency: Duration::from_millis(2),
+        ..LoopbackConfig::default()
+    });
+    let (config_a, config_b) = configs(5);
+    let mut a = Peer::new(first, near, config_a);
+    let mut b = Peer::new(second, far, config_b);
+
+    play(&mut a, &mut b, 20);
+    // A frame neither peer knows about puts the second game out of step.
+    b.runtime.run_frame().expect("run frame");
+
+    let deadline = Instant::now() + Duration::from_secs(20);
+    let err = loop {
+        assert!(Instant::now() < deadline, "desync not reported");
+        if let Err(err) = a.step(60) {
+            break err;
+        }
+        if let Err(err) = b.step(60) {
+            break err;
+        }
+        thread::sleep(Duration::from_millis(1));
+    };
+    assert!(
+        matches!(err, NetplayError::Desync { frame } if frame >= 20),
+        "{err}"
+    );
+}
+
+#[test]
+fn lost_hashes_are_counted_as_skipped() {
+    let (Some(first), Some(second)) = (mock_runtime("skip-a"), mock_runtime("skip-b")) else {
+        return;
+    };
+    let (near, far) = LoopbackTransport::pair(LoopbackConfig {
+        latency: Duration::from_millis(2),
+        loss: 0.5,
+        seed: 3,
+    });
+    let (config_a, config_b) = configs(5);
+    let mut a = Peer::new(first, near, config_a);
+    let mut b = Peer::new(second, far, config_b);
+
+    play(&mut a, &mut b, 200);
+
+    // Every check but the few still in flight was either made or dropped, not kept around.
+    for stats in [a.session.stats(), b.session.stats()] {
+        assert!(stats.hashes_matched > 0, "{stats:?}");
+        assert!(stats.hashes_skipped > 0, "{stats:?}");
+        assert!(
+            stats.hashes_matched + stats.hashes_skipped >= 30,
+            "{stats:?}"
+        );
+        assert!(
+            stats.hashes_matched + stats.hashes_skipped <= 40,
+            "{stats:?}"
+        );
+    }
+}
@@ -0,0 +1,71 @@
+use playbyte_emulation::{EmulatorRuntime, RewindBuffer, RewindConfig, RuntimeConfig};
+use playbyte_libretro::testing::mock_content;
+
+#[test]
+fn buffer_hands_states_back_newest_first_within_its_budget() {
+    // Noisy states of growing size, so deltas neither vanish nor line up.
+    let mut seed = 0x9e37_79b9_u32;
+    let states: Vec<Vec<u8>> = (0..40)
+        .map(|step| {
+            (0..4096 + step)
+                .map(|_| {
+                    seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                    (seed >> 24) as u8
+                })
+                .collect()
+        })
+        .collect();
+
+    let mut buffer = RewindBuffer::new(RewindConfig {
+        interval: 1,
+        memory_limit: 4096 * 3,
+    });
+    for state in &states {
+        buffer.push(state.clone()).expect("push state");
+        assert!(buffer.memory_used() <= 4096 * 3);
+    }
+    let kept = buffer.len();
+    assert!(kept > 1 && kept < states.len(), "kept {kept} snapshots");
+
+    for expected in states.iter().rev().take(kept) {
+        assert_eq!(buffer.pop().expect("pop state").as_ref(), Some(expected));
+    }
+    // The oldest snapshot stays put once reached.
+    let oldest = &states[states.len() - kept];
+    assert_eq!(buffer.pop().expect("pop state").as_ref(), Some(oldest));
+    assert_eq!(buffer.len(), 1);
+}
+
+#[test]
+fn runtime_rewinds_to_earlier_frames() {
+    let Some((mock, rom)) = mock_content("rewind", "") else {
+        return;
+    };
+    let config = RuntimeConfig {
+        rewind: Some(RewindConfig {
+            interval: 1,
+            ..RewindConfig::default()
+        }),
+        ..RuntimeConfig::default()
+    };
+    let mut runtime = EmulatorRuntime::with_config(mock, &rom, config).expect("runtime init");
+
+    let mut states = Vec::new();
+    for _ in 0..10 {
+        runtime.run_frame().expect("run frame");
+        states.push(runtime.serialize().expect("serialize"));
+    }
+    assert_eq!(runtime.rewind_buffer().map(RewindBuffer::len), Some(10));
+
+    // Each step restores a snapshot and runs one frame from it.
+    assert!(runtime.rewind_step().expect("rewind"));
+    assert!(runtime.rewind_step().expect("rewind"));
+    assert_eq!(runtime.serialize().expect("serialize"), states[9]);
+    assert!(runtime.rewind_step().expect("rewind"));
+    assert_eq!(runtime.serialize().expect("serialize"), states[8]);
+
+    runtime.run_frame().expect("run frame");
+    assert_eq!(runtime.serialize().expect("serialize"), states[9]);
+
+    let _ = std::fs::remove_file(rom);
+}
@@ -0,0 +1,93 @@
+use playbyte_emulation::{EmulatorRuntime, RunAheadConfig, RuntimeConfig};
+use playbyte_libretro::testing::mock_content;
+
+fn with_run_ahead(frames: u32, second_instance: bool) -> RuntimeConfig {
+    RuntimeConfig {
+        run_ahead: Some(RunAheadConfig {
+            frames,
+            second_instance,
+        }),
+        ..RuntimeConfig::default()
+    }
+}
+
+/// The game stays where it would be without run-ahead, but shows the picture from
+/// `frames` later.
+fn check_shows_frames_ahead(second_instance: bool) {
+    let Some((mock, rom)) = mock_content(&format!("run-ahead-{second_instance}"), "") else {
+        return;
+    };
+    let mut plain = EmulatorRuntime::new(mock, &rom).expect("runtime init");
+    let mut ahead = EmulatorRuntime::with_config(mock, &rom, with_run_ahead(2, second_instance))
+        .expect("runtime init");
+    assert_eq!(
+        ahead.run_ahead(),
+        Some(RunAheadConfig {
+            frames: 2,
+            second_instance
+        })
+    );
+
+    let mut pictures = Vec::new();
+    let mut states = Vec::new();
+    for _ in 0..12 {
+        plain.run_frame().expect("run frame");
+        pictures.push(plain.latest_frame().expect("frame").data);
+        states.push(plain.serialize().expect("serialize"));
+    }
+    assert_ne!(pictures[0], pictures[2]);
+    for frame in 0..10 {
+        ahead.run_frame().expect("run frame");
+        assert_eq!(ahead.serialize().expect("serialize"), states[frame]);
+        assert_eq!(
+            ahead.latest_frame().expect("frame").data,
+            pictures[frame + 2]
+        );
+    }
+    // Only the real frames are heard.
+    assert_eq!(ahead.audio_buffer().len(), 10 * 800 * 2);
+    // The core knows which states run-ahead saves, and which the test does.
+    let context = if second_instance { 2 } else { 1 };
+    let contexts = savestate_contexts(&ahead);
+    assert!(contexts.ends_with(&[context, 0].repeat(10)), "{contexts:?}");
+}
+
+/// The GET_SAVESTATE_CONTEXT answers the mock core logged, each time one changed.
+fn savestate_contexts(runtime: &EmulatorRuntime) -> Vec<i32> {
+    runtime
+        .recent_log()
+        .expect("recent log")
+        .into_iter()
+        .filter_map(|line| {
+            line.message
+                .strip_prefix("env GET_SAVESTATE_CONTEXT = ")
+                .map(|context| context.parse().expect("context"))
+        })
+        .collect()
+}
+
+#[test]
+fn single_instance_shows_frames_ahead() {
+    check_shows_frames_ahead(false);
+}
+
+#[test]
+fn second_instance_shows_frames_ahead() {
+    check_shows_frames_ahead(true);
+}
+
+#[test]
+fn serialization_quirks_turn_run_ahead_off() {
+    // INCOMPLETE, MUST_INITIALIZE, CORE_VARIABLE_SIZE and SINGLE_SESSION.
+    for quirks in [1, 2, 4, 16] {
+        let Some((mock, rom)) = mock_content(
+            &format!("run-ahead-quirks-{quirks}"),
+            &format!("quirks:{quirks}"),
+        ) else {
+            return;
+        };
+        let runtime = EmulatorRuntime::with_config(mock, &rom, with_run_ahead(1, true))
+            .expect("runtime init");
+        assert_eq!(runtime.run_ahead(), None, "quirks {quirks}");
+    }
+}
@@ -0,0 +1,144 @@
+use playbyte_emulation::{
+    triple_buffer::triple_buffer, EmulationEvent, EmulationThread, EmulatorRuntime, Pacing,
+    RuntimeError,
+};
+use playbyte_libretro::testing::mock_content;
+use std::time::{Duration, Instant};
+
+const WAIT: Duration = Duration::from_secs(5);
+
+fn mock_runtime(flags: &str, name: &str) -> Option<EmulatorRuntime> {
+    let (mock, rom) = mock_content(name, flags)?;
+    Some(EmulatorRuntime::new(mock, &rom).expect("runtime init failed"))
+}
+
+/// Polls `check` until it returns something or `WAIT` runs out.
+fn wait_for<T>(mut check: impl FnMut() -> Option<T>) -> Option<T> {
+    let deadline = Instant::now() + WAIT;
+    while Instant::now() < deadline {
+        if let Some(value) = check() {
+            return Some(value);
+        }
+        std::thread::sleep(Duration::from_millis(2));
+    }
+    None
+}
+
+#[test]
+fn triple_buffer_hands_over_only_the_newest_value() {
+    let (mut writer, mut reader) = triple_buffer::<u32>();
+    assert!(!reader.update());
+    assert_eq!(*reader.read(), 0);
+
+    writer.publish(1);
+    writer.publish(2);
+    writer.publish(3);
+    assert!(reader.update());
+    assert_eq!(*reader.read(), 3);
+    assert!(!reader.update());
+    assert_eq!(*reader.read(), 3);
+
+    writer.publish(4);
+    assert!(reader.update());
+    assert_eq!(*reader.read(), 4);
+}
+
+#[test]
+fn commands_without_a_game_report_it() {
+    let thread = EmulationThread::spawn().expect("spawn emulation thread");
+    assert!(thread.runtime().is_none());
+    assert!(matches!(thread.serialize(), Err(RuntimeError::NoGame)));
+}
+
+#[test]
+fn thread_runs_the_game_and_publishes_frames() {
+    let Some(runtime) = mock_runtime("", "thread-run") else {
+        return;
+    };
+    let mut thread = EmulationThread::spawn().expect("spawn emulation thread");
+    thread.load(runtime);
+    assert_eq!(thread.runtime().map(|view| view.fps()), Some(60.0));
+
+    let frame = wait_for(|| thread.new_frame()).expect("no frame published");
+    assert!(frame.width > 0 && frame.height > 0);
+
+    // Paused, the game holds still between commands, so states can be compared.
+    thread.set_paused(true);
+    let state = thread.serialize().expect("serialize");
+    assert_eq!(thread.serialize().expect("serialize"), state);
+    thread.set_paused(false);
+    thread.new_frame();
+    wait_for(|| thread.new_frame()).expect("no frame after unpausing");
+    thread.set_paused(true);
+    assert_ne!(thread.serialize().expect("serialize"), state);
+    thread.unserialize(state.clone()).expect("unserialize");
+    assert_eq!(thread.serialize().expect("serialize"), state);
+
+    thread.unload();
+    assert!(thread.runtime().is_none());
+    assert!(matches!(thread.serialize(), Err(RuntimeError::NoGame)));
+}
+
+#[test]
+fn display_pacing_runs_only_the_frames_ticked() {
+    let Some(runtime) = mock_runtime("", "thread-display") else {
+        return;
+    };
+    let mut thread = EmulationThread::spawn().expect("spawn emulation thread");
+    thread.set_pacing(Pacing::Display);
+    thread.load(runtime);
+    let start = thread.serialize().expect("serialize");
+    std::thread::sleep(Duration::from_millis(100));
+    assert_eq!(thread.serialize().expect("serialize"), start);
+
+    thread.tick(Duration::from_millis(50));
+    wait_for(|| thread.new_frame()).expect("no frame after ticking");
+}
+
+#[test]
+fn av_changes_reach_the_render_side() {
+    let Some(runtime) = mock_runtime("timing", "thread-av") else {
+        return;
+    };
+    let mut thread = EmulationThread::spawn().expect("spawn emulation thread");
+    thread.load(runtime);
+    let event = wait_for(|| thread.poll_event()).expect("no AV change reported");
+    match event {
+        EmulationEvent::AvChanged(change) => {
+            assert!(change.timing_changed);
+            assert_eq!(change.av_info.timing.fps, 50.0);
+        }
+        EmulationEvent::Stopped(err) => panic!("emulation stopped: {err}"),
+    }
+    assert_eq!(thread.runtime().map(|view| view.fps()), Some(50.0));
+}
+
+#[test]
+fn cores_are_told_when_the_game_runs_fast() {
+    let Some(runtime) = mock_runtime("", "thread-fast") else {
+        return;
+    };
+    let mut thread = EmulationThread::spawn().expect("spawn emulation thread");
+    thread.load(runtime);
+    // The answer the core got on its latest frame, as it logs each change.
+    let fast_forwarding = |thread: &EmulationThread, expected: &str| {
+        let expected = expected.to_string();
+        wait_for(|| {
+            let log = thread.call(|runtime| runtime.recent_log()).ok()?;
+            let last = log
+                .iter()
+                .rev()
+                .find_map(|line| line.message.strip_prefix("env GET_FASTFORWARDING = "))?;
+            (last == expected).then_some(())
+        })
+        .is_some()
+    };
+
+    assert!(fast_forwarding(&thread, "false"));
+    thread.set_speed(Some(2.0));
+    assert!(fast_forwarding(&thread, "true"));
+    thread.set_speed(Some(0.5));
+    assert!(fast_forwarding(&thread, "false"));
+    thread.set_speed(None);
+    assert!(fast_forwarding(&thread, "true"));
+}
@@ -0,0 +1,138 @@
+use crate::{hash_bytes, is_rom_file, FeedError};
+use sevenz_rust::{Password, SevenZReader};
+use std::{
+    fmt,
+    fs::{self, File},
+    io::{self, Read},
+    path::{Path, PathBuf},
+};
+use zip::ZipArchive;
+
+/// Archive members larger than this are not ROMs we can run, so they are skipped unread.
+const MAX_MEMBER_SIZE: u64 = 64 * 1024 * 1024;
+
+/// Where a ROM lives: a plain file, or a member of a zip or 7z archive.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RomSource {
+    pub path: PathBuf,
+    /// Name of the ROM inside the archive at `path`.
+    pub member: Option<String>,
+}
+
+impl RomSource {
+    pub fn file(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            member: None,
+        }
+    }
+
+    pub fn is_archived(&self) -> bool {
+        self.member.is_some()
+    }
+
+    /// The ROM's own file name, e.g. for picking a system from its extension or a title
+    /// from its stem. For archive members this is the member name without directories.
+    pub fn rom_name(&self) -> &Path {
+        match &self.member {
+            Some(member) => Path::new(member.rsplit('/').next().unwrap_or(member)),
+            None => &self.path,
+        }
+    }
+
+    /// Reads the ROM, extracting it when it is archived.
+    pub fn read(&self) -> Result<Vec<u8>, FeedError> {
+        let Some(member) = &self.member else {
+            return Ok(fs::read(&self.path)?);
+        };
+        let mut found = None;
+        for_each_rom(&self.path, |name, data| {
+            if name == member {
+                found = Some(data);
+                false
+            } else {
+                true
+            }
+        })?;
+        found.ok_or_else(|| {
+            FeedError::Archive(format!("{} has no member {member}", self.path.display()))
+        })
+    }
+}
+
+impl fmt::Display for RomSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.member {
+            Some(member) => write!(f, "{}#{member}", self.path.display()),
+            None => write!(f, "{}", self.path.display()),
+        }
+    }
+}
+
+pub(crate) fn is_archive(path: &Path) -> bool {
+    matches!(
+        path.extension().and_then(|ext| ext.to_str()).map(|ext| ext.to_lowercase()),
+        Some(ext) if ext == "zip" || ext == "7z"
+    )
+}
+
+/// Hashes every ROM inside an archive; returns `(sha1, source)` pairs.
+pub(crate) fn scan_archive(path: &Path) -> Result<Vec<(String, RomSource)>, FeedError> {
+    let mut roms = Vec::new();
+    for_each_rom(path, |name, data| {
+        roms.push((
+            hash_bytes(&data),
+            RomSource {
+                path: path.to_path_buf(),
+                member: Some(name.to_string()),
+            },
+        ));
+        true
+    })?;
+    Ok(roms)
+}
+
+/// Calls `f` with the name and contents of each ROM in the archive until it returns false.
+fn for_each_rom(path: &Path, mut f: impl FnMut(&str, Vec<u8>) -> bool) -> Result<(), FeedError> {
+    let is_zip = path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("zip"));
+    if is_zip {
+        let mut archive = ZipArchive::new(File::open(path)?).map_err(archive_error(path))?;
+        for index in 0..archive.len() {
+            let mut entry = archive.by_index(index).map_err(archive_error(path))?;
+            let name = entry.name().to_string();
+            if !entry.is_file() || !wanted(&name, entry.size()) {
+                continue;
+            }
+            let mut data = Vec::with_capacity(entry.size() as usize);
+            entry.read_to_end(&mut data).map_err(archive_error(path))?;
+            if !f(&name, data) {
+                break;
+            }
+        }
+        return Ok(());
+    }
+
+    let mut archive = SevenZReader::open(path, Password::empty()).map_err(archive_error(path))?;
+    archive
+        .for_each_entries(|entry, reader| {
+            if entry.is_directory() || !wanted(entry.name(), entry.size()) {
+                // Solid archives decode every member in order, so skipped ones are drained.
+                io::copy(reader, &mut io::sink())?;
+                return Ok(true);
+            }
+            let mut data = Vec::with_capacity(entry.size() as usize);
+            reader.read_to_end(&mut data)?;
+            Ok(f(entry.name(), data))
+        })
+        .map_err(archive_error(path))
+}
+
+fn wanted(name: &str, size: u64) -> bool {
+    size <= MAX_MEMBER_SIZE && is_rom_file(Path::new(name))
+}
+
+fn archive_error<E: fmt::Display>(path: &Path) -> impl Fn(E) -> FeedError + '_ {
+    move |err| FeedError::Archive(format!("{}: {err}", path.display()))
+}
@@ -0,0 +1,149 @@
+use crate::{hash_file, FeedError};
+use playbyte_types::System;
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+use walkdir::WalkDir;
+
+/// BIOS images are tiny; anything larger is not worth hashing while scanning.
+const MAX_BIOS_SIZE: u64 = 64 * 1024;
+
+/// A BIOS or boot ROM a core can pick up from the system directory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KnownBios {
+    pub system: System,
+    /// Name the cores look for, relative to the system directory.
+    pub file_name: &'static str,
+    pub description: &'static str,
+    pub sha1: &'static str,
+}
+
+pub const KNOWN_BIOS: &[KnownBios] = &[
+    KnownBios {
+        system: System::Nes,
+        file_name: "disksys.rom",
+        description: "Famicom Disk System BIOS",
+        sha1: "57fe1bdee955bb48d357e463ccbf129496930b62",
+    },
+    KnownBios {
+        system: System::Gbc,
+        file_name: "gb_bios.bin",
+        description: "Game Boy boot ROM",
+        sha1: "4ed31ec6b0b175bb109c0eb5fd3d193da823339f",
+    },
+    KnownBios {
+        system: System::Gbc,
+        file_name: "gbc_bios.bin",
+        description: "Game Boy Color boot ROM",
+        sha1: "1293d68bf9643bc4f36954c1e80e38f39864528d",
+    },
+    KnownBios {
+        system: System::Gba,
+        file_name: "gba_bios.bin",
+        description: "Game Boy Advance BIOS",
+        sha1: "300c20df6731a33952ded8c436f7f186d25d3492",
+    },
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BiosStatus {
+    Present,
+    Missing,
+    /// A file with the expected name exists but is not the known dump.
+    Bad {
+        sha1: String,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct BiosEntry {
+    pub bios: KnownBios,
+    /// Where the core expects the file.
+    pub path: PathBuf,
+    pub status: BiosStatus,
+    /// A matching dump found under another name or in a subdirectory, if the expected file
+    /// is missing or bad. Cores only look at `path`, so it has to be renamed or moved there.
+    pub found_elsewhere: Option<PathBuf>,
+}
+
+/// Known BIOS files and their state in the system directory.
+#[derive(Debug)]
+pub struct BiosRegistry {
+    root: PathBuf,
+    entries: Vec<BiosEntry>,
+}
+
+impl BiosRegistry {
+    pub fn new(root: impl AsRef<Path>) -> Self {
+        let root = root.as_ref().to_path_buf();
+        let entries = KNOWN_BIOS
+            .iter()
+            .map(|bios| BiosEntry {
+                bios: *bios,
+                path: root.join(bios.file_name),
+                status: BiosStatus::Missing,
+                found_elsewhere: None,
+            })
+            .collect();
+        Self { root, entries }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Hashes the system directory and updates every entry; returns how many are present.
+    ///
+    /// A missing directory just leaves everything `Missing`.
+    pub fn scan(&mut self) -> Result<usize, FeedError> {
+        let mut by_hash: HashMap<String, PathBuf> = HashMap::new();
+        for entry in WalkDir::new(&self.root).into_iter().filter_map(Result::ok) {
+            if !entry.file_type().is_file() {
+                continue;
+            }
+            let small = entry
+                .metadata()
+                .map(|metadata| metadata.len() <= MAX_BIOS_SIZE)
+                .unwrap_or(false);
+            if small {
+                by_hash.insert(hash_file(entry.path())?, entry.path().to_path_buf());
+            }
+        }
+
+        let mut present = 0;
+        for entry in &mut self.entries {
+            entry.status = match fs::metadata(&entry.path) {
+                Ok(_) => {
+                    let sha1 = hash_file(&entry.path)?;
+                    if sha1 == entry.bios.sha1 {
+                        BiosStatus::Present
+                    } else {
+                        BiosStatus::Bad { sha1 }
+                    }
+                }
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => BiosStatus::Missing,
+                Err(err) => return Err(err.into()),
+            };
+            entry.found_elsewhere = match entry.status {
+                BiosStatus::Present => {
+                    present += 1;
+                    None
+                }
+                _ => by_hash.get(entry.bios.sha1).cloned(),
+            };
+        }
+        Ok(present)
+    }
+
+    pub fn entries(&self) -> &[BiosEntry] {
+        &self.entries
+    }
+
+    pub fn for_system(&self, system: System) -> impl Iterator<Item = &BiosEntry> {
+        self.entries
+            .iter()
+            .filter(move |entry| entry.bios.system == system)
+    }
+}
@@ -1,11 +1,16 @@
+mod archive;
+mod bios;
 mod romdb;
 
-use playbyte_types::{ByteMetadata, System};
+pub use archive::RomSource;
+pub use bios::{BiosEntry, BiosRegistry, BiosStatus, KnownBios, KNOWN_BIOS};
+use playbyte_types::{ByteMetadata, Resolution, RunAheadSettings, System, BYTE_FORMAT_VERSION};
 use romdb::{build_thumbnail_url, cover_path, RomDatabase};
 use serde::Deserialize;
 use sha1::{Digest, Sha1};
+use sha2::Sha256;
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
     fs,
     path::{Path, PathBuf},
     sync::{Arc, Mutex},
@@ -23,6 +28,12 @@ pub enum FeedError {
     Http(#[from] reqwest::Error),
     #[error("missing metadata for byte {0}")]
     MissingMetadata(String),
+    #[error("archive error: {0}")]
+    Archive(String),
+    #[error("byte {byte_id} uses format version {version}, which this build cannot read")]
+    UnsupportedByteFormat { byte_id: String, version: u32 },
+    #[error("byte {byte_id} is damaged: {reason}")]
+    CorruptByte { byte_id: String, reason: String },
 }
 
 #[derive(Clone)]
@@ -53,6 +64,10 @@ impl LocalByteStore {
         self.root.join("rom_titles.json")
     }
 
+    fn run_ahead_path(&self) -> PathBuf {
+        self.root.join("run_ahead.json")
+    }
+
     fn rom_official_overrides_path(&self) -> PathBuf {
         self.root.join("rom_official_overrides.json")
     }
@@ -65,6 +80,24 @@ impl LocalByteStore {
         self.root.join("covers")
     }
 
+    pub fn saves_root(&self) -> PathBuf {
+        self.root.join("saves")
+    }
+
+    pub fn save_ram_path(&self, rom_sha1: &str) -> PathBuf {
+        self.saves_root().join(format!("{rom_sha1}.srm"))
+    }
+
+    pub fn rtc_path(&self, rom_sha1: &str) -> PathBuf {
+        self.saves_root().join(format!("{rom_sha1}.rtc"))
+    }
+
+    fn core_options_path(&self, core_id: &str) -> PathBuf {
+        self.root
+            .join("core_options")
+            .join(format!("{core_id}.json"))
+    }
+
     pub fn load_index(&self) -> Result<Vec<ByteMetadata>, FeedError> {
         let bytes_root = self.bytes_root();
         if !bytes_root.exists() {
@@ -115,6 +148,8 @@ impl LocalByteStore {
         Ok(metadata)
     }
 
+    /// The Byte's uncompressed state, after checking its files against the hashes and sizes
+    /// in its metadata. Bytes in an older format are migrated first.
     pub fn load_state(&self, byte_id: &str) -> Result<Vec<u8>, FeedError> {
         if let Ok(guard) = self.state_cache.lock() {
             if let Some(cached) = guard.get(byte_id) {
@@ -122,13 +157,24 @@ impl LocalByteStore {
             }
         }
 
-        let metadata = self.get(byte_id)?;
-        let path = self
-            .bytes_root()
-            .join(&metadata.byte_id)
-            .join(&metadata.state_path);
-        let compressed = fs::read(path)?;
+        let mut metadata = self.get(byte_id)?;
+        if metadata.format_version > BYTE_FORMAT_VERSION {
+            return Err(FeedError::UnsupportedByteFormat {
+                byte_id: metadata.byte_id,
+                version: metadata.format_version,
+            });
+        }
+        if metadata.format_version < BYTE_FORMAT_VERSION {
+            metadata = self.migrate(metadata)?;
+        }
+        let byte_dir = self.bytes_root().join(&metadata.byte_id);
+        let compressed = fs::read(byte_dir.join(&metadata.state_path))?;
         let state = zstd::stream::decode_all(&compressed[..])?;
+        verify_state(&metadata, &state)?;
+        verify_thumbnail(
+            &metadata,
+            &fs::read(byte_dir.join(&metadata.thumbnail_path))?,
+        )?;
         let state_arc = Arc::new(state.clone());
         if let Ok(mut guard) = self.state_cache.lock() {
             guard.insert(byte_id.to_string(), state_arc);
@@ -149,6 +195,7 @@ impl LocalByteStore {
             .join(&metadata.byte_id)
             .join(&metadata.thumbnail_path);
         let data = fs::read(path)?;
+        verify_thumbnail(&metadata, &data)?;
         let data_arc = Arc::new(data.clone());
         if let Ok(mut guard) = self.thumbnail_cache.lock() {
             guard.insert(byte_id.to_string(), data_arc);
@@ -156,12 +203,62 @@ impl LocalByteStore {
         Ok(data)
     }
 
+    /// Replaces a Byte's thumbnail and its hash, leaving its state alone.
+    pub fn store_thumbnail(&self, byte_id: &str, thumbnail: &[u8]) -> Result<(), FeedError> {
+        let mut metadata = self.get(byte_id)?;
+        let path = self
+            .bytes_root()
+            .join(&metadata.byte_id)
+            .join(&metadata.thumbnail_path);
+        fs::write(path, thumbnail)?;
+        if !metadata.thumbnail_sha256.is_empty() {
+            metadata.thumbnail_sha256 = sha256_hex(thumbnail);
+            self.update_metadata(&metadata)?;
+        }
+        if let Ok(mut guard) = self.thumbnail_cache.lock() {
+            guard.insert(byte_id.to_string(), Arc::new(thumbnail.to_vec()));
+        }
+        Ok(())
+    }
+
+    /// Stores an encoded input movie as the Byte's ghost, replacing any it had, and
+    /// returns the updated metadata.
+    pub fn store_ghost(&self, byte_id: &str, movie: &[u8]) -> Result<ByteMetadata, FeedError> {
+        let mut metadata = self.get(byte_id)?;
+        let ghost_path = metadata
+            .ghost_path
+            .get_or_insert_with(|| "ghost.pbm".to_string());
+        fs::write(self.bytes_root().join(byte_id).join(ghost_path), movie)?;
+        self.update_metadata(&metadata)?;
+        Ok(metadata)
+    }
+
+    /// The Byte's encoded ghost movie, or `None` when it has none.
+    pub fn load_ghost(&self, byte_id: &str) -> Result<Option<Vec<u8>>, FeedError> {
+        let metadata = self.get(byte_id)?;
+        let Some(ghost_path) = metadata.ghost_path else {
+            return Ok(None);
+        };
+        Ok(Some(fs::read(
+            self.bytes_root().join(&metadata.byte_id).join(ghost_path),
+        )?))
+    }
+
+    /// Writes a new Byte and returns its metadata with the format version, hashes and state
+    /// size filled in. The caller fills in everything else, `serialize_size` included.
     pub fn save_byte(
         &self,
         metadata: &ByteMetadata,
         state: &[u8],
         thumbnail: &[u8],
-    ) -> Result<(), FeedError> {
+    ) -> Result<ByteMetadata, FeedError> {
+        let metadata = &ByteMetadata {
+            format_version: BYTE_FORMAT_VERSION,
+            state_sha256: sha256_hex(state),
+            thumbnail_sha256: sha256_hex(thumbnail),
+            state_size: state.len() as u64,
+            ..metadata.clone()
+        };
         let byte_dir = self.bytes_root().join(&metadata.byte_id);
         fs::create_dir_all(&byte_dir)?;
         let metadata_path = byte_dir.join("byte.json");
@@ -179,7 +276,27 @@ impl LocalByteStore {
             guard.push(metadata.clone());
         }
 
-        Ok(())
+        Ok(metadata.clone())
+    }
+
+    /// Brings a Byte in an older format up to `BYTE_FORMAT_VERSION` and saves its metadata.
+    fn migrate(&self, mut metadata: ByteMetadata) -> Result<ByteMetadata, FeedError> {
+        let byte_dir = self.bytes_root().join(&metadata.byte_id);
+        if metadata.format_version < 2 {
+            // Version 1 recorded no hashes or sizes, so they come from the files as they are.
+            // Its thumbnails were stored at the size of the picture. What the core reported
+            // as its serialize size is lost, so that stays unknown.
+            let compressed = fs::read(byte_dir.join(&metadata.state_path))?;
+            let state = zstd::stream::decode_all(&compressed[..])?;
+            let thumbnail = fs::read(byte_dir.join(&metadata.thumbnail_path))?;
+            metadata.state_sha256 = sha256_hex(&state);
+            metadata.state_size = state.len() as u64;
+            metadata.thumbnail_sha256 = sha256_hex(&thumbnail);
+            metadata.resolution = png_size(&thumbnail);
+        }
+        metadata.format_version = BYTE_FORMAT_VERSION;
+        self.update_metadata(&metadata)?;
+        Ok(metadata)
     }
 
     pub fn update_metadata(&self, metadata: &ByteMetadata) -> Result<(), FeedError> {
@@ -190,7 +307,10 @@ impl LocalByteStore {
         fs::write(metadata_path, serialized)?;
 
         if let Ok(mut guard) = self.index.lock() {
-            if let Some(entry) = guard.iter_mut().find(|entry| entry.byte_id == metadata.byte_id) {
+            if let Some(entry) = guard
+                .iter_mut()
+                .find(|entry| entry.byte_id == metadata.byte_id)
+            {
                 *entry = metadata.clone();
             } else {
                 guard.push(metadata.clone());
@@ -209,6 +329,16 @@ impl LocalByteStore {
         Ok(serde_json::from_str(&data)?)
     }
 
+    /// Per-core run-ahead settings, keyed by core id.
+    pub fn load_run_ahead(&self) -> Result<HashMap<String, RunAheadSettings>, FeedError> {
+        let path = self.run_ahead_path();
+        if !path.exists() {
+            return Ok(HashMap::new());
+        }
+        let data = fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&data)?)
+    }
+
     pub fn set_rom_title(&self, sha1: &str, title: &str) -> Result<(), FeedError> {
         let mut titles = self.load_rom_titles()?;
         let trimmed = title.trim();
@@ -250,6 +380,52 @@ impl LocalByteStore {
         Ok(())
     }
 
+    pub fn has_core_options(&self, core_id: &str) -> bool {
+        self.core_options_path(core_id).exists()
+    }
+
+    pub fn load_core_options(&self, core_id: &str) -> Result<HashMap<String, String>, FeedError> {
+        let path = self.core_options_path(core_id);
+        if !path.exists() {
+            return Ok(HashMap::new());
+        }
+        let data = fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&data)?)
+    }
+
+    pub fn save_core_options(
+        &self,
+        core_id: &str,
+        values: &HashMap<String, String>,
+    ) -> Result<(), FeedError> {
+        let path = self.core_options_path(core_id);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        // Sorted so the file stays diff-friendly for people editing it by hand.
+        let sorted: BTreeMap<&String, &String> = values.iter().collect();
+        let serialized = serde_json::to_string_pretty(&sorted)?;
+        fs::write(path, serialized)?;
+        Ok(())
+    }
+
+    /// Battery save RAM for a ROM, kept apart from Byte states.
+    pub fn load_save_ram(&self, rom_sha1: &str) -> Result<Option<Vec<u8>>, FeedError> {
+        read_optional(&self.save_ram_path(rom_sha1))
+    }
+
+    pub fn store_save_ram(&self, rom_sha1: &str, data: &[u8]) -> Result<(), FeedError> {
+        write_replacing(&self.save_ram_path(rom_sha1), data)
+    }
+
+    pub fn load_rtc(&self, rom_sha1: &str) -> Result<Option<Vec<u8>>, FeedError> {
+        read_optional(&self.rtc_path(rom_sha1))
+    }
+
+    pub fn store_rtc(&self, rom_sha1: &str, data: &[u8]) -> Result<(), FeedError> {
+        write_replacing(&self.rtc_path(rom_sha1), data)
+    }
+
     pub fn load_romdb(&self, system: System) -> Result<RomDatabase, FeedError> {
         if let Ok(guard) = self.romdb_cache.lock() {
             if let Some(db) = guard.get(&system) {
@@ -316,7 +492,7 @@ impl LocalByteStore {
 #[derive(Debug, Default)]
 pub struct RomLibrary {
     roots: Vec<PathBuf>,
-    index: HashMap<String, PathBuf>,
+    index: HashMap<String, RomSource>,
 }
 
 impl RomLibrary {
@@ -339,25 +515,36 @@ impl RomLibrary {
                     continue;
                 }
                 let path = entry.path();
-                if !is_rom_file(path) {
-                    continue;
+                if is_rom_file(path) {
+                    let hash = hash_file(path)?;
+                    self.index.insert(hash, RomSource::file(path));
+                    count += 1;
+                } else if archive::is_archive(path) {
+                    // ROMs are matched by the hash of the ROM itself, not of its archive.
+                    // A damaged archive only loses its own ROMs, not the whole scan.
+                    let roms = match archive::scan_archive(path) {
+                        Ok(roms) => roms,
+                        Err(FeedError::Archive(_)) => continue,
+                        Err(err) => return Err(err),
+                    };
+                    for (hash, source) in roms {
+                        self.index.insert(hash, source);
+                        count += 1;
+                    }
                 }
-                let hash = hash_file(path)?;
-                self.index.insert(hash, path.to_path_buf());
-                count += 1;
             }
         }
         Ok(count)
     }
 
-    pub fn find_by_hash(&self, sha1: &str) -> Option<PathBuf> {
+    pub fn find_by_hash(&self, sha1: &str) -> Option<RomSource> {
         self.index.get(sha1).cloned()
     }
 
-    pub fn entries(&self) -> Vec<(String, PathBuf)> {
+    pub fn entries(&self) -> Vec<(String, RomSource)> {
         self.index
             .iter()
-            .map(|(sha1, path)| (sha1.clone(), path.clone()))
+            .map(|(sha1, source)| (sha1.clone(), source.clone()))
             .collect()
     }
 
@@ -432,8 +619,78 @@ fn is_rom_file(path: &Path) -> bool {
 }
 
 fn hash_file(path: &Path) -> Result<String, FeedError> {
-    let data = fs::read(path)?;
+    Ok(hash_bytes(&fs::read(path)?))
+}
+
+fn hash_bytes(data: &[u8]) -> String {
     let mut hasher = Sha1::new();
     hasher.update(data);
-    Ok(format!("{:x}", hasher.finalize()))
+    format!("{:x}", hasher.finalize())
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(data);
+    format!("{:x}", hasher.finalize())
+}
+
+fn verify_state(metadata: &ByteMetadata, state: &[u8]) -> Result<(), FeedError> {
+    let reason = if state.len() as u64 != metadata.state_size {
+        format!(
+            "state is {} bytes, expected {}",
+            state.len(),
+            metadata.state_size
+        )
+    } else if sha256_hex(state) != metadata.state_sha256 {
+        "state does not match its hash".to_string()
+    } else {
+        return Ok(());
+    };
+    Err(FeedError::CorruptByte {
+        byte_id: metadata.byte_id.clone(),
+        reason,
+    })
+}
+
+/// Thumbnails of Bytes that have not been migrated yet have no hash to check.
+fn verify_thumbnail(metadata: &ByteMetadata, thumbnail: &[u8]) -> Result<(), FeedError> {
+    if metadata.thumbnail_sha256.is_empty() || sha256_hex(thumbnail) == metadata.thumbnail_sha256 {
+        return Ok(());
+    }
+    Err(FeedError::CorruptByte {
+        byte_id: metadata.byte_id.clone(),
+        reason: "thumbnail does not match its hash".to_string(),
+    })
+}
+
+/// Width and height from a PNG's header.
+fn png_size(data: &[u8]) -> Option<Resolution> {
+    if data.len() < 24 || !data.starts_with(b"\x89PNG\r\n\x1a\n") || &data[12..16] != b"IHDR" {
+        return None;
+    }
+    Some(Resolution {
+        width: u32::from_be_bytes(data[16..20].try_into().ok()?),
+        height: u32::from_be_bytes(data[20..24].try_into().ok()?),
+    })
+}
+
+fn read_optional(path: &Path) -> Result<Option<Vec<u8>>, FeedError> {
+    match fs::read(path) {
+        Ok(data) => Ok(Some(data)),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Writes through a temp file so a crash mid-write never leaves a truncated save behind.
+fn write_replacing(path: &Path, data: &[u8]) -> Result<(), FeedError> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut temp_path = path.as_os_str().to_owned();
+    temp_path.push(".tmp");
+    let temp_path = PathBuf::from(temp_path);
+    fs::write(&temp_path, data)?;
+    fs::rename(&temp_path, path)?;
+    Ok(())
 }
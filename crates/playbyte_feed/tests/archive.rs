@@ -0,0 +1,109 @@
+use playbyte_feed::{RomLibrary, RomSource};
+use sevenz_rust::{SevenZArchiveEntry, SevenZWriter};
+use std::io::Write;
+use zip::{write::SimpleFileOptions, CompressionMethod, ZipWriter};
+
+#[test]
+fn zipped_roms_hash_like_the_bare_file() {
+    let root = std::env::temp_dir().join(format!("playbyte-archive-test-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&root);
+    let bare = root.join("bare");
+    let zipped = root.join("zipped");
+    std::fs::create_dir_all(&bare).expect("create bare dir");
+    std::fs::create_dir_all(&zipped).expect("create zipped dir");
+
+    let rom: Vec<u8> = (0..4096u32).map(|value| (value * 7) as u8).collect();
+    std::fs::write(bare.join("game.gb"), &rom).expect("write rom");
+
+    let archive_path = zipped.join("game.zip");
+    let mut writer = ZipWriter::new(std::fs::File::create(&archive_path).expect("create zip"));
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    writer
+        .start_file("readme.txt", options)
+        .expect("start readme");
+    writer.write_all(b"not a rom").expect("write readme");
+    writer
+        .start_file("roms/game.gb", options)
+        .expect("start rom");
+    writer.write_all(&rom).expect("write rom member");
+    writer.finish().expect("finish zip");
+
+    let mut plain = RomLibrary::new();
+    plain.add_root(&bare);
+    assert_eq!(plain.scan().expect("scan bare"), 1);
+    let (sha1, _) = plain.entries().remove(0);
+
+    let mut library = RomLibrary::new();
+    library.add_root(&zipped);
+    assert_eq!(library.scan().expect("scan zipped"), 1);
+    let source = library.find_by_hash(&sha1).expect("zipped rom indexed");
+    assert_eq!(
+        source,
+        RomSource {
+            path: archive_path.clone(),
+            member: Some("roms/game.gb".to_string()),
+        }
+    );
+    assert_eq!(source.rom_name(), std::path::Path::new("game.gb"));
+    assert_eq!(source.read().expect("extract rom"), rom);
+
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[test]
+fn roms_in_7z_archives_are_indexed() {
+    let root = std::env::temp_dir().join(format!("playbyte-7z-test-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&root);
+    let archived = root.join("archived");
+    std::fs::create_dir_all(&archived).expect("create archived dir");
+
+    let rom: Vec<u8> = (0..4096u32).map(|value| (value * 13) as u8).collect();
+    let bare = root.join("game.gba");
+    std::fs::write(&bare, &rom).expect("write rom");
+
+    let archive_path = archived.join("game.7z");
+    let mut writer = SevenZWriter::create(&archive_path).expect("create 7z");
+    writer
+        .push_archive_entry(
+            SevenZArchiveEntry::from_path(&bare, "roms/game.gba".to_string()),
+            Some(std::fs::File::open(&bare).expect("open rom")),
+        )
+        .expect("add rom");
+    writer.finish().expect("finish 7z");
+
+    let mut library = RomLibrary::new();
+    library.add_root(&archived);
+    assert_eq!(library.scan().expect("scan 7z"), 1);
+    let (_, source) = library.entries().remove(0);
+    assert_eq!(source.member.as_deref(), Some("roms/game.gba"));
+    assert_eq!(source.read().expect("extract rom"), rom);
+
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[test]
+fn corrupt_archives_do_not_stop_the_scan() {
+    let root = std::env::temp_dir().join(format!("playbyte-corrupt-test-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&root);
+    std::fs::create_dir_all(&root).expect("create root");
+    std::fs::write(root.join("good.gb"), [0x42; 1024]).expect("write rom");
+
+    let rom: Vec<u8> = (0..4096u32).map(|value| (value * 5) as u8).collect();
+    let mut writer = ZipWriter::new(std::io::Cursor::new(Vec::new()));
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    writer.start_file("game.nes", options).expect("start rom");
+    writer.write_all(&rom).expect("write rom member");
+    let mut zip = writer.finish().expect("finish zip").into_inner();
+    // Damage the compressed data but leave the directory intact, so the member still lists.
+    let data_start = 30 + "game.nes".len();
+    for byte in &mut zip[data_start..data_start + 16] {
+        *byte ^= 0xff;
+    }
+    std::fs::write(root.join("broken.zip"), zip).expect("write zip");
+
+    let mut library = RomLibrary::new();
+    library.add_root(&root);
+    assert_eq!(library.scan().expect("scan with corrupt zip"), 1);
+
+    let _ = std::fs::remove_dir_all(&root);
+}
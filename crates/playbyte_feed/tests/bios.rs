@@ -0,0 +1,23 @@
+use playbyte_feed::{BiosRegistry, BiosStatus};
+use playbyte_types::System;
+
+#[test]
+fn bios_scan_reports_missing_and_bad_files() {
+    let root = std::env::temp_dir().join(format!("playbyte-bios-test-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&root);
+    std::fs::create_dir_all(&root).expect("create system dir");
+    std::fs::write(root.join("gba_bios.bin"), b"not a bios").expect("write bios");
+
+    let mut registry = BiosRegistry::new(&root);
+    let present = registry.scan().expect("scan failed");
+    assert_eq!(present, 0);
+
+    let gba: Vec<_> = registry.for_system(System::Gba).collect();
+    assert_eq!(gba.len(), 1);
+    assert!(matches!(gba[0].status, BiosStatus::Bad { .. }));
+    assert!(registry
+        .for_system(System::Gbc)
+        .all(|entry| entry.status == BiosStatus::Missing));
+
+    let _ = std::fs::remove_dir_all(&root);
+}
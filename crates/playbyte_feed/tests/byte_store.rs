@@ -0,0 +1,197 @@
+use playbyte_feed::{FeedError, LocalByteStore};
+use playbyte_types::{ByteMetadata, Resolution, BYTE_FORMAT_VERSION};
+use std::{fs, path::PathBuf};
+
+/// A `byte.json` as written before Bytes had a format version.
+const UNVERSIONED_METADATA: &str = r#"{
+  "byte_id": "byte-1",
+  "system": "gba",
+  "core_id": "mGBA",
+  "core_semver": "0.10.2",
+  "rom_sha1": "da39a3ee5e6b4b0d3255bfef95601890afd80709",
+  "region": null,
+  "title": "Byte",
+  "description": "",
+  "tags": [],
+  "author": "local",
+  "created_at": "2024-01-01T00:00:00Z",
+  "thumbnail_path": "thumbnail.png",
+  "state_path": "state.zst"
+}"#;
+
+/// The signature and header of a 240x160 PNG, which is all the store reads of it.
+fn thumbnail() -> Vec<u8> {
+    let mut png = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR".to_vec();
+    png.extend_from_slice(&240u32.to_be_bytes());
+    png.extend_from_slice(&160u32.to_be_bytes());
+    png.extend_from_slice(&[8, 6, 0, 0, 0]);
+    png
+}
+
+fn data_root(name: &str) -> PathBuf {
+    let root =
+        std::env::temp_dir().join(format!("playbyte-byte-store-{name}-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&root);
+    root
+}
+
+fn unversioned() -> ByteMetadata {
+    serde_json::from_str(UNVERSIONED_METADATA).expect("parse metadata")
+}
+
+#[test]
+fn saved_bytes_carry_hashes_and_sizes() {
+    let root = data_root("save");
+    let state = vec![7u8; 4096];
+    // A core with variable-size states may report more than it writes.
+    let metadata = ByteMetadata {
+        serialize_size: 8192,
+        ..unversioned()
+    };
+    let saved = LocalByteStore::new(&root)
+        .save_byte(&metadata, &state, &thumbnail())
+        .expect("save byte");
+    assert_eq!(saved.format_version, BYTE_FORMAT_VERSION);
+    assert_eq!(saved.state_size, 4096);
+    assert_eq!(saved.serialize_size, 8192);
+    assert_eq!(saved.state_sha256.len(), 64);
+    assert_eq!(saved.thumbnail_sha256.len(), 64);
+
+    let store = LocalByteStore::new(&root);
+    assert_eq!(store.load_state("byte-1").expect("load state"), state);
+    assert_eq!(
+        store.load_thumbnail("byte-1").expect("thumbnail"),
+        thumbnail()
+    );
+
+    let _ = fs::remove_dir_all(&root);
+}
+
+#[test]
+fn damaged_bytes_are_refused() {
+    let root = data_root("damaged");
+    let state = vec![7u8; 4096];
+    LocalByteStore::new(&root)
+        .save_byte(&unversioned(), &state, &thumbnail())
+        .expect("save byte");
+    let byte_dir = root.join("bytes").join("byte-1");
+
+    // Same size, different contents.
+    let mut other = state.clone();
+    other[100] = 8;
+    fs::write(
+        byte_dir.join("state.zst"),
+        zstd::stream::encode_all(&other[..], 3).expect("compress"),
+    )
+    .expect("write state");
+    let err = LocalByteStore::new(&root)
+        .load_state("byte-1")
+        .expect_err("state changed");
+    assert!(matches!(err, FeedError::CorruptByte { .. }), "{err}");
+
+    // A truncated state.
+    fs::write(
+        byte_dir.join("state.zst"),
+        zstd::stream::encode_all(&state[..1000], 3).expect("compress"),
+    )
+    .expect("write state");
+    let err = LocalByteStore::new(&root)
+        .load_state("byte-1")
+        .expect_err("state truncated");
+    assert!(err.to_string().contains("1000 bytes"), "{err}");
+
+    // An intact state with a swapped thumbnail.
+    fs::write(
+        byte_dir.join("state.zst"),
+        zstd::stream::encode_all(&state[..], 3).expect("compress"),
+    )
+    .expect("write state");
+    fs::write(byte_dir.join("thumbnail.png"), b"not a thumbnail").expect("write thumbnail");
+    let store = LocalByteStore::new(&root);
+    assert!(matches!(
+        store.load_state("byte-1"),
+        Err(FeedError::CorruptByte { .. })
+    ));
+    assert!(matches!(
+        store.load_thumbnail("byte-1"),
+        Err(FeedError::CorruptByte { .. })
+    ));
+
+    // Replacing the thumbnail through the store updates its hash.
+    store
+        .store_thumbnail("byte-1", &thumbnail())
+        .expect("store thumbnail");
+    assert_eq!(
+        LocalByteStore::new(&root)
+            .load_state("byte-1")
+            .expect("load state"),
+        state
+    );
+
+    let _ = fs::remove_dir_all(&root);
+}
+
+#[test]
+fn unversioned_bytes_are_migrated_on_load() {
+    let root = data_root("migrate");
+    let byte_dir = root.join("bytes").join("byte-1");
+    fs::create_dir_all(&byte_dir).expect("create byte dir");
+    fs::write(byte_dir.join("byte.json"), UNVERSIONED_METADATA).expect("write metadata");
+    let state = vec![3u8; 2048];
+    fs::write(
+        byte_dir.join("state.zst"),
+        zstd::stream::encode_all(&state[..], 3).expect("compress"),
+    )
+    .expect("write state");
+    fs::write(byte_dir.join("thumbnail.png"), thumbnail()).expect("write thumbnail");
+
+    let store = LocalByteStore::new(&root);
+    assert_eq!(store.get("byte-1").expect("metadata").format_version, 1);
+    assert_eq!(store.load_state("byte-1").expect("load state"), state);
+
+    let written: ByteMetadata =
+        serde_json::from_str(&fs::read_to_string(byte_dir.join("byte.json")).expect("read"))
+            .expect("parse metadata");
+    assert_eq!(written.format_version, BYTE_FORMAT_VERSION);
+    assert_eq!(written.state_size, 2048);
+    // What the core reported was never recorded, so it stays unknown.
+    assert_eq!(written.serialize_size, 0);
+    assert!(!written.state_sha256.is_empty());
+    assert!(!written.thumbnail_sha256.is_empty());
+    assert_eq!(
+        written.resolution,
+        Some(Resolution {
+            width: 240,
+            height: 160
+        })
+    );
+    assert_eq!(store.get("byte-1").expect("metadata").format_version, 2);
+
+    let _ = fs::remove_dir_all(&root);
+}
+
+#[test]
+fn bytes_from_newer_versions_are_refused() {
+    let root = data_root("newer");
+    let byte_dir = root.join("bytes").join("byte-1");
+    fs::create_dir_all(&byte_dir).expect("create byte dir");
+    let metadata = ByteMetadata {
+        format_version: BYTE_FORMAT_VERSION + 1,
+        ..unversioned()
+    };
+    fs::write(
+        byte_dir.join("byte.json"),
+        serde_json::to_string(&metadata).expect("serialize metadata"),
+    )
+    .expect("write metadata");
+
+    let err = LocalByteStore::new(&root)
+        .load_state("byte-1")
+        .expect_err("newer format");
+    assert!(
+        matches!(err, FeedError::UnsupportedByteFormat { version, .. } if version == BYTE_FORMAT_VERSION + 1),
+        "{err}"
+    );
+
+    let _ = fs::remove_dir_all(&root);
+}
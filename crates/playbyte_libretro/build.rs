@@ -0,0 +1,40 @@
+use std::{env, path::PathBuf};
+
+fn main() {
+    println!("cargo:rerun-if-changed=csrc/log_shim.c");
+    cc::Build::new()
+        .file("csrc/log_shim.c")
+        .compile("playbyte_log_shim");
+
+    build_mock_core();
+}
+
+/// Compiles `csrc/mock_core.c` into a loadable core for the conformance tests.
+///
+/// Failing to build it only skips those tests, so it never fails the crate build.
+fn build_mock_core() {
+    println!("cargo:rerun-if-changed=csrc/mock_core.c");
+    let extension = match env::var("CARGO_CFG_TARGET_OS").as_deref() {
+        Ok("linux") => "so",
+        Ok("macos") => "dylib",
+        _ => return,
+    };
+    let out_dir = PathBuf::from(env::var("OUT_DIR").expect("OUT_DIR is set by cargo"));
+    let output = out_dir.join(format!("mock_libretro.{extension}"));
+
+    let compiler = cc::Build::new().cargo_metadata(false).get_compiler();
+    let status = compiler
+        .to_command()
+        .args(["-shared", "-fPIC", "-O1", "-o"])
+        .arg(&output)
+        .arg("csrc/mock_core.c")
+        .status();
+    match status {
+        Ok(status) if status.success() => {
+            println!("cargo:rustc-env=PLAYBYTE_MOCK_CORE={}", output.display());
+        }
+        _ => println!(
+            "cargo:warning=could not build the mock libretro core; its tests will be skipped"
+        ),
+    }
+}
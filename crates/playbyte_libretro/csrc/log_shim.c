@@ -0,0 +1,34 @@
+/*
+ * printf-style entry points for RETRO_ENVIRONMENT_GET_LOG_INTERFACE.
+ *
+ * Rust cannot define C-variadic functions on stable, so the formatting happens here and
+ * the finished line is handed to playbyte_libretro_log_message. Like the other callbacks,
+ * there is one entry point per instance slot; keep the count in sync with
+ * MAX_CORE_INSTANCES.
+ */
+#include <stdarg.h>
+#include <stdio.h>
+
+void playbyte_libretro_log_message(unsigned slot, int level, const char *message);
+
+static void forward(unsigned slot, int level, const char *fmt, va_list args) {
+    char message[2048];
+    if (!fmt) {
+        return;
+    }
+    vsnprintf(message, sizeof(message), fmt, args);
+    playbyte_libretro_log_message(slot, level, message);
+}
+
+#define PLAYBYTE_LOG_SLOT(n)                                                  \
+    void playbyte_libretro_log_slot##n(int level, const char *fmt, ...) {     \
+        va_list args;                                                         \
+        va_start(args, fmt);                                                  \
+        forward(n, level, fmt, args);                                         \
+        va_end(args);                                                         \
+    }
+
+PLAYBYTE_LOG_SLOT(0)
+PLAYBYTE_LOG_SLOT(1)
+PLAYBYTE_LOG_SLOT(2)
+PLAYBYTE_LOG_SLOT(3)
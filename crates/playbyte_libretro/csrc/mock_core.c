@@ -0,0 +1,853 @@
+/*
+ * A tiny libretro core for the environment conformance tests.
+ *
+ * It queries the frontend through every environment command it can, reports what it got
+ * back through the log interface as "env NAME = VALUE" lines, and declares options,
+ * controllers, input descriptors and a memory map. The game file is a list of
+ * whitespace-separated flags:
+ *
+ *   fail      refuse to load, after logging an error
+ *   resize    SET_GEOMETRY to 320x288 at frame 10
+ *   timing    SET_SYSTEM_AV_INFO with 50 fps and 32 kHz audio at frame 10
+ *   shutdown  SHUTDOWN at frame 5
+ *   quirks:N  SET_SERIALIZATION_QUIRKS with N while loading
+ *   gl        render through SET_HW_RENDER with an OpenGL context, failing if refused
+ *   drift     tint the picture by the frames run since loading, which states leave out, so
+ *             a game restored to an earlier frame looks different from the first time
+ *   options:F declare mock_speed and mock_filter while loading, in format F: v0, v1,
+ *             v1_intl, v2 or v2_intl
+ *   redeclare SET_VARIABLES again at frame 1, with mock_palette as mono|warm
+ *   hang      never return from the first frame
+ *
+ * Every frame it polls GET_VARIABLE_UPDATE and logs the new mock_palette when told to, and
+ * logs GET_FASTFORWARDING whenever the answer changes. Saving or loading a state logs
+ * GET_SAVESTATE_CONTEXT the same way.
+ * Each frame draws a solid XRGB8888 colour derived from an LCG and queues 800 stereo
+ * samples. The LCG state is the whole serialized state and is also exposed as system RAM.
+ * Video RAM holds what the input callback answered during the last frame instead: four
+ * int16 values for each of ports 0 and 1, read from whichever device the frontend plugged
+ * in, followed by the port 0 pointer's x, y and pressed state.
+ * In GL mode the frame is red with a green bottom half instead, drawn with the origin at
+ * the bottom left so the frontend has to flip it.
+ */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+#define RETRO_API_VERSION 1
+
+#define RETRO_ENVIRONMENT_SET_ROTATION 1
+#define RETRO_ENVIRONMENT_GET_OVERSCAN 2
+#define RETRO_ENVIRONMENT_GET_CAN_DUPE 3
+#define RETRO_ENVIRONMENT_SET_MESSAGE 6
+#define RETRO_ENVIRONMENT_SHUTDOWN 7
+#define RETRO_ENVIRONMENT_SET_PERFORMANCE_LEVEL 8
+#define RETRO_ENVIRONMENT_GET_SYSTEM_DIRECTORY 9
+#define RETRO_ENVIRONMENT_SET_PIXEL_FORMAT 10
+#define RETRO_ENVIRONMENT_SET_INPUT_DESCRIPTORS 11
+#define RETRO_ENVIRONMENT_SET_HW_RENDER 14
+#define RETRO_ENVIRONMENT_GET_VARIABLE 15
+#define RETRO_ENVIRONMENT_SET_VARIABLES 16
+#define RETRO_ENVIRONMENT_SET_SUPPORT_NO_GAME 18
+#define RETRO_ENVIRONMENT_GET_LIBRETRO_PATH 19
+#define RETRO_ENVIRONMENT_GET_LOG_INTERFACE 27
+#define RETRO_ENVIRONMENT_GET_SAVE_DIRECTORY 31
+#define RETRO_ENVIRONMENT_SET_SYSTEM_AV_INFO 32
+#define RETRO_ENVIRONMENT_SET_CONTROLLER_INFO 35
+#define RETRO_ENVIRONMENT_SET_MEMORY_MAPS (36 | 0x10000)
+#define RETRO_ENVIRONMENT_SET_GEOMETRY 37
+#define RETRO_ENVIRONMENT_GET_LANGUAGE 39
+#define RETRO_ENVIRONMENT_GET_VARIABLE_UPDATE 17
+#define RETRO_ENVIRONMENT_SET_SUPPORT_ACHIEVEMENTS 42
+#define RETRO_ENVIRONMENT_SET_SERIALIZATION_QUIRKS 44
+#define RETRO_ENVIRONMENT_GET_AUDIO_VIDEO_ENABLE (47 | 0x10000)
+#define RETRO_ENVIRONMENT_GET_FASTFORWARDING (49 | 0x10000)
+#define RETRO_ENVIRONMENT_GET_PREFERRED_HW_RENDER 56
+#define RETRO_ENVIRONMENT_GET_INPUT_BITMASKS (51 | 0x10000)
+#define RETRO_ENVIRONMENT_GET_INPUT_MAX_USERS 61
+#define RETRO_ENVIRONMENT_SET_CORE_OPTIONS 53
+#define RETRO_ENVIRONMENT_SET_CORE_OPTIONS_INTL 54
+#define RETRO_ENVIRONMENT_SET_CORE_OPTIONS_V2 67
+#define RETRO_ENVIRONMENT_SET_CORE_OPTIONS_V2_INTL 68
+#define RETRO_ENVIRONMENT_GET_SAVESTATE_CONTEXT (72 | 0x10000)
+
+#define RETRO_DEVICE_JOYPAD 1
+#define RETRO_DEVICE_MOUSE 2
+#define RETRO_DEVICE_LIGHTGUN 4
+#define RETRO_DEVICE_ANALOG 5
+#define RETRO_DEVICE_POINTER 6
+#define RETRO_DEVICE_SUBCLASS(base, id) ((((id) + 1) << 8) | (base))
+#define RETRO_DEVICE_ID_JOYPAD_B 0
+#define RETRO_DEVICE_ID_JOYPAD_A 8
+#define RETRO_DEVICE_ID_JOYPAD_MASK 256
+#define RETRO_DEVICE_ID_MOUSE_X 0
+#define RETRO_DEVICE_ID_MOUSE_Y 1
+#define RETRO_DEVICE_ID_MOUSE_LEFT 2
+#define RETRO_DEVICE_ID_MOUSE_WHEELUP 4
+#define RETRO_DEVICE_ID_LIGHTGUN_TRIGGER 2
+#define RETRO_DEVICE_ID_LIGHTGUN_SCREEN_X 13
+#define RETRO_DEVICE_ID_LIGHTGUN_SCREEN_Y 14
+#define RETRO_DEVICE_ID_LIGHTGUN_IS_OFFSCREEN 15
+#define RETRO_DEVICE_ID_POINTER_X 0
+#define RETRO_DEVICE_ID_POINTER_Y 1
+#define RETRO_DEVICE_ID_POINTER_PRESSED 2
+#define RETRO_DEVICE_INDEX_ANALOG_LEFT 0
+#define RETRO_DEVICE_ID_ANALOG_X 0
+#define RETRO_DEVICE_ID_ANALOG_Y 1
+#define RETRO_MEMORY_SAVE_RAM 0
+#define RETRO_MEMORY_SYSTEM_RAM 2
+#define RETRO_MEMORY_VIDEO_RAM 3
+#define RETRO_PIXEL_FORMAT_XRGB8888 1
+#define RETRO_LOG_INFO 1
+#define RETRO_LOG_ERROR 3
+#define RETRO_HW_CONTEXT_OPENGL 1
+#define RETRO_HW_FRAME_BUFFER_VALID ((void *)-1)
+
+#define GL_COLOR_BUFFER_BIT 0x4000
+#define GL_SCISSOR_TEST 0x0C11
+#define GL_FRAMEBUFFER 0x8D40
+
+#define BASE_WIDTH 160
+#define BASE_HEIGHT 144
+#define MAX_WIDTH 320
+#define MAX_HEIGHT 288
+#define SAMPLE_RATE 48000.0
+#define FRAMES_PER_RUN 800
+#define MOCK_PORTS 2
+#define SAVE_RAM_SIZE 8192
+#define RETRO_NUM_CORE_OPTION_VALUES_MAX 128
+
+typedef bool (*retro_environment_t)(unsigned cmd, void *data);
+typedef void (*retro_video_refresh_t)(const void *data, unsigned width, unsigned height,
+                                      size_t pitch);
+typedef void (*retro_audio_sample_t)(int16_t left, int16_t right);
+typedef size_t (*retro_audio_sample_batch_t)(const int16_t *data, size_t frames);
+typedef void (*retro_input_poll_t)(void);
+typedef int16_t (*retro_input_state_t)(unsigned port, unsigned device, unsigned index,
+                                       unsigned id);
+typedef void (*retro_log_printf_t)(int level, const char *fmt, ...);
+typedef void (*retro_hw_context_reset_t)(void);
+typedef uintptr_t (*retro_hw_get_current_framebuffer_t)(void);
+typedef void (*retro_proc_address_t)(void);
+typedef retro_proc_address_t (*retro_hw_get_proc_address_t)(const char *sym);
+
+typedef void (*gl_bind_framebuffer_t)(unsigned target, unsigned framebuffer);
+typedef void (*gl_viewport_t)(int x, int y, int width, int height);
+typedef void (*gl_clear_color_t)(float r, float g, float b, float a);
+typedef void (*gl_clear_t)(unsigned mask);
+typedef void (*gl_toggle_t)(unsigned cap);
+
+struct retro_log_callback {
+    retro_log_printf_t log;
+};
+
+struct retro_hw_render_callback {
+    unsigned context_type;
+    retro_hw_context_reset_t context_reset;
+    retro_hw_get_current_framebuffer_t get_current_framebuffer;
+    retro_hw_get_proc_address_t get_proc_address;
+    bool depth;
+    bool stencil;
+    bool bottom_left_origin;
+    unsigned version_major;
+    unsigned version_minor;
+    bool cache_context;
+    retro_hw_context_reset_t context_destroy;
+    bool debug_context;
+};
+
+struct retro_message {
+    const char *msg;
+    unsigned frames;
+};
+
+struct retro_variable {
+    const char *key;
+    const char *value;
+};
+
+struct retro_core_option_value {
+    const char *value;
+    const char *label;
+};
+
+struct retro_core_option_definition {
+    const char *key;
+    const char *desc;
+    const char *info;
+    struct retro_core_option_value values[RETRO_NUM_CORE_OPTION_VALUES_MAX];
+    const char *default_value;
+};
+
+struct retro_core_options_intl {
+    const struct retro_core_option_definition *us;
+    const struct retro_core_option_definition *local;
+};
+
+struct retro_core_option_v2_category {
+    const char *key;
+    const char *desc;
+    const char *info;
+};
+
+struct retro_core_option_v2_definition {
+    const char *key;
+    const char *desc;
+    const char *desc_categorized;
+    const char *info;
+    const char *info_categorized;
+    const char *category_key;
+    struct retro_core_option_value values[RETRO_NUM_CORE_OPTION_VALUES_MAX];
+    const char *default_value;
+};
+
+struct retro_core_options_v2 {
+    const struct retro_core_option_v2_category *categories;
+    const struct retro_core_option_v2_definition *definitions;
+};
+
+struct retro_core_options_v2_intl {
+    const struct retro_core_options_v2 *us;
+    const struct retro_core_options_v2 *local;
+};
+
+struct retro_input_descriptor {
+    unsigned port;
+    unsigned device;
+    unsigned index;
+    unsigned id;
+    const char *description;
+};
+
+struct retro_controller_description {
+    const char *desc;
+    unsigned id;
+};
+
+struct retro_controller_info {
+    const struct retro_controller_description *types;
+    unsigned num_types;
+};
+
+struct retro_memory_descriptor {
+    uint64_t flags;
+    void *ptr;
+    size_t offset;
+    size_t start;
+    size_t select;
+    size_t disconnect;
+    size_t len;
+    const char *addrspace;
+};
+
+struct retro_memory_map {
+    const struct retro_memory_descriptor *descriptors;
+    unsigned num_descriptors;
+};
+
+struct retro_system_info {
+    const char *library_name;
+    const char *library_version;
+    const char *valid_extensions;
+    bool need_fullpath;
+    bool block_extract;
+};
+
+struct retro_game_geometry {
+    unsigned base_width;
+    unsigned base_height;
+    unsigned max_width;
+    unsigned max_height;
+    float aspect_ratio;
+};
+
+struct retro_system_timing {
+    double fps;
+    double sample_rate;
+};
+
+struct retro_system_av_info {
+    struct retro_game_geometry geometry;
+    struct retro_system_timing timing;
+};
+
+struct retro_game_info {
+    const char *path;
+    const void *data;
+    size_t size;
+    const char *meta;
+};
+
+static retro_environment_t environ_cb;
+static retro_video_refresh_t video_cb;
+static retro_audio_sample_batch_t audio_batch_cb;
+static retro_input_poll_t input_poll_cb;
+static retro_input_state_t input_state_cb;
+static retro_log_printf_t log_cb;
+
+static uint32_t frame[MAX_WIDTH * MAX_HEIGHT];
+static int16_t audio[FRAMES_PER_RUN * 2];
+static uint8_t save_ram[SAVE_RAM_SIZE];
+static int16_t input_echo[MOCK_PORTS * 4 + 3];
+static unsigned port_device[MOCK_PORTS] = {RETRO_DEVICE_JOYPAD, RETRO_DEVICE_JOYPAD};
+static uint64_t seed;
+static unsigned frame_count;
+static unsigned frames_run;
+static unsigned width = BASE_WIDTH;
+static unsigned height = BASE_HEIGHT;
+static bool flag_resize;
+static bool flag_timing;
+static bool flag_shutdown;
+static bool flag_gl;
+static bool flag_drift;
+static bool flag_redeclare;
+static bool flag_hang;
+static char flag_options[16];
+static bool input_bitmasks;
+/* The last GET_FASTFORWARDING answer logged: 0, 1, or -1 before the first. */
+static int fast_forwarding = -1;
+/* The last GET_SAVESTATE_CONTEXT answer logged, or -1 before the first. */
+static int savestate_context = -1;
+static struct retro_hw_render_callback hw_render;
+static struct {
+    gl_bind_framebuffer_t bind_framebuffer;
+    gl_viewport_t viewport;
+    gl_clear_color_t clear_color;
+    gl_clear_t clear;
+    gl_toggle_t enable;
+    gl_toggle_t disable;
+    gl_viewport_t scissor;
+} gl;
+
+static void say(int level, const char *fmt, ...) {
+    char buffer[512];
+    va_list args;
+    if (!log_cb) {
+        return;
+    }
+    va_start(args, fmt);
+    vsnprintf(buffer, sizeof(buffer), fmt, args);
+    va_end(args);
+    log_cb(level, "%s\n", buffer);
+}
+
+static void report_bool(const char *name, unsigned cmd) {
+    bool value = false;
+    bool ok = environ_cb(cmd, &value);
+    say(RETRO_LOG_INFO, "env %s = %s", name, ok ? (value ? "true" : "false") : "refused");
+}
+
+static void report_unsigned(const char *name, unsigned cmd) {
+    unsigned value = 0;
+    if (environ_cb(cmd, &value)) {
+        say(RETRO_LOG_INFO, "env %s = %u", name, value);
+    } else {
+        say(RETRO_LOG_INFO, "env %s = refused", name);
+    }
+}
+
+static void report_path(const char *name, unsigned cmd) {
+    const char *value = NULL;
+    if (environ_cb(cmd, &value) && value) {
+        say(RETRO_LOG_INFO, "env %s = %s", name, value);
+    } else {
+        say(RETRO_LOG_INFO, "env %s = refused", name);
+    }
+}
+
+static void context_reset(void) {
+    gl.bind_framebuffer = (gl_bind_framebuffer_t)hw_render.get_proc_address("glBindFramebuffer");
+    gl.viewport = (gl_viewport_t)hw_render.get_proc_address("glViewport");
+    gl.clear_color = (gl_clear_color_t)hw_render.get_proc_address("glClearColor");
+    gl.clear = (gl_clear_t)hw_render.get_proc_address("glClear");
+    gl.enable = (gl_toggle_t)hw_render.get_proc_address("glEnable");
+    gl.disable = (gl_toggle_t)hw_render.get_proc_address("glDisable");
+    gl.scissor = (gl_viewport_t)hw_render.get_proc_address("glScissor");
+    say(RETRO_LOG_INFO, "env context_reset = %s",
+        gl.bind_framebuffer && gl.clear && gl.scissor ? "ready" : "missing functions");
+}
+
+static void context_destroy(void) {
+    memset(&gl, 0, sizeof(gl));
+    say(RETRO_LOG_INFO, "env context_destroy = done");
+}
+
+static void draw_gl(void) {
+    if (!gl.clear) {
+        return;
+    }
+    gl.bind_framebuffer(GL_FRAMEBUFFER, (unsigned)hw_render.get_current_framebuffer());
+    gl.viewport(0, 0, (int)width, (int)height);
+    gl.clear_color(1.0f, 0.0f, 0.0f, 1.0f);
+    gl.clear(GL_COLOR_BUFFER_BIT);
+    gl.enable(GL_SCISSOR_TEST);
+    gl.scissor(0, 0, (int)width, (int)height / 2);
+    gl.clear_color(0.0f, 1.0f, 0.0f, 1.0f);
+    gl.clear(GL_COLOR_BUFFER_BIT);
+    gl.disable(GL_SCISSOR_TEST);
+    video_cb(RETRO_HW_FRAME_BUFFER_VALID, width, height, 0);
+}
+
+/* Declares the options named in the header comment, in the format the content asked for.
+ * mock_filter's default is not one of its values, so frontends fall back to the first. */
+static void declare_options(void) {
+    static const struct retro_variable v0[] = {
+        {"mock_speed", "Speed; normal|fast|slow"},
+        {"mock_broken", "no description separator"},
+        {NULL, NULL},
+    };
+    static const struct retro_core_option_definition v1[] = {
+        {"mock_speed",
+         "Speed",
+         "How fast the mock runs",
+         {{"normal", "Normal"}, {"fast", "Fast"}, {"slow", NULL}, {NULL, NULL}},
+         "fast"},
+        {"mock_filter", "Filter", NULL, {{"off", NULL}, {"on", NULL}, {NULL, NULL}}, "sharp"},
+        {NULL, NULL, NULL, {{NULL, NULL}}, NULL},
+    };
+    static const struct retro_core_option_definition local[] = {
+        {"mock_local", "Lokal", NULL, {{"an", NULL}, {NULL, NULL}}, "an"},
+        {NULL, NULL, NULL, {{NULL, NULL}}, NULL},
+    };
+    static const struct retro_core_options_intl v1_intl = {v1, local};
+    static const struct retro_core_option_v2_category categories[] = {
+        {"video", "Video", "Picture settings"},
+        {NULL, NULL, NULL},
+    };
+    static const struct retro_core_option_v2_definition definitions[] = {
+        {"mock_speed",
+         "Speed",
+         "Video > Speed",
+         "How fast the mock runs",
+         NULL,
+         "video",
+         {{"normal", "Normal"}, {"fast", "Fast"}, {"slow", NULL}, {NULL, NULL}},
+         "fast"},
+        {"mock_filter",
+         "Filter",
+         NULL,
+         NULL,
+         NULL,
+         NULL,
+         {{"off", NULL}, {"on", NULL}, {NULL, NULL}},
+         "sharp"},
+        {NULL, NULL, NULL, NULL, NULL, NULL, {{NULL, NULL}}, NULL},
+    };
+    static const struct retro_core_options_v2 v2 = {categories, definitions};
+    static const struct retro_core_options_v2_intl v2_intl = {&v2, NULL};
+
+    if (strcmp(flag_options, "v0") == 0) {
+        environ_cb(RETRO_ENVIRONMENT_SET_VARIABLES, (void *)v0);
+    } else if (strcmp(flag_options, "v1") == 0) {
+        environ_cb(RETRO_ENVIRONMENT_SET_CORE_OPTIONS, (void *)v1);
+    } else if (strcmp(flag_options, "v1_intl") == 0) {
+        environ_cb(RETRO_ENVIRONMENT_SET_CORE_OPTIONS_INTL, (void *)&v1_intl);
+    } else if (strcmp(flag_options, "v2") == 0) {
+        environ_cb(RETRO_ENVIRONMENT_SET_CORE_OPTIONS_V2, (void *)&v2);
+    } else if (strcmp(flag_options, "v2_intl") == 0) {
+        environ_cb(RETRO_ENVIRONMENT_SET_CORE_OPTIONS_V2_INTL, (void *)&v2_intl);
+    }
+}
+
+void retro_set_environment(retro_environment_t cb) {
+    static const struct retro_variable variables[] = {
+        {"mock_palette", "Palette; warm|cool"},
+        {NULL, NULL},
+    };
+    static const struct retro_controller_description pads[] = {
+        {"Mock Pad", RETRO_DEVICE_JOYPAD},
+        {"Mock Stick", RETRO_DEVICE_ANALOG},
+    };
+    static const struct retro_controller_description extras[] = {
+        {"Mock Mouse", RETRO_DEVICE_MOUSE},
+        {"Mock Gun", RETRO_DEVICE_SUBCLASS(RETRO_DEVICE_LIGHTGUN, 0)},
+    };
+    static const struct retro_controller_info ports[] = {
+        {pads, 2},
+        {extras, 2},
+        {NULL, 0},
+    };
+    bool no_game = false;
+
+    environ_cb = cb;
+    environ_cb(RETRO_ENVIRONMENT_SET_VARIABLES, (void *)variables);
+    environ_cb(RETRO_ENVIRONMENT_SET_CONTROLLER_INFO, (void *)ports);
+    environ_cb(RETRO_ENVIRONMENT_SET_SUPPORT_NO_GAME, &no_game);
+}
+
+void retro_set_video_refresh(retro_video_refresh_t cb) { video_cb = cb; }
+void retro_set_audio_sample(retro_audio_sample_t cb) { (void)cb; }
+void retro_set_audio_sample_batch(retro_audio_sample_batch_t cb) { audio_batch_cb = cb; }
+void retro_set_input_poll(retro_input_poll_t cb) { input_poll_cb = cb; }
+void retro_set_input_state(retro_input_state_t cb) { input_state_cb = cb; }
+
+void retro_init(void) {
+    struct retro_log_callback logging;
+    if (environ_cb(RETRO_ENVIRONMENT_GET_LOG_INTERFACE, &logging)) {
+        log_cb = logging.log;
+    }
+    seed = 1;
+    frame_count = 0;
+    width = BASE_WIDTH;
+    height = BASE_HEIGHT;
+    memset(save_ram, 0, sizeof(save_ram));
+    memset(input_echo, 0, sizeof(input_echo));
+    port_device[0] = RETRO_DEVICE_JOYPAD;
+    port_device[1] = RETRO_DEVICE_JOYPAD;
+}
+
+void retro_deinit(void) { log_cb = NULL; }
+
+unsigned retro_api_version(void) { return RETRO_API_VERSION; }
+
+void retro_get_system_info(struct retro_system_info *info) {
+    memset(info, 0, sizeof(*info));
+    info->library_name = "Mock";
+    info->library_version = "1";
+    info->valid_extensions = "mock|bin";
+    info->need_fullpath = false;
+}
+
+void retro_get_system_av_info(struct retro_system_av_info *info) {
+    info->geometry.base_width = width;
+    info->geometry.base_height = height;
+    info->geometry.max_width = MAX_WIDTH;
+    info->geometry.max_height = MAX_HEIGHT;
+    info->geometry.aspect_ratio = (float)width / (float)height;
+    info->timing.fps = 60.0;
+    info->timing.sample_rate = SAMPLE_RATE;
+}
+
+void retro_set_controller_port_device(unsigned port, unsigned device) {
+    say(RETRO_LOG_INFO, "env port %u device = %u", port, device);
+    if (port < MOCK_PORTS) {
+        port_device[port] = device;
+    }
+}
+
+void retro_reset(void) { seed = 1; }
+
+static void parse_flags(const struct retro_game_info *game, bool *fail, uint64_t *quirks) {
+    char text[256];
+    char *token;
+    size_t len = game->size < sizeof(text) - 1 ? game->size : sizeof(text) - 1;
+
+    memcpy(text, game->data, len);
+    text[len] = '\0';
+    for (token = strtok(text, " \t\r\n"); token; token = strtok(NULL, " \t\r\n")) {
+        if (strcmp(token, "fail") == 0) {
+            *fail = true;
+        } else if (strcmp(token, "resize") == 0) {
+            flag_resize = true;
+        } else if (strcmp(token, "timing") == 0) {
+            flag_timing = true;
+        } else if (strcmp(token, "shutdown") == 0) {
+            flag_shutdown = true;
+        } else if (strcmp(token, "gl") == 0) {
+            flag_gl = true;
+        } else if (strcmp(token, "drift") == 0) {
+            flag_drift = true;
+        } else if (strcmp(token, "hang") == 0) {
+            flag_hang = true;
+        } else if (strcmp(token, "redeclare") == 0) {
+            flag_redeclare = true;
+        } else if (strncmp(token, "options:", 8) == 0) {
+            snprintf(flag_options, sizeof(flag_options), "%s", token + 8);
+        } else if (strncmp(token, "quirks:", 7) == 0) {
+            *quirks = strtoull(token + 7, NULL, 10);
+        }
+    }
+}
+
+bool retro_load_game(const struct retro_game_info *game) {
+    static struct retro_input_descriptor descriptors[] = {
+        {0, RETRO_DEVICE_JOYPAD, 0, RETRO_DEVICE_ID_JOYPAD_A, "Jump"},
+        {0, RETRO_DEVICE_JOYPAD, 0, RETRO_DEVICE_ID_JOYPAD_B, "Run"},
+        {0, 0, 0, 0, NULL},
+    };
+    struct retro_memory_descriptor regions[2];
+    struct retro_memory_map map = {regions, 2};
+    struct retro_message message = {"mock core ready", 60};
+    struct retro_variable palette = {"mock_palette", NULL};
+    unsigned format = RETRO_PIXEL_FORMAT_XRGB8888;
+    unsigned rotation = 0;
+    unsigned level = 4;
+    bool achievements = true;
+    bool fail = false;
+    uint64_t quirks = 0;
+
+    flag_resize = flag_timing = flag_shutdown = flag_gl = flag_drift = flag_redeclare = flag_hang =
+        false;
+    flag_options[0] = '\0';
+    frames_run = 0;
+    fast_forwarding = -1;
+    savestate_context = -1;
+    if (!game || !game->data) {
+        say(RETRO_LOG_ERROR, "no content");
+        return false;
+    }
+    parse_flags(game, &fail, &quirks);
+    if (fail) {
+        say(RETRO_LOG_ERROR, "content asked to fail");
+        return false;
+    }
+    if (!environ_cb(RETRO_ENVIRONMENT_SET_PIXEL_FORMAT, &format)) {
+        say(RETRO_LOG_ERROR, "XRGB8888 is not supported");
+        return false;
+    }
+
+    report_bool("GET_OVERSCAN", RETRO_ENVIRONMENT_GET_OVERSCAN);
+    report_bool("GET_CAN_DUPE", RETRO_ENVIRONMENT_GET_CAN_DUPE);
+    report_bool("GET_INPUT_BITMASKS", RETRO_ENVIRONMENT_GET_INPUT_BITMASKS);
+    input_bitmasks = environ_cb(RETRO_ENVIRONMENT_GET_INPUT_BITMASKS, NULL);
+    report_unsigned("GET_LANGUAGE", RETRO_ENVIRONMENT_GET_LANGUAGE);
+    report_unsigned("GET_INPUT_MAX_USERS", RETRO_ENVIRONMENT_GET_INPUT_MAX_USERS);
+    report_unsigned("GET_AUDIO_VIDEO_ENABLE", RETRO_ENVIRONMENT_GET_AUDIO_VIDEO_ENABLE);
+    report_unsigned("GET_PREFERRED_HW_RENDER", RETRO_ENVIRONMENT_GET_PREFERRED_HW_RENDER);
+    report_path("GET_SYSTEM_DIRECTORY", RETRO_ENVIRONMENT_GET_SYSTEM_DIRECTORY);
+    report_path("GET_SAVE_DIRECTORY", RETRO_ENVIRONMENT_GET_SAVE_DIRECTORY);
+    report_path("GET_LIBRETRO_PATH", RETRO_ENVIRONMENT_GET_LIBRETRO_PATH);
+    declare_options();
+    if (environ_cb(RETRO_ENVIRONMENT_GET_VARIABLE, &palette) && palette.value) {
+        say(RETRO_LOG_INFO, "env GET_VARIABLE mock_palette = %s", palette.value);
+    }
+    say(RETRO_LOG_INFO, "env SET_ROTATION 0 = %s",
+        environ_cb(RETRO_ENVIRONMENT_SET_ROTATION, &rotation) ? "true" : "false");
+
+    environ_cb(RETRO_ENVIRONMENT_SET_INPUT_DESCRIPTORS, descriptors);
+    environ_cb(RETRO_ENVIRONMENT_SET_PERFORMANCE_LEVEL, &level);
+    environ_cb(RETRO_ENVIRONMENT_SET_SUPPORT_ACHIEVEMENTS, &achievements);
+    if (quirks) {
+        environ_cb(RETRO_ENVIRONMENT_SET_SERIALIZATION_QUIRKS, &quirks);
+    }
+    if (flag_gl) {
+        memset(&hw_render, 0, sizeof(hw_render));
+        hw_render.context_type = RETRO_HW_CONTEXT_OPENGL;
+        hw_render.context_reset = context_reset;
+        hw_render.context_destroy = context_destroy;
+        hw_render.bottom_left_origin = true;
+        if (!environ_cb(RETRO_ENVIRONMENT_SET_HW_RENDER, &hw_render)) {
+            say(RETRO_LOG_ERROR, "hardware rendering refused");
+            return false;
+        }
+    }
+
+    memset(regions, 0, sizeof(regions));
+    regions[0].ptr = &seed;
+    regions[0].start = 0xc000;
+    regions[0].len = sizeof(seed);
+    regions[0].addrspace = "WRAM";
+    regions[1].ptr = save_ram;
+    regions[1].start = 0xa000;
+    regions[1].len = sizeof(save_ram);
+    regions[1].addrspace = "SRAM";
+    environ_cb(RETRO_ENVIRONMENT_SET_MEMORY_MAPS, &map);
+    environ_cb(RETRO_ENVIRONMENT_SET_MESSAGE, &message);
+    return true;
+}
+
+bool retro_load_game_special(unsigned type, const struct retro_game_info *info, size_t num) {
+    (void)type;
+    (void)info;
+    (void)num;
+    return false;
+}
+
+void retro_unload_game(void) {}
+
+unsigned retro_get_region(void) { return 0; }
+
+static void echo_input(void) {
+    static const unsigned mouse_ids[] = {RETRO_DEVICE_ID_MOUSE_X, RETRO_DEVICE_ID_MOUSE_Y,
+                                         RETRO_DEVICE_ID_MOUSE_LEFT,
+                                         RETRO_DEVICE_ID_MOUSE_WHEELUP};
+    static const unsigned gun_ids[] = {
+        RETRO_DEVICE_ID_LIGHTGUN_SCREEN_X, RETRO_DEVICE_ID_LIGHTGUN_SCREEN_Y,
+        RETRO_DEVICE_ID_LIGHTGUN_TRIGGER, RETRO_DEVICE_ID_LIGHTGUN_IS_OFFSCREEN};
+    int16_t *pointer = &input_echo[MOCK_PORTS * 4];
+    unsigned port;
+    unsigned i;
+
+    memset(input_echo, 0, sizeof(input_echo));
+    for (port = 0; port < MOCK_PORTS; port++) {
+        int16_t *out = &input_echo[port * 4];
+        unsigned device = port_device[port];
+        switch (device & 0xff) {
+        case RETRO_DEVICE_JOYPAD:
+        case RETRO_DEVICE_ANALOG:
+            out[0] = input_state_cb(port, RETRO_DEVICE_ANALOG, RETRO_DEVICE_INDEX_ANALOG_LEFT,
+                                    RETRO_DEVICE_ID_ANALOG_X);
+            out[1] = input_state_cb(port, RETRO_DEVICE_ANALOG, RETRO_DEVICE_INDEX_ANALOG_LEFT,
+                                    RETRO_DEVICE_ID_ANALOG_Y);
+            out[2] = input_state_cb(port, RETRO_DEVICE_JOYPAD, 0, RETRO_DEVICE_ID_JOYPAD_A);
+            if (input_bitmasks) {
+                out[3] = input_state_cb(port, RETRO_DEVICE_JOYPAD, 0, RETRO_DEVICE_ID_JOYPAD_MASK);
+            }
+            break;
+        case RETRO_DEVICE_MOUSE:
+            for (i = 0; i < 4; i++) {
+                out[i] = input_state_cb(port, device, 0, mouse_ids[i]);
+            }
+            break;
+        case RETRO_DEVICE_LIGHTGUN:
+            for (i = 0; i < 4; i++) {
+                out[i] = input_state_cb(port, device, 0, gun_ids[i]);
+            }
+            break;
+        default:
+            break;
+        }
+    }
+    pointer[0] = input_state_cb(0, RETRO_DEVICE_POINTER, 0, RETRO_DEVICE_ID_POINTER_X);
+    pointer[1] = input_state_cb(0, RETRO_DEVICE_POINTER, 0, RETRO_DEVICE_ID_POINTER_Y);
+    pointer[2] = input_state_cb(0, RETRO_DEVICE_POINTER, 0, RETRO_DEVICE_ID_POINTER_PRESSED);
+}
+
+static uint32_t next_random(void) {
+    seed = seed * 6364136223846793005ULL + 1442695040888963407ULL;
+    return (uint32_t)(seed >> 33);
+}
+
+void retro_run(void) {
+    uint32_t colour;
+    bool updated = false;
+    bool fast = false;
+    size_t i;
+
+    input_poll_cb();
+    if (flag_hang) {
+        for (;;) {
+            /* Spin until the frontend gives up on us. */
+        }
+    }
+    if (input_state_cb(0, RETRO_DEVICE_JOYPAD, 0, RETRO_DEVICE_ID_JOYPAD_A)) {
+        seed ^= 0xa5a5a5a5ULL;
+    }
+    echo_input();
+    frame_count++;
+    frames_run++;
+
+    if (flag_resize && frame_count == 10) {
+        struct retro_game_geometry geometry = {MAX_WIDTH, MAX_HEIGHT, MAX_WIDTH, MAX_HEIGHT,
+                                               (float)MAX_WIDTH / (float)MAX_HEIGHT};
+        if (environ_cb(RETRO_ENVIRONMENT_SET_GEOMETRY, &geometry)) {
+            width = MAX_WIDTH;
+            height = MAX_HEIGHT;
+        }
+    }
+    if (flag_timing && frame_count == 10) {
+        struct retro_system_av_info info;
+        retro_get_system_av_info(&info);
+        info.timing.fps = 50.0;
+        info.timing.sample_rate = 32000.0;
+        environ_cb(RETRO_ENVIRONMENT_SET_SYSTEM_AV_INFO, &info);
+    }
+    if (flag_shutdown && frame_count == 5) {
+        environ_cb(RETRO_ENVIRONMENT_SHUTDOWN, NULL);
+    }
+    if (flag_redeclare && frame_count == 1) {
+        static const struct retro_variable variables[] = {
+            {"mock_palette", "Palette; mono|warm"},
+            {NULL, NULL},
+        };
+        environ_cb(RETRO_ENVIRONMENT_SET_VARIABLES, (void *)variables);
+    }
+    if (environ_cb(RETRO_ENVIRONMENT_GET_FASTFORWARDING, &fast) && (int)fast != fast_forwarding) {
+        fast_forwarding = fast;
+        say(RETRO_LOG_INFO, "env GET_FASTFORWARDING = %s", fast ? "true" : "false");
+    }
+    if (environ_cb(RETRO_ENVIRONMENT_GET_VARIABLE_UPDATE, &updated) && updated) {
+        struct retro_variable palette = {"mock_palette", NULL};
+        if (environ_cb(RETRO_ENVIRONMENT_GET_VARIABLE, &palette) && palette.value) {
+            say(RETRO_LOG_INFO, "env GET_VARIABLE_UPDATE mock_palette = %s", palette.value);
+        }
+    }
+
+    colour = next_random() & 0x00ffffff;
+    if (flag_drift) {
+        colour ^= (frames_run - frame_count) & 0xff;
+    }
+    if (flag_gl) {
+        draw_gl();
+    } else {
+        for (i = 0; i < (size_t)width * height; i++) {
+            frame[i] = colour;
+        }
+        video_cb(frame, width, height, width * sizeof(uint32_t));
+    }
+
+    for (i = 0; i < FRAMES_PER_RUN * 2; i++) {
+        audio[i] = (int16_t)(next_random() & 0x0fff);
+    }
+    audio_batch_cb(audio, FRAMES_PER_RUN);
+}
+
+size_t retro_serialize_size(void) { return sizeof(seed) + sizeof(frame_count); }
+
+static void report_savestate_context(void) {
+    int context = 0;
+    if (environ_cb(RETRO_ENVIRONMENT_GET_SAVESTATE_CONTEXT, &context) &&
+        context != savestate_context) {
+        savestate_context = context;
+        say(RETRO_LOG_INFO, "env GET_SAVESTATE_CONTEXT = %d", context);
+    }
+}
+
+bool retro_serialize(void *data, size_t size) {
+    report_savestate_context();
+    if (size < retro_serialize_size()) {
+        return false;
+    }
+    memcpy(data, &seed, sizeof(seed));
+    memcpy((uint8_t *)data + sizeof(seed), &frame_count, sizeof(frame_count));
+    return true;
+}
+
+bool retro_unserialize(const void *data, size_t size) {
+    report_savestate_context();
+    if (size < retro_serialize_size()) {
+        return false;
+    }
+    memcpy(&seed, data, sizeof(seed));
+    memcpy(&frame_count, (const uint8_t *)data + sizeof(seed), sizeof(frame_count));
+    return true;
+}
+
+void retro_cheat_reset(void) {}
+
+void retro_cheat_set(unsigned index, bool enabled, const char *code) {
+    (void)index;
+    (void)enabled;
+    (void)code;
+}
+
+void *retro_get_memory_data(unsigned id) {
+    switch (id) {
+    case RETRO_MEMORY_SAVE_RAM:
+        return save_ram;
+    case RETRO_MEMORY_SYSTEM_RAM:
+        return &seed;
+    case RETRO_MEMORY_VIDEO_RAM:
+        return input_echo;
+    default:
+        return NULL;
+    }
+}
+
+size_t retro_get_memory_size(unsigned id) {
+    switch (id) {
+    case RETRO_MEMORY_SAVE_RAM:
+        return sizeof(save_ram);
+    case RETRO_MEMORY_SYSTEM_RAM:
+        return sizeof(seed);
+    case RETRO_MEMORY_VIDEO_RAM:
+        return sizeof(input_echo);
+    default:
+        return 0;
+    }
+}
@@ -0,0 +1,145 @@
+//! RETRO_ENVIRONMENT_GET_LOG_INTERFACE support.
+//!
+//! Cores log through a printf-style callback. `csrc/log_shim.c` formats the message and
+//! passes it back here, where it goes to the `log` crate under a `libretro::<core id>`
+//! target and into a short per-core history.
+
+use crate::{with_callbacks, MAX_CORE_INSTANCES};
+use std::{
+    collections::VecDeque,
+    ffi::CStr,
+    fmt,
+    os::raw::{c_char, c_int, c_uint},
+};
+
+/// Number of lines each core keeps for error reports.
+pub const LOG_HISTORY: usize = 64;
+
+pub(crate) type RetroLogPrintfFn = unsafe extern "C" fn(level: c_int, fmt: *const c_char, ...);
+
+#[repr(C)]
+pub(crate) struct RetroLogCallback {
+    pub log: RetroLogPrintfFn,
+}
+
+extern "C" {
+    fn playbyte_libretro_log_slot0(level: c_int, fmt: *const c_char, ...);
+    fn playbyte_libretro_log_slot1(level: c_int, fmt: *const c_char, ...);
+    fn playbyte_libretro_log_slot2(level: c_int, fmt: *const c_char, ...);
+    fn playbyte_libretro_log_slot3(level: c_int, fmt: *const c_char, ...);
+}
+
+pub(crate) const LOG_PRINTF: [RetroLogPrintfFn; MAX_CORE_INSTANCES] = [
+    playbyte_libretro_log_slot0,
+    playbyte_libretro_log_slot1,
+    playbyte_libretro_log_slot2,
+    playbyte_libretro_log_slot3,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum LogLevel {
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+impl LogLevel {
+    pub(crate) fn from_raw(level: c_int) -> Self {
+        match level {
+            0 => Self::Debug,
+            1 => Self::Info,
+            2 => Self::Warn,
+            _ => Self::Error,
+        }
+    }
+}
+
+impl From<LogLevel> for log::Level {
+    fn from(level: LogLevel) -> Self {
+        match level {
+            LogLevel::Debug => log::Level::Debug,
+            LogLevel::Info => log::Level::Info,
+            LogLevel::Warn => log::Level::Warn,
+            LogLevel::Error => log::Level::Error,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogLine {
+    pub level: LogLevel,
+    pub message: String,
+}
+
+impl fmt::Display for LogLine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let level = match self.level {
+            LogLevel::Debug => "debug",
+            LogLevel::Info => "info",
+            LogLevel::Warn => "warn",
+            LogLevel::Error => "error",
+        };
+        write!(f, "[{level}] {}", self.message)
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct CoreLog {
+    target: String,
+    lines: VecDeque<LogLine>,
+}
+
+impl Default for CoreLog {
+    fn default() -> Self {
+        Self {
+            target: "libretro".to_string(),
+            lines: VecDeque::with_capacity(LOG_HISTORY),
+        }
+    }
+}
+
+impl CoreLog {
+    pub fn set_core_id(&mut self, core_id: &str) {
+        self.target = format!("libretro::{core_id}");
+    }
+
+    pub fn record(&mut self, level: LogLevel, message: &str) {
+        log::log!(target: &self.target, level.into(), "{message}");
+        if self.lines.len() == LOG_HISTORY {
+            self.lines.pop_front();
+        }
+        self.lines.push_back(LogLine {
+            level,
+            message: message.to_string(),
+        });
+    }
+
+    pub fn lines(&self) -> Vec<LogLine> {
+        self.lines.iter().cloned().collect()
+    }
+}
+
+/// Called by the C shim with a fully formatted message.
+#[no_mangle]
+unsafe extern "C" fn playbyte_libretro_log_message(
+    slot: c_uint,
+    level: c_int,
+    message: *const c_char,
+) {
+    let slot = slot as usize;
+    if message.is_null() || slot >= MAX_CORE_INSTANCES {
+        return;
+    }
+    let message = CStr::from_ptr(message).to_string_lossy();
+    // Cores terminate most messages with a newline of their own.
+    let message = message.trim_end();
+    if message.is_empty() {
+        return;
+    }
+    let _ = with_callbacks(slot, |callbacks| {
+        callbacks
+            .log_mut()
+            .record(LogLevel::from_raw(level), message)
+    });
+}
@@ -0,0 +1,629 @@
+//! The `retro_environment` dispatcher and the typed values cores hand us through it.
+//!
+//! `docs/environment.md` lists every command and whether we answer, record or refuse it.
+
+use crate::{
+    core_log::{self, LogLevel},
+    hw_render::{self, HwRenderCallbacks},
+    options, with_callbacks, Callbacks, CoreOption, CoreOptionCategory, RetroGameGeometry,
+    RetroPixelFormat, RetroSystemAvInfo, SystemAvInfo,
+};
+use std::{
+    ffi::{CStr, CString},
+    os::raw::{c_char, c_int, c_uint, c_void},
+    ptr,
+};
+
+/// Flag some commands carry while they are still marked experimental in `libretro.h`.
+const RETRO_ENVIRONMENT_EXPERIMENTAL: u32 = 0x10000;
+
+const RETRO_ENVIRONMENT_SET_ROTATION: u32 = 1;
+const RETRO_ENVIRONMENT_GET_OVERSCAN: u32 = 2;
+const RETRO_ENVIRONMENT_GET_CAN_DUPE: u32 = 3;
+const RETRO_ENVIRONMENT_SET_MESSAGE: u32 = 6;
+const RETRO_ENVIRONMENT_SHUTDOWN: u32 = 7;
+const RETRO_ENVIRONMENT_SET_PERFORMANCE_LEVEL: u32 = 8;
+const RETRO_ENVIRONMENT_GET_SYSTEM_DIRECTORY: u32 = 9;
+const RETRO_ENVIRONMENT_SET_PIXEL_FORMAT: u32 = 10;
+const RETRO_ENVIRONMENT_SET_INPUT_DESCRIPTORS: u32 = 11;
+const RETRO_ENVIRONMENT_SET_HW_RENDER: u32 = 14;
+const RETRO_ENVIRONMENT_GET_VARIABLE: u32 = 15;
+const RETRO_ENVIRONMENT_SET_VARIABLES: u32 = 16;
+const RETRO_ENVIRONMENT_GET_VARIABLE_UPDATE: u32 = 17;
+const RETRO_ENVIRONMENT_SET_SUPPORT_NO_GAME: u32 = 18;
+const RETRO_ENVIRONMENT_GET_LIBRETRO_PATH: u32 = 19;
+const RETRO_ENVIRONMENT_GET_INPUT_DEVICE_CAPABILITIES: u32 = 24;
+const RETRO_ENVIRONMENT_GET_LOG_INTERFACE: u32 = 27;
+const RETRO_ENVIRONMENT_GET_SAVE_DIRECTORY: u32 = 31;
+const RETRO_ENVIRONMENT_SET_SYSTEM_AV_INFO: u32 = 32;
+const RETRO_ENVIRONMENT_SET_CONTROLLER_INFO: u32 = 35;
+const RETRO_ENVIRONMENT_SET_MEMORY_MAPS: u32 = 36;
+const RETRO_ENVIRONMENT_SET_GEOMETRY: u32 = 37;
+const RETRO_ENVIRONMENT_GET_LANGUAGE: u32 = 39;
+const RETRO_ENVIRONMENT_SET_SUPPORT_ACHIEVEMENTS: u32 = 42;
+const RETRO_ENVIRONMENT_SET_SERIALIZATION_QUIRKS: u32 = 44;
+/// Shares its number with SET_SERIALIZATION_QUIRKS and only differs by the experimental flag.
+const RETRO_ENVIRONMENT_SET_HW_SHARED_CONTEXT: u32 = 44 | RETRO_ENVIRONMENT_EXPERIMENTAL;
+const RETRO_ENVIRONMENT_GET_AUDIO_VIDEO_ENABLE: u32 = 47;
+const RETRO_ENVIRONMENT_GET_FASTFORWARDING: u32 = 49;
+const RETRO_ENVIRONMENT_GET_INPUT_BITMASKS: u32 = 51;
+const RETRO_ENVIRONMENT_GET_CORE_OPTIONS_VERSION: u32 = 52;
+const RETRO_ENVIRONMENT_SET_CORE_OPTIONS: u32 = 53;
+const RETRO_ENVIRONMENT_SET_CORE_OPTIONS_INTL: u32 = 54;
+const RETRO_ENVIRONMENT_SET_CORE_OPTIONS_DISPLAY: u32 = 55;
+const RETRO_ENVIRONMENT_GET_PREFERRED_HW_RENDER: u32 = 56;
+const RETRO_ENVIRONMENT_GET_MESSAGE_INTERFACE_VERSION: u32 = 59;
+const RETRO_ENVIRONMENT_SET_MESSAGE_EXT: u32 = 60;
+const RETRO_ENVIRONMENT_GET_INPUT_MAX_USERS: u32 = 61;
+const RETRO_ENVIRONMENT_SET_CORE_OPTIONS_V2: u32 = 67;
+const RETRO_ENVIRONMENT_SET_CORE_OPTIONS_V2_INTL: u32 = 68;
+const RETRO_ENVIRONMENT_SET_VARIABLE: u32 = 70;
+const RETRO_ENVIRONMENT_GET_SAVESTATE_CONTEXT: u32 = 72;
+
+const CORE_OPTIONS_VERSION: u32 = 2;
+const MESSAGE_INTERFACE_VERSION: u32 = 1;
+/// GET_INPUT_DEVICE_CAPABILITIES bits: joypad, mouse, lightgun, analog and pointer.
+const INPUT_DEVICE_CAPABILITIES: u64 = (1 << 1) | (1 << 2) | (1 << 4) | (1 << 5) | (1 << 6);
+/// GET_AUDIO_VIDEO_ENABLE bits: video and audio are both wanted.
+const AUDIO_VIDEO_ENABLED: i32 = 0b11;
+const RETRO_HW_CONTEXT_OPENGL: c_uint = 1;
+
+#[repr(C)]
+struct RetroMessage {
+    msg: *const c_char,
+    frames: c_uint,
+}
+
+#[repr(C)]
+struct RetroMessageExt {
+    msg: *const c_char,
+    duration: c_uint,
+    priority: c_uint,
+    level: c_uint,
+    target: c_uint,
+    kind: c_uint,
+    progress: i8,
+}
+
+#[repr(C)]
+struct RetroInputDescriptor {
+    port: c_uint,
+    device: c_uint,
+    index: c_uint,
+    id: c_uint,
+    description: *const c_char,
+}
+
+#[repr(C)]
+struct RetroControllerDescription {
+    desc: *const c_char,
+    id: c_uint,
+}
+
+#[repr(C)]
+struct RetroControllerInfo {
+    types: *const RetroControllerDescription,
+    num_types: c_uint,
+}
+
+#[repr(C)]
+struct RetroMemoryDescriptor {
+    flags: u64,
+    ptr: *mut c_void,
+    offset: usize,
+    start: usize,
+    select: usize,
+    disconnect: usize,
+    len: usize,
+    addrspace: *const c_char,
+}
+
+#[repr(C)]
+struct RetroMemoryMap {
+    descriptors: *const RetroMemoryDescriptor,
+    num_descriptors: c_uint,
+}
+
+/// Why the frontend is saving or loading a state, reported through GET_SAVESTATE_CONTEXT.
+#[repr(i32)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SavestateContext {
+    #[default]
+    Normal = 0,
+    /// Run-ahead restores the state on this same instance.
+    RunaheadSameInstance = 1,
+    /// Run-ahead moves the state to a second instance of the same core.
+    RunaheadSameBinary = 2,
+    /// Netplay rolls back to the state on this instance.
+    RollbackNetplay = 3,
+}
+
+/// Language reported through GET_LANGUAGE.
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Language {
+    #[default]
+    English = 0,
+    Japanese = 1,
+    French = 2,
+    Spanish = 3,
+    German = 4,
+    Italian = 5,
+    Dutch = 6,
+    PortugueseBrazil = 7,
+    PortuguesePortugal = 8,
+    Russian = 9,
+    Korean = 10,
+    ChineseTraditional = 11,
+    ChineseSimplified = 12,
+}
+
+/// A button label from SET_INPUT_DESCRIPTORS, e.g. "Jump" for port 0 joypad B.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InputDescriptor {
+    pub port: u32,
+    pub device: u32,
+    pub index: u32,
+    pub id: u32,
+    pub description: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ControllerDescription {
+    pub description: String,
+    /// Device id to pass to `retro_set_controller_port_device`.
+    pub device: u32,
+}
+
+/// Devices a core accepts on one port, from SET_CONTROLLER_INFO.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ControllerInfo {
+    pub types: Vec<ControllerDescription>,
+}
+
+/// One entry of SET_MEMORY_MAPS, describing where a block sits in the emulated address space.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemoryDescriptor {
+    /// `RETRO_MEMDESC_*` flags.
+    pub flags: u64,
+    /// Whether the core handed us host memory for the block, rather than only its layout.
+    pub has_data: bool,
+    pub offset: usize,
+    pub start: usize,
+    pub select: usize,
+    pub disconnect: usize,
+    pub len: usize,
+    pub address_space: Option<String>,
+}
+
+/// Flags from SET_SERIALIZATION_QUIRKS.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SerializationQuirks(pub u64);
+
+impl SerializationQuirks {
+    pub const INCOMPLETE: u64 = 1 << 0;
+    pub const MUST_INITIALIZE: u64 = 1 << 1;
+    pub const CORE_VARIABLE_SIZE: u64 = 1 << 2;
+    pub const FRONT_VARIABLE_SIZE: u64 = 1 << 3;
+    pub const SINGLE_SESSION: u64 = 1 << 4;
+    pub const ENDIAN_DEPENDENT: u64 = 1 << 5;
+    pub const PLATFORM_DEPENDENT: u64 = 1 << 6;
+
+    pub fn contains(self, flag: u64) -> bool {
+        self.0 & flag == flag
+    }
+
+    /// Whether the core reported no quirks at all.
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Whether a state can be restored by another session, possibly on another machine.
+    pub fn is_portable(self) -> bool {
+        let mut unportable = Self::INCOMPLETE | Self::SINGLE_SESSION | Self::PLATFORM_DEPENDENT;
+        if cfg!(target_endian = "big") {
+            // Everything we share states with is little-endian.
+            unportable |= Self::ENDIAN_DEPENDENT;
+        }
+        self.0 & unportable == 0
+    }
+
+    /// Whether the state size may change within a session.
+    pub fn variable_size(self) -> bool {
+        self.contains(Self::CORE_VARIABLE_SIZE)
+    }
+}
+
+/// What a core has declared through the environment callback so far.
+#[derive(Debug, Default)]
+pub(crate) struct EnvironmentState {
+    pub input_descriptors: Vec<InputDescriptor>,
+    pub controller_info: Vec<ControllerInfo>,
+    pub memory_map: Vec<MemoryDescriptor>,
+    pub supports_no_game: bool,
+    pub supports_achievements: bool,
+    pub performance_level: u32,
+    pub serialization_quirks: SerializationQuirks,
+    pub shutdown_requested: bool,
+    /// Answer for GET_FASTFORWARDING: the frontend runs the game faster than normal speed.
+    pub fast_forwarding: bool,
+    /// Answer for GET_SAVESTATE_CONTEXT.
+    pub savestate_context: SavestateContext,
+    /// SET_SYSTEM_AV_INFO / SET_GEOMETRY updates not yet picked up by `LibretroCore`.
+    pub pending_av_info: Option<SystemAvInfo>,
+    pub pending_geometry: Option<RetroGameGeometry>,
+    /// Accepted SET_HW_RENDER request; the context is created once the game has loaded.
+    pub hw_render: Option<HwRenderCallbacks>,
+    /// Framebuffer object handed out through `get_current_framebuffer`.
+    pub hw_framebuffer: u32,
+    /// Size of a frame presented with RETRO_HW_FRAME_BUFFER_VALID and not yet read back.
+    pub hw_frame: Option<(u32, u32)>,
+}
+
+unsafe fn opt_string(ptr: *const c_char) -> Option<String> {
+    if ptr.is_null() {
+        return None;
+    }
+    Some(CStr::from_ptr(ptr).to_string_lossy().into_owned())
+}
+
+unsafe fn write<T>(data: *mut c_void, value: T) -> bool {
+    if data.is_null() {
+        return false;
+    }
+    *(data as *mut T) = value;
+    true
+}
+
+fn update_state(slot: usize, f: impl FnOnce(&mut EnvironmentState)) -> bool {
+    with_callbacks(slot, |callbacks| f(&mut callbacks.environment_mut())).is_some()
+}
+
+pub(crate) unsafe fn dispatch(slot: usize, cmd: u32, data: *mut c_void) -> bool {
+    if cmd == RETRO_ENVIRONMENT_SET_HW_SHARED_CONTEXT {
+        // Every core has a context of its own, so sharing it is always fine.
+        return hw_render::gl_available();
+    }
+    match cmd & !RETRO_ENVIRONMENT_EXPERIMENTAL {
+        RETRO_ENVIRONMENT_SET_ROTATION => {
+            // Frames are never rotated; only the identity rotation is accepted.
+            !data.is_null() && *(data as *const c_uint) == 0
+        }
+        RETRO_ENVIRONMENT_GET_OVERSCAN => write(data, false),
+        RETRO_ENVIRONMENT_GET_CAN_DUPE => write(data, true),
+        RETRO_ENVIRONMENT_SET_MESSAGE => {
+            if data.is_null() {
+                return false;
+            }
+            let message = &*(data as *const RetroMessage);
+            record_message(slot, LogLevel::Info, message.msg)
+        }
+        RETRO_ENVIRONMENT_SET_MESSAGE_EXT => {
+            if data.is_null() {
+                return false;
+            }
+            let message = &*(data as *const RetroMessageExt);
+            record_message(
+                slot,
+                LogLevel::from_raw(message.level as c_int),
+                message.msg,
+            )
+        }
+        RETRO_ENVIRONMENT_GET_MESSAGE_INTERFACE_VERSION => write(data, MESSAGE_INTERFACE_VERSION),
+        RETRO_ENVIRONMENT_SHUTDOWN => update_state(slot, |state| state.shutdown_requested = true),
+        RETRO_ENVIRONMENT_SET_PERFORMANCE_LEVEL => {
+            if data.is_null() {
+                return false;
+            }
+            let level = *(data as *const c_uint);
+            update_state(slot, |state| state.performance_level = level)
+        }
+        RETRO_ENVIRONMENT_GET_SYSTEM_DIRECTORY => {
+            report_path(slot, data, |callbacks| callbacks.system_directory.as_ref())
+        }
+        RETRO_ENVIRONMENT_GET_SAVE_DIRECTORY => {
+            report_path(slot, data, |callbacks| callbacks.save_directory.as_ref())
+        }
+        RETRO_ENVIRONMENT_GET_LIBRETRO_PATH => {
+            report_path(slot, data, |callbacks| callbacks.core_path.as_ref())
+        }
+        RETRO_ENVIRONMENT_SET_PIXEL_FORMAT => {
+            if data.is_null() {
+                return false;
+            }
+            let format = *(data as *const RetroPixelFormat);
+            let supported = matches!(
+                format,
+                RetroPixelFormat::_0rgb1555 | RetroPixelFormat::Xrgb8888 | RetroPixelFormat::Rgb565
+            );
+            if supported {
+                let _ = with_callbacks(slot, |callbacks| callbacks.set_pixel_format(format));
+            }
+            supported
+        }
+        RETRO_ENVIRONMENT_SET_HW_RENDER => {
+            if data.is_null() {
+                return false;
+            }
+            hw_render::negotiate(slot, &mut *(data as *mut hw_render::RetroHwRenderCallback))
+        }
+        RETRO_ENVIRONMENT_GET_PREFERRED_HW_RENDER => {
+            hw_render::gl_available() && write(data, RETRO_HW_CONTEXT_OPENGL)
+        }
+        RETRO_ENVIRONMENT_SET_INPUT_DESCRIPTORS => {
+            let descriptors = read_input_descriptors(data as *const RetroInputDescriptor);
+            update_state(slot, |state| state.input_descriptors = descriptors)
+        }
+        RETRO_ENVIRONMENT_SET_CONTROLLER_INFO => {
+            let info = read_controller_info(data as *const RetroControllerInfo);
+            update_state(slot, |state| state.controller_info = info)
+        }
+        RETRO_ENVIRONMENT_SET_MEMORY_MAPS => {
+            if data.is_null() {
+                return false;
+            }
+            let map = read_memory_map(&*(data as *const RetroMemoryMap));
+            update_state(slot, |state| state.memory_map = map)
+        }
+        RETRO_ENVIRONMENT_GET_INPUT_DEVICE_CAPABILITIES => write(data, INPUT_DEVICE_CAPABILITIES),
+        RETRO_ENVIRONMENT_GET_INPUT_MAX_USERS => write(data, crate::MAX_PORTS as c_uint),
+        // Cores usually pass no data and go by the return value alone.
+        RETRO_ENVIRONMENT_GET_INPUT_BITMASKS => {
+            let supported = with_callbacks(slot, |callbacks| callbacks.input_bitmasks);
+            if supported != Some(true) {
+                return false;
+            }
+            if !data.is_null() {
+                write(data, true);
+            }
+            true
+        }
+        RETRO_ENVIRONMENT_SET_SUPPORT_NO_GAME => {
+            if data.is_null() {
+                return false;
+            }
+            let supported = *(data as *const bool);
+            update_state(slot, |state| state.supports_no_game = supported)
+        }
+        RETRO_ENVIRONMENT_SET_SUPPORT_ACHIEVEMENTS => {
+            if data.is_null() {
+                return false;
+            }
+            let supported = *(data as *const bool);
+            update_state(slot, |state| state.supports_achievements = supported)
+        }
+        RETRO_ENVIRONMENT_SET_SYSTEM_AV_INFO => {
+            if data.is_null() {
+                return false;
+            }
+            let info = &*(data as *const RetroSystemAvInfo);
+            let info = SystemAvInfo {
+                geometry: info.geometry,
+                timing: info.timing,
+            };
+            update_state(slot, |state| {
+                state.pending_av_info = Some(info);
+                state.pending_geometry = None;
+            })
+        }
+        RETRO_ENVIRONMENT_SET_GEOMETRY => {
+            if data.is_null() {
+                return false;
+            }
+            let geometry = *(data as *const RetroGameGeometry);
+            update_state(slot, |state| state.pending_geometry = Some(geometry))
+        }
+        RETRO_ENVIRONMENT_GET_LANGUAGE => {
+            let language = with_callbacks(slot, |callbacks| callbacks.language);
+            write(data, language.unwrap_or_default() as c_uint)
+        }
+        RETRO_ENVIRONMENT_SET_SERIALIZATION_QUIRKS => {
+            if data.is_null() {
+                return false;
+            }
+            let data = data as *mut u64;
+            let quirks = SerializationQuirks(*data);
+            // The frontend answers by setting the quirks it supports; states of any size are.
+            *data |= SerializationQuirks::FRONT_VARIABLE_SIZE;
+            update_state(slot, |state| state.serialization_quirks = quirks)
+        }
+        RETRO_ENVIRONMENT_GET_AUDIO_VIDEO_ENABLE => write(data, AUDIO_VIDEO_ENABLED),
+        RETRO_ENVIRONMENT_GET_FASTFORWARDING => {
+            let fast_forwarding = with_callbacks(slot, |callbacks| {
+                callbacks.environment_mut().fast_forwarding
+            });
+            write(data, fast_forwarding.unwrap_or(false))
+        }
+        RETRO_ENVIRONMENT_GET_SAVESTATE_CONTEXT => {
+            let context = with_callbacks(slot, |callbacks| {
+                callbacks.environment_mut().savestate_context
+            });
+            write(data, context.unwrap_or_default() as i32)
+        }
+        RETRO_ENVIRONMENT_GET_LOG_INTERFACE => {
+            if data.is_null() {
+                return false;
+            }
+            (*(data as *mut core_log::RetroLogCallback)).log = core_log::LOG_PRINTF[slot];
+            true
+        }
+        RETRO_ENVIRONMENT_GET_VARIABLE => {
+            if data.is_null() {
+                return false;
+            }
+            let variable = &mut *(data as *mut options::RetroVariable);
+            variable.value = ptr::null();
+            if variable.key.is_null() {
+                return false;
+            }
+            let key = CStr::from_ptr(variable.key).to_string_lossy();
+            with_callbacks(slot, |callbacks| {
+                let store = callbacks.options_mut();
+                match store.get(&key) {
+                    // The CString lives in the store until the option changes again.
+                    Some(value) => {
+                        variable.value = value.as_ptr();
+                        true
+                    }
+                    None => false,
+                }
+            })
+            .unwrap_or(false)
+        }
+        RETRO_ENVIRONMENT_SET_VARIABLES => {
+            let definitions = options::read_v0(data as *const options::RetroVariable);
+            define_core_options(slot, definitions, Vec::new())
+        }
+        RETRO_ENVIRONMENT_GET_VARIABLE_UPDATE => {
+            if data.is_null() {
+                return false;
+            }
+            let updated = with_callbacks(slot, |callbacks| callbacks.options_mut().take_updated())
+                .unwrap_or(false);
+            write(data, updated)
+        }
+        RETRO_ENVIRONMENT_GET_CORE_OPTIONS_VERSION => write(data, CORE_OPTIONS_VERSION),
+        RETRO_ENVIRONMENT_SET_CORE_OPTIONS => {
+            let definitions = options::read_v1(data as *const options::RetroCoreOptionDefinition);
+            define_core_options(slot, definitions, Vec::new())
+        }
+        RETRO_ENVIRONMENT_SET_CORE_OPTIONS_INTL => {
+            let definitions = options::read_v1_intl(data as *const options::RetroCoreOptionsIntl);
+            define_core_options(slot, definitions, Vec::new())
+        }
+        RETRO_ENVIRONMENT_SET_CORE_OPTIONS_V2 => {
+            let (definitions, categories) =
+                options::read_v2(data as *const options::RetroCoreOptionsV2);
+            define_core_options(slot, definitions, categories)
+        }
+        RETRO_ENVIRONMENT_SET_CORE_OPTIONS_V2_INTL => {
+            let (definitions, categories) =
+                options::read_v2_intl(data as *const options::RetroCoreOptionsV2Intl);
+            define_core_options(slot, definitions, categories)
+        }
+        RETRO_ENVIRONMENT_SET_CORE_OPTIONS_DISPLAY => {
+            let Some((key, visible)) =
+                options::read_display(data as *const options::RetroCoreOptionDisplay)
+            else {
+                return false;
+            };
+            with_callbacks(slot, |callbacks| {
+                callbacks.options_mut().set_visible(&key, visible)
+            })
+            .is_some()
+        }
+        RETRO_ENVIRONMENT_SET_VARIABLE => {
+            // A null payload is a capability query.
+            if data.is_null() {
+                return true;
+            }
+            let variable = &*(data as *const options::RetroVariable);
+            if variable.key.is_null() || variable.value.is_null() {
+                return false;
+            }
+            let key = CStr::from_ptr(variable.key).to_string_lossy();
+            let value = CStr::from_ptr(variable.value).to_string_lossy();
+            with_callbacks(slot, |callbacks| {
+                callbacks.options_mut().set(&key, &value).is_ok()
+            })
+            .unwrap_or(false)
+        }
+        other => {
+            log::debug!("refusing unsupported environment command {other}");
+            false
+        }
+    }
+}
+
+/// Answers a path query with a pointer that lives as long as the core's callbacks.
+unsafe fn report_path(
+    slot: usize,
+    data: *mut c_void,
+    path: fn(&Callbacks) -> Option<&CString>,
+) -> bool {
+    let path = with_callbacks(slot, |callbacks| path(callbacks).map(|path| path.as_ptr()));
+    match path.flatten() {
+        Some(path) => write(data, path),
+        None => false,
+    }
+}
+
+/// On-screen messages have nowhere to go yet, so they end up in the core log.
+unsafe fn record_message(slot: usize, level: LogLevel, message: *const c_char) -> bool {
+    let Some(message) = opt_string(message) else {
+        return false;
+    };
+    with_callbacks(slot, |callbacks| {
+        callbacks.log_mut().record(level, message.trim_end())
+    })
+    .is_some()
+}
+
+fn define_core_options(
+    slot: usize,
+    definitions: Vec<CoreOption>,
+    categories: Vec<CoreOptionCategory>,
+) -> bool {
+    with_callbacks(slot, |callbacks| {
+        callbacks.options_mut().define(definitions, categories)
+    })
+    .is_some()
+}
+
+unsafe fn read_input_descriptors(mut entry: *const RetroInputDescriptor) -> Vec<InputDescriptor> {
+    let mut descriptors = Vec::new();
+    if entry.is_null() {
+        return descriptors;
+    }
+    while let Some(description) = opt_string((*entry).description) {
+        let raw = &*entry;
+        descriptors.push(InputDescriptor {
+            port: raw.port,
+            device: raw.device,
+            index: raw.index,
+            id: raw.id,
+            description,
+        });
+        entry = entry.add(1);
+    }
+    descriptors
+}
+
+unsafe fn read_controller_info(mut port: *const RetroControllerInfo) -> Vec<ControllerInfo> {
+    let mut ports = Vec::new();
+    if port.is_null() {
+        return ports;
+    }
+    while !(*port).types.is_null() {
+        let raw = &*port;
+        let types = std::slice::from_raw_parts(raw.types, raw.num_types as usize)
+            .iter()
+            .map(|entry| ControllerDescription {
+                description: opt_string(entry.desc).unwrap_or_default(),
+                device: entry.id,
+            })
+            .collect();
+        ports.push(ControllerInfo { types });
+        port = port.add(1);
+    }
+    ports
+}
+
+unsafe fn read_memory_map(map: &RetroMemoryMap) -> Vec<MemoryDescriptor> {
+    if map.descriptors.is_null() {
+        return Vec::new();
+    }
+    std::slice::from_raw_parts(map.descriptors, map.num_descriptors as usize)
+        .iter()
+        .map(|entry| MemoryDescriptor {
+            flags: entry.flags,
+            has_data: !entry.ptr.is_null(),
+            offset: entry.offset,
+            start: entry.start,
+            select: entry.select,
+            disconnect: entry.disconnect,
+            len: entry.len,
+            address_space: opt_string(entry.addrspace).filter(|name| !name.is_empty()),
+        })
+        .collect()
+}
@@ -0,0 +1,516 @@
+//! RETRO_ENVIRONMENT_SET_HW_RENDER support.
+//!
+//! Cores that render with OpenGL or OpenGL ES get an offscreen EGL context of their own. It
+//! is created on Mesa's surfaceless platform when that is available, so it also works with
+//! no display server under llvmpipe. The core draws into a framebuffer object we own; the
+//! frames it presents are read back after `retro_run` and handed to the video callback as
+//! XRGB8888, exactly like a software frame.
+//!
+//! Vulkan and Direct3D requests are refused, so cores that can fall back to another
+//! renderer do.
+
+use crate::{with_callbacks, MAX_CORE_INSTANCES};
+use glow::HasContext;
+use khronos_egl as egl;
+use once_cell::sync::OnceCell;
+use std::{
+    ffi::CStr,
+    os::raw::{c_char, c_uint, c_void},
+    ptr,
+};
+
+/// Passed as the frame data by cores that rendered into the framebuffer we gave them.
+pub(crate) const RETRO_HW_FRAME_BUFFER_VALID: *const c_void = usize::MAX as *const c_void;
+
+const RETRO_HW_CONTEXT_OPENGL: u32 = 1;
+const RETRO_HW_CONTEXT_OPENGLES2: u32 = 2;
+const RETRO_HW_CONTEXT_OPENGL_CORE: u32 = 3;
+const RETRO_HW_CONTEXT_OPENGLES3: u32 = 4;
+const RETRO_HW_CONTEXT_OPENGLES_VERSION: u32 = 5;
+const RETRO_HW_CONTEXT_VULKAN: u32 = 6;
+
+/// `EGL_PLATFORM_SURFACELESS_MESA`, from EGL_MESA_platform_surfaceless.
+const PLATFORM_SURFACELESS_MESA: egl::Enum = 0x31DD;
+
+type RetroHwContextResetFn = unsafe extern "C" fn();
+type RetroHwGetCurrentFramebufferFn = unsafe extern "C" fn() -> usize;
+type RetroHwGetProcAddressFn = unsafe extern "C" fn(sym: *const c_char) -> *const c_void;
+
+#[repr(C)]
+pub(crate) struct RetroHwRenderCallback {
+    context_type: c_uint,
+    context_reset: Option<RetroHwContextResetFn>,
+    get_current_framebuffer: Option<RetroHwGetCurrentFramebufferFn>,
+    get_proc_address: Option<RetroHwGetProcAddressFn>,
+    depth: bool,
+    stencil: bool,
+    bottom_left_origin: bool,
+    version_major: c_uint,
+    version_minor: c_uint,
+    cache_context: bool,
+    context_destroy: Option<RetroHwContextResetFn>,
+    debug_context: bool,
+}
+
+/// The graphics APIs a core can ask for through SET_HW_RENDER.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HwContextType {
+    /// Compatibility-profile desktop OpenGL.
+    OpenGl,
+    OpenGlCore,
+    OpenGles2,
+    OpenGles3,
+    /// OpenGL ES at the version given in the request.
+    OpenGlesVersion,
+    Vulkan,
+}
+
+impl HwContextType {
+    fn from_raw(value: u32) -> Option<Self> {
+        match value {
+            RETRO_HW_CONTEXT_OPENGL => Some(Self::OpenGl),
+            RETRO_HW_CONTEXT_OPENGLES2 => Some(Self::OpenGles2),
+            RETRO_HW_CONTEXT_OPENGL_CORE => Some(Self::OpenGlCore),
+            RETRO_HW_CONTEXT_OPENGLES3 => Some(Self::OpenGles3),
+            RETRO_HW_CONTEXT_OPENGLES_VERSION => Some(Self::OpenGlesVersion),
+            RETRO_HW_CONTEXT_VULKAN => Some(Self::Vulkan),
+            _ => None,
+        }
+    }
+
+    fn is_gl(self) -> bool {
+        self != Self::Vulkan
+    }
+}
+
+/// What a core asked for through SET_HW_RENDER.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HwRenderRequest {
+    pub context_type: HwContextType,
+    pub version_major: u32,
+    pub version_minor: u32,
+    pub depth: bool,
+    pub stencil: bool,
+    /// Whether the core renders with OpenGL's bottom-up row order.
+    pub bottom_left_origin: bool,
+}
+
+/// An accepted request along with the core's own context callbacks.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct HwRenderCallbacks {
+    pub request: HwRenderRequest,
+    pub context_reset: Option<RetroHwContextResetFn>,
+    pub context_destroy: Option<RetroHwContextResetFn>,
+}
+
+struct Egl {
+    instance: egl::DynamicInstance<egl::EGL1_4>,
+    display: egl::Display,
+}
+
+// The display handle is process-wide and EGL calls on it are thread-safe.
+unsafe impl Send for Egl {}
+unsafe impl Sync for Egl {}
+
+static EGL: OnceCell<Option<Egl>> = OnceCell::new();
+
+fn egl() -> Option<&'static Egl> {
+    EGL.get_or_init(|| match unsafe { load_egl() } {
+        Ok(egl) => Some(egl),
+        Err(err) => {
+            log::info!("hardware rendering unavailable: {err}");
+            None
+        }
+    })
+    .as_ref()
+}
+
+unsafe fn load_egl() -> Result<Egl, String> {
+    let instance = egl::DynamicInstance::<egl::EGL1_4>::load_required()
+        .map_err(|err| format!("failed to load libEGL: {err}"))?;
+    // The surfaceless platform needs no window system; the default display is the fallback.
+    let display = instance
+        .upcast::<egl::EGL1_5>()
+        .and_then(|egl| {
+            egl.get_platform_display(
+                PLATFORM_SURFACELESS_MESA,
+                egl::DEFAULT_DISPLAY,
+                &[egl::ATTRIB_NONE],
+            )
+            .ok()
+        })
+        .or_else(|| instance.get_display(egl::DEFAULT_DISPLAY))
+        .ok_or("no EGL display")?;
+    instance
+        .initialize(display)
+        .map_err(|err| format!("failed to initialize EGL: {err}"))?;
+    Ok(Egl { instance, display })
+}
+
+/// Whether OpenGL cores can be given a context on this machine.
+pub fn gl_available() -> bool {
+    egl().is_some()
+}
+
+/// Handles SET_HW_RENDER: accepts OpenGL requests and fills in our side of the interface.
+pub(crate) unsafe fn negotiate(slot: usize, callback: &mut RetroHwRenderCallback) -> bool {
+    let Some(context_type) = HwContextType::from_raw(callback.context_type) else {
+        log::info!("refusing hardware context type {}", callback.context_type);
+        return false;
+    };
+    if !context_type.is_gl() || !gl_available() {
+        log::info!("refusing {context_type:?} hardware rendering");
+        return false;
+    }
+    let accepted = HwRenderCallbacks {
+        request: HwRenderRequest {
+            context_type,
+            version_major: callback.version_major,
+            version_minor: callback.version_minor,
+            depth: callback.depth,
+            stencil: callback.stencil,
+            bottom_left_origin: callback.bottom_left_origin,
+        },
+        context_reset: callback.context_reset,
+        context_destroy: callback.context_destroy,
+    };
+    let recorded = with_callbacks(slot, |callbacks| {
+        callbacks.environment_mut().hw_render = Some(accepted);
+    })
+    .is_some();
+    if recorded {
+        callback.get_current_framebuffer = Some(CURRENT_FRAMEBUFFER[slot]);
+        callback.get_proc_address = Some(get_proc_address);
+    }
+    recorded
+}
+
+const CURRENT_FRAMEBUFFER: [RetroHwGetCurrentFramebufferFn; MAX_CORE_INSTANCES] = [
+    current_framebuffer::<0>,
+    current_framebuffer::<1>,
+    current_framebuffer::<2>,
+    current_framebuffer::<3>,
+];
+
+unsafe extern "C" fn current_framebuffer<const SLOT: usize>() -> usize {
+    let framebuffer = with_callbacks(SLOT, |callbacks| callbacks.environment_mut().hw_framebuffer);
+    framebuffer.unwrap_or(0) as usize
+}
+
+unsafe extern "C" fn get_proc_address(symbol: *const c_char) -> *const c_void {
+    if symbol.is_null() {
+        return ptr::null();
+    }
+    match (egl(), CStr::from_ptr(symbol).to_str()) {
+        (Some(egl), Ok(name)) => proc_address(egl, name),
+        _ => ptr::null(),
+    }
+}
+
+fn proc_address(egl: &Egl, name: &str) -> *const c_void {
+    egl.instance
+        .get_proc_address(name)
+        .map_or(ptr::null(), |function| function as *const c_void)
+}
+
+/// A core's EGL context and the framebuffer it renders into.
+pub(crate) struct HwRenderer {
+    egl: &'static Egl,
+    context: egl::Context,
+    /// A 1x1 pbuffer, only when the driver cannot make a context current without a surface.
+    surface: Option<egl::Surface>,
+    gl: glow::Context,
+    framebuffer: glow::Framebuffer,
+    color: glow::Texture,
+    depth_stencil: Option<glow::Renderbuffer>,
+    width: u32,
+    height: u32,
+    bottom_left_origin: bool,
+}
+
+// EGL contexts may move between threads as long as they are current on at most one thread
+// at a time; `with_current` releases the context again before returning.
+unsafe impl Send for HwRenderer {}
+
+impl HwRenderer {
+    pub fn new(request: &HwRenderRequest, width: u32, height: u32) -> Result<Self, String> {
+        let egl = egl().ok_or("EGL is not available")?;
+        let (api, renderable, version) = match request.context_type {
+            HwContextType::OpenGl | HwContextType::OpenGlCore => (
+                egl::OPENGL_API,
+                egl::OPENGL_BIT,
+                (request.version_major, request.version_minor),
+            ),
+            HwContextType::OpenGles2 => (egl::OPENGL_ES_API, egl::OPENGL_ES2_BIT, (2, 0)),
+            HwContextType::OpenGles3 => (egl::OPENGL_ES_API, egl::OPENGL_ES3_BIT, (3, 0)),
+            HwContextType::OpenGlesVersion => (
+                egl::OPENGL_ES_API,
+                egl::OPENGL_ES3_BIT,
+                (request.version_major, request.version_minor),
+            ),
+            HwContextType::Vulkan => return Err("Vulkan is not supported".to_string()),
+        };
+        let instance = &egl.instance;
+        instance
+            .bind_api(api)
+            .map_err(|err| format!("failed to bind the GL API: {err}"))?;
+        let config = instance
+            .choose_first_config(
+                egl.display,
+                &[
+                    egl::SURFACE_TYPE,
+                    egl::PBUFFER_BIT,
+                    egl::RENDERABLE_TYPE,
+                    renderable,
+                    egl::RED_SIZE,
+                    8,
+                    egl::GREEN_SIZE,
+                    8,
+                    egl::BLUE_SIZE,
+                    8,
+                    egl::NONE,
+                ],
+            )
+            .map_err(|err| format!("failed to choose an EGL config: {err}"))?
+            .ok_or("no EGL config supports the requested API")?;
+
+        let mut attributes = Vec::new();
+        if version.0 > 0 {
+            attributes.extend([
+                egl::CONTEXT_MAJOR_VERSION,
+                version.0 as egl::Int,
+                egl::CONTEXT_MINOR_VERSION,
+                version.1 as egl::Int,
+            ]);
+        }
+        if request.context_type == HwContextType::OpenGlCore {
+            attributes.extend([
+                egl::CONTEXT_OPENGL_PROFILE_MASK,
+                egl::CONTEXT_OPENGL_CORE_PROFILE_BIT,
+            ]);
+        }
+        attributes.push(egl::NONE);
+        let context = instance
+            .create_context(egl.display, config, None, &attributes)
+            .map_err(|err| format!("failed to create a GL context: {err}"))?;
+
+        let surface = if instance
+            .make_current(egl.display, None, None, Some(context))
+            .is_ok()
+        {
+            None
+        } else {
+            let surface = instance
+                .create_pbuffer_surface(
+                    egl.display,
+                    config,
+                    &[egl::WIDTH, 1, egl::HEIGHT, 1, egl::NONE],
+                )
+                .map_err(|err| format!("failed to create a pbuffer: {err}"))?;
+            instance
+                .make_current(egl.display, Some(surface), Some(surface), Some(context))
+                .map_err(|err| format!("failed to make the GL context current: {err}"))?;
+            Some(surface)
+        };
+
+        let gl = unsafe { glow::Context::from_loader_function(|name| proc_address(egl, name)) };
+        let result = unsafe { Self::create_framebuffer(&gl, request, width, height) };
+        let _ = instance.make_current(egl.display, None, None, None);
+        let (framebuffer, color, depth_stencil) = match result {
+            Ok(objects) => objects,
+            Err(err) => {
+                if let Some(surface) = surface {
+                    let _ = instance.destroy_surface(egl.display, surface);
+                }
+                let _ = instance.destroy_context(egl.display, context);
+                return Err(err);
+            }
+        };
+        Ok(Self {
+            egl,
+            context,
+            surface,
+            gl,
+            framebuffer,
+            color,
+            depth_stencil,
+            width,
+            height,
+            bottom_left_origin: request.bottom_left_origin,
+        })
+    }
+
+    unsafe fn create_framebuffer(
+        gl: &glow::Context,
+        request: &HwRenderRequest,
+        width: u32,
+        height: u32,
+    ) -> Result<(glow::Framebuffer, glow::Texture, Option<glow::Renderbuffer>), String> {
+        let framebuffer = gl.create_framebuffer()?;
+        let color = gl.create_texture()?;
+        let depth_stencil = if request.depth || request.stencil {
+            Some(gl.create_renderbuffer()?)
+        } else {
+            None
+        };
+        gl.bind_framebuffer(glow::FRAMEBUFFER, Some(framebuffer));
+        Self::allocate(gl, color, depth_stencil, width, height);
+        gl.framebuffer_texture_2d(
+            glow::FRAMEBUFFER,
+            glow::COLOR_ATTACHMENT0,
+            glow::TEXTURE_2D,
+            Some(color),
+            0,
+        );
+        if let Some(renderbuffer) = depth_stencil {
+            gl.framebuffer_renderbuffer(
+                glow::FRAMEBUFFER,
+                glow::DEPTH_ATTACHMENT,
+                glow::RENDERBUFFER,
+                Some(renderbuffer),
+            );
+            if request.stencil {
+                gl.framebuffer_renderbuffer(
+                    glow::FRAMEBUFFER,
+                    glow::STENCIL_ATTACHMENT,
+                    glow::RENDERBUFFER,
+                    Some(renderbuffer),
+                );
+            }
+        }
+        let status = gl.check_framebuffer_status(glow::FRAMEBUFFER);
+        gl.bind_framebuffer(glow::FRAMEBUFFER, None);
+        if status != glow::FRAMEBUFFER_COMPLETE {
+            return Err(format!("framebuffer is incomplete (status {status:#x})"));
+        }
+        Ok((framebuffer, color, depth_stencil))
+    }
+
+    unsafe fn allocate(
+        gl: &glow::Context,
+        color: glow::Texture,
+        depth_stencil: Option<glow::Renderbuffer>,
+        width: u32,
+        height: u32,
+    ) {
+        gl.bind_texture(glow::TEXTURE_2D, Some(color));
+        gl.tex_parameter_i32(
+            glow::TEXTURE_2D,
+            glow::TEXTURE_MIN_FILTER,
+            glow::NEAREST as i32,
+        );
+        gl.tex_image_2d(
+            glow::TEXTURE_2D,
+            0,
+            glow::RGBA as i32,
+            width as i32,
+            height as i32,
+            0,
+            glow::RGBA,
+            glow::UNSIGNED_BYTE,
+            None,
+        );
+        gl.bind_texture(glow::TEXTURE_2D, None);
+        if let Some(renderbuffer) = depth_stencil {
+            gl.bind_renderbuffer(glow::RENDERBUFFER, Some(renderbuffer));
+            gl.renderbuffer_storage(
+                glow::RENDERBUFFER,
+                glow::DEPTH24_STENCIL8,
+                width as i32,
+                height as i32,
+            );
+            gl.bind_renderbuffer(glow::RENDERBUFFER, None);
+        }
+    }
+
+    /// The framebuffer object the core should render into.
+    pub fn framebuffer_id(&self) -> u32 {
+        self.framebuffer.0.get()
+    }
+
+    /// Runs `f` with the context current on this thread, releasing it afterwards.
+    pub fn with_current<R>(&self, f: impl FnOnce() -> R) -> R {
+        let instance = &self.egl.instance;
+        if let Err(err) = instance.make_current(
+            self.egl.display,
+            self.surface,
+            self.surface,
+            Some(self.context),
+        ) {
+            log::error!("failed to make the GL context current: {err}");
+        }
+        let result = f();
+        let _ = instance.make_current(self.egl.display, None, None, None);
+        result
+    }
+
+    /// Grows the framebuffer when SET_SYSTEM_AV_INFO raised the maximum frame size.
+    pub fn ensure_size(&mut self, width: u32, height: u32) {
+        if width <= self.width && height <= self.height {
+            return;
+        }
+        let width = width.max(self.width);
+        let height = height.max(self.height);
+        self.with_current(|| unsafe {
+            Self::allocate(&self.gl, self.color, self.depth_stencil, width, height)
+        });
+        self.width = width;
+        self.height = height;
+    }
+
+    /// Reads back a presented frame as top-down XRGB8888. Call with the context current.
+    pub fn read_frame(&self, width: u32, height: u32) -> (Vec<u8>, u32, u32) {
+        let width = width.min(self.width);
+        let height = height.min(self.height);
+        let row = width as usize * 4;
+        let mut rgba = vec![0u8; row * height as usize];
+        unsafe {
+            self.gl
+                .bind_framebuffer(glow::FRAMEBUFFER, Some(self.framebuffer));
+            self.gl.pixel_store_i32(glow::PACK_ALIGNMENT, 4);
+            self.gl.read_pixels(
+                0,
+                0,
+                width as i32,
+                height as i32,
+                glow::RGBA,
+                glow::UNSIGNED_BYTE,
+                glow::PixelPackData::Slice(&mut rgba),
+            );
+            self.gl.bind_framebuffer(glow::FRAMEBUFFER, None);
+        }
+
+        // glReadPixels starts at the bottom row. Cores with a bottom-left origin put the top
+        // of the image last; the others draw upside down, so their rows are already in order.
+        let mut frame = vec![0u8; rgba.len()];
+        for (y, source) in rgba.chunks_exact(row).enumerate() {
+            let target_row = if self.bottom_left_origin {
+                height as usize - 1 - y
+            } else {
+                y
+            };
+            let target = &mut frame[target_row * row..(target_row + 1) * row];
+            for (pixel, source) in target.chunks_exact_mut(4).zip(source.chunks_exact(4)) {
+                pixel.copy_from_slice(&[source[2], source[1], source[0], 0xff]);
+            }
+        }
+        (frame, width, height)
+    }
+}
+
+impl Drop for HwRenderer {
+    fn drop(&mut self) {
+        self.with_current(|| unsafe {
+            self.gl.delete_framebuffer(self.framebuffer);
+            self.gl.delete_texture(self.color);
+            if let Some(renderbuffer) = self.depth_stencil {
+                self.gl.delete_renderbuffer(renderbuffer);
+            }
+        });
+        let instance = &self.egl.instance;
+        if let Some(surface) = self.surface {
+            let _ = instance.destroy_surface(self.egl.display, surface);
+        }
+        let _ = instance.destroy_context(self.egl.display, self.context);
+    }
+}
@@ -0,0 +1,132 @@
+//! Bookkeeping that lets several `LibretroCore`s share one process.
+//!
+//! The libretro callbacks carry no user data, so every live core is assigned a slot and
+//! handed trampolines monomorphised for that slot. Cores also keep their state in globals,
+//! so loading a shared object that is already in use goes through a private copy of the
+//! file; the dynamic loader then treats it as a separate library with its own globals.
+
+use crate::{Callbacks, LibretroError};
+use once_cell::sync::OnceCell;
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+};
+
+/// Number of cores that can be loaded at the same time.
+pub const MAX_CORE_INSTANCES: usize = 4;
+
+type Slot = Mutex<Option<Arc<Callbacks>>>;
+
+static SLOTS: OnceCell<[Slot; MAX_CORE_INSTANCES]> = OnceCell::new();
+static IMAGES_IN_USE: OnceCell<Mutex<HashSet<PathBuf>>> = OnceCell::new();
+static NEXT_COPY_ID: AtomicUsize = AtomicUsize::new(0);
+
+fn slots() -> &'static [Slot; MAX_CORE_INSTANCES] {
+    SLOTS.get_or_init(|| std::array::from_fn(|_| Mutex::new(None)))
+}
+
+fn images_in_use() -> &'static Mutex<HashSet<PathBuf>> {
+    IMAGES_IN_USE.get_or_init(|| Mutex::new(HashSet::new()))
+}
+
+pub(crate) fn slot_callbacks(slot: usize) -> Option<Arc<Callbacks>> {
+    slots()[slot]
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .clone()
+}
+
+/// A claimed callback slot; released again when dropped.
+pub(crate) struct InstanceSlot {
+    index: usize,
+}
+
+impl InstanceSlot {
+    pub fn claim(callbacks: Arc<Callbacks>) -> Result<Self, LibretroError> {
+        for (index, slot) in slots().iter().enumerate() {
+            let mut guard = slot.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            if guard.is_none() {
+                *guard = Some(callbacks);
+                return Ok(Self { index });
+            }
+        }
+        Err(LibretroError::TooManyInstances(MAX_CORE_INSTANCES))
+    }
+
+    pub fn index(&self) -> usize {
+        self.index
+    }
+}
+
+impl Drop for InstanceSlot {
+    fn drop(&mut self) {
+        let mut guard = slots()[self.index]
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        *guard = None;
+    }
+}
+
+/// The on-disk shared object a core was loaded from.
+///
+/// The first instance of a core uses the original file; further instances get a copy in the
+/// temp directory that is deleted once the instance (and its `Library`) is gone.
+pub(crate) struct CoreImage {
+    source: PathBuf,
+    path: PathBuf,
+    copied: bool,
+}
+
+impl CoreImage {
+    pub fn acquire(path: &Path) -> Result<Self, LibretroError> {
+        let source = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        let mut in_use = images_in_use()
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if in_use.insert(source.clone()) {
+            return Ok(Self {
+                path: source.clone(),
+                source,
+                copied: false,
+            });
+        }
+        drop(in_use);
+
+        let file_name = source
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "core".to_string());
+        let copy_id = NEXT_COPY_ID.fetch_add(1, Ordering::Relaxed);
+        let path = std::env::temp_dir().join(format!(
+            "playbyte-{}-{copy_id}-{file_name}",
+            std::process::id()
+        ));
+        std::fs::copy(&source, &path).map_err(LibretroError::StageCore)?;
+        Ok(Self {
+            source,
+            path,
+            copied: true,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for CoreImage {
+    fn drop(&mut self) {
+        if self.copied {
+            let _ = std::fs::remove_file(&self.path);
+        } else {
+            images_in_use()
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .remove(&self.source);
+        }
+    }
+}
@@ -1,17 +1,40 @@
+mod core_log;
+mod environment;
+mod hw_render;
+mod instance;
+mod options;
+
+pub use core_log::{LogLevel, LogLine, LOG_HISTORY};
+pub use environment::{
+    ControllerDescription, ControllerInfo, InputDescriptor, Language, MemoryDescriptor,
+    SavestateContext, SerializationQuirks,
+};
+pub use hw_render::{gl_available, HwContextType, HwRenderRequest};
+pub use instance::MAX_CORE_INSTANCES;
+pub use options::{CoreOption, CoreOptionCategory, CoreOptionValue};
+
+use core_log::CoreLog;
+use environment::EnvironmentState;
+use hw_render::HwRenderer;
+use instance::{CoreImage, InstanceSlot};
 use libloading::Library;
-use once_cell::sync::OnceCell;
+use options::OptionStore;
 use std::{
+    collections::HashMap,
     ffi::{CStr, CString},
     os::raw::{c_char, c_void},
-    path::Path,
+    path::{Path, PathBuf},
     ptr,
-    sync::{Arc, Mutex},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
 };
 use thiserror::Error;
 
 const RETRO_API_VERSION: u32 = 1;
-const RETRO_ENVIRONMENT_GET_CAN_DUPE: u32 = 3;
-const RETRO_ENVIRONMENT_SET_PIXEL_FORMAT: u32 = 10;
+/// How many controller ports frontends answer input for; the answer to GET_INPUT_MAX_USERS.
+pub const MAX_PORTS: usize = 4;
 
 #[repr(u32)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -21,6 +44,18 @@ pub enum RetroPixelFormat {
     Rgb565 = 2,
 }
 
+/// Memory regions a core can expose through `retro_get_memory_data`.
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MemoryRegion {
+    /// Battery-backed cartridge RAM; what frontends persist as `.srm`.
+    SaveRam = 0,
+    /// Real-time clock state, persisted next to the save RAM.
+    Rtc = 1,
+    SystemRam = 2,
+    VideoRam = 3,
+}
+
 #[repr(C)]
 struct RetroSystemInfo {
     library_name: *const c_char,
@@ -48,6 +83,19 @@ pub struct RetroGameGeometry {
     pub aspect_ratio: f32,
 }
 
+impl RetroGameGeometry {
+    /// Display aspect ratio; libretro uses zero or less to mean square pixels.
+    pub fn display_aspect(&self) -> f32 {
+        if self.aspect_ratio.is_finite() && self.aspect_ratio > 0.0 {
+            self.aspect_ratio
+        } else if self.base_height > 0 {
+            self.base_width as f32 / self.base_height as f32
+        } else {
+            1.0
+        }
+    }
+}
+
 #[repr(C)]
 #[derive(Debug, Clone, Copy)]
 pub struct RetroSystemTiming {
@@ -86,6 +134,89 @@ pub struct VideoFrame {
     pub data: Vec<u8>,
 }
 
+impl VideoFrame {
+    /// The frame as tightly packed RGBA8 pixels, opaque whatever the source format. A frame
+    /// whose pitch or data is too short for its size comes out black.
+    pub fn to_rgba(&self) -> Vec<u8> {
+        let width = self.width as usize;
+        let height = self.height as usize;
+        if width == 0 || height == 0 {
+            return Vec::new();
+        }
+        let mut out = vec![0u8; width * height * 4];
+
+        let bytes_per_pixel = match self.pixel_format {
+            RetroPixelFormat::Xrgb8888 => 4,
+            RetroPixelFormat::Rgb565 | RetroPixelFormat::_0rgb1555 => 2,
+        };
+        let min_pitch = width.saturating_mul(bytes_per_pixel);
+        if self.pitch < min_pitch {
+            return out;
+        }
+        if self.data.len() < self.pitch.saturating_mul(height) {
+            return out;
+        }
+
+        match self.pixel_format {
+            RetroPixelFormat::Xrgb8888 => {
+                for y in 0..height {
+                    let row_start = y * self.pitch;
+                    let row = &self.data[row_start..row_start + min_pitch];
+                    for x in 0..width {
+                        let src = x * 4;
+                        let dst = (y * width + x) * 4;
+                        let b = row[src];
+                        let g = row[src + 1];
+                        let r = row[src + 2];
+                        out[dst] = r;
+                        out[dst + 1] = g;
+                        out[dst + 2] = b;
+                        out[dst + 3] = 0xff;
+                    }
+                }
+            }
+            RetroPixelFormat::Rgb565 => {
+                for y in 0..height {
+                    let row_start = y * self.pitch;
+                    let row = &self.data[row_start..row_start + min_pitch];
+                    for x in 0..width {
+                        let src = x * 2;
+                        let value = u16::from_le_bytes([row[src], row[src + 1]]);
+                        let r = ((value >> 11) & 0x1f) as u8;
+                        let g = ((value >> 5) & 0x3f) as u8;
+                        let b = (value & 0x1f) as u8;
+                        let dst = (y * width + x) * 4;
+                        out[dst] = (r << 3) | (r >> 2);
+                        out[dst + 1] = (g << 2) | (g >> 4);
+                        out[dst + 2] = (b << 3) | (b >> 2);
+                        out[dst + 3] = 0xff;
+                    }
+                }
+            }
+            RetroPixelFormat::_0rgb1555 => {
+                for y in 0..height {
+                    let row_start = y * self.pitch;
+                    let row = &self.data[row_start..row_start + min_pitch];
+                    for x in 0..width {
+                        let src = x * 2;
+                        let value = u16::from_le_bytes([row[src], row[src + 1]]);
+                        let r = ((value >> 10) & 0x1f) as u8;
+                        let g = ((value >> 5) & 0x1f) as u8;
+                        let b = (value & 0x1f) as u8;
+                        let dst = (y * width + x) * 4;
+                        out[dst] = (r << 3) | (r >> 2);
+                        out[dst + 1] = (g << 3) | (g >> 2);
+                        out[dst + 2] = (b << 3) | (b >> 2);
+                        out[dst + 3] = 0xff;
+                    }
+                }
+            }
+        }
+
+        out
+    }
+}
+
 type RetroEnvironmentFn = unsafe extern "C" fn(cmd: u32, data: *mut c_void) -> bool;
 type RetroVideoRefreshFn =
     unsafe extern "C" fn(data: *const c_void, width: u32, height: u32, pitch: usize);
@@ -99,6 +230,7 @@ type RetroDeinitFn = unsafe extern "C" fn();
 type RetroApiVersionFn = unsafe extern "C" fn() -> u32;
 type RetroGetSystemInfoFn = unsafe extern "C" fn(info: *mut RetroSystemInfo);
 type RetroGetSystemAvInfoFn = unsafe extern "C" fn(info: *mut RetroSystemAvInfo);
+type RetroSetControllerPortDeviceFn = unsafe extern "C" fn(port: u32, device: u32);
 type RetroSetEnvironmentFn = unsafe extern "C" fn(cb: RetroEnvironmentFn);
 type RetroSetVideoRefreshFn = unsafe extern "C" fn(cb: RetroVideoRefreshFn);
 type RetroSetAudioSampleFn = unsafe extern "C" fn(cb: RetroAudioSampleFn);
@@ -111,6 +243,8 @@ type RetroRunFn = unsafe extern "C" fn();
 type RetroSerializeSizeFn = unsafe extern "C" fn() -> usize;
 type RetroSerializeFn = unsafe extern "C" fn(data: *mut c_void, size: usize) -> bool;
 type RetroUnserializeFn = unsafe extern "C" fn(data: *const c_void, size: usize) -> bool;
+type RetroGetMemoryDataFn = unsafe extern "C" fn(id: u32) -> *mut c_void;
+type RetroGetMemorySizeFn = unsafe extern "C" fn(id: u32) -> usize;
 
 struct Symbols {
     retro_init: RetroInitFn,
@@ -124,12 +258,15 @@ struct Symbols {
     retro_set_audio_sample_batch: RetroSetAudioSampleBatchFn,
     retro_set_input_poll: RetroSetInputPollFn,
     retro_set_input_state: RetroSetInputStateFn,
+    retro_set_controller_port_device: RetroSetControllerPortDeviceFn,
     retro_load_game: RetroLoadGameFn,
     retro_unload_game: RetroUnloadGameFn,
     retro_run: RetroRunFn,
     retro_serialize_size: RetroSerializeSizeFn,
     retro_serialize: RetroSerializeFn,
     retro_unserialize: RetroUnserializeFn,
+    retro_get_memory_data: RetroGetMemoryDataFn,
+    retro_get_memory_size: RetroGetMemorySizeFn,
 }
 
 impl Symbols {
@@ -146,30 +283,46 @@ impl Symbols {
             retro_set_audio_sample_batch: *lib.get(b"retro_set_audio_sample_batch\0")?,
             retro_set_input_poll: *lib.get(b"retro_set_input_poll\0")?,
             retro_set_input_state: *lib.get(b"retro_set_input_state\0")?,
+            retro_set_controller_port_device: *lib.get(b"retro_set_controller_port_device\0")?,
             retro_load_game: *lib.get(b"retro_load_game\0")?,
             retro_unload_game: *lib.get(b"retro_unload_game\0")?,
             retro_run: *lib.get(b"retro_run\0")?,
             retro_serialize_size: *lib.get(b"retro_serialize_size\0")?,
             retro_serialize: *lib.get(b"retro_serialize\0")?,
             retro_unserialize: *lib.get(b"retro_unserialize\0")?,
+            retro_get_memory_data: *lib.get(b"retro_get_memory_data\0")?,
+            retro_get_memory_size: *lib.get(b"retro_get_memory_size\0")?,
         })
     }
 }
 
+pub type VideoRefreshCallback = Box<dyn Fn(&[u8], u32, u32, usize, RetroPixelFormat) + Send + Sync>;
+pub type AudioSampleBatchCallback = Box<dyn Fn(&[i16]) + Send + Sync>;
+pub type InputPollCallback = Box<dyn Fn() + Send + Sync>;
+pub type InputStateCallback = Box<dyn Fn(u32, u32, u32, u32) -> i16 + Send + Sync>;
+
 pub struct Callbacks {
-    pub video_refresh: Box<dyn Fn(&[u8], u32, u32, usize, RetroPixelFormat) + Send + Sync>,
-    pub audio_sample_batch: Box<dyn Fn(&[i16]) + Send + Sync>,
-    pub input_poll: Box<dyn Fn() + Send + Sync>,
-    pub input_state: Box<dyn Fn(u32, u32, u32, u32) -> i16 + Send + Sync>,
+    pub video_refresh: VideoRefreshCallback,
+    pub audio_sample_batch: AudioSampleBatchCallback,
+    pub input_poll: InputPollCallback,
+    pub input_state: InputStateCallback,
     pixel_format: Mutex<RetroPixelFormat>,
+    options: Mutex<OptionStore>,
+    system_directory: Option<CString>,
+    save_directory: Option<CString>,
+    core_path: Option<CString>,
+    language: Language,
+    input_bitmasks: bool,
+    log: Mutex<CoreLog>,
+    environment: Mutex<EnvironmentState>,
 }
 
 impl Callbacks {
     pub fn new(
-        video_refresh: Box<dyn Fn(&[u8], u32, u32, usize, RetroPixelFormat) + Send + Sync>,
-        audio_sample_batch: Box<dyn Fn(&[i16]) + Send + Sync>,
-        input_poll: Box<dyn Fn() + Send + Sync>,
-        input_state: Box<dyn Fn(u32, u32, u32, u32) -> i16 + Send + Sync>,
+        video_refresh: VideoRefreshCallback,
+        audio_sample_batch: AudioSampleBatchCallback,
+        input_poll: InputPollCallback,
+        input_state: InputStateCallback,
     ) -> Self {
         Self {
             video_refresh,
@@ -177,9 +330,68 @@ impl Callbacks {
             input_poll,
             input_state,
             pixel_format: Mutex::new(RetroPixelFormat::_0rgb1555),
+            options: Mutex::new(OptionStore::default()),
+            system_directory: None,
+            save_directory: None,
+            core_path: None,
+            language: Language::default(),
+            input_bitmasks: false,
+            log: Mutex::new(CoreLog::default()),
+            environment: Mutex::new(EnvironmentState::default()),
         }
     }
 
+    /// Seeds option values the core should see as soon as it declares its options.
+    ///
+    /// Values that are not valid for the declared option fall back to the core default.
+    pub fn with_core_options(self, values: HashMap<String, String>) -> Self {
+        self.options_mut().set_overrides(values);
+        self
+    }
+
+    /// Directory reported through GET_SYSTEM_DIRECTORY, where cores look for BIOS files.
+    pub fn with_system_directory(mut self, path: impl AsRef<Path>) -> Self {
+        self.system_directory = path_cstring(path.as_ref());
+        self
+    }
+
+    /// Directory reported through GET_SAVE_DIRECTORY for saves the core writes itself.
+    pub fn with_save_directory(mut self, path: impl AsRef<Path>) -> Self {
+        self.save_directory = path_cstring(path.as_ref());
+        self
+    }
+
+    /// Language reported through GET_LANGUAGE; English unless set.
+    pub fn with_language(mut self, language: Language) -> Self {
+        self.language = language;
+        self
+    }
+
+    /// Answers GET_INPUT_BITMASKS, promising that `input_state` returns every joypad button
+    /// at once when asked for `RETRO_DEVICE_ID_JOYPAD_MASK` (id 256).
+    pub fn with_input_bitmasks(mut self) -> Self {
+        self.input_bitmasks = true;
+        self
+    }
+
+    fn options_mut(&self) -> std::sync::MutexGuard<'_, OptionStore> {
+        self.options
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn log_mut(&self) -> std::sync::MutexGuard<'_, CoreLog> {
+        self.log
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn environment_mut(&self) -> std::sync::MutexGuard<'_, EnvironmentState> {
+        self.environment
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
     fn set_pixel_format(&self, format: RetroPixelFormat) {
         let mut guard = self
             .pixel_format
@@ -204,63 +416,115 @@ pub enum LibretroError {
     ApiVersion { expected: u32, actual: u32 },
     #[error("missing libretro symbol: {0}")]
     MissingSymbol(String),
-    #[error("libretro core failed to load game")]
-    LoadGame,
+    #[error("libretro core failed to load game{}", last_problem(.log))]
+    LoadGame { log: Vec<LogLine> },
     #[error("libretro core failed to serialize state")]
     Serialize,
     #[error("libretro core failed to unserialize state")]
     Unserialize,
+    #[error("core cannot save state until it has run a frame")]
+    StateNotReady,
+    #[error("state is {actual} bytes but the core expects {expected}")]
+    StateSize { expected: usize, actual: usize },
     #[error("no frame captured during smoke test")]
     NoFrame,
+    #[error("core does not declare option '{0}'")]
+    UnknownOption(String),
+    #[error("'{value}' is not a valid value for core option '{key}'")]
+    InvalidOptionValue { key: String, value: String },
+    #[error("core does not expose {0:?} memory")]
+    MemoryUnavailable(MemoryRegion),
+    #[error("at most {0} libretro cores can be loaded at once")]
+    TooManyInstances(usize),
+    #[error("failed to stage a private copy of the core: {0}")]
+    StageCore(std::io::Error),
+    #[error("failed to write content for a core that needs a file path: {0}")]
+    StageContent(std::io::Error),
+    #[error("failed to read content: {0}")]
+    ReadContent(std::io::Error),
+    #[error("failed to set up hardware rendering: {0}")]
+    HwRender(String),
     #[error("invalid utf-8 in core metadata")]
     Utf8(#[from] std::str::Utf8Error),
 }
 
-static CALLBACKS: OnceCell<Mutex<Option<Arc<Callbacks>>>> = OnceCell::new();
+impl LibretroError {
+    /// What the core logged before the failure, oldest first; empty for most errors.
+    pub fn core_log(&self) -> &[LogLine] {
+        match self {
+            Self::LoadGame { log } => log,
+            _ => &[],
+        }
+    }
+}
+
+fn last_problem(log: &[LogLine]) -> String {
+    log.iter()
+        .rev()
+        .find(|line| line.level >= LogLevel::Warn)
+        .map(|line| format!(": {}", line.message))
+        .unwrap_or_default()
+}
+
+/// `mgba_libretro.so` -> `mgba`, used to tag the core's log output.
+fn core_id(path: &Path) -> String {
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "core".to_string());
+    match stem.strip_suffix("_libretro") {
+        Some(id) => id.to_string(),
+        None => stem,
+    }
+}
 
-fn callbacks_cell() -> &'static Mutex<Option<Arc<Callbacks>>> {
-    CALLBACKS.get_or_init(|| Mutex::new(None))
+fn path_cstring(path: &Path) -> Option<CString> {
+    CString::new(path.to_string_lossy().into_owned()).ok()
 }
 
-fn with_callbacks<F, R>(f: F) -> Option<R>
+fn with_callbacks<F, R>(slot: usize, f: F) -> Option<R>
 where
     F: FnOnce(&Callbacks) -> R,
 {
-    let callbacks = callbacks_cell()
-        .lock()
-        .unwrap_or_else(|poisoned| poisoned.into_inner())
-        .clone();
+    let callbacks = instance::slot_callbacks(slot);
     callbacks.as_deref().map(f)
 }
 
-unsafe extern "C" fn environment_callback(cmd: u32, data: *mut c_void) -> bool {
-    match cmd {
-        RETRO_ENVIRONMENT_GET_CAN_DUPE => {
-            if !data.is_null() {
-                *(data as *mut bool) = true;
-                return true;
-            }
-            false
-        }
-        RETRO_ENVIRONMENT_SET_PIXEL_FORMAT => {
-            if data.is_null() {
-                return false;
-            }
-            let format = *(data as *const RetroPixelFormat);
-            let supported = matches!(
-                format,
-                RetroPixelFormat::_0rgb1555 | RetroPixelFormat::Xrgb8888 | RetroPixelFormat::Rgb565
-            );
-            if supported {
-                let _ = with_callbacks(|callbacks| callbacks.set_pixel_format(format));
-            }
-            supported
+/// Callback entry points handed to a core, bound to the instance slot it was loaded into.
+struct Trampolines {
+    environment: RetroEnvironmentFn,
+    video_refresh: RetroVideoRefreshFn,
+    audio_sample: RetroAudioSampleFn,
+    audio_sample_batch: RetroAudioSampleBatchFn,
+    input_poll: RetroInputPollFn,
+    input_state: RetroInputStateFn,
+}
+
+impl Trampolines {
+    const fn for_slot<const SLOT: usize>() -> Self {
+        Self {
+            environment: environment_callback::<SLOT>,
+            video_refresh: video_refresh_callback::<SLOT>,
+            audio_sample: audio_sample_callback::<SLOT>,
+            audio_sample_batch: audio_sample_batch_callback::<SLOT>,
+            input_poll: input_poll_callback::<SLOT>,
+            input_state: input_state_callback::<SLOT>,
         }
-        _ => false,
     }
 }
 
-unsafe extern "C" fn video_refresh_callback(
+const TRAMPOLINES: [Trampolines; MAX_CORE_INSTANCES] = [
+    Trampolines::for_slot::<0>(),
+    Trampolines::for_slot::<1>(),
+    Trampolines::for_slot::<2>(),
+    Trampolines::for_slot::<3>(),
+];
+
+unsafe extern "C" fn environment_callback<const SLOT: usize>(cmd: u32, data: *mut c_void) -> bool {
+    environment::dispatch(SLOT, cmd, data)
+}
+
+unsafe extern "C" fn video_refresh_callback<const SLOT: usize>(
     data: *const c_void,
     width: u32,
     height: u32,
@@ -269,55 +533,121 @@ unsafe extern "C" fn video_refresh_callback(
     if data.is_null() || width == 0 || height == 0 {
         return;
     }
+    if data == hw_render::RETRO_HW_FRAME_BUFFER_VALID {
+        // Read back by `LibretroCore::run_frame` once the core returns.
+        let _ = with_callbacks(SLOT, |callbacks| {
+            callbacks.environment_mut().hw_frame = Some((width, height));
+        });
+        return;
+    }
     let byte_len = pitch.saturating_mul(height as usize);
     let slice = std::slice::from_raw_parts(data as *const u8, byte_len);
-    let _ = with_callbacks(|callbacks| {
+    let _ = with_callbacks(SLOT, |callbacks| {
         let format = callbacks.pixel_format();
         (callbacks.video_refresh)(slice, width, height, pitch, format);
     });
 }
 
-unsafe extern "C" fn audio_sample_callback(left: i16, right: i16) {
+unsafe extern "C" fn audio_sample_callback<const SLOT: usize>(left: i16, right: i16) {
     let samples = [left, right];
-    let _ = with_callbacks(|callbacks| {
+    let _ = with_callbacks(SLOT, |callbacks| {
         (callbacks.audio_sample_batch)(&samples);
     });
 }
 
-unsafe extern "C" fn audio_sample_batch_callback(data: *const i16, frames: usize) -> usize {
+unsafe extern "C" fn audio_sample_batch_callback<const SLOT: usize>(
+    data: *const i16,
+    frames: usize,
+) -> usize {
     if data.is_null() || frames == 0 {
         return 0;
     }
     let slice = std::slice::from_raw_parts(data, frames * 2);
-    let _ = with_callbacks(|callbacks| {
+    let _ = with_callbacks(SLOT, |callbacks| {
         (callbacks.audio_sample_batch)(slice);
     });
     frames
 }
 
-unsafe extern "C" fn input_poll_callback() {
-    let _ = with_callbacks(|callbacks| {
+unsafe extern "C" fn input_poll_callback<const SLOT: usize>() {
+    let _ = with_callbacks(SLOT, |callbacks| {
         (callbacks.input_poll)();
     });
 }
 
-unsafe extern "C" fn input_state_callback(port: u32, device: u32, index: u32, id: u32) -> i16 {
-    with_callbacks(|callbacks| (callbacks.input_state)(port, device, index, id)).unwrap_or(0)
+unsafe extern "C" fn input_state_callback<const SLOT: usize>(
+    port: u32,
+    device: u32,
+    index: u32,
+    id: u32,
+) -> i16 {
+    with_callbacks(SLOT, |callbacks| {
+        (callbacks.input_state)(port, device, index, id)
+    })
+    .unwrap_or(0)
 }
 
 struct LoadedGame {
     _path: CString,
     _data: Vec<u8>,
+    _staged: Option<StagedContent>,
+    /// The GL context of a core that renders in hardware.
+    hw: Option<HwRenderer>,
+}
+
+static NEXT_CONTENT_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// In-memory content written to the temp directory for a core that only loads from a path.
+/// The directory is removed with the loaded game.
+struct StagedContent {
+    /// The staged file; its directory belongs to it alone and goes with it.
+    path: PathBuf,
+}
+
+impl StagedContent {
+    fn write(name: &str, data: &[u8]) -> std::io::Result<Self> {
+        let dir = std::env::temp_dir().join(format!(
+            "playbyte-content-{}-{}",
+            std::process::id(),
+            NEXT_CONTENT_ID.fetch_add(1, Ordering::Relaxed)
+        ));
+        // Keep the file name, since cores look at the extension, but nothing above it.
+        let file_name = Path::new(name)
+            .file_name()
+            .unwrap_or_else(|| "content".as_ref());
+        std::fs::create_dir_all(&dir)?;
+        let staged = Self {
+            path: dir.join(file_name),
+        };
+        std::fs::write(&staged.path, data)?;
+        Ok(staged)
+    }
+}
+
+impl Drop for StagedContent {
+    fn drop(&mut self) {
+        if let Some(dir) = self.path.parent() {
+            let _ = std::fs::remove_dir_all(dir);
+        }
+    }
 }
 
 pub struct LibretroCore {
+    // Field order matters on drop: the slot is released and the library unloaded before
+    // the image file backing it is removed.
+    _slot: InstanceSlot,
     _lib: Library,
+    _image: CoreImage,
     symbols: Symbols,
     system_info: SystemInfo,
     system_av_info: SystemAvInfo,
     callbacks: Arc<Callbacks>,
     game_loaded: bool,
-    loaded_game: Option<LoadedGame>,
+    // Boxed, since a GL context carries the whole GL function table with it.
+    loaded_game: Option<Box<LoadedGame>>,
+    av_changed: bool,
+    /// Whether `retro_run` has been called since the game was loaded.
+    has_run: bool,
 }
 
 impl LibretroCore {
@@ -336,8 +666,9 @@ impl LibretroCore {
         unsafe { Self::read_system_info(&symbols) }
     }
 
-    pub fn load(path: impl AsRef<Path>, callbacks: Callbacks) -> Result<Self, LibretroError> {
-        let lib = unsafe { Library::new(path.as_ref())? };
+    pub fn load(path: impl AsRef<Path>, mut callbacks: Callbacks) -> Result<Self, LibretroError> {
+        let image = CoreImage::acquire(path.as_ref())?;
+        let lib = unsafe { Library::new(image.path())? };
         let symbols = unsafe { Symbols::load(&lib)? };
 
         let api_version = unsafe { (symbols.retro_api_version)() };
@@ -348,21 +679,19 @@ impl LibretroCore {
             });
         }
 
+        callbacks.log_mut().set_core_id(&core_id(path.as_ref()));
+        callbacks.core_path = path_cstring(path.as_ref());
         let callbacks = Arc::new(callbacks);
-        {
-            let mut guard = callbacks_cell()
-                .lock()
-                .unwrap_or_else(|poisoned| poisoned.into_inner());
-            *guard = Some(callbacks.clone());
-        }
+        let slot = InstanceSlot::claim(callbacks.clone())?;
+        let trampolines = &TRAMPOLINES[slot.index()];
 
         unsafe {
-            (symbols.retro_set_environment)(environment_callback);
-            (symbols.retro_set_video_refresh)(video_refresh_callback);
-            (symbols.retro_set_audio_sample)(audio_sample_callback);
-            (symbols.retro_set_audio_sample_batch)(audio_sample_batch_callback);
-            (symbols.retro_set_input_poll)(input_poll_callback);
-            (symbols.retro_set_input_state)(input_state_callback);
+            (symbols.retro_set_environment)(trampolines.environment);
+            (symbols.retro_set_video_refresh)(trampolines.video_refresh);
+            (symbols.retro_set_audio_sample)(trampolines.audio_sample);
+            (symbols.retro_set_audio_sample_batch)(trampolines.audio_sample_batch);
+            (symbols.retro_set_input_poll)(trampolines.input_poll);
+            (symbols.retro_set_input_state)(trampolines.input_state);
             (symbols.retro_init)();
         }
 
@@ -382,13 +711,17 @@ impl LibretroCore {
         };
 
         Ok(Self {
+            _slot: slot,
             _lib: lib,
+            _image: image,
             symbols,
             system_info,
             system_av_info,
             callbacks,
             game_loaded: false,
             loaded_game: None,
+            av_changed: false,
+            has_run: false,
         })
     }
 
@@ -404,41 +737,274 @@ impl LibretroCore {
         self.callbacks.pixel_format()
     }
 
+    pub fn core_options(&self) -> Vec<CoreOption> {
+        self.callbacks.options_mut().definitions().to_vec()
+    }
+
+    pub fn core_option_categories(&self) -> Vec<CoreOptionCategory> {
+        self.callbacks.options_mut().categories().to_vec()
+    }
+
+    pub fn core_option(&self, key: &str) -> Option<String> {
+        self.callbacks.options_mut().value(key)
+    }
+
+    pub fn core_option_values(&self) -> HashMap<String, String> {
+        let store = self.callbacks.options_mut();
+        store
+            .definitions()
+            .iter()
+            .filter_map(|option| Some((option.key.clone(), store.value(&option.key)?)))
+            .collect()
+    }
+
+    /// Changes an option; the core picks it up on its next GET_VARIABLE_UPDATE poll.
+    pub fn set_core_option(&self, key: &str, value: &str) -> Result<(), LibretroError> {
+        self.callbacks.options_mut().set(key, value)
+    }
+
+    pub fn input_descriptors(&self) -> Vec<InputDescriptor> {
+        self.callbacks.environment_mut().input_descriptors.clone()
+    }
+
+    /// Devices the core accepts, indexed by port.
+    pub fn controller_info(&self) -> Vec<ControllerInfo> {
+        self.callbacks.environment_mut().controller_info.clone()
+    }
+
+    /// Plugs a device into a port; `device` is one of the ids from `controller_info`, or a
+    /// base `RETRO_DEVICE_*` type.
+    pub fn set_controller_port_device(&self, port: u32, device: u32) {
+        unsafe { (self.symbols.retro_set_controller_port_device)(port, device) }
+    }
+
+    pub fn memory_map(&self) -> Vec<MemoryDescriptor> {
+        self.callbacks.environment_mut().memory_map.clone()
+    }
+
+    pub fn supports_no_game(&self) -> bool {
+        self.callbacks.environment_mut().supports_no_game
+    }
+
+    pub fn supports_achievements(&self) -> bool {
+        self.callbacks.environment_mut().supports_achievements
+    }
+
+    pub fn performance_level(&self) -> u32 {
+        self.callbacks.environment_mut().performance_level
+    }
+
+    pub fn serialization_quirks(&self) -> SerializationQuirks {
+        self.callbacks.environment_mut().serialization_quirks
+    }
+
+    /// Whether states from this core can be shared with other sessions and machines.
+    pub fn is_state_portable(&self) -> bool {
+        self.serialization_quirks().is_portable()
+    }
+
+    /// Whether the core asked to be shut down through RETRO_ENVIRONMENT_SHUTDOWN.
+    pub fn shutdown_requested(&self) -> bool {
+        self.callbacks.environment_mut().shutdown_requested
+    }
+
+    /// Tells the core through GET_FASTFORWARDING whether the game runs faster than normal.
+    pub fn set_fast_forwarding(&self, fast_forwarding: bool) {
+        self.callbacks.environment_mut().fast_forwarding = fast_forwarding;
+    }
+
+    /// Tells the core through GET_SAVESTATE_CONTEXT why the next states are saved or loaded.
+    pub fn set_savestate_context(&self, context: SavestateContext) {
+        self.callbacks.environment_mut().savestate_context = context;
+    }
+
+    /// The context the core renders with, if it asked for one through SET_HW_RENDER.
+    /// Its frames still arrive through the video callback, read back as XRGB8888.
+    pub fn hw_render(&self) -> Option<HwRenderRequest> {
+        self.hw()?;
+        let environment = self.callbacks.environment_mut();
+        environment.hw_render.map(|hw_render| hw_render.request)
+    }
+
+    /// The most recent messages the core logged, oldest first.
+    pub fn recent_log(&self) -> Vec<LogLine> {
+        self.callbacks.log_mut().lines()
+    }
+
     pub fn load_game(&mut self, path: impl AsRef<Path>) -> Result<(), LibretroError> {
-        let data = std::fs::read(&path).map_err(|_| LibretroError::LoadGame)?;
-        let c_path = CString::new(path.as_ref().to_string_lossy().as_bytes())
-            .map_err(|_| LibretroError::LoadGame)?;
+        let path = path.as_ref();
+        // Cores that need the full path open the file themselves.
+        let data = if self.system_info.need_fullpath {
+            Vec::new()
+        } else {
+            std::fs::read(path).map_err(LibretroError::ReadContent)?
+        };
+        self.load_game_info(path_cstring(path), data, None)
+    }
+
+    /// Loads content that is not a file of its own, such as a ROM extracted from an archive.
+    ///
+    /// `name` stands in for the path, so its extension should match the content. Cores that
+    /// need a full path get a temporary file instead, removed when the game is unloaded.
+    pub fn load_game_from_memory(
+        &mut self,
+        name: &str,
+        data: Vec<u8>,
+    ) -> Result<(), LibretroError> {
+        if self.system_info.need_fullpath {
+            let staged = StagedContent::write(name, &data).map_err(LibretroError::StageContent)?;
+            let path = path_cstring(&staged.path);
+            return self.load_game_info(path, Vec::new(), Some(staged));
+        }
+        self.load_game_info(CString::new(name).ok(), data, None)
+    }
+
+    fn load_game_info(
+        &mut self,
+        path: Option<CString>,
+        data: Vec<u8>,
+        staged: Option<StagedContent>,
+    ) -> Result<(), LibretroError> {
+        let c_path = path.ok_or(LibretroError::LoadGame { log: Vec::new() })?;
         let game = RetroGameInfo {
             path: c_path.as_ptr(),
-            data: data.as_ptr() as *const c_void,
+            data: if self.system_info.need_fullpath {
+                ptr::null()
+            } else {
+                data.as_ptr() as *const c_void
+            },
             size: data.len(),
             meta: ptr::null(),
         };
 
-        let loaded_game = LoadedGame {
+        let mut loaded_game = Box::new(LoadedGame {
             _path: c_path,
             _data: data,
-        };
+            _staged: staged,
+            hw: None,
+        });
         let ok = unsafe { (self.symbols.retro_load_game)(&game) };
         if !ok {
-            return Err(LibretroError::LoadGame);
+            return Err(LibretroError::LoadGame {
+                log: self.recent_log(),
+            });
         }
         self.system_av_info = unsafe { Self::read_system_av_info(&self.symbols) };
+        // Changes made while loading are already part of the AV info just read.
+        self.apply_av_updates();
+        self.av_changed = false;
+        self.has_run = false;
+        match self.start_hw_render() {
+            Ok(hw) => loaded_game.hw = hw,
+            Err(err) => {
+                unsafe { (self.symbols.retro_unload_game)() };
+                self.callbacks.environment_mut().hw_render = None;
+                return Err(err);
+            }
+        }
         self.game_loaded = true;
         self.loaded_game = Some(loaded_game);
         Ok(())
     }
 
+    /// Creates the context a SET_HW_RENDER core asked for while loading and lets the core
+    /// set up its GL state in it.
+    fn start_hw_render(&self) -> Result<Option<HwRenderer>, LibretroError> {
+        let Some(hw_render) = self.callbacks.environment_mut().hw_render else {
+            return Ok(None);
+        };
+        let geometry = self.system_av_info.geometry;
+        let renderer = HwRenderer::new(
+            &hw_render.request,
+            geometry.max_width.max(geometry.base_width),
+            geometry.max_height.max(geometry.base_height),
+        )
+        .map_err(LibretroError::HwRender)?;
+        self.callbacks.environment_mut().hw_framebuffer = renderer.framebuffer_id();
+        if let Some(context_reset) = hw_render.context_reset {
+            renderer.with_current(|| unsafe { context_reset() });
+        }
+        Ok(Some(renderer))
+    }
+
+    fn hw(&self) -> Option<&HwRenderer> {
+        self.loaded_game.as_ref()?.hw.as_ref()
+    }
+
+    /// Runs `f` with the core's GL context current, if it has one.
+    fn in_context<R>(&self, f: impl FnOnce() -> R) -> R {
+        match self.hw() {
+            Some(hw) => hw.with_current(f),
+            None => f(),
+        }
+    }
+
     pub fn unload_game(&mut self) {
         if self.game_loaded {
-            unsafe { (self.symbols.retro_unload_game)() };
+            self.in_context(|| unsafe { (self.symbols.retro_unload_game)() });
+            if let Some(hw) = self.hw() {
+                let context_destroy = self
+                    .callbacks
+                    .environment_mut()
+                    .hw_render
+                    .take()
+                    .and_then(|hw_render| hw_render.context_destroy);
+                if let Some(context_destroy) = context_destroy {
+                    hw.with_current(|| unsafe { context_destroy() });
+                }
+            }
             self.game_loaded = false;
             self.loaded_game = None;
         }
     }
 
     pub fn run_frame(&mut self) {
-        unsafe { (self.symbols.retro_run)() };
+        match self.hw() {
+            Some(hw) => hw.with_current(|| {
+                unsafe { (self.symbols.retro_run)() };
+                let presented = self.callbacks.environment_mut().hw_frame.take();
+                if let Some((width, height)) = presented {
+                    let (frame, width, height) = hw.read_frame(width, height);
+                    (self.callbacks.video_refresh)(
+                        &frame,
+                        width,
+                        height,
+                        width as usize * 4,
+                        RetroPixelFormat::Xrgb8888,
+                    );
+                }
+            }),
+            None => unsafe { (self.symbols.retro_run)() },
+        }
+        self.has_run = true;
+        self.apply_av_updates();
+        if let Some(hw) = self.loaded_game.as_mut().and_then(|game| game.hw.as_mut()) {
+            let geometry = self.system_av_info.geometry;
+            hw.ensure_size(geometry.max_width, geometry.max_height);
+        }
+    }
+
+    /// The AV info, if the core changed it through SET_GEOMETRY or SET_SYSTEM_AV_INFO since
+    /// the last call. Frame-rate and sample-rate changes only come with SET_SYSTEM_AV_INFO.
+    pub fn take_av_change(&mut self) -> Option<SystemAvInfo> {
+        std::mem::take(&mut self.av_changed).then_some(self.system_av_info)
+    }
+
+    /// Folds SET_SYSTEM_AV_INFO and SET_GEOMETRY calls into `system_av_info`.
+    fn apply_av_updates(&mut self) {
+        let mut environment = self.callbacks.environment_mut();
+        if let Some(info) = environment.pending_av_info.take() {
+            self.system_av_info = info;
+            self.av_changed = true;
+        }
+        if let Some(geometry) = environment.pending_geometry.take() {
+            // SET_GEOMETRY may not change the maximum size the core allocated for.
+            self.av_changed = true;
+            let current = &mut self.system_av_info.geometry;
+            current.base_width = geometry.base_width;
+            current.base_height = geometry.base_height;
+            current.aspect_ratio = geometry.aspect_ratio;
+        }
     }
 
     pub fn run_frames(&mut self, frames: usize) {
@@ -452,10 +1018,20 @@ impl LibretroCore {
     }
 
     pub fn serialize(&self) -> Result<Vec<u8>, LibretroError> {
+        let quirks = self.serialization_quirks();
+        if quirks.contains(SerializationQuirks::MUST_INITIALIZE) && !self.has_run {
+            return Err(LibretroError::StateNotReady);
+        }
+        // Variable-size cores may report a different size every time, so always ask right
+        // before serializing.
         let size = self.serialize_size();
+        if size == 0 {
+            return Err(LibretroError::Serialize);
+        }
         let mut buffer = vec![0u8; size];
-        let ok =
-            unsafe { (self.symbols.retro_serialize)(buffer.as_mut_ptr() as *mut c_void, size) };
+        let ok = self.in_context(|| unsafe {
+            (self.symbols.retro_serialize)(buffer.as_mut_ptr() as *mut c_void, size)
+        });
         if ok {
             Ok(buffer)
         } else {
@@ -463,9 +1039,21 @@ impl LibretroCore {
         }
     }
 
+    /// Restores a state. Unless the core declared CORE_VARIABLE_SIZE, the state must be
+    /// exactly `serialize_size()` bytes; cores tend to misread anything else silently.
     pub fn unserialize(&self, data: &[u8]) -> Result<(), LibretroError> {
-        let ok =
-            unsafe { (self.symbols.retro_unserialize)(data.as_ptr() as *const c_void, data.len()) };
+        if !self.serialization_quirks().variable_size() {
+            let expected = self.serialize_size();
+            if data.len() != expected {
+                return Err(LibretroError::StateSize {
+                    expected,
+                    actual: data.len(),
+                });
+            }
+        }
+        let ok = self.in_context(|| unsafe {
+            (self.symbols.retro_unserialize)(data.as_ptr() as *const c_void, data.len())
+        });
         if ok {
             Ok(())
         } else {
@@ -473,6 +1061,43 @@ impl LibretroCore {
         }
     }
 
+    /// Size in bytes of a memory region, or 0 when the core does not expose it.
+    pub fn memory_size(&self, region: MemoryRegion) -> usize {
+        unsafe { (self.symbols.retro_get_memory_size)(region as u32) }
+    }
+
+    /// Borrows a memory region the core exposes. Only meaningful after `load_game`.
+    pub fn memory(&self, region: MemoryRegion) -> Option<&[u8]> {
+        let size = self.memory_size(region);
+        let data = unsafe { (self.symbols.retro_get_memory_data)(region as u32) };
+        if data.is_null() || size == 0 {
+            return None;
+        }
+        Some(unsafe { std::slice::from_raw_parts(data as *const u8, size) })
+    }
+
+    pub fn memory_mut(&mut self, region: MemoryRegion) -> Option<&mut [u8]> {
+        let size = self.memory_size(region);
+        let data = unsafe { (self.symbols.retro_get_memory_data)(region as u32) };
+        if data.is_null() || size == 0 {
+            return None;
+        }
+        Some(unsafe { std::slice::from_raw_parts_mut(data as *mut u8, size) })
+    }
+
+    /// Copies `data` into a memory region, e.g. to restore a `.srm` file.
+    ///
+    /// Only the overlapping prefix is copied when the sizes differ, as other frontends do
+    /// for saves written by a different core version.
+    pub fn write_memory(&mut self, region: MemoryRegion, data: &[u8]) -> Result<(), LibretroError> {
+        let memory = self
+            .memory_mut(region)
+            .ok_or(LibretroError::MemoryUnavailable(region))?;
+        let len = data.len().min(memory.len());
+        memory[..len].copy_from_slice(&data[..len]);
+        Ok(())
+    }
+
     unsafe fn read_system_info(symbols: &Symbols) -> Result<SystemInfo, LibretroError> {
         let mut info = RetroSystemInfo {
             library_name: ptr::null(),
@@ -524,6 +1149,32 @@ impl Drop for LibretroCore {
     }
 }
 
+/// Helpers for tests in this workspace.
+#[doc(hidden)]
+pub mod testing {
+    use std::path::{Path, PathBuf};
+
+    /// The mock core `build.rs` compiles from `csrc/mock_core.c`, when the toolchain can
+    /// build shared libraries.
+    pub fn mock_core_path() -> Option<&'static Path> {
+        option_env!("PLAYBYTE_MOCK_CORE").map(Path::new)
+    }
+
+    /// The mock core and a game file for it holding `flags`, named after `name` so tests
+    /// running side by side get their own. Without the mock core, notes that the test is
+    /// skipped and returns `None`.
+    pub fn mock_content(name: &str, flags: &str) -> Option<(&'static Path, PathBuf)> {
+        let Some(mock) = mock_core_path() else {
+            eprintln!("Skipping test: mock core not built");
+            return None;
+        };
+        let content =
+            std::env::temp_dir().join(format!("playbyte-{name}-{}.mock", std::process::id()));
+        std::fs::write(&content, flags).expect("write mock content");
+        Some((mock, content))
+    }
+}
+
 pub fn smoke_test(
     core_path: impl AsRef<Path>,
     rom_path: impl AsRef<Path>,
@@ -533,7 +1184,9 @@ pub fn smoke_test(
     let capture = last_frame.clone();
     let callbacks = Callbacks::new(
         Box::new(move |data, width, height, pitch, format| {
-            let mut guard = capture.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            let mut guard = capture
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
             *guard = Some(VideoFrame {
                 width,
                 height,
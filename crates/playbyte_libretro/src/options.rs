@@ -0,0 +1,373 @@
+use crate::LibretroError;
+use std::{
+    collections::HashMap,
+    ffi::{CStr, CString},
+    os::raw::c_char,
+};
+
+const RETRO_NUM_CORE_OPTION_VALUES_MAX: usize = 128;
+
+#[repr(C)]
+pub(crate) struct RetroVariable {
+    pub key: *const c_char,
+    pub value: *const c_char,
+}
+
+#[repr(C)]
+struct RetroCoreOptionValue {
+    value: *const c_char,
+    label: *const c_char,
+}
+
+#[repr(C)]
+pub(crate) struct RetroCoreOptionDefinition {
+    key: *const c_char,
+    desc: *const c_char,
+    info: *const c_char,
+    values: [RetroCoreOptionValue; RETRO_NUM_CORE_OPTION_VALUES_MAX],
+    default_value: *const c_char,
+}
+
+#[repr(C)]
+pub(crate) struct RetroCoreOptionsIntl {
+    us: *const RetroCoreOptionDefinition,
+    local: *const RetroCoreOptionDefinition,
+}
+
+#[repr(C)]
+struct RetroCoreOptionV2Category {
+    key: *const c_char,
+    desc: *const c_char,
+    info: *const c_char,
+}
+
+#[repr(C)]
+struct RetroCoreOptionV2Definition {
+    key: *const c_char,
+    desc: *const c_char,
+    desc_categorized: *const c_char,
+    info: *const c_char,
+    info_categorized: *const c_char,
+    category_key: *const c_char,
+    values: [RetroCoreOptionValue; RETRO_NUM_CORE_OPTION_VALUES_MAX],
+    default_value: *const c_char,
+}
+
+#[repr(C)]
+pub(crate) struct RetroCoreOptionsV2 {
+    categories: *const RetroCoreOptionV2Category,
+    definitions: *const RetroCoreOptionV2Definition,
+}
+
+#[repr(C)]
+pub(crate) struct RetroCoreOptionsV2Intl {
+    us: *const RetroCoreOptionsV2,
+    local: *const RetroCoreOptionsV2,
+}
+
+#[repr(C)]
+pub(crate) struct RetroCoreOptionDisplay {
+    key: *const c_char,
+    visible: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CoreOptionValue {
+    pub value: String,
+    pub label: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CoreOption {
+    pub key: String,
+    pub description: String,
+    pub info: Option<String>,
+    pub category: Option<String>,
+    pub values: Vec<CoreOptionValue>,
+    pub default_value: String,
+    pub visible: bool,
+}
+
+impl CoreOption {
+    pub fn accepts(&self, value: &str) -> bool {
+        self.values.iter().any(|candidate| candidate.value == value)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CoreOptionCategory {
+    pub key: String,
+    pub description: String,
+    pub info: Option<String>,
+}
+
+/// Per-core option definitions and the values handed back through GET_VARIABLE.
+///
+/// Values are kept as `CString`s so the pointers we return to the core stay valid
+/// until the option is changed again, as the libretro API requires.
+#[derive(Debug, Default)]
+pub(crate) struct OptionStore {
+    definitions: Vec<CoreOption>,
+    categories: Vec<CoreOptionCategory>,
+    values: HashMap<String, CString>,
+    overrides: HashMap<String, String>,
+    updated: bool,
+}
+
+impl OptionStore {
+    pub fn set_overrides(&mut self, overrides: HashMap<String, String>) {
+        self.overrides = overrides;
+    }
+
+    pub fn definitions(&self) -> &[CoreOption] {
+        &self.definitions
+    }
+
+    pub fn categories(&self) -> &[CoreOptionCategory] {
+        &self.categories
+    }
+
+    pub fn define(&mut self, definitions: Vec<CoreOption>, categories: Vec<CoreOptionCategory>) {
+        let mut values = HashMap::with_capacity(definitions.len());
+        for option in &definitions {
+            // Prefer host overrides, which follow `set`, then whatever was active before a
+            // re-declaration.
+            let value = self
+                .overrides
+                .get(&option.key)
+                .cloned()
+                .filter(|value| option.accepts(value))
+                .or_else(|| {
+                    self.value(&option.key)
+                        .filter(|value| option.accepts(value))
+                })
+                .unwrap_or_else(|| option.default_value.clone());
+            if let Ok(value) = CString::new(value) {
+                values.insert(option.key.clone(), value);
+            }
+        }
+        self.definitions = definitions;
+        self.categories = categories;
+        self.values = values;
+        self.updated = false;
+    }
+
+    pub fn get(&self, key: &str) -> Option<&CStr> {
+        self.values.get(key).map(CString::as_c_str)
+    }
+
+    pub fn value(&self, key: &str) -> Option<String> {
+        self.get(key)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string)
+    }
+
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), LibretroError> {
+        let option = self
+            .definitions
+            .iter()
+            .find(|option| option.key == key)
+            .ok_or_else(|| LibretroError::UnknownOption(key.to_string()))?;
+        if !option.accepts(value) {
+            return Err(LibretroError::InvalidOptionValue {
+                key: key.to_string(),
+                value: value.to_string(),
+            });
+        }
+        let value = CString::new(value).map_err(|_| LibretroError::InvalidOptionValue {
+            key: key.to_string(),
+            value: value.to_string(),
+        })?;
+        // A later re-declaration must not bring back an older override.
+        self.overrides
+            .insert(key.to_string(), value.to_string_lossy().into_owned());
+        if self.values.get(key) != Some(&value) {
+            self.values.insert(key.to_string(), value);
+            self.updated = true;
+        }
+        Ok(())
+    }
+
+    pub fn set_visible(&mut self, key: &str, visible: bool) {
+        if let Some(option) = self.definitions.iter_mut().find(|option| option.key == key) {
+            option.visible = visible;
+        }
+    }
+
+    pub fn take_updated(&mut self) -> bool {
+        std::mem::take(&mut self.updated)
+    }
+}
+
+unsafe fn opt_string(ptr: *const c_char) -> Option<String> {
+    if ptr.is_null() {
+        return None;
+    }
+    let value = CStr::from_ptr(ptr).to_string_lossy().into_owned();
+    if value.is_empty() {
+        None
+    } else {
+        Some(value)
+    }
+}
+
+unsafe fn read_values(values: &[RetroCoreOptionValue]) -> Vec<CoreOptionValue> {
+    values
+        .iter()
+        .take_while(|entry| !entry.value.is_null())
+        .filter_map(|entry| {
+            Some(CoreOptionValue {
+                value: opt_string(entry.value)?,
+                label: opt_string(entry.label),
+            })
+        })
+        .collect()
+}
+
+fn default_or_first(default_value: Option<String>, values: &[CoreOptionValue]) -> String {
+    default_value
+        .filter(|value| values.iter().any(|candidate| &candidate.value == value))
+        .or_else(|| values.first().map(|value| value.value.clone()))
+        .unwrap_or_default()
+}
+
+/// Parses a legacy `SET_VARIABLES` entry of the form `"Description; a|b|c"`.
+fn parse_v0_value(key: String, raw: &str) -> Option<CoreOption> {
+    let (description, choices) = raw.split_once(';')?;
+    let values: Vec<CoreOptionValue> = choices
+        .trim_start()
+        .split('|')
+        .filter(|value| !value.is_empty())
+        .map(|value| CoreOptionValue {
+            value: value.to_string(),
+            label: None,
+        })
+        .collect();
+    let default_value = values.first()?.value.clone();
+    Some(CoreOption {
+        key,
+        description: description.trim().to_string(),
+        info: None,
+        category: None,
+        values,
+        default_value,
+        visible: true,
+    })
+}
+
+pub(crate) unsafe fn read_v0(mut variables: *const RetroVariable) -> Vec<CoreOption> {
+    let mut options = Vec::new();
+    if variables.is_null() {
+        return options;
+    }
+    while !(*variables).key.is_null() {
+        let entry = &*variables;
+        if let (Some(key), Some(raw)) = (opt_string(entry.key), opt_string(entry.value)) {
+            if let Some(option) = parse_v0_value(key, &raw) {
+                options.push(option);
+            }
+        }
+        variables = variables.add(1);
+    }
+    options
+}
+
+pub(crate) unsafe fn read_v1(mut definitions: *const RetroCoreOptionDefinition) -> Vec<CoreOption> {
+    let mut options = Vec::new();
+    if definitions.is_null() {
+        return options;
+    }
+    while !(*definitions).key.is_null() {
+        let entry = &*definitions;
+        if let Some(key) = opt_string(entry.key) {
+            let values = read_values(&entry.values);
+            let default_value = default_or_first(opt_string(entry.default_value), &values);
+            options.push(CoreOption {
+                description: opt_string(entry.desc).unwrap_or_else(|| key.clone()),
+                key,
+                info: opt_string(entry.info),
+                category: None,
+                values,
+                default_value,
+                visible: true,
+            });
+        }
+        definitions = definitions.add(1);
+    }
+    options
+}
+
+pub(crate) unsafe fn read_v1_intl(intl: *const RetroCoreOptionsIntl) -> Vec<CoreOption> {
+    if intl.is_null() {
+        return Vec::new();
+    }
+    read_v1((*intl).us)
+}
+
+pub(crate) unsafe fn read_v2(
+    options: *const RetroCoreOptionsV2,
+) -> (Vec<CoreOption>, Vec<CoreOptionCategory>) {
+    if options.is_null() {
+        return (Vec::new(), Vec::new());
+    }
+    let options = &*options;
+
+    let mut categories = Vec::new();
+    let mut category = options.categories;
+    if !category.is_null() {
+        while !(*category).key.is_null() {
+            let entry = &*category;
+            if let Some(key) = opt_string(entry.key) {
+                categories.push(CoreOptionCategory {
+                    description: opt_string(entry.desc).unwrap_or_else(|| key.clone()),
+                    key,
+                    info: opt_string(entry.info),
+                });
+            }
+            category = category.add(1);
+        }
+    }
+
+    let mut definitions = Vec::new();
+    let mut definition = options.definitions;
+    if !definition.is_null() {
+        while !(*definition).key.is_null() {
+            let entry = &*definition;
+            if let Some(key) = opt_string(entry.key) {
+                let values = read_values(&entry.values);
+                let default_value = default_or_first(opt_string(entry.default_value), &values);
+                definitions.push(CoreOption {
+                    description: opt_string(entry.desc).unwrap_or_else(|| key.clone()),
+                    key,
+                    info: opt_string(entry.info),
+                    category: opt_string(entry.category_key),
+                    values,
+                    default_value,
+                    visible: true,
+                });
+            }
+            definition = definition.add(1);
+        }
+    }
+
+    (definitions, categories)
+}
+
+pub(crate) unsafe fn read_v2_intl(
+    intl: *const RetroCoreOptionsV2Intl,
+) -> (Vec<CoreOption>, Vec<CoreOptionCategory>) {
+    if intl.is_null() {
+        return (Vec::new(), Vec::new());
+    }
+    read_v2((*intl).us)
+}
+
+pub(crate) unsafe fn read_display(
+    display: *const RetroCoreOptionDisplay,
+) -> Option<(String, bool)> {
+    if display.is_null() {
+        return None;
+    }
+    let display = &*display;
+    Some((opt_string(display.key)?, display.visible))
+}
@@ -0,0 +1,170 @@
+use playbyte_libretro::{
+    gl_available,
+    testing::{mock_content, mock_core_path},
+    Callbacks, Language, LibretroCore, LibretroError, SerializationQuirks,
+};
+use std::sync::Mutex;
+
+/// Keeps the tests from claiming more instance slots than the crate has.
+static SERIAL: Mutex<()> = Mutex::new(());
+
+fn callbacks() -> Callbacks {
+    Callbacks::new(
+        Box::new(|_, _, _, _, _| {}),
+        Box::new(|_| {}),
+        Box::new(|| {}),
+        Box::new(|_, _, _, _| 0),
+    )
+}
+
+fn env_value(core: &LibretroCore, name: &str) -> Option<String> {
+    let prefix = format!("env {name} = ");
+    core.recent_log()
+        .iter()
+        .find_map(|line| line.message.strip_prefix(&prefix).map(str::to_string))
+}
+
+#[test]
+fn mock_core_sees_frontend_answers_and_declarations() {
+    let Some((mock, game)) = mock_content("env-answers", "quirks:4") else {
+        return;
+    };
+    let _guard = SERIAL
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    let system = std::env::temp_dir().join("playbyte-env-system");
+    let callbacks = callbacks()
+        .with_system_directory(&system)
+        .with_language(Language::German)
+        .with_input_bitmasks();
+    let mut core = LibretroCore::load(mock, callbacks).expect("load mock core");
+    core.load_game(&game).expect("load content");
+
+    assert_eq!(env_value(&core, "GET_LANGUAGE").as_deref(), Some("4"));
+    assert_eq!(env_value(&core, "GET_CAN_DUPE").as_deref(), Some("true"));
+    assert_eq!(
+        env_value(&core, "GET_INPUT_BITMASKS").as_deref(),
+        Some("true")
+    );
+    assert_eq!(
+        env_value(&core, "GET_INPUT_MAX_USERS").as_deref(),
+        Some("4")
+    );
+    assert_eq!(
+        env_value(&core, "GET_AUDIO_VIDEO_ENABLE").as_deref(),
+        Some("3")
+    );
+    assert_eq!(
+        env_value(&core, "GET_PREFERRED_HW_RENDER").as_deref(),
+        Some(if gl_available() { "1" } else { "refused" })
+    );
+    assert_eq!(
+        env_value(&core, "GET_SAVE_DIRECTORY").as_deref(),
+        Some("refused")
+    );
+    assert_eq!(env_value(&core, "SET_ROTATION 0").as_deref(), Some("true"));
+    assert_eq!(
+        env_value(&core, "GET_SYSTEM_DIRECTORY"),
+        Some(system.to_string_lossy().into_owned())
+    );
+    assert_eq!(
+        env_value(&core, "GET_LIBRETRO_PATH"),
+        Some(mock.to_string_lossy().into_owned())
+    );
+    assert_eq!(
+        env_value(&core, "GET_VARIABLE mock_palette").as_deref(),
+        Some("warm")
+    );
+    assert!(core
+        .recent_log()
+        .iter()
+        .any(|line| line.message == "mock core ready"));
+
+    let descriptors = core.input_descriptors();
+    assert_eq!(descriptors.len(), 2);
+    assert_eq!(descriptors[0].description, "Jump");
+    let controllers = core.controller_info();
+    assert_eq!(controllers.len(), 2);
+    assert_eq!(controllers[0].types[0].description, "Mock Pad");
+    assert_eq!(controllers[1].types[1].description, "Mock Gun");
+    assert_eq!(controllers[1].types[1].device, 0x104);
+    let map = core.memory_map();
+    assert_eq!(map.len(), 2);
+    assert_eq!(map[1].start, 0xa000);
+    assert_eq!(map[1].address_space.as_deref(), Some("SRAM"));
+    assert!(map.iter().all(|descriptor| descriptor.has_data));
+    assert!(!core.supports_no_game());
+    assert!(core.supports_achievements());
+    assert_eq!(core.performance_level(), 4);
+    assert!(core
+        .serialization_quirks()
+        .contains(SerializationQuirks::CORE_VARIABLE_SIZE));
+
+    let _ = std::fs::remove_file(game);
+}
+
+#[test]
+fn geometry_and_timing_updates_apply_after_the_frame() {
+    let Some((mock, game)) = mock_content("env-updates", "resize timing shutdown") else {
+        return;
+    };
+    let _guard = SERIAL
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    let mut core = LibretroCore::load(mock, callbacks()).expect("load mock core");
+    core.load_game(&game).expect("load content");
+
+    let before = core.system_av_info();
+    assert_eq!(before.geometry.base_width, 160);
+    assert_eq!(before.timing.fps, 60.0);
+
+    core.run_frames(5);
+    assert!(core.shutdown_requested());
+    core.run_frames(6);
+    let after = core.system_av_info();
+    assert_eq!(after.geometry.base_width, 320);
+    assert_eq!(after.geometry.base_height, 288);
+    assert_eq!(after.geometry.max_width, 320);
+    assert_eq!(after.timing.fps, 50.0);
+
+    let _ = std::fs::remove_file(game);
+}
+
+#[test]
+fn refused_content_carries_the_core_log() {
+    let Some((mock, game)) = mock_content("env-fail", "fail") else {
+        return;
+    };
+    let _guard = SERIAL
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    let mut core = LibretroCore::load(mock, callbacks()).expect("load mock core");
+
+    match core.load_game(&game) {
+        Err(LibretroError::LoadGame { log }) => {
+            assert!(log
+                .iter()
+                .any(|line| line.message == "content asked to fail"));
+        }
+        other => panic!("expected LoadGame error, got {other:?}"),
+    }
+
+    let _ = std::fs::remove_file(game);
+}
+
+#[test]
+fn content_loads_from_memory_under_its_name() {
+    let Some(mock) = mock_core_path() else {
+        eprintln!("mock core not built; skipping");
+        return;
+    };
+    let _guard = SERIAL
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    let mut core = LibretroCore::load(mock, callbacks()).expect("load mock core");
+    core.load_game_from_memory("roms/inner.mock", b"resize".to_vec())
+        .expect("load content from memory");
+
+    core.run_frames(11);
+    assert_eq!(core.system_av_info().geometry.base_width, 320);
+}
@@ -0,0 +1,60 @@
+use playbyte_libretro::{
+    gl_available, testing::mock_core_path, Callbacks, HwContextType, LibretroCore,
+    RetroPixelFormat, VideoFrame,
+};
+use std::sync::{Arc, Mutex};
+
+#[test]
+fn gl_frames_come_back_as_top_down_xrgb8888() {
+    let Some(mock) = mock_core_path() else {
+        eprintln!("mock core not built; skipping");
+        return;
+    };
+    if !gl_available() {
+        eprintln!("no EGL implementation; skipping");
+        return;
+    }
+    let latest: Arc<Mutex<Option<VideoFrame>>> = Arc::new(Mutex::new(None));
+    let capture = latest.clone();
+    let callbacks = Callbacks::new(
+        Box::new(move |data, width, height, pitch, pixel_format| {
+            *capture.lock().unwrap() = Some(VideoFrame {
+                width,
+                height,
+                pitch,
+                pixel_format,
+                data: data.to_vec(),
+            });
+        }),
+        Box::new(|_| {}),
+        Box::new(|| {}),
+        Box::new(|_, _, _, _| 0),
+    );
+    let mut core = LibretroCore::load(mock, callbacks).expect("load mock core");
+    core.load_game_from_memory("hw.mock", b"gl".to_vec())
+        .expect("load content");
+    let request = core.hw_render().expect("core renders in hardware");
+    assert_eq!(request.context_type, HwContextType::OpenGl);
+    assert!(core
+        .recent_log()
+        .iter()
+        .any(|line| line.message == "env context_reset = ready"));
+
+    core.run_frames(2);
+    let frame = latest.lock().unwrap().take().expect("frame presented");
+    assert_eq!((frame.width, frame.height), (160, 144));
+    assert_eq!(frame.pitch, 160 * 4);
+    assert_eq!(frame.pixel_format, RetroPixelFormat::Xrgb8888);
+    let pixel = |x: usize, y: usize| {
+        let offset = y * frame.pitch + x * 4;
+        [
+            frame.data[offset + 2],
+            frame.data[offset + 1],
+            frame.data[offset],
+        ]
+    };
+    assert_eq!(pixel(0, 0), [0xff, 0, 0]);
+    assert_eq!(pixel(159, 71), [0xff, 0, 0]);
+    assert_eq!(pixel(0, 72), [0, 0xff, 0]);
+    assert_eq!(pixel(80, 143), [0, 0xff, 0]);
+}
@@ -0,0 +1,263 @@
+use playbyte_libretro::{
+    testing::{mock_content, mock_core_path},
+    Callbacks, CoreOption, CoreOptionCategory, LibretroCore, LibretroError,
+};
+use std::{collections::HashMap, sync::Mutex};
+
+/// Keeps the tests from claiming more instance slots than the crate has.
+static SERIAL: Mutex<()> = Mutex::new(());
+
+fn callbacks() -> Callbacks {
+    Callbacks::new(
+        Box::new(|_, _, _, _, _| {}),
+        Box::new(|_| {}),
+        Box::new(|| {}),
+        Box::new(|_, _, _, _| 0),
+    )
+}
+
+/// Every value the core logged for `name`, oldest first.
+fn env_values(core: &LibretroCore, name: &str) -> Vec<String> {
+    let prefix = format!("env {name} = ");
+    core.recent_log()
+        .iter()
+        .filter_map(|line| line.message.strip_prefix(&prefix).map(str::to_string))
+        .collect()
+}
+
+/// Loads the mock core with `flags` as its content.
+fn load(mock: &std::path::Path, callbacks: Callbacks, name: &str, flags: &str) -> LibretroCore {
+    let mut core = LibretroCore::load(mock, callbacks).expect("load mock core");
+    let (_, game) = mock_content(&format!("options-{name}"), flags).expect("mock core built");
+    core.load_game(&game).expect("load content");
+    let _ = std::fs::remove_file(game);
+    core
+}
+
+fn option<'a>(options: &'a [CoreOption], key: &str) -> &'a CoreOption {
+    options
+        .iter()
+        .find(|option| option.key == key)
+        .unwrap_or_else(|| panic!("{key} declared"))
+}
+
+fn values(option: &CoreOption) -> Vec<&str> {
+    option
+        .values
+        .iter()
+        .map(|value| value.value.as_str())
+        .collect()
+}
+
+#[test]
+fn legacy_variables_default_to_their_first_value() {
+    let Some(mock) = mock_core_path() else {
+        eprintln!("mock core not built; skipping");
+        return;
+    };
+    let _guard = SERIAL
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    let core = load(mock, callbacks(), "v0", "options:v0");
+
+    // The entry without a "Description; values" separator is skipped.
+    let options = core.core_options();
+    assert_eq!(options.len(), 1, "{options:?}");
+    let speed = option(&options, "mock_speed");
+    assert_eq!(speed.description, "Speed");
+    assert_eq!(values(speed), ["normal", "fast", "slow"]);
+    assert!(speed.values.iter().all(|value| value.label.is_none()));
+    assert_eq!(speed.default_value, "normal");
+    assert_eq!(speed.info, None);
+    assert_eq!(speed.category, None);
+    assert!(speed.visible);
+    assert_eq!(core.core_option("mock_speed").as_deref(), Some("normal"));
+    assert!(core.core_option_categories().is_empty());
+}
+
+#[test]
+fn core_option_definitions_are_parsed_in_every_format() {
+    let Some(mock) = mock_core_path() else {
+        eprintln!("mock core not built; skipping");
+        return;
+    };
+    let _guard = SERIAL
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    for format in ["v1", "v1_intl", "v2", "v2_intl"] {
+        let core = load(mock, callbacks(), format, &format!("options:{format}"));
+        let categorized = format.starts_with("v2");
+
+        // The intl formats are read in English; the local definitions are ignored.
+        let options = core.core_options();
+        let keys: Vec<&str> = options.iter().map(|option| option.key.as_str()).collect();
+        assert_eq!(keys, ["mock_speed", "mock_filter"], "{format}");
+
+        let speed = option(&options, "mock_speed");
+        assert_eq!(speed.description, "Speed", "{format}");
+        assert_eq!(
+            speed.info.as_deref(),
+            Some("How fast the mock runs"),
+            "{format}"
+        );
+        assert_eq!(values(speed), ["normal", "fast", "slow"], "{format}");
+        assert_eq!(speed.values[0].label.as_deref(), Some("Normal"), "{format}");
+        assert_eq!(speed.values[2].label, None, "{format}");
+        assert_eq!(speed.default_value, "fast", "{format}");
+        assert_eq!(
+            speed.category.as_deref(),
+            categorized.then_some("video"),
+            "{format}"
+        );
+
+        // A default that is not one of the values falls back to the first value.
+        let filter = option(&options, "mock_filter");
+        assert_eq!(filter.default_value, "off", "{format}");
+        assert_eq!(filter.info, None, "{format}");
+        assert_eq!(filter.category, None, "{format}");
+
+        assert_eq!(
+            core.core_option_values(),
+            HashMap::from([
+                ("mock_speed".to_string(), "fast".to_string()),
+                ("mock_filter".to_string(), "off".to_string()),
+            ]),
+            "{format}"
+        );
+        let categories = core.core_option_categories();
+        if categorized {
+            assert_eq!(
+                categories,
+                [CoreOptionCategory {
+                    key: "video".to_string(),
+                    description: "Video".to_string(),
+                    info: Some("Picture settings".to_string()),
+                }],
+                "{format}"
+            );
+        } else {
+            assert!(categories.is_empty(), "{format}");
+        }
+    }
+}
+
+#[test]
+fn invalid_values_are_refused() {
+    let Some(mock) = mock_core_path() else {
+        eprintln!("mock core not built; skipping");
+        return;
+    };
+    let _guard = SERIAL
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+    // Stored values that the core does not declare are ignored in favour of the default.
+    let overrides = HashMap::from([
+        ("mock_palette".to_string(), "hot".to_string()),
+        ("mock_missing".to_string(), "on".to_string()),
+    ]);
+    let mut core = load(
+        mock,
+        callbacks().with_core_options(overrides),
+        "invalid",
+        "",
+    );
+    assert_eq!(env_values(&core, "GET_VARIABLE mock_palette"), ["warm"]);
+    assert_eq!(core.core_option("mock_missing"), None);
+
+    assert!(matches!(
+        core.set_core_option("mock_palette", "hot"),
+        Err(LibretroError::InvalidOptionValue { .. })
+    ));
+    assert!(matches!(
+        core.set_core_option("mock_missing", "on"),
+        Err(LibretroError::UnknownOption(key)) if key == "mock_missing"
+    ));
+    assert_eq!(core.core_option("mock_palette").as_deref(), Some("warm"));
+
+    // Nothing changed, so the core is not told to look again.
+    core.run_frame();
+    assert!(env_values(&core, "GET_VARIABLE_UPDATE mock_palette").is_empty());
+
+    core.set_core_option("mock_palette", "cool")
+        .expect("set option");
+    core.run_frame();
+    core.run_frame();
+    assert_eq!(
+        env_values(&core, "GET_VARIABLE_UPDATE mock_palette"),
+        ["cool"]
+    );
+}
+
+#[test]
+fn stored_values_apply_when_valid() {
+    let Some(mock) = mock_core_path() else {
+        eprintln!("mock core not built; skipping");
+        return;
+    };
+    let _guard = SERIAL
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    let overrides = HashMap::from([("mock_palette".to_string(), "cool".to_string())]);
+    let core = load(mock, callbacks().with_core_options(overrides), "stored", "");
+    assert_eq!(env_values(&core, "GET_VARIABLE mock_palette"), ["cool"]);
+    assert_eq!(
+        core.core_option_values(),
+        HashMap::from([("mock_palette".to_string(), "cool".to_string())])
+    );
+}
+
+#[test]
+fn redeclared_variables_keep_values_that_are_still_valid() {
+    let Some(mock) = mock_core_path() else {
+        eprintln!("mock core not built; skipping");
+        return;
+    };
+    let _guard = SERIAL
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+    // An untouched value the new declaration still accepts is kept.
+    let mut core = load(mock, callbacks(), "kept", "redeclare");
+    core.run_frame();
+    let options = core.core_options();
+    assert_eq!(values(option(&options, "mock_palette")), ["mono", "warm"]);
+    assert_eq!(core.core_option("mock_palette").as_deref(), Some("warm"));
+    drop(core);
+
+    // A value the new declaration drops goes back to the new default, and the change made
+    // before it no longer counts as an update.
+    let mut core = load(mock, callbacks(), "dropped", "redeclare");
+    core.set_core_option("mock_palette", "cool")
+        .expect("set option");
+    core.run_frame();
+    assert_eq!(core.core_option("mock_palette").as_deref(), Some("mono"));
+    assert!(env_values(&core, "GET_VARIABLE_UPDATE mock_palette").is_empty());
+    drop(core);
+
+    // A stored value the first declaration refused applies once the core accepts it.
+    let overrides = HashMap::from([("mock_palette".to_string(), "mono".to_string())]);
+    let mut core = load(
+        mock,
+        callbacks().with_core_options(overrides),
+        "override",
+        "redeclare",
+    );
+    assert_eq!(env_values(&core, "GET_VARIABLE mock_palette"), ["warm"]);
+    core.run_frame();
+    assert_eq!(core.core_option("mock_palette").as_deref(), Some("mono"));
+    drop(core);
+
+    // A value set at runtime wins over the stored one once the core redeclares.
+    let overrides = HashMap::from([("mock_palette".to_string(), "mono".to_string())]);
+    let mut core = load(
+        mock,
+        callbacks().with_core_options(overrides),
+        "set",
+        "redeclare",
+    );
+    core.set_core_option("mock_palette", "warm")
+        .expect("set option");
+    core.run_frame();
+    assert_eq!(core.core_option("mock_palette").as_deref(), Some("warm"));
+}
@@ -0,0 +1,82 @@
+use playbyte_libretro::{testing::mock_content, Callbacks, LibretroCore, LibretroError};
+use std::{path::PathBuf, sync::Mutex};
+
+/// Keeps the tests from claiming more instance slots than the crate has.
+static SERIAL: Mutex<()> = Mutex::new(());
+
+fn load(name: &str, flags: &str) -> Option<(LibretroCore, PathBuf)> {
+    let (mock, content) = mock_content(&format!("serialize-{name}"), flags)?;
+    let callbacks = Callbacks::new(
+        Box::new(|_, _, _, _, _| {}),
+        Box::new(|_| {}),
+        Box::new(|| {}),
+        Box::new(|_, _, _, _| 0),
+    );
+    let mut core = LibretroCore::load(mock, callbacks).expect("load mock core");
+    core.load_game(&content).expect("load content");
+    Some((core, content))
+}
+
+#[test]
+fn fixed_size_states_must_match_the_core_size() {
+    let _guard = SERIAL
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    let Some((mut core, content)) = load("fixed", "") else {
+        eprintln!("mock core not built; skipping");
+        return;
+    };
+    assert!(core.is_state_portable());
+    core.run_frames(3);
+    let state = core.serialize().expect("serialize failed");
+    core.unserialize(&state).expect("unserialize failed");
+
+    let mut padded = state.clone();
+    padded.extend_from_slice(&[0; 8]);
+    match core.unserialize(&padded) {
+        Err(LibretroError::StateSize { expected, actual }) => {
+            assert_eq!(expected, state.len());
+            assert_eq!(actual, padded.len());
+        }
+        other => panic!("expected StateSize error, got {other:?}"),
+    }
+    let _ = std::fs::remove_file(content);
+}
+
+#[test]
+fn variable_size_cores_accept_other_sizes() {
+    let _guard = SERIAL
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    // CORE_VARIABLE_SIZE
+    let Some((mut core, content)) = load("variable", "quirks:4") else {
+        eprintln!("mock core not built; skipping");
+        return;
+    };
+    core.run_frame();
+    let mut state = core.serialize().expect("serialize failed");
+    state.extend_from_slice(&[0; 8]);
+    core.unserialize(&state).expect("unserialize failed");
+    assert!(core.is_state_portable());
+    let _ = std::fs::remove_file(content);
+}
+
+#[test]
+fn quirks_gate_serialization_and_portability() {
+    let _guard = SERIAL
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    // MUST_INITIALIZE | SINGLE_SESSION
+    let Some((mut core, content)) = load("quirks", "quirks:18") else {
+        eprintln!("mock core not built; skipping");
+        return;
+    };
+    assert!(!core.is_state_portable());
+    assert!(matches!(
+        core.serialize(),
+        Err(LibretroError::StateNotReady)
+    ));
+    core.run_frame();
+    core.serialize().expect("serialize after a frame failed");
+    let _ = std::fs::remove_file(content);
+}
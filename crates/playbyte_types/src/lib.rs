@@ -1,4 +1,9 @@
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// The `byte.json` schema this build writes. Bytes from older versions are migrated when
+/// their state is loaded; ones from newer versions are refused.
+pub const BYTE_FORMAT_VERSION: u32 = 2;
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
 #[serde(rename_all = "lowercase")]
@@ -9,8 +14,27 @@ pub enum System {
     Gba,
 }
 
+/// A core's run-ahead, as set in `run_ahead.json` under the data root.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RunAheadSettings {
+    /// Frames to run ahead; 0 turns run-ahead off for the core.
+    pub frames: u32,
+    #[serde(default)]
+    pub second_instance: bool,
+}
+
+/// A picture size in pixels.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Resolution {
+    pub width: u32,
+    pub height: u32,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ByteMetadata {
+    /// `BYTE_FORMAT_VERSION` when the Byte was written; 1 for Bytes from before versioning.
+    #[serde(default = "unversioned_format")]
+    pub format_version: u32,
     pub byte_id: String,
     pub system: System,
     pub core_id: String,
@@ -24,4 +48,30 @@ pub struct ByteMetadata {
     pub created_at: String,
     pub thumbnail_path: String,
     pub state_path: String,
+    /// An input movie (`.pbm`) played from the Byte's state before the player takes over.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ghost_path: Option<String>,
+    /// SHA-256 of the uncompressed state, in lowercase hex.
+    #[serde(default)]
+    pub state_sha256: String,
+    /// SHA-256 of the thumbnail file, in lowercase hex.
+    #[serde(default)]
+    pub thumbnail_sha256: String,
+    /// Length of the uncompressed state in bytes.
+    #[serde(default)]
+    pub state_size: u64,
+    /// What the core's `retro_serialize_size` reported when the state was saved, checked
+    /// against the core that loads it. 0 when unknown, as for Bytes from format version 1.
+    #[serde(default)]
+    pub serialize_size: u64,
+    /// Core option values the Byte was made under, keyed by option key.
+    #[serde(default)]
+    pub core_options: BTreeMap<String, String>,
+    /// The size of the picture when the Byte was made.
+    #[serde(default)]
+    pub resolution: Option<Resolution>,
+}
+
+fn unversioned_format() -> u32 {
+    1
 }
@@ -1,4 +1,7 @@
+mod test_roms;
+
 use anyhow::{bail, Context, Result};
+use playbyte_emulation::{verify_determinism, DeterminismConfig, GameContent, RuntimeError};
 use std::{
     fs,
     path::{Path, PathBuf},
@@ -57,8 +60,10 @@ fn main() -> Result<()> {
     match args.next().as_deref() {
         Some("build-cores") => build_cores(),
         Some("package") => package(),
+        Some("verify-determinism") => verify_cores(args),
+        Some("test-roms") => test_roms::write_all(Path::new("test-roms")),
         _ => {
-            eprintln!("Usage: cargo xtask <build-cores|package>");
+            eprintln!("Usage: cargo xtask <build-cores|package|verify-determinism|test-roms>");
             Ok(())
         }
     }
@@ -146,6 +151,19 @@ fn package() -> Result<()> {
     let binary_dst = package_dir.join(binary_name);
     fs::copy(&binary_src, &binary_dst)?;
 
+    // Helper used by `--isolate-cores`; the app looks for it next to its own binary.
+    let host_name = if cfg!(target_os = "windows") {
+        "playbyte_core_host.exe"
+    } else {
+        "playbyte_core_host"
+    };
+    let host_src = PathBuf::from("target/release").join(host_name);
+    if host_src.exists() {
+        fs::copy(&host_src, package_dir.join(host_name))?;
+    } else {
+        println!("Skipping {host_name}: not built");
+    }
+
     let cores_src = PathBuf::from("dist/cores");
     if cores_src.exists() {
         let cores_dst = package_dir.join("cores");
@@ -166,6 +184,95 @@ fn package() -> Result<()> {
     Ok(())
 }
 
+/// Runs the determinism verifier on every core in `dist/cores` with every test ROM it
+/// accepts; a core that accepts none of them fails. Options: `--roms <dir>` (default
+/// `test-roms`), `--frames <n>`, `--seed <n>`.
+fn verify_cores(mut args: impl Iterator<Item = String>) -> Result<()> {
+    let mut roms_dir = PathBuf::from("test-roms");
+    let mut config = DeterminismConfig::default();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().with_context(|| format!("{arg} needs a value"));
+        match arg.as_str() {
+            "--roms" => roms_dir = PathBuf::from(value()?),
+            "--frames" => config.frames = value()?.parse().context("invalid --frames")?,
+            "--seed" => config.seed = value()?.parse().context("invalid --seed")?,
+            other => bail!("unknown option {other}"),
+        }
+    }
+
+    let cores = sorted_files(Path::new("dist/cores"))
+        .context("no cores in dist/cores (run cargo xtask build-cores)")?;
+    let roms = sorted_files(&roms_dir)
+        .with_context(|| format!("failed to read test ROMs from {}", roms_dir.display()))?;
+    let mut failed = Vec::new();
+    let mut untested = Vec::new();
+    for core in cores
+        .iter()
+        .filter(|path| path.extension().is_some_and(|ext| ext == core_extension()))
+    {
+        let core_name = core
+            .file_stem()
+            .with_context(|| format!("no file name in {}", core.display()))?
+            .to_string_lossy();
+        let mut tested = false;
+        for rom in &roms {
+            let rom_name = rom
+                .file_name()
+                .with_context(|| format!("no file name in {}", rom.display()))?
+                .to_string_lossy();
+            let report = match verify_determinism(core, GameContent::Path(rom.clone()), &config) {
+                Ok(report) => report,
+                Err(RuntimeError::IncompatibleRom { .. }) => continue,
+                Err(err) => {
+                    println!("{core_name} / {rom_name}: error: {err}");
+                    failed.push(format!("{core_name} / {rom_name}"));
+                    tested = true;
+                    continue;
+                }
+            };
+            tested = true;
+            match report.divergence {
+                Some(divergence) => {
+                    println!("{core_name} / {rom_name}: {divergence}");
+                    failed.push(format!("{core_name} / {rom_name}"));
+                }
+                None => println!(
+                    "{core_name} / {rom_name}: ok ({} frames, {} restores)",
+                    report.frames_compared, report.restores
+                ),
+            }
+        }
+        if !tested {
+            println!("{core_name}: no test ROM in {}", roms_dir.display());
+            untested.push(core_name.into_owned());
+        }
+    }
+
+    let mut problems = Vec::new();
+    if !failed.is_empty() {
+        problems.push(format!("not deterministic: {}", failed.join(", ")));
+    }
+    if !untested.is_empty() {
+        problems.push(format!("no test ROM for {}", untested.join(", ")));
+    }
+    if !problems.is_empty() {
+        bail!("{}", problems.join("; "));
+    }
+    Ok(())
+}
+
+fn sorted_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_type()?.is_file() {
+            files.push(entry.path());
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
 fn core_extension() -> &'static str {
     if cfg!(target_os = "windows") {
         "dll"
@@ -0,0 +1,293 @@
+//! The ROMs in `test-roms/`, written for this repository so they can be checked in.
+//!
+//! Each one is a few dozen hand-assembled instructions that read the joypad once a frame,
+//! add the buttons plus one to a counter in RAM, and show the counter as the backdrop
+//! colour and, where a square wave is a register write away, as the pitch of a tone. That
+//! keeps the picture, the sound and the state changing every frame without a title screen
+//! to wait on.
+
+use anyhow::{Context, Result};
+use std::{fs, path::Path};
+
+/// Mapper 0 with 16 KiB of PRG ROM, loaded at $C000. The last instruction is the `rti`
+/// the interrupt vectors point at.
+#[rustfmt::skip]
+const NES_PROGRAM: &[u8] = &[
+    // reset:
+    0x78, // sei
+    0xd8, // cld
+    0xa2, 0xff, // ldx #$ff
+    0x9a, // txs
+    0xa9, 0x00, // lda #$00
+    0x8d, 0x00, 0x20, // sta $2000 ; no NMI
+    0x8d, 0x01, 0x20, // sta $2001 ; rendering off, so the backdrop fills the screen
+    0xa9, 0x01, // lda #$01
+    0x8d, 0x15, 0x40, // sta $4015 ; pulse 1 on
+    // loop:
+    0xa9, 0x01, // lda #$01
+    0x8d, 0x16, 0x40, // sta $4016 ; latch the joypads
+    0xa9, 0x00, // lda #$00
+    0x8d, 0x16, 0x40, // sta $4016
+    0xa2, 0x08, // ldx #$08
+    // read:
+    0xad, 0x16, 0x40, // lda $4016
+    0x4a, // lsr a
+    0x26, 0x00, // rol $00 ; buttons
+    0xca, // dex
+    0xd0, 0xf7, // bne read
+    0xa5, 0x01, // lda $01
+    0x18, // clc
+    0x65, 0x00, // adc $00
+    0x69, 0x01, // adc #$01
+    0x85, 0x01, // sta $01 ; counter += buttons + 1
+    0x2c, 0x02, 0x20, // bit $2002
+    0xa9, 0x3f, // lda #$3f
+    0x8d, 0x06, 0x20, // sta $2006
+    0xa9, 0x00, // lda #$00
+    0x8d, 0x06, 0x20, // sta $2006
+    0xa5, 0x01, // lda $01
+    0x29, 0x3f, // and #$3f
+    0x8d, 0x07, 0x20, // sta $2007 ; backdrop colour
+    0xa9, 0x20, // lda #$20
+    0x8d, 0x06, 0x20, // sta $2006
+    0xa9, 0x00, // lda #$00
+    0x8d, 0x06, 0x20, // sta $2006 ; point the PPU away from the palette again
+    0xa9, 0xbf, // lda #$bf
+    0x8d, 0x00, 0x40, // sta $4000 ; 50% duty, full volume
+    0xa5, 0x01, // lda $01
+    0x8d, 0x02, 0x40, // sta $4002 ; period from the counter
+    0xa9, 0x08, // lda #$08
+    0x8d, 0x03, 0x40, // sta $4003
+    // wait:
+    0x2c, 0x02, 0x20, // bit $2002
+    0x10, 0xfb, // bpl wait ; until vblank
+    0x4c, 0x12, 0xc0, // jmp loop
+    // nmi:
+    0x40, // rti
+];
+
+/// Runs from $0150 on a 32 KiB cartridge without a mapper.
+#[rustfmt::skip]
+const GB_PROGRAM: &[u8] = &[
+    // start:
+    0xf3, // di
+    0x31, 0xfe, 0xff, // ld sp, $fffe
+    0x3e, 0x80, // ld a, $80
+    0xe0, 0x26, // ldh ($26), a ; sound on
+    0x3e, 0x77, // ld a, $77
+    0xe0, 0x24, // ldh ($24), a ; full volume
+    0x3e, 0x11, // ld a, $11
+    0xe0, 0x25, // ldh ($25), a ; channel 1 on both sides
+    // loop:
+    0x3e, 0x20, // ld a, $20
+    0xe0, 0x00, // ldh ($00), a ; d-pad
+    0xf0, 0x00, // ldh a, ($00)
+    0xf0, 0x00, // ldh a, ($00)
+    0x47, // ld b, a
+    0x3e, 0x10, // ld a, $10
+    0xe0, 0x00, // ldh ($00), a ; buttons
+    0xf0, 0x00, // ldh a, ($00)
+    0xf0, 0x00, // ldh a, ($00)
+    0xa8, // xor b
+    0x21, 0x00, 0xc0, // ld hl, $c000
+    0x86, // add a, (hl)
+    0x3c, // inc a
+    0x77, // ld (hl), a ; counter += buttons + 1
+    0xe0, 0x47, // ldh ($47), a ; background palette
+    0xe0, 0x13, // ldh ($13), a ; channel 1 frequency
+    0x3e, 0xf0, // ld a, $f0
+    0xe0, 0x12, // ldh ($12), a ; full volume
+    0x3e, 0x87, // ld a, $87
+    0xe0, 0x14, // ldh ($14), a ; restart channel 1
+    // wait:
+    0xf0, 0x44, // ldh a, ($44)
+    0xfe, 0x90, // cp 144
+    0x20, 0xfa, // jr nz, wait ; until vblank
+    // vblank:
+    0xf0, 0x44, // ldh a, ($44)
+    0xfe, 0x90, // cp 144
+    0x28, 0xfa, // jr z, vblank ; until the next line
+    0x18, 0xce, // jr loop
+];
+
+/// LoROM, 32 KiB, running from $8000 in bank 0. The last instruction is the `rti` the
+/// interrupt vectors point at.
+#[rustfmt::skip]
+const SNES_PROGRAM: &[u8] = &[
+    // reset:
+    0x78, // sei
+    0x18, // clc
+    0xfb, // xce ; native mode, 8-bit registers
+    0xa9, 0x0f, // lda #$0f
+    0x8d, 0x00, 0x21, // sta $2100 ; screen on, full brightness
+    0xa9, 0x01, // lda #$01
+    0x8d, 0x00, 0x42, // sta $4200 ; read the joypads every frame
+    // loop:
+    0xad, 0x12, 0x42, // lda $4212
+    0x10, 0xfb, // bpl loop ; until vblank
+    // joypad:
+    0xad, 0x12, 0x42, // lda $4212
+    0x29, 0x01, // and #$01
+    0xd0, 0xf9, // bne joypad ; until the joypads are read
+    0xad, 0x18, 0x42, // lda $4218
+    0x18, // clc
+    0x65, 0x00, // adc $00
+    0x69, 0x01, // adc #$01
+    0x85, 0x00, // sta $00 ; counter += buttons + 1
+    0xa9, 0x00, // lda #$00
+    0x8d, 0x21, 0x21, // sta $2121
+    0xa5, 0x00, // lda $00
+    0x8d, 0x22, 0x21, // sta $2122
+    0x29, 0x7f, // and #$7f
+    0x8d, 0x22, 0x21, // sta $2122 ; backdrop colour
+    // active:
+    0xad, 0x12, 0x42, // lda $4212
+    0x30, 0xfb, // bmi active ; until vblank ends
+    0x80, 0xd4, // bra loop
+    // nmi:
+    0x40, // rti
+];
+
+/// ARM code running from 0x080000C0, right after the cartridge header.
+#[rustfmt::skip]
+const GBA_PROGRAM: &[u32] = &[
+    // start:
+    0xe3a00301, // mov r0, #0x04000000
+    0xe3a01000, // mov r1, #0
+    0xe1c010b0, // strh r1, [r0] ; mode 0, no layers: the backdrop fills the screen
+    0xe3a01080, // mov r1, #0x80
+    0xe1c018b4, // strh r1, [r0, #0x84] ; sound on
+    0xe3a01077, // mov r1, #0x77
+    0xe3811c11, // orr r1, r1, #0x1100
+    0xe1c018b0, // strh r1, [r0, #0x80] ; channel 1 on both sides, full volume
+    0xe3a01002, // mov r1, #2
+    0xe1c018b2, // strh r1, [r0, #0x82]
+    0xe3a02405, // mov r2, #0x05000000 ; palette
+    0xe3a03403, // mov r3, #0x03000000 ; counter
+    0xe2804c01, // add r4, r0, #0x100
+    // loop:
+    0xe1d413b0, // ldrh r1, [r4, #0x30] ; buttons
+    0xe5935000, // ldr r5, [r3]
+    0xe0855001, // add r5, r5, r1
+    0xe2855001, // add r5, r5, #1
+    0xe5835000, // str r5, [r3] ; counter += buttons + 1
+    0xe1c250b0, // strh r5, [r2] ; backdrop colour
+    0xe3a06cf0, // mov r6, #0xf000
+    0xe3866080, // orr r6, r6, #0x80
+    0xe1c066b2, // strh r6, [r0, #0x62] ; full volume, 50% duty
+    0xe1a06a85, // mov r6, r5, lsl #21
+    0xe1a06aa6, // mov r6, r6, lsr #21
+    0xe3866c80, // orr r6, r6, #0x8000
+    0xe1c066b4, // strh r6, [r0, #0x64] ; restart at a frequency from the counter
+    // wait:
+    0xe1d060b6, // ldrh r6, [r0, #6]
+    0xe35600a0, // cmp r6, #160
+    0x1afffffc, // bne wait ; until vblank
+    // vblank:
+    0xe1d060b6, // ldrh r6, [r0, #6]
+    0xe35600a0, // cmp r6, #160
+    0x0afffffc, // beq vblank ; until the next line
+    0xeaffffeb, // b loop
+];
+
+/// The logo every Game Boy cartridge header carries; some cores check it.
+const GB_LOGO: [u8; 48] = [
+    0xce, 0xed, 0x66, 0x66, 0xcc, 0x0d, 0x00, 0x0b, 0x03, 0x73, 0x00, 0x83, 0x00, 0x0c, 0x00, 0x0d,
+    0x00, 0x08, 0x11, 0x1f, 0x88, 0x89, 0x00, 0x0e, 0xdc, 0xcc, 0x6e, 0xe6, 0xdd, 0xdd, 0xd9, 0x99,
+    0xbb, 0xbb, 0x67, 0x63, 0x6e, 0x0e, 0xec, 0xcc, 0xdd, 0xdc, 0x99, 0x9f, 0xbb, 0xb9, 0x33, 0x3e,
+];
+
+/// Writes every test ROM into `dir`, replacing the ones already there.
+pub fn write_all(dir: &Path) -> Result<()> {
+    fs::create_dir_all(dir)?;
+    for (name, rom) in [
+        ("playbyte-test.nes", nes()),
+        ("playbyte-test.sfc", snes()),
+        ("playbyte-test.gb", gb()),
+        ("playbyte-test.gba", gba()),
+    ] {
+        let path = dir.join(name);
+        fs::write(&path, rom).with_context(|| format!("failed to write {}", path.display()))?;
+        println!("Wrote {}", path.display());
+    }
+    Ok(())
+}
+
+fn nes() -> Vec<u8> {
+    let mut prg = vec![0xff; 0x4000];
+    prg[..NES_PROGRAM.len()].copy_from_slice(NES_PROGRAM);
+    let reset = 0xc000u16;
+    let rti = reset + NES_PROGRAM.len() as u16 - 1;
+    // NMI, reset and IRQ.
+    for (at, vector) in [(0x3ffa, rti), (0x3ffc, reset), (0x3ffe, rti)] {
+        prg[at..at + 2].copy_from_slice(&vector.to_le_bytes());
+    }
+
+    // iNES: one 16 KiB PRG bank, one 8 KiB CHR bank, mapper 0.
+    let mut rom = b"NES\x1a\x01\x01".to_vec();
+    rom.resize(16, 0);
+    rom.extend(prg);
+    rom.resize(rom.len() + 0x2000, 0);
+    rom
+}
+
+fn gb() -> Vec<u8> {
+    let mut rom = vec![0; 0x8000];
+    // nop; jp $0150
+    rom[0x100..0x104].copy_from_slice(&[0x00, 0xc3, 0x50, 0x01]);
+    rom[0x104..0x134].copy_from_slice(&GB_LOGO);
+    rom[0x134..0x141].copy_from_slice(b"PLAYBYTE TEST");
+    // ROM only, 32 KiB, no RAM, sold outside Japan.
+    rom[0x14a] = 0x01;
+    rom[0x150..0x150 + GB_PROGRAM.len()].copy_from_slice(GB_PROGRAM);
+
+    rom[0x14d] = rom[0x134..0x14d]
+        .iter()
+        .fold(0u8, |sum, byte| sum.wrapping_sub(*byte).wrapping_sub(1));
+    let checksum = rom
+        .iter()
+        .fold(0u16, |sum, byte| sum.wrapping_add(u16::from(*byte)));
+    rom[0x14e..0x150].copy_from_slice(&checksum.to_be_bytes());
+    rom
+}
+
+fn snes() -> Vec<u8> {
+    let mut rom = vec![0; 0x8000];
+    rom[..SNES_PROGRAM.len()].copy_from_slice(SNES_PROGRAM);
+    rom[0x7fc0..0x7fd5].copy_from_slice(b"PLAYBYTE TEST        ");
+    // LoROM, ROM only, 32 KiB, no RAM, North America.
+    rom[0x7fd5..0x7fdb].copy_from_slice(&[0x20, 0x00, 0x05, 0x00, 0x01, 0x00]);
+    let reset = 0x8000u16;
+    let rti = reset + SNES_PROGRAM.len() as u16 - 1;
+    for at in (0x7fe4..0x8000).step_by(2) {
+        rom[at..at + 2].copy_from_slice(&rti.to_le_bytes());
+    }
+    rom[0x7ffc..0x7ffe].copy_from_slice(&reset.to_le_bytes());
+
+    // The checksum and its complement always add up to the same bytes, so summing with
+    // placeholders gives the final sum.
+    rom[0x7fdc..0x7fe0].copy_from_slice(&[0xff, 0xff, 0x00, 0x00]);
+    let checksum = rom
+        .iter()
+        .fold(0u16, |sum, byte| sum.wrapping_add(u16::from(*byte)));
+    rom[0x7fdc..0x7fde].copy_from_slice(&(!checksum).to_le_bytes());
+    rom[0x7fde..0x7fe0].copy_from_slice(&checksum.to_le_bytes());
+    rom
+}
+
+fn gba() -> Vec<u8> {
+    let mut rom = vec![0; 0x8000];
+    // b 0x080000C0. The logo after it is left blank, which only the real BIOS checks.
+    rom[0..4].copy_from_slice(&0xea00_002eu32.to_le_bytes());
+    rom[0xa0..0xac].copy_from_slice(b"PLAYBYTE    ");
+    rom[0xac..0xb2].copy_from_slice(b"PBTE00");
+    rom[0xb2] = 0x96;
+    rom[0xbd] = rom[0xa0..0xbd]
+        .iter()
+        .fold(0u8, |sum, byte| sum.wrapping_sub(*byte))
+        .wrapping_sub(0x19);
+    for (word, at) in GBA_PROGRAM.iter().zip((0xc0..).step_by(4)) {
+        rom[at..at + 4].copy_from_slice(&word.to_le_bytes());
+    }
+    rom
+}